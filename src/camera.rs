@@ -0,0 +1,362 @@
+//! Scrolling view onto a room
+
+use failure::{err_msg, Error};
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect as SdlRect};
+use sdl2::render::{Canvas, RenderTarget};
+
+use geom::Rect;
+use room::Room;
+
+/// The part of a room that is visible on screen, in room pixel coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Camera {
+    /// Returns a camera centered on the point (`x`, `y`) showing at most
+    /// `view_width`×`view_height` pixels of `room`.
+    ///
+    /// The camera never shows anything outside the room; along an axis where
+    /// the room is smaller than the view, the whole room is shown.
+    pub fn following(x: f32, y: f32, view_width: u32, view_height: u32, room: &Room) -> Camera {
+        let (x, width) = follow_axis(x, view_width as f32, room.pixel_width());
+        let (y, height) = follow_axis(y, view_height as f32, room.pixel_height());
+        Camera {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the camera moved by `dx`×`dy` pixels, even if that shows some of
+    /// what's outside the room.
+    pub fn shifted(&self, dx: f32, dy: f32) -> Camera {
+        Camera {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..*self
+        }
+    }
+
+    pub fn left(&self) -> f32 {
+        self.x
+    }
+
+    pub fn top(&self) -> f32 {
+        self.y
+    }
+
+    /// Returns the logical size in pixels of the area the camera renders to.
+    pub fn logical_size(&self) -> (u32, u32) {
+        (self.width.round() as u32, self.height.round() as u32)
+    }
+
+    /// Sets up `canvas` to draw what the camera sees as large as fits the output
+    /// without distorting it, with bars in `bar_color` filling the space left over.
+    ///
+    /// The fit is checked every time, so it follows window resizes and fullscreen
+    /// toggles, but the canvas is only changed when the fit does, since changing it
+    /// makes some backends redo work and glitch while resizing.
+    pub fn letterbox<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        bar_color: Color,
+    ) -> Result<(), Error> {
+        let output_size = canvas.output_size().map_err(err_msg)?;
+        let (scale, viewport) = letterbox_fit(output_size, self.logical_size());
+        // The viewport is in scaled pixels, so the scale has to be set first
+        if canvas.scale() != (scale, scale) {
+            canvas.set_scale(scale, scale).map_err(err_msg)?;
+        }
+        if canvas.viewport() != viewport {
+            canvas.set_viewport(viewport);
+        }
+        // Clearing ignores the viewport, so this paints the bars
+        canvas.set_draw_color(bar_color);
+        canvas.clear();
+        Ok(())
+    }
+
+    /// Returns whether any part of `rect` is visible to the camera.
+    pub fn sees(&self, rect: &Rect) -> bool {
+        rect.right() > self.x && rect.left() < self.x + self.width && rect.bottom() > self.y
+            && rect.top() < self.y + self.height
+    }
+
+    /// Converts a rect in room coordinates to an SDL rect in screen coordinates.
+    pub fn sdl_rect(&self, rect: &Rect) -> SdlRect {
+        // Round the camera position separately so that all objects move by whole pixels together
+        let offset_x = self.x.round();
+        let offset_y = self.y.round();
+        SdlRect::new(
+            (rect.left() - offset_x).round() as i32,
+            (rect.top() - offset_y).round() as i32,
+            (rect.right() - rect.left()).round() as u32,
+            (rect.bottom() - rect.top()).round() as u32,
+        )
+    }
+
+    /// Converts a point in room coordinates to an SDL point in screen coordinates.
+    pub fn sdl_point(&self, x: f32, y: f32) -> Point {
+        Point::new(
+            (x - self.x.round()).round() as i32,
+            (y - self.y.round()).round() as i32,
+        )
+    }
+}
+
+/// How the camera frames the player inside a camera zone.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum CameraMode {
+    /// Follow the player like outside of any zone
+    Follow,
+    /// Stay horizontally centered on the zone, only scrolling vertically
+    LockX,
+    /// Stay vertically centered on the zone, only scrolling horizontally
+    LockY,
+    /// Stay centered on the zone
+    Locked,
+}
+
+/// A part of a room in which the camera frames the player differently.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CameraZone {
+    pub rect: Rect,
+    pub mode: CameraMode,
+}
+
+impl CameraZone {
+    /// Returns whether the point (`x`, `y`) lies inside the zone.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        self.rect.contains_point(x, y)
+    }
+
+    /// Returns the point the camera centers on while the player is at (`x`, `y`).
+    pub fn target(&self, x: f32, y: f32) -> (f32, f32) {
+        let center_x = (self.rect.left() + self.rect.right()) / 2.0;
+        let center_y = (self.rect.top() + self.rect.bottom()) / 2.0;
+        match self.mode {
+            CameraMode::Follow => (x, y),
+            CameraMode::LockX => (center_x, y),
+            CameraMode::LockY => (x, center_y),
+            CameraMode::Locked => (center_x, center_y),
+        }
+    }
+
+    /// Returns the color the zone is outlined with in the editor.
+    pub fn color(&self) -> Color {
+        match self.mode {
+            CameraMode::Follow => Color::RGB(0x80, 0x80, 0x80),
+            CameraMode::LockX => Color::RGB(0x40, 0xc0, 0xff),
+            CameraMode::LockY => Color::RGB(0xff, 0xc0, 0x40),
+            CameraMode::Locked => Color::RGB(0xff, 0x40, 0xff),
+        }
+    }
+}
+
+/// Returns the scale at which a view of `logical_size` pixels best fits an output of
+/// `output_size` pixels, and where to draw it, in scaled pixels, to center it.
+///
+/// The scale is a whole number so that all pixels are the same size, unless the view
+/// only fits when shrunk.
+pub fn letterbox_fit(output_size: (u32, u32), logical_size: (u32, u32)) -> (f32, SdlRect) {
+    let (output_width, output_height) = (output_size.0 as f32, output_size.1 as f32);
+    let (logical_width, logical_height) = (logical_size.0.max(1), logical_size.1.max(1));
+    let fit = (output_width / logical_width as f32).min(output_height / logical_height as f32);
+    let scale = if fit >= 1.0 { fit.floor() } else { fit.max(0.01) };
+    let left = ((output_width / scale - logical_width as f32) / 2.0).max(0.0);
+    let top = ((output_height / scale - logical_height as f32) / 2.0).max(0.0);
+    let viewport = SdlRect::new(
+        left.round() as i32,
+        top.round() as i32,
+        logical_width,
+        logical_height,
+    );
+    (scale, viewport)
+}
+
+/// Computes the start position and size of the view along one axis, centering
+/// it on `center` but keeping it within `0..room_size`.
+pub fn follow_axis(center: f32, view_size: f32, room_size: f32) -> (f32, f32) {
+    if room_size <= view_size {
+        (0.0, room_size)
+    } else {
+        let start = (center - view_size / 2.0).max(0.0).min(room_size - view_size);
+        (start, view_size)
+    }
+}
+
+/// Returns the center of a camera that was centered on `previous` after following
+/// a player for `dt` seconds.
+///
+/// The camera eases towards `target`, moved `lead` pixels ahead in `direction`
+/// (1 for right, -1 for left), covering the same fraction of the distance per
+/// second at any tick rate. Easing never overshoots, and the center is kept
+/// where a view of `view_size` stays within `room_size`.
+pub fn follow_smoothly(
+    target: (f32, f32),
+    direction: f32,
+    lead: f32,
+    previous: (f32, f32),
+    dt: f32,
+    view_size: (u32, u32),
+    room_size: (f32, f32),
+) -> (f32, f32) {
+    // Rate at which the distance to the target shrinks, per second
+    const EASE_RATE: f32 = 6.0;
+    let clamp = |center: f32, view_size: u32, room_size: f32| {
+        let (start, size) = follow_axis(center, view_size as f32, room_size);
+        start + size / 2.0
+    };
+    let target_x = clamp(target.0 + direction * lead, view_size.0, room_size.0);
+    let target_y = clamp(target.1, view_size.1, room_size.1);
+    let remaining = (-EASE_RATE * dt.max(0.0)).exp();
+    (
+        clamp(target_x + (previous.0 - target_x) * remaining, view_size.0, room_size.0),
+        clamp(target_y + (previous.1 - target_y) * remaining, view_size.1, room_size.1),
+    )
+}
+
+/// Direction in which the player looks ahead by holding a key while standing still.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Peek {
+    Up,
+    Down,
+}
+
+/// Returns how far to move the camera's target vertically while the player has
+/// been peeking in direction `peek` for `held_time` seconds.
+///
+/// Nothing happens until the key has been held for a moment, so that quick
+/// presses don't move the camera.
+pub fn peek_offset(peek: Option<Peek>, held_time: f32, distance: f32) -> f32 {
+    const PEEK_DELAY: f32 = 0.4;
+    match peek {
+        _ if held_time < PEEK_DELAY => 0.0,
+        Some(Peek::Up) => -distance,
+        Some(Peek::Down) => distance,
+        None => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEW: (u32, u32) = (320, 240);
+    const ROOM: (f32, f32) = (1000.0, 1000.0);
+
+    #[test]
+    fn camera_stays_inside_room() {
+        let center = follow_smoothly((10.0, 10.0), 1.0, 0.0, (10.0, 10.0), 0.1, VIEW, ROOM);
+        assert_eq!(center, (160.0, 120.0));
+        let center = follow_smoothly((990.0, 995.0), 1.0, 32.0, (500.0, 500.0), 10.0, VIEW, ROOM);
+        assert_eq!(center, (840.0, 880.0));
+        // A room smaller than the view is centered
+        let small_room = (200.0, 100.0);
+        let center = follow_smoothly((10.0, 10.0), 1.0, 32.0, (0.0, 0.0), 0.1, VIEW, small_room);
+        assert_eq!(center, (100.0, 50.0));
+    }
+
+    #[test]
+    fn camera_leads_in_the_direction_faced() {
+        let right = follow_smoothly((500.0, 500.0), 1.0, 32.0, (500.0, 500.0), 10.0, VIEW, ROOM);
+        assert_eq!(right, (532.0, 500.0));
+        let left = follow_smoothly((500.0, 500.0), -1.0, 32.0, right, 10.0, VIEW, ROOM);
+        assert_eq!(left, (468.0, 500.0));
+    }
+
+    #[test]
+    fn camera_converges_without_overshooting() {
+        let mut center = (200.0, 700.0);
+        for _ in 0..600 {
+            let next = follow_smoothly((500.0, 500.0), 1.0, 0.0, center, 1.0 / 150.0, VIEW, ROOM);
+            assert!(next.0 >= center.0 && next.0 <= 500.0, "{:?} after {:?}", next, center);
+            assert!(next.1 <= center.1 && next.1 >= 500.0, "{:?} after {:?}", next, center);
+            center = next;
+        }
+        assert!((center.0 - 500.0).abs() < 0.01 && (center.1 - 500.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn camera_eases_alike_at_any_tick_rate() {
+        let follow = |ticks_per_second: u32| {
+            let dt = 1.0 / ticks_per_second as f32;
+            (0..ticks_per_second / 4).fold((200.0, 500.0), |center, _| {
+                follow_smoothly((500.0, 500.0), 1.0, 0.0, center, dt, VIEW, ROOM)
+            })
+        };
+        let (fast, slow) = (follow(240), follow(60));
+        assert!((fast.0 - slow.0).abs() < 0.01, "{:?} vs {:?}", fast, slow);
+        assert!(fast.0 < 500.0);
+    }
+
+    #[test]
+    fn reversing_at_room_edge_stays_inside_room() {
+        let mut center = (840.0, 500.0);
+        for tick in 0..100 {
+            let direction = if tick % 2 == 0 { -1.0 } else { 1.0 };
+            center = follow_smoothly((990.0, 500.0), direction, 64.0, center, 0.05, VIEW, ROOM);
+            assert!(center.0 >= 160.0 && center.0 <= 840.0, "left the room at {:?}", center);
+        }
+    }
+
+    #[test]
+    fn view_stays_within_larger_room() {
+        assert_eq!(follow_axis(500.0, 320.0, 1000.0), (340.0, 320.0));
+        assert_eq!(follow_axis(10.0, 320.0, 1000.0), (0.0, 320.0));
+        assert_eq!(follow_axis(990.0, 320.0, 1000.0), (680.0, 320.0));
+        // Exactly as large as the view
+        assert_eq!(follow_axis(100.0, 320.0, 320.0), (0.0, 320.0));
+    }
+
+    #[test]
+    fn view_shows_whole_smaller_room() {
+        assert_eq!(follow_axis(10.0, 320.0, 200.0), (0.0, 200.0));
+        assert_eq!(follow_axis(190.0, 320.0, 200.0), (0.0, 200.0));
+    }
+
+    #[test]
+    fn camera_shrinks_to_room_along_smaller_axis() {
+        let room = Room::new(100, 10, 16);
+        let camera = Camera::following(800.0, 80.0, VIEW.0, VIEW.1, &room);
+        assert_eq!((camera.left(), camera.top()), (640.0, 0.0));
+        assert_eq!(camera.logical_size(), (320, 160));
+        let camera = Camera::following(1590.0, 80.0, VIEW.0, VIEW.1, &room);
+        assert_eq!(camera.left(), 1280.0);
+        assert!(camera.sees(&Rect::new(1590.0, 150.0, 8.0, 8.0)));
+        assert!(!camera.sees(&Rect::new(1270.0, 150.0, 8.0, 8.0)));
+    }
+
+    #[test]
+    fn peeking_waits_before_moving_camera() {
+        for &peek in &[None, Some(Peek::Up), Some(Peek::Down)] {
+            assert_eq!(peek_offset(peek, 0.0, 64.0), 0.0);
+            assert_eq!(peek_offset(peek, 0.39, 64.0), 0.0);
+        }
+        assert_eq!(peek_offset(Some(Peek::Up), 0.4, 64.0), -64.0);
+        assert_eq!(peek_offset(Some(Peek::Down), 0.4, 64.0), 64.0);
+        assert_eq!(peek_offset(Some(Peek::Down), 10.0, 64.0), 64.0);
+        assert_eq!(peek_offset(None, 10.0, 64.0), 0.0);
+    }
+
+    #[test]
+    fn peeking_camera_stays_inside_room() {
+        let peeked = |y: f32, peek: Peek| {
+            let target = (500.0, y + peek_offset(Some(peek), 1.0, 64.0));
+            follow_smoothly(target, 1.0, 0.0, target, 1.0, VIEW, ROOM).1
+        };
+        // Away from the edges the view moves by the whole distance
+        assert_eq!(peeked(500.0, Peek::Down), 564.0);
+        assert_eq!(peeked(500.0, Peek::Up), 436.0);
+        // But never past them
+        assert_eq!(peeked(850.0, Peek::Down), 880.0);
+        assert_eq!(peeked(150.0, Peek::Up), 120.0);
+    }
+}
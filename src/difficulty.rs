@@ -0,0 +1,143 @@
+//! Presets making the game easier or harder
+
+use std::str::FromStr;
+
+use failure::Error;
+
+use physics::PhysicsConfig;
+
+/// How hard the game is, adjusting the physics and how forgiving deaths are.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl FromStr for Difficulty {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Difficulty, Error> {
+        match name.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => bail!("Unknown difficulty {:?}; use easy, normal or hard", name),
+        }
+    }
+}
+
+impl Difficulty {
+    /// Returns the factor applied to gravity and fall speed.
+    pub fn gravity_multiplier(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.75,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.2,
+        }
+    }
+
+    /// Returns the factor applied to the time after walking off a ledge in which
+    /// a jump is still allowed.
+    pub fn coyote_time_multiplier(self) -> f32 {
+        match self {
+            Difficulty::Easy => 2.0,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 0.5,
+        }
+    }
+
+    /// Returns the number of lives to start with, or `None` for infinitely many.
+    pub fn starting_lives(self) -> Option<u32> {
+        match self {
+            Difficulty::Easy => None,
+            Difficulty::Normal => Some(3),
+            Difficulty::Hard => Some(1),
+        }
+    }
+
+    /// Returns whether defeated enemies come back when the player dies.
+    pub fn respawns_hazards(self) -> bool {
+        match self {
+            Difficulty::Easy | Difficulty::Normal => false,
+            Difficulty::Hard => true,
+        }
+    }
+
+    /// Returns a copy of `physics` adjusted for this difficulty.
+    pub fn apply(self, physics: &PhysicsConfig) -> PhysicsConfig {
+        PhysicsConfig {
+            fall_speed: physics.fall_speed * self.gravity_multiplier(),
+            coyote_time: physics.coyote_time * self.coyote_time_multiplier(),
+            ..physics.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use input::{Action, InputEvent};
+    use level::Level;
+    use model::{Model, PlayerVertState};
+    use room::Room;
+
+    /// Returns a model of a tall, empty room at `difficulty`.
+    fn model_at(difficulty: Difficulty) -> Model {
+        let path = "test.json";
+        let mut model = Model::new(Level::with_room(path, Room::new(10, 30, 16)), path, 150);
+        model.set_difficulty(difficulty);
+        model
+    }
+
+    /// Returns how many ticks a full jump keeps the player in the air.
+    fn hang_ticks(model: &mut Model) -> u32 {
+        let tick = Duration::new(0, 1_000_000_000 / 150);
+        model.update(tick);
+        model.handle_input(InputEvent::Pressed(Action::Jump));
+        model.update(tick);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Jumping);
+        let mut ticks = 1;
+        while model.player().vert_state() != PlayerVertState::Standing {
+            model.update(tick);
+            ticks += 1;
+            assert!(ticks < 1000, "Player never landed");
+        }
+        model.handle_input(InputEvent::Released(Action::Jump));
+        model.update(tick);
+        ticks
+    }
+
+    #[test]
+    fn easy_mode_keeps_jumps_in_the_air_longer() {
+        let normal = hang_ticks(&mut model_at(Difficulty::Normal));
+        let easy = hang_ticks(&mut model_at(Difficulty::Easy));
+        let hard = hang_ticks(&mut model_at(Difficulty::Hard));
+        assert!(easy > normal + 10, "{} vs {} ticks", easy, normal);
+        assert!(hard < normal, "{} vs {} ticks", hard, normal);
+    }
+
+    #[test]
+    fn difficulty_is_applied_once_however_often_the_room_restarts() {
+        let mut model = model_at(Difficulty::Easy);
+        let first = hang_ticks(&mut model);
+        for _ in 0..3 {
+            model.reset();
+            assert_eq!(hang_ticks(&mut model), first);
+        }
+        model.set_difficulty(Difficulty::Easy);
+        assert_eq!(hang_ticks(&mut model), first);
+    }
+
+    #[test]
+    fn applying_to_default_physics_scales_only_its_knobs() {
+        let physics = PhysicsConfig::default();
+        let easy = Difficulty::Easy.apply(&physics);
+        assert_eq!(easy.fall_speed, physics.fall_speed * 0.75);
+        assert_eq!(easy.coyote_time, physics.coyote_time * 2.0);
+        assert_eq!(easy.jump_speed, physics.jump_speed);
+        assert_eq!(Difficulty::Normal.apply(&physics).fall_speed, physics.fall_speed);
+    }
+}
@@ -1,22 +1,58 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
 use failure::{err_msg, Error};
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
 use sdl2::render::{Canvas, RenderTarget};
 
-use room::Room;
+use camera::Camera;
+use geom::{Direction, TileCoord};
+use graphics::RenderContext;
+use room::{Room, TileKind};
+
+/// Messages that signs placed in the editor cycle through
+const SIGN_PRESETS: [&str; 4] = [
+    "Use the arrow keys to move and Up to jump",
+    "Press L at a sign to read it",
+    "Collect every coin on your way",
+    "Watch out!",
+];
 
 pub struct Editor {
     room: Room,
-    cursor_x: u32,
-    cursor_y: u32,
+    /// File path the room is saved to
+    path: String,
+    cursor: TileCoord,
+    undo_stack: Vec<Room>,
+    /// Whether the room was edited since it was last saved or switched to
+    unsaved: bool,
+    recording: Option<Macro>,
+    current_macro: Macro,
+    /// Where the cursor was when the current macro's recording stopped
+    macro_end: Option<TileCoord>,
+    repeat_count: u32,
+    /// Channel of the switches and toggle blocks placed next
+    channel: u32,
+    view_width: u32,
+    view_height: u32,
 }
 
 impl Editor {
-    pub fn new(room: Room) -> Editor {
+    pub fn new(path: &str, room: Room) -> Editor {
         Editor {
             room,
-            cursor_x: 0,
-            cursor_y: 0,
+            path: path.to_string(),
+            cursor: TileCoord::default(),
+            undo_stack: Vec::new(),
+            unsaved: false,
+            recording: None,
+            current_macro: Macro::default(),
+            macro_end: None,
+            repeat_count: 1,
+            channel: 0,
+            view_width: 640,
+            view_height: 480,
         }
     }
 
@@ -24,32 +60,544 @@ impl Editor {
         &self.room
     }
 
+    /// Sets the maximum size in pixels of the part of the room that is shown at once.
+    pub fn set_view_size(&mut self, width: u32, height: u32) {
+        self.view_width = width;
+        self.view_height = height;
+    }
+
+    /// Returns the index of the tile the cursor is on.
+    pub fn cursor(&self) -> TileCoord {
+        self.cursor
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Switches to editing another room, discarding the undo history.
+    pub fn set_room(&mut self, path: &str, room: Room) {
+        self.room = room;
+        self.path = path.to_string();
+        self.cursor = TileCoord::default();
+        self.undo_stack.clear();
+        self.unsaved = false;
+    }
+
+    pub fn save(&mut self) -> Result<(), Error> {
+        self.room.save(&self.path)?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Returns whether the room has edits that haven't been saved yet.
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.unsaved
+    }
+
+    pub fn current_macro(&self) -> &Macro {
+        &self.current_macro
+    }
+
+    pub fn set_macro(&mut self, editor_macro: Macro) {
+        self.current_macro = editor_macro;
+    }
+
     pub fn key_pressed(&mut self, key: Keycode) {
         match key {
-            Keycode::Left => self.cursor_x = self.cursor_x.saturating_sub(1),
-            Keycode::Right => self.cursor_x = (self.cursor_x + 1).min(self.room.width() - 1),
-            Keycode::Up => self.cursor_y = self.cursor_y.saturating_sub(1),
-            Keycode::Down => self.cursor_y = (self.cursor_y + 1).min(self.room.height() - 1),
-            Keycode::Space => self.room
-                .toggle_tile_at_index(self.cursor_x, self.cursor_y)
+            // Q starts recording a macro, or stops recording and keeps the result
+            Keycode::Q => match self.recording.take() {
+                Some(recorded) => {
+                    debug!("Recorded macro of {} commands", recorded.commands.len());
+                    self.current_macro = recorded;
+                    self.macro_end = Some(self.cursor);
+                }
+                None => {
+                    debug!("Recording macro");
+                    self.recording = Some(Macro::default());
+                }
+            },
+            // P makes each repetition start where the cursor is now, relative to where
+            // the recording ended
+            Keycode::P => match self.macro_end {
+                Some(end) => {
+                    let offset = (self.cursor.x - end.x, self.cursor.y - end.y);
+                    debug!("Macro repeat offset is now {:?}", offset);
+                    self.current_macro.repeat_offset = offset;
+                }
+                None => debug!("No macro recorded to set the repeat offset of"),
+            },
+            Keycode::Return => {
+                let editor_macro = self.current_macro.clone();
+                let repeat_count = self.repeat_count;
+                self.replay(&editor_macro, repeat_count);
+            }
+            Keycode::Z => self.undo(),
+            _ => {
+                if let Some(count) = repeat_count_for_key(key) {
+                    debug!("Macro repeat count is now {}", count);
+                    self.repeat_count = count;
+                } else if let Some(command) = Command::from_keycode(key) {
+                    if let Some(ref mut recording) = self.recording {
+                        recording.commands.push(command);
+                    }
+                    if command.edits_room() {
+                        self.push_undo();
+                    }
+                    self.execute(command);
+                }
+            }
+        }
+    }
+
+    /// Replays `editor_macro` `repeat_count` times as a single undoable step.
+    ///
+    /// After each repetition the cursor is moved by the macro's repeat offset.
+    /// Steps that would take the cursor out of the room are clamped to its
+    /// edges with a warning.
+    pub fn replay(&mut self, editor_macro: &Macro, repeat_count: u32) {
+        if editor_macro.commands.is_empty() {
+            debug!("No macro to replay");
+            return;
+        }
+        debug!(
+            "Replaying macro of {} commands {} times",
+            editor_macro.commands.len(),
+            repeat_count
+        );
+        self.push_undo();
+        for _ in 0..repeat_count {
+            for &command in &editor_macro.commands {
+                let cursor = self.cursor;
+                self.execute(command);
+                if command.direction().is_some() && self.cursor == cursor {
+                    warn!(
+                        "Macro step {:?} leaves the room at ({}, {}); skipping",
+                        command, cursor.x, cursor.y
+                    );
+                }
+            }
+            let (offset_x, offset_y) = editor_macro.repeat_offset;
+            let new_x = self.cursor.x as i64 + offset_x as i64;
+            let new_y = self.cursor.y as i64 + offset_y as i64;
+            let max_x = self.room.width() as i64 - 1;
+            let max_y = self.room.height() as i64 - 1;
+            if new_x < 0 || new_x > max_x || new_y < 0 || new_y > max_y {
+                warn!(
+                    "Macro repeat offset moves cursor out of bounds to ({}, {}); clamping",
+                    new_x, new_y
+                );
+            }
+            self.cursor = TileCoord::new(
+                new_x.min(max_x).max(0) as i32,
+                new_y.min(max_y).max(0) as i32,
+            );
+        }
+    }
+
+    fn execute(&mut self, command: Command) {
+        // The cursor never leaves the room, so its index is never negative
+        let (x, y) = (self.cursor.x as u32, self.cursor.y as u32);
+        match command {
+            Command::MoveLeft => self.move_cursor(Direction::Left),
+            Command::MoveRight => self.move_cursor(Direction::Right),
+            Command::MoveUp => self.move_cursor(Direction::Up),
+            Command::MoveDown => self.move_cursor(Direction::Down),
+            Command::ToggleTile => self.room
+                .toggle_tile_at_index(x, y, TileKind::Filled)
                 .unwrap_or_else(|error| {
                     // Cursor got out of bounds somehow, so reset it
                     error!("{}; resetting cursor", error);
-                    self.cursor_x = 0;
-                    self.cursor_y = 0;
+                    self.cursor = TileCoord::default();
                 }),
-            _ => (),
+            Command::SetSpawn => self.room
+                .set_spawn(x, y)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleCoin => self.room
+                .toggle_coin_at_index(x, y)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleGoal => self.room
+                .toggle_tile_at_index(x, y, TileKind::Goal)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleSlopeUp => self.room
+                .toggle_tile_at_index(x, y, TileKind::SlopeUp)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleSlopeDown => self.room
+                .toggle_tile_at_index(x, y, TileKind::SlopeDown)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleGravityFlip => self.room
+                .toggle_tile_at_index(x, y, TileKind::GravityFlip)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleBounce => self.room
+                .toggle_tile_at_index(x, y, TileKind::Bounce)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleCrusher => self.room
+                .toggle_crusher_at_index(x, y)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleDoor => self.room
+                .toggle_tile_at_index(x, y, TileKind::Door)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleBlink => self.room
+                .toggle_tile_at_index(x, y, TileKind::Blink)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleTeleporter => self.room
+                .toggle_teleporter_at_index(x, y)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleCrumble => self.room
+                .toggle_tile_at_index(x, y, TileKind::Crumble)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleCrate => self.room
+                .toggle_crate_at_index(x, y)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleTurret => self.room
+                .toggle_turret_at_index(x, y)
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::CycleSign => {
+                // Place the first preset, then cycle through the rest before removing the sign
+                let next = match self.room.sign_text_at_index(x, y) {
+                    Some(text) => SIGN_PRESETS
+                        .iter()
+                        .position(|&preset| preset == text)
+                        .and_then(|index| SIGN_PRESETS.get(index + 1)),
+                    None => SIGN_PRESETS.first(),
+                };
+                let text = next.map(|text| text.to_string());
+                self.room
+                    .set_sign_at_index(x, y, text)
+                    .unwrap_or_else(|error| error!("{}", error))
+            }
+            Command::NextChannel => {
+                // Cycle through as many channels as there are distinct colors
+                const CHANNELS: u32 = 6;
+                self.channel = (self.channel + 1) % CHANNELS;
+                debug!("Placing switches and toggle blocks on channel {}", self.channel);
+            }
+            Command::ToggleSwitch => self.room
+                .toggle_tile_at_index(x, y, TileKind::Switch {
+                    channel: self.channel,
+                    one_shot: false,
+                })
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleOneShotSwitch => self.room
+                .toggle_tile_at_index(x, y, TileKind::Switch {
+                    channel: self.channel,
+                    one_shot: true,
+                })
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleToggleBlock => self.room
+                .toggle_tile_at_index(x, y, TileKind::ToggleBlock(self.channel))
+                .unwrap_or_else(|error| error!("{}", error)),
+            Command::ToggleKey => self.room
+                .toggle_key_at_index(x, y)
+                .unwrap_or_else(|error| error!("{}", error)),
+        }
+    }
+
+    /// Moves the cursor a tile in `direction`, unless that would take it out of the room.
+    fn move_cursor(&mut self, direction: Direction) {
+        let (dx, dy) = direction.delta();
+        let max_x = self.room.width() as i32 - 1;
+        let max_y = self.room.height() as i32 - 1;
+        self.cursor.x = (self.cursor.x + dx).min(max_x).max(0);
+        self.cursor.y = (self.cursor.y + dy).min(max_y).max(0);
+    }
+
+    fn push_undo(&mut self) {
+        const MAX_UNDO_STEPS: usize = 100;
+        if self.undo_stack.len() == MAX_UNDO_STEPS {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(self.room.clone());
+        self.unsaved = true;
+    }
+
+    fn undo(&mut self) {
+        match self.undo_stack.pop() {
+            Some(room) => {
+                debug!("Undoing last edit");
+                self.room = room;
+                self.unsaved = true;
+            }
+            None => debug!("Nothing to undo"),
+        }
+    }
+
+    /// Draws the room around the cursor, with its tiles animated as they are
+    /// `clock` seconds into their animations.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        context: &mut RenderContext<T::Context>,
+        clock: f32,
+    ) -> Result<(), Error> {
+        let cursor_center = self.cursor.center(self.room.tile_size());
+        let camera = Camera::following(
+            cursor_center.x,
+            cursor_center.y,
+            self.view_width,
+            self.view_height,
+            &self.room,
+        );
+        self.room.render(canvas, &camera, context, clock)?;
+        for entity in self.room.entities() {
+            entity.render(canvas, &camera, &context.theme)?;
+        }
+        for zone in self.room.camera_zones() {
+            canvas.set_draw_color(zone.color());
+            canvas
+                .draw_rect(camera.sdl_rect(&zone.rect))
+                .map_err(err_msg)?;
+        }
+        canvas.set_draw_color(context.theme.editor_spawn());
+        canvas
+            .draw_rect(camera.sdl_rect(&self.room.spawn_tile().rect))
+            .map_err(err_msg)?;
+        canvas.set_draw_color(context.theme.editor_cursor());
+        canvas
+            .draw_rect(camera.sdl_rect(&self.cursor.rect(self.room.tile_size())))
+            .map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// A single editing step that can be recorded into a `Macro`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Command {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ToggleTile,
+    SetSpawn,
+    ToggleCoin,
+    ToggleGoal,
+    ToggleSlopeUp,
+    ToggleSlopeDown,
+    ToggleGravityFlip,
+    ToggleBounce,
+    ToggleCrusher,
+    ToggleDoor,
+    ToggleKey,
+    ToggleBlink,
+    ToggleTeleporter,
+    ToggleCrumble,
+    ToggleCrate,
+    NextChannel,
+    ToggleSwitch,
+    ToggleOneShotSwitch,
+    ToggleToggleBlock,
+    ToggleTurret,
+    CycleSign,
+}
+
+impl Command {
+    pub fn from_keycode(key: Keycode) -> Option<Command> {
+        match key {
+            Keycode::Left => Some(Command::MoveLeft),
+            Keycode::Right => Some(Command::MoveRight),
+            Keycode::Up => Some(Command::MoveUp),
+            Keycode::Down => Some(Command::MoveDown),
+            Keycode::Space => Some(Command::ToggleTile),
+            Keycode::S => Some(Command::SetSpawn),
+            Keycode::C => Some(Command::ToggleCoin),
+            Keycode::G => Some(Command::ToggleGoal),
+            Keycode::Slash => Some(Command::ToggleSlopeUp),
+            Keycode::Backslash => Some(Command::ToggleSlopeDown),
+            Keycode::V => Some(Command::ToggleGravityFlip),
+            Keycode::B => Some(Command::ToggleBounce),
+            Keycode::X => Some(Command::ToggleCrusher),
+            Keycode::D => Some(Command::ToggleDoor),
+            Keycode::K => Some(Command::ToggleKey),
+            Keycode::N => Some(Command::ToggleBlink),
+            Keycode::T => Some(Command::ToggleTeleporter),
+            Keycode::U => Some(Command::ToggleCrumble),
+            Keycode::O => Some(Command::ToggleCrate),
+            Keycode::M => Some(Command::NextChannel),
+            Keycode::H => Some(Command::ToggleSwitch),
+            Keycode::I => Some(Command::ToggleOneShotSwitch),
+            Keycode::J => Some(Command::ToggleToggleBlock),
+            Keycode::Y => Some(Command::ToggleTurret),
+            Keycode::L => Some(Command::CycleSign),
+            _ => None,
+        }
+    }
+
+    /// Returns the direction the command moves the cursor in, if it's a movement.
+    pub fn direction(&self) -> Option<Direction> {
+        match *self {
+            Command::MoveLeft => Some(Direction::Left),
+            Command::MoveRight => Some(Direction::Right),
+            Command::MoveUp => Some(Direction::Up),
+            Command::MoveDown => Some(Direction::Down),
+            _ => None,
         }
     }
 
-    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<(), Error> {
-        self.room.render(canvas)?;
-        canvas.set_draw_color(Color::RGB(0xFF, 0x00, 0x00));
-        let cursor_rect = self.room
-            .tile_at_index(self.cursor_x, self.cursor_y)
-            .rect
-            .sdl_rect();
-        canvas.draw_rect(cursor_rect).map_err(err_msg)?;
+    /// Returns whether the command changes the room, rather than only moving the cursor
+    /// or changing what's placed next.
+    pub fn edits_room(&self) -> bool {
+        match *self {
+            Command::MoveLeft | Command::MoveRight | Command::MoveUp | Command::MoveDown => false,
+            Command::NextChannel => false,
+            Command::ToggleTile => true,
+            Command::SetSpawn | Command::ToggleCoin | Command::ToggleGoal => true,
+            Command::ToggleSlopeUp | Command::ToggleSlopeDown | Command::ToggleGravityFlip => true,
+            Command::ToggleBounce | Command::ToggleCrusher => true,
+            Command::ToggleDoor | Command::ToggleKey | Command::ToggleBlink => true,
+            Command::ToggleTeleporter | Command::ToggleCrumble | Command::ToggleCrate => true,
+            Command::ToggleSwitch | Command::ToggleOneShotSwitch => true,
+            Command::ToggleToggleBlock | Command::ToggleTurret | Command::CycleSign => true,
+        }
+    }
+}
+
+/// A recorded list of editor commands.
+///
+/// When replayed several times in a row, the cursor is additionally moved by
+/// `repeat_offset` tiles after each repetition.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Macro {
+    pub commands: Vec<Command>,
+    #[serde(default)]
+    pub repeat_offset: (i32, i32),
+}
+
+impl Macro {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        let writer = BufWriter::new(file);
+        ::serde_json::to_writer(writer, self)?;
         Ok(())
     }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Macro, Error> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let editor_macro = ::serde_json::from_reader(reader)?;
+        Ok(editor_macro)
+    }
+}
+
+fn repeat_count_for_key(key: Keycode) -> Option<u32> {
+    match key {
+        Keycode::Num1 => Some(1),
+        Keycode::Num2 => Some(2),
+        Keycode::Num3 => Some(3),
+        Keycode::Num4 => Some(4),
+        Keycode::Num5 => Some(5),
+        Keycode::Num6 => Some(6),
+        Keycode::Num7 => Some(7),
+        Keycode::Num8 => Some(8),
+        Keycode::Num9 => Some(9),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind_at(editor: &Editor, x: i32, y: i32) -> TileKind {
+        editor.room().tile_at_index(x, y).kind
+    }
+
+    fn press(editor: &mut Editor, keys: &[Keycode]) {
+        for &key in keys {
+            editor.key_pressed(key);
+        }
+    }
+
+    #[test]
+    fn recorded_macro_replays_on_another_room() {
+        let mut recorder = Editor::new("a.json", Room::new(8, 4, 16));
+        press(&mut recorder, &[Keycode::Q, Keycode::Space, Keycode::Right, Keycode::Q]);
+        assert_eq!(recorder.current_macro().commands.len(), 2);
+        assert_eq!(kind_at(&recorder, 0, 0), TileKind::Filled);
+
+        let mut editor = Editor::new("b.json", Room::new(8, 4, 16));
+        editor.set_macro(recorder.current_macro().clone());
+        press(&mut editor, &[Keycode::Num3, Keycode::Return]);
+        for x in 0..3 {
+            assert_eq!(kind_at(&editor, x, 0), TileKind::Filled);
+        }
+        assert_eq!(kind_at(&editor, 3, 0), TileKind::Empty);
+        assert_eq!(editor.cursor(), TileCoord::new(3, 0));
+    }
+
+    #[test]
+    fn replay_moves_by_repeat_offset() {
+        let mut editor = Editor::new("a.json", Room::new(8, 4, 16));
+        editor.set_macro(Macro {
+            commands: vec![Command::ToggleTile, Command::MoveRight],
+            repeat_offset: (0, 1),
+        });
+        press(&mut editor, &[Keycode::Num3, Keycode::Return]);
+        for i in 0..3 {
+            assert_eq!(kind_at(&editor, i, i), TileKind::Filled);
+        }
+        assert_eq!(kind_at(&editor, 1, 0), TileKind::Empty);
+        assert_eq!(editor.cursor(), TileCoord::new(3, 3));
+    }
+
+    #[test]
+    fn repeat_offset_is_set_from_recording_end() {
+        let mut editor = Editor::new("a.json", Room::new(8, 4, 16));
+        press(&mut editor, &[Keycode::Q, Keycode::Space, Keycode::Right, Keycode::Q]);
+        press(&mut editor, &[Keycode::Down, Keycode::Left, Keycode::P]);
+        assert_eq!(editor.current_macro().repeat_offset, (-1, 1));
+    }
+
+    #[test]
+    fn replay_stays_in_room() {
+        let mut editor = Editor::new("a.json", Room::new(3, 4, 16));
+        editor.set_macro(Macro {
+            commands: vec![Command::ToggleTile, Command::MoveRight],
+            repeat_offset: (0, -1),
+        });
+        press(&mut editor, &[Keycode::Num3, Keycode::Return]);
+        for x in 0..3 {
+            assert_eq!(kind_at(&editor, x, 0), TileKind::Filled);
+        }
+        assert_eq!(editor.cursor(), TileCoord::new(2, 0));
+    }
+
+    #[test]
+    fn replay_is_undone_in_one_step() {
+        let mut editor = Editor::new("a.json", Room::new(8, 4, 16));
+        editor.set_macro(Macro {
+            commands: vec![Command::ToggleTile, Command::MoveRight],
+            repeat_offset: (0, 0),
+        });
+        press(&mut editor, &[Keycode::Num4, Keycode::Return, Keycode::Z]);
+        for x in 0..4 {
+            assert_eq!(kind_at(&editor, x, 0), TileKind::Empty);
+        }
+    }
+
+    #[test]
+    fn macro_survives_json() {
+        let editor_macro = Macro {
+            commands: vec![Command::ToggleCoin, Command::MoveDown],
+            repeat_offset: (2, -1),
+        };
+        let json = ::serde_json::to_string(&editor_macro).unwrap();
+        let loaded: Macro = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.commands, editor_macro.commands);
+        assert_eq!(loaded.repeat_offset, editor_macro.repeat_offset);
+    }
+
+    #[test]
+    fn edits_are_unsaved_until_switching_rooms() {
+        let mut editor = Editor::new("a.json", Room::new(8, 4, 16));
+        assert!(!editor.has_unsaved_changes());
+        press(&mut editor, &[Keycode::Right]);
+        assert!(!editor.has_unsaved_changes());
+        press(&mut editor, &[Keycode::Space]);
+        assert!(editor.has_unsaved_changes());
+        // Undoing changes the room again, so it still differs from the saved one
+        press(&mut editor, &[Keycode::Z]);
+        assert!(editor.has_unsaved_changes());
+        editor.set_room("b.json", Room::new(8, 4, 16));
+        assert!(!editor.has_unsaved_changes());
+    }
 }
@@ -0,0 +1,524 @@
+//! Short-lived effects that make things like jumps and deaths look livelier
+
+use std::collections::VecDeque;
+
+use failure::{err_msg, Error};
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, RenderTarget};
+
+use camera::Camera;
+use geom::Rect;
+use theme::Theme;
+
+/// A small square that flies off and falls down until its time is up.
+///
+/// Particles never collide with anything, so they can't affect the game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Particle {
+    xpos: f32,
+    ypos: f32,
+    xspeed: f32,
+    yspeed: f32,
+    /// Ticks until the particle disappears
+    ticks_left: u32,
+    kind: ParticleKind,
+}
+
+impl Particle {
+    /// Size of a particle in pixels
+    pub const SIZE: f32 = 2.0;
+
+    /// Returns the rect the particle covers, `time_delta` seconds after its last update.
+    pub fn rect(&self, time_delta: f32) -> Rect {
+        Rect::new(
+            self.xpos + self.xspeed * time_delta - Particle::SIZE / 2.0,
+            self.ypos + self.yspeed * time_delta - Particle::SIZE / 2.0,
+            Particle::SIZE,
+            Particle::SIZE,
+        )
+    }
+}
+
+/// What a particle was spawned by, which decides the theme color it's drawn in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParticleKind {
+    /// Kicked up by jumping or landing hard
+    Dust,
+    /// Scattered by a player dying
+    Death,
+}
+
+/// How many particles to spawn at once and how they fly off.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Burst {
+    pub count: u32,
+    /// Directions the particles fan out over, from the first to the second angle
+    /// in radians, with zero pointing right and a quarter turn pointing down
+    pub angles: (f32, f32),
+    /// Speed of the fastest particles in pixels per second
+    pub speed: f32,
+    /// Ticks until the particles disappear
+    pub lifetime: u32,
+    pub kind: ParticleKind,
+}
+
+/// The particles alive in a room, at most `Particles::MAX_COUNT` at a time.
+///
+/// Bursts spread their particles in a fixed pattern rather than randomly, so the
+/// particles are as deterministic as the rest of the model.
+#[derive(Clone, Debug, Default)]
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    /// Most particles alive at once; further bursts replace the oldest particles
+    pub const MAX_COUNT: usize = 256;
+    /// Downward acceleration of particles in pixels per second squared
+    const GRAVITY: f32 = 400.0;
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Returns the number of particles alive.
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Spawns the particles of `burst` at (`x`, `y`).
+    pub fn burst(&mut self, x: f32, y: f32, burst: &Burst) {
+        let (min_angle, max_angle) = burst.angles;
+        for index in 0..burst.count {
+            let fraction = (index as f32 + 0.5) / burst.count as f32;
+            let angle = min_angle + (max_angle - min_angle) * fraction;
+            // Alternate between full and reduced speed so the burst isn't a perfect arc
+            let speed = if index % 2 == 0 { burst.speed } else { burst.speed * 0.6 };
+            self.particles.push(Particle {
+                xpos: x,
+                ypos: y,
+                xspeed: angle.cos() * speed,
+                yspeed: angle.sin() * speed,
+                ticks_left: burst.lifetime.max(1),
+                kind: burst.kind,
+            });
+        }
+        if self.particles.len() > Particles::MAX_COUNT {
+            let excess = self.particles.len() - Particles::MAX_COUNT;
+            self.particles.drain(..excess);
+        }
+    }
+
+    /// Moves the particles by `dt` seconds and removes the ones whose time is up.
+    pub fn update(&mut self, dt: f32) {
+        for particle in &mut self.particles {
+            particle.yspeed += Particles::GRAVITY * dt;
+            particle.xpos += particle.xspeed * dt;
+            particle.ypos += particle.yspeed * dt;
+            particle.ticks_left -= 1;
+        }
+        self.particles.retain(|particle| particle.ticks_left > 0);
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        time_delta: f32,
+    ) -> Result<(), Error> {
+        for particle in &self.particles {
+            let rect = particle.rect(time_delta);
+            if !camera.sees(&rect) {
+                continue;
+            }
+            canvas.set_draw_color(match particle.kind {
+                ParticleKind::Dust => theme.dust(),
+                ParticleKind::Death => theme.death_particles(),
+            });
+            canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fading copies of where the player was over the last few ticks, drawn behind
+/// them while they sprint.
+///
+/// Like particles, the copies are only drawn and never collide with anything.
+#[derive(Clone, Debug, Default)]
+pub struct Trail {
+    /// Rects the player covered and how many ticks ago, newest first
+    images: VecDeque<(Rect, u32)>,
+}
+
+impl Trail {
+    /// Number of ticks a copy lasts, which is also the most copies there can be
+    pub const MAX_AGE: u32 = 8;
+
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+
+    /// Ages the copies by a tick and drops the ones that are too old, then adds a
+    /// copy at `rect` if there is one. Once no more copies are added, the trail
+    /// dies out on its own.
+    pub fn update(&mut self, rect: Option<Rect>) {
+        for image in &mut self.images {
+            image.1 += 1;
+        }
+        while let Some(&(_, age)) = self.images.back() {
+            if age < Trail::MAX_AGE {
+                break;
+            }
+            self.images.pop_back();
+        }
+        if let Some(rect) = rect {
+            self.images.push_front((rect, 0));
+        }
+    }
+
+    /// Draws the copies in `color`, the oldest ones the faintest.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        color: Color,
+    ) -> Result<(), Error> {
+        // Opacity of the newest copy
+        const ALPHA: f32 = 128.0;
+        // Draw the oldest first, so the newer copies go on top
+        for &(rect, age) in self.images.iter().rev() {
+            if !camera.sees(&rect) {
+                continue;
+            }
+            let fraction_left = 1.0 - age as f32 / Trail::MAX_AGE as f32;
+            let alpha = (ALPHA * fraction_left).round() as u8;
+            canvas.set_draw_color(Color::RGBA(color.r, color.g, color.b, alpha));
+            canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fading the view to black while the player dies, and back in once they respawn.
+///
+/// The fade advances with the model's ticks rather than with frames, so that it's
+/// always black exactly when the player respawns, and only uses the time since
+/// the last tick to move smoothly between ticks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fade {
+    Clear,
+    /// Going black over `total` ticks, with `ticks_left` ticks to go, which may be
+    /// more than `total` if the fade hasn't started yet
+    Out { ticks_left: u32, total: u32 },
+    /// Becoming clear again over `total` ticks, with `ticks_left` ticks to go
+    In { ticks_left: u32, total: u32 },
+}
+
+impl Fade {
+    /// Returns a fade taking `ticks` ticks that's fully black `ticks_until_black`
+    /// ticks from now.
+    pub fn fading_out(ticks: u32, ticks_until_black: u32) -> Fade {
+        Fade::Out {
+            ticks_left: ticks_until_black,
+            total: ticks.max(1),
+        }
+    }
+
+    /// Returns a fade from black taking `ticks` ticks.
+    pub fn fading_in(ticks: u32) -> Fade {
+        let total = ticks.max(1);
+        Fade::In {
+            ticks_left: total,
+            total,
+        }
+    }
+
+    /// Advances the fade by a tick. A fade out stays black once it's done, until
+    /// it's replaced by a fade in.
+    pub fn tick(&mut self) {
+        *self = match *self {
+            Fade::Out { ticks_left, total } => Fade::Out {
+                ticks_left: ticks_left.saturating_sub(1),
+                total,
+            },
+            Fade::In { ticks_left, total } if ticks_left > 1 => Fade::In {
+                ticks_left: ticks_left - 1,
+                total,
+            },
+            Fade::In { .. } | Fade::Clear => Fade::Clear,
+        };
+    }
+
+    pub fn is_fading_in(&self) -> bool {
+        match *self {
+            Fade::In { .. } => true,
+            Fade::Out { .. } | Fade::Clear => false,
+        }
+    }
+
+    /// Returns how dark the view is, from 0 for not at all to 1 for black,
+    /// `progress` of the way from the last tick to the next.
+    pub fn darkness(&self, progress: f32) -> f32 {
+        let fraction_left = |ticks_left: u32, total: u32| {
+            ((ticks_left as f32 - progress) / total as f32).clamp(0.0, 1.0)
+        };
+        match *self {
+            Fade::Clear => 0.0,
+            Fade::Out { ticks_left, total } => 1.0 - fraction_left(ticks_left, total),
+            Fade::In { ticks_left, total } => fraction_left(ticks_left, total),
+        }
+    }
+}
+
+/// The view jolting about for a moment, after a hard landing or a death.
+///
+/// The jolts are worked out from the tick they happen on rather than drawn at
+/// random, so the same run always shakes the same way. Shaking only moves where
+/// the view is drawn, never where anything is in the room.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shake {
+    /// Largest offset in pixels, at the start of the shake
+    strength: f32,
+    ticks_left: u32,
+    total: u32,
+    /// Tick the shake started on, which picks its jolts
+    seed: u64,
+}
+
+impl Shake {
+    pub fn none() -> Shake {
+        Shake {
+            strength: 0.0,
+            ticks_left: 0,
+            total: 1,
+            seed: 0,
+        }
+    }
+
+    /// Returns a shake of up to `strength` pixels that dies down over `ticks` ticks,
+    /// jolting the way tick `seed` picks.
+    pub fn new(strength: f32, ticks: u32, seed: u64) -> Shake {
+        let total = ticks.max(1);
+        Shake {
+            strength,
+            ticks_left: total,
+            total,
+            seed,
+        }
+    }
+
+    /// Returns whether this shake is stronger right now than `other`, so a weak
+    /// shake doesn't cut a strong one short.
+    pub fn is_stronger_than(&self, other: &Shake) -> bool {
+        self.strength * self.fraction_left(0.0) > other.strength * other.fraction_left(0.0)
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks_left = self.ticks_left.saturating_sub(1);
+    }
+
+    /// Returns how far in pixels to move the view, `progress` of the way from the
+    /// last tick to the next. Each tick jolts somewhere new, less far as the shake
+    /// dies down, until it's back to exactly nothing.
+    pub fn offset(&self, progress: f32) -> (f32, f32) {
+        let fraction_left = self.fraction_left(progress);
+        if self.strength <= 0.0 || fraction_left <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let step = u64::from(self.total - self.ticks_left);
+        let hash = mix(self.seed ^ step.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        // Two numbers between -1 and 1 from the halves of the hash
+        let x = (hash & 0xffff) as f32 / 0x7fff as f32 - 1.0;
+        let y = (hash >> 32 & 0xffff) as f32 / 0x7fff as f32 - 1.0;
+        // Die down faster than linearly, so the end of the shake is barely felt
+        let amount = self.strength * fraction_left * fraction_left;
+        (x * amount, y * amount)
+    }
+
+    fn fraction_left(&self, progress: f32) -> f32 {
+        ((self.ticks_left as f32 - progress) / self.total as f32).clamp(0.0, 1.0)
+    }
+}
+
+/// Scrambles the bits of `value`, so that nearby values give unrelated results.
+fn mix(mut value: u64) -> u64 {
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    value ^= value >> 33;
+    value = value.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    value ^ value >> 33
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 150.0;
+
+    fn burst(count: u32, lifetime: u32) -> Burst {
+        Burst {
+            count,
+            angles: (0.0, 1.0),
+            speed: 100.0,
+            lifetime,
+            kind: ParticleKind::Dust,
+        }
+    }
+
+    #[test]
+    fn particles_disappear_when_their_time_is_up() {
+        let mut particles = Particles::default();
+        particles.burst(0.0, 0.0, &burst(8, 5));
+        for _ in 0..4 {
+            particles.update(DT);
+        }
+        assert_eq!(particles.len(), 8);
+        particles.update(DT);
+        assert!(particles.is_empty());
+    }
+
+    #[test]
+    fn particles_replace_the_oldest_past_max_count() {
+        let mut particles = Particles::default();
+        for lifetime in 1..=Particles::MAX_COUNT as u32 + 10 {
+            particles.burst(0.0, 0.0, &burst(1, lifetime));
+        }
+        assert_eq!(particles.len(), Particles::MAX_COUNT);
+        // The ten with the shortest lifetimes were the oldest
+        assert_eq!(particles.particles[0].ticks_left, 11);
+    }
+
+    fn trail_ages(trail: &Trail) -> Vec<u32> {
+        trail.images.iter().map(|&(_, age)| age).collect()
+    }
+
+    #[test]
+    fn trail_keeps_at_most_max_age_copies_newest_first() {
+        let mut trail = Trail::default();
+        for x in 0..20 {
+            trail.update(Some(Rect::new(x as f32, 0.0, 8.0, 20.0)));
+        }
+        assert_eq!(trail_ages(&trail), (0..Trail::MAX_AGE).collect::<Vec<_>>());
+        let lefts: Vec<f32> = trail.images.iter().map(|&(rect, _)| rect.left()).collect();
+        assert_eq!(lefts, [19.0, 18.0, 17.0, 16.0, 15.0, 14.0, 13.0, 12.0]);
+    }
+
+    #[test]
+    fn trail_dies_out_once_nothing_is_added() {
+        let mut trail = Trail::default();
+        trail.update(Some(Rect::new(0.0, 0.0, 8.0, 20.0)));
+        trail.update(Some(Rect::new(1.0, 0.0, 8.0, 20.0)));
+        trail.update(None);
+        assert_eq!(trail_ages(&trail), [1, 2]);
+        for _ in 0..Trail::MAX_AGE - 3 {
+            trail.update(None);
+        }
+        assert_eq!(trail_ages(&trail), [Trail::MAX_AGE - 2, Trail::MAX_AGE - 1]);
+        trail.update(None);
+        assert_eq!(trail_ages(&trail), [Trail::MAX_AGE - 1]);
+        trail.update(None);
+        assert!(trail.images.is_empty());
+    }
+
+    #[test]
+    fn cleared_trail_starts_over() {
+        let mut trail = Trail::default();
+        trail.update(Some(Rect::new(0.0, 0.0, 8.0, 20.0)));
+        trail.update(Some(Rect::new(1.0, 0.0, 8.0, 20.0)));
+        trail.clear();
+        assert!(trail.images.is_empty());
+        trail.update(Some(Rect::new(2.0, 0.0, 8.0, 20.0)));
+        assert_eq!(trail_ages(&trail), [0]);
+    }
+
+    #[test]
+    fn fade_out_waits_then_darkens_and_stays_black() {
+        let mut fade = Fade::fading_out(4, 6);
+        let mut darkness = Vec::new();
+        for _ in 0..8 {
+            darkness.push(fade.darkness(0.0));
+            fade.tick();
+        }
+        assert_eq!(darkness, [0.0, 0.0, 0.0, 0.25, 0.5, 0.75, 1.0, 1.0]);
+        assert!(!fade.is_fading_in());
+        assert_ne!(fade, Fade::Clear);
+    }
+
+    #[test]
+    fn fade_in_clears_after_its_ticks() {
+        let mut fade = Fade::fading_in(4);
+        let mut darkness = Vec::new();
+        while fade.is_fading_in() {
+            darkness.push(fade.darkness(0.0));
+            fade.tick();
+        }
+        assert_eq!(darkness, [1.0, 0.75, 0.5, 0.25]);
+        assert_eq!(fade, Fade::Clear);
+        assert_eq!(fade.darkness(0.0), 0.0);
+    }
+
+    #[test]
+    fn fade_darkness_follows_progress_between_ticks() {
+        assert_eq!(Fade::fading_in(4).darkness(0.5), 0.875);
+        assert_eq!(Fade::fading_out(4, 4).darkness(0.5), 0.125);
+        assert_eq!(Fade::Clear.darkness(0.5), 0.0);
+    }
+
+    #[test]
+    fn instant_fade_in_takes_a_tick() {
+        let mut fade = Fade::fading_in(0);
+        assert_eq!(fade.darkness(0.0), 1.0);
+        fade.tick();
+        assert_eq!(fade, Fade::Clear);
+    }
+
+    #[test]
+    fn shake_dies_down_to_nothing() {
+        let mut shake = Shake::new(8.0, 10, 42);
+        let mut last_envelope = f32::INFINITY;
+        for _ in 0..10 {
+            let envelope = 8.0 * shake.fraction_left(0.0).powi(2);
+            assert!(envelope < last_envelope);
+            let (x, y) = shake.offset(0.0);
+            assert!(x.abs() <= envelope && y.abs() <= envelope);
+            last_envelope = envelope;
+            shake.tick();
+        }
+        assert_eq!(shake.offset(0.0), (0.0, 0.0));
+        shake.tick();
+        assert_eq!(shake.offset(0.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn shake_jolts_the_same_way_for_the_same_seed() {
+        let (mut first, mut second) = (Shake::new(8.0, 10, 7), Shake::new(8.0, 10, 7));
+        assert_ne!(first.offset(0.0), (0.0, 0.0));
+        for _ in 0..10 {
+            assert_eq!(first.offset(0.5), second.offset(0.5));
+            first.tick();
+            second.tick();
+        }
+        assert_ne!(Shake::new(8.0, 10, 7).offset(0.0), Shake::new(8.0, 10, 8).offset(0.0));
+    }
+
+    #[test]
+    fn weaker_shake_does_not_replace_stronger_one() {
+        let mut strong = Shake::new(8.0, 10, 0);
+        let weak = Shake::new(2.0, 10, 0);
+        assert!(strong.is_stronger_than(&weak));
+        assert!(!weak.is_stronger_than(&strong));
+        assert!(weak.is_stronger_than(&Shake::none()));
+        // Once the strong shake has nearly died down, a fresh weak one takes over
+        for _ in 0..8 {
+            strong.tick();
+        }
+        assert!(weak.is_stronger_than(&strong));
+        assert_eq!(Shake::none().offset(0.0), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,874 @@
+//! Objects placed in rooms besides tiles
+
+use failure::{err_msg, Error};
+use sdl2::render::{Canvas, RenderTarget};
+
+use camera::Camera;
+use geom::{Circle, Rect, Vec2};
+use room::Room;
+use theme::Theme;
+
+/// An object placed in a room, as stored in the room's entity list.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Entity {
+    Platform(PlatformDef),
+    /// A coin centered on the given position
+    Coin { x: f32, y: f32 },
+    /// An enemy with its top left corner at the given position
+    Enemy { x: f32, y: f32 },
+    Crusher(CrusherDef),
+    /// A key centered on the given position, which opens one door
+    Key { x: f32, y: f32 },
+    /// A square crate of the given size with its top left corner at the given position
+    Crate { x: f32, y: f32, size: f32 },
+    Turret(TurretDef),
+    Sign(SignDef),
+}
+
+impl Entity {
+    /// Renders the entity at its initial position, e.g. for the editor.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        match *self {
+            Entity::Platform(ref def) => {
+                Platform::new(def.clone()).render(canvas, camera, theme, 0.0)
+            }
+            Entity::Coin { x, y } => Coin::new(x, y).render(canvas, camera, theme),
+            Entity::Enemy { x, y } => Enemy::new(x, y).render(canvas, camera, theme, 0.0),
+            Entity::Crusher(ref def) => {
+                Crusher::new(def.clone()).render(canvas, camera, theme, 0.0)
+            }
+            Entity::Key { x, y } => Key::new(x, y).render(canvas, camera, theme),
+            Entity::Crate { x, y, size } => {
+                Crate::new(x, y, size).render(canvas, camera, theme, 0.0)
+            }
+            Entity::Turret(ref def) => Turret::new(def.clone()).render(canvas, camera, theme),
+            Entity::Sign(ref def) => Sign::new(def.clone()).render(canvas, camera, theme),
+        }
+    }
+}
+
+/// Definition of a platform moving along a path of waypoints.
+///
+/// The platform moves from each waypoint to the next at a constant speed,
+/// returning to the first one after the last.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlatformDef {
+    pub width: f32,
+    pub height: f32,
+    /// Positions of the platform's top left corner to move between
+    pub waypoints: Vec<(f32, f32)>,
+    /// Speed in pixels per second
+    pub speed: f32,
+}
+
+/// A moving platform as simulated by the model.
+#[derive(Clone, Debug)]
+pub struct Platform {
+    def: PlatformDef,
+    xpos: f32,
+    ypos: f32,
+    xspeed: f32,
+    yspeed: f32,
+    /// Index of the waypoint the platform is moving towards
+    target: usize,
+}
+
+impl Platform {
+    pub fn new(def: PlatformDef) -> Platform {
+        let (xpos, ypos) = def.waypoints.first().cloned().unwrap_or((0.0, 0.0));
+        Platform {
+            xpos,
+            ypos,
+            xspeed: 0.0,
+            yspeed: 0.0,
+            target: if def.waypoints.len() > 1 { 1 } else { 0 },
+            def,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.xpos, self.ypos, self.def.width, self.def.height)
+    }
+
+    /// Moves the platform along its path, returning its displacement.
+    pub fn update(&mut self, dt: f32) -> (f32, f32) {
+        let (old_xpos, old_ypos) = (self.xpos, self.ypos);
+        let mut distance_left = self.def.speed * dt;
+        // Turning around at a waypoint happens within the tick, so the platform never pauses
+        while distance_left > 0.0 && self.def.waypoints.len() > 1 {
+            let (target_x, target_y) = self.def.waypoints[self.target];
+            let (to_x, to_y) = (target_x - self.xpos, target_y - self.ypos);
+            let to_target = (to_x * to_x + to_y * to_y).sqrt();
+            if to_target > distance_left {
+                self.xpos += to_x / to_target * distance_left;
+                self.ypos += to_y / to_target * distance_left;
+                break;
+            }
+            self.xpos = target_x;
+            self.ypos = target_y;
+            distance_left -= to_target;
+            self.target = (self.target + 1) % self.def.waypoints.len();
+        }
+        let (dx, dy) = (self.xpos - old_xpos, self.ypos - old_ypos);
+        self.xspeed = dx / dt;
+        self.yspeed = dy / dt;
+        (dx, dy)
+    }
+
+    /// Renders the platform `time_delta` seconds ahead of its simulated position.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        time_delta: f32,
+    ) -> Result<(), Error> {
+        let rect = Rect::new(
+            self.xpos + self.xspeed * time_delta,
+            self.ypos + self.yspeed * time_delta,
+            self.def.width,
+            self.def.height,
+        );
+        canvas.set_draw_color(theme.platform());
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// Definition of a crusher: a solid block that repeatedly slams from one position
+/// to another and slowly pulls back.
+///
+/// Each cycle it waits retracted, strikes, holds extended and retracts, in that order.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CrusherDef {
+    pub width: f32,
+    pub height: f32,
+    /// Position of the top left corner when retracted
+    pub start: (f32, f32),
+    /// Position of the top left corner when extended
+    pub end: (f32, f32),
+    /// Seconds spent retracted before striking
+    pub wait_time: f32,
+    /// Seconds taken to move from `start` to `end`
+    pub strike_time: f32,
+    /// Seconds spent extended before retracting
+    pub hold_time: f32,
+    /// Seconds taken to move from `end` back to `start`
+    pub retract_time: f32,
+}
+
+/// A crusher as simulated by the model.
+#[derive(Clone, Debug)]
+pub struct Crusher {
+    def: CrusherDef,
+    xpos: f32,
+    ypos: f32,
+    xspeed: f32,
+    yspeed: f32,
+    /// Seconds since the start of the current cycle
+    cycle_time: f32,
+}
+
+impl Crusher {
+    pub fn new(def: CrusherDef) -> Crusher {
+        let (xpos, ypos) = def.start;
+        Crusher {
+            xpos,
+            ypos,
+            xspeed: 0.0,
+            yspeed: 0.0,
+            cycle_time: 0.0,
+            def,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.xpos, self.ypos, self.def.width, self.def.height)
+    }
+
+    /// Moves the crusher along its cycle, returning its displacement.
+    pub fn update(&mut self, dt: f32) -> (f32, f32) {
+        let def = &self.def;
+        let cycle_duration = def.wait_time + def.strike_time + def.hold_time + def.retract_time;
+        if cycle_duration > 0.0 {
+            self.cycle_time = (self.cycle_time + dt) % cycle_duration;
+        }
+        // How far along the way from `start` to `end` the crusher is
+        let strike_start = def.wait_time;
+        let hold_start = strike_start + def.strike_time;
+        let retract_start = hold_start + def.hold_time;
+        let t = self.cycle_time;
+        let extension = if t < strike_start {
+            0.0
+        } else if t < hold_start {
+            (t - strike_start) / def.strike_time
+        } else if t < retract_start {
+            1.0
+        } else {
+            1.0 - (t - retract_start) / def.retract_time
+        };
+        let (old_xpos, old_ypos) = (self.xpos, self.ypos);
+        self.xpos = def.start.0 + (def.end.0 - def.start.0) * extension;
+        self.ypos = def.start.1 + (def.end.1 - def.start.1) * extension;
+        let (dx, dy) = (self.xpos - old_xpos, self.ypos - old_ypos);
+        self.xspeed = dx / dt;
+        self.yspeed = dy / dt;
+        (dx, dy)
+    }
+
+    /// Renders the crusher `time_delta` seconds ahead of its simulated position.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        time_delta: f32,
+    ) -> Result<(), Error> {
+        let rect = Rect::new(
+            self.xpos + self.xspeed * time_delta,
+            self.ypos + self.yspeed * time_delta,
+            self.def.width,
+            self.def.height,
+        );
+        canvas.set_draw_color(theme.crusher());
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        canvas.set_draw_color(theme.crusher_outline());
+        canvas.draw_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// Definition of a turret: a solid block that fires projectiles at a fixed interval.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TurretDef {
+    /// Position of the top left corner
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    /// Velocity of the projectiles in pixels per second
+    pub projectile_velocity: (f32, f32),
+    /// Seconds between shots
+    pub interval: f32,
+}
+
+/// A turret as simulated by the model.
+#[derive(Clone, Debug)]
+pub struct Turret {
+    def: TurretDef,
+    /// Seconds since the last shot
+    since_shot: f32,
+}
+
+impl Turret {
+    pub fn new(def: TurretDef) -> Turret {
+        Turret {
+            def,
+            since_shot: 0.0,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.def.x, self.def.y, self.def.size, self.def.size)
+    }
+
+    /// Advances the turret's reload time, returning the projectile it fires, if any.
+    pub fn update(&mut self, dt: f32) -> Option<Projectile> {
+        self.since_shot += dt;
+        if self.since_shot < self.def.interval || self.def.interval <= 0.0 {
+            return None;
+        }
+        self.since_shot -= self.def.interval;
+        let center_x = self.def.x + self.def.size / 2.0;
+        let center_y = self.def.y + self.def.size / 2.0;
+        let (xspeed, yspeed) = self.def.projectile_velocity;
+        Some(Projectile::new(center_x, center_y, xspeed, yspeed))
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        let rect = self.rect();
+        canvas.set_draw_color(theme.turret());
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        // Draw the barrel on the side the turret fires towards
+        let (xspeed, yspeed) = self.def.projectile_velocity;
+        let speed = (xspeed * xspeed + yspeed * yspeed).sqrt().max(1.0);
+        let half = self.def.size / 2.0;
+        let barrel_size = self.def.size / 4.0;
+        let barrel = Rect::new(
+            rect.left() + half + xspeed / speed * half - barrel_size / 2.0,
+            rect.top() + half + yspeed / speed * half - barrel_size / 2.0,
+            barrel_size,
+            barrel_size,
+        );
+        canvas.set_draw_color(theme.turret_barrel());
+        canvas.fill_rect(camera.sdl_rect(&barrel)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// A projectile flying in a straight line until it hits something.
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    xpos: f32,
+    ypos: f32,
+    xspeed: f32,
+    yspeed: f32,
+}
+
+impl Projectile {
+    pub const SIZE: f32 = 4.0;
+
+    /// Creates a projectile centered on (`x`, `y`).
+    pub fn new(x: f32, y: f32, xspeed: f32, yspeed: f32) -> Projectile {
+        Projectile {
+            xpos: x - Projectile::SIZE / 2.0,
+            ypos: y - Projectile::SIZE / 2.0,
+            xspeed,
+            yspeed,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.xpos, self.ypos, Projectile::SIZE, Projectile::SIZE)
+    }
+
+    /// Moves the projectile in steps no longer than half its size, so it can't
+    /// skip through anything thin.
+    ///
+    /// Returns the rects it passed through, and whether it's still flying rather
+    /// than having hit a filled tile or left the room.
+    pub fn update(&mut self, dt: f32, room: &Room) -> (Vec<Rect>, bool) {
+        let (dx, dy) = (self.xspeed * dt, self.yspeed * dt);
+        let distance = (dx * dx + dy * dy).sqrt();
+        let steps = (distance / (Projectile::SIZE / 2.0)).ceil().max(1.0) as u32;
+        let mut path = vec![self.rect()];
+        for _ in 0..steps {
+            self.xpos += dx / steps as f32;
+            self.ypos += dy / steps as f32;
+            let rect = self.rect();
+            let in_room = rect.right() > 0.0
+                && rect.left() < room.pixel_width()
+                && rect.bottom() > 0.0
+                && rect.top() < room.pixel_height();
+            if !in_room || has_filled_tile(room, &rect) {
+                return (path, false);
+            }
+            path.push(rect);
+        }
+        (path, true)
+    }
+
+    /// Renders the projectile `time_delta` seconds ahead of its simulated position.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        time_delta: f32,
+    ) -> Result<(), Error> {
+        let rect = Rect::new(
+            self.xpos + self.xspeed * time_delta,
+            self.ypos + self.yspeed * time_delta,
+            Projectile::SIZE,
+            Projectile::SIZE,
+        );
+        canvas.set_draw_color(theme.projectile());
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// Definition of a sign showing a message to the player at it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignDef {
+    /// Position of the top left corner
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    pub text: String,
+    /// Whether the message is shown as soon as the player reaches the sign, rather
+    /// than when they press look up
+    #[serde(default)]
+    pub automatic: bool,
+}
+
+/// A sign as simulated by the model.
+#[derive(Clone, Debug)]
+pub struct Sign {
+    def: SignDef,
+}
+
+impl Sign {
+    pub fn new(def: SignDef) -> Sign {
+        Sign { def }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.def.x, self.def.y, self.def.size, self.def.size)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.def.text
+    }
+
+    pub fn is_automatic(&self) -> bool {
+        self.def.automatic
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        let size = self.def.size;
+        // A board on a post, standing on the bottom of the tile
+        let post = Rect::new(
+            self.def.x + size * 7.0 / 16.0,
+            self.def.y + size / 2.0,
+            size / 8.0,
+            size / 2.0,
+        );
+        let board = Rect::new(
+            self.def.x + size / 8.0,
+            self.def.y + size / 8.0,
+            size * 3.0 / 4.0,
+            size / 2.0,
+        );
+        canvas.set_draw_color(theme.sign_post());
+        canvas.fill_rect(camera.sdl_rect(&post)).map_err(err_msg)?;
+        canvas.set_draw_color(theme.sign_board());
+        canvas.fill_rect(camera.sdl_rect(&board)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// A coin as simulated by the model, which can be collected once.
+#[derive(Clone, Debug)]
+pub struct Coin {
+    rect: Rect,
+    collected: bool,
+}
+
+impl Coin {
+    pub const SIZE: f32 = 6.0;
+    /// Distance from the coin's center within which a player collects it, a little
+    /// beyond its sides so that brushing past a corner still counts
+    pub const PICKUP_RADIUS: f32 = 4.0;
+
+    /// Creates a coin centered on (`x`, `y`).
+    pub fn new(x: f32, y: f32) -> Coin {
+        Coin {
+            rect: Rect::new(
+                x - Coin::SIZE / 2.0,
+                y - Coin::SIZE / 2.0,
+                Coin::SIZE,
+                Coin::SIZE,
+            ),
+            collected: false,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Returns the area in which a player collects the coin.
+    pub fn pickup_area(&self) -> Circle {
+        let center = Vec2::new(
+            (self.rect.left() + self.rect.right()) / 2.0,
+            (self.rect.top() + self.rect.bottom()) / 2.0,
+        );
+        Circle::new(center, Coin::PICKUP_RADIUS)
+    }
+
+    pub fn is_collected(&self) -> bool {
+        self.collected
+    }
+
+    pub fn collect(&mut self) {
+        self.collected = true;
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        if self.collected {
+            return Ok(());
+        }
+        canvas.set_draw_color(theme.coin());
+        canvas.fill_rect(camera.sdl_rect(&self.rect)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// A key as simulated by the model, which can be picked up once.
+#[derive(Clone, Debug)]
+pub struct Key {
+    rect: Rect,
+    collected: bool,
+}
+
+impl Key {
+    pub const WIDTH: f32 = 6.0;
+    pub const HEIGHT: f32 = 10.0;
+
+    /// Creates a key centered on (`x`, `y`).
+    pub fn new(x: f32, y: f32) -> Key {
+        Key {
+            rect: Rect::new(
+                x - Key::WIDTH / 2.0,
+                y - Key::HEIGHT / 2.0,
+                Key::WIDTH,
+                Key::HEIGHT,
+            ),
+            collected: false,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn is_collected(&self) -> bool {
+        self.collected
+    }
+
+    pub fn collect(&mut self) {
+        self.collected = true;
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        if self.collected {
+            return Ok(());
+        }
+        canvas.set_draw_color(theme.key());
+        canvas.fill_rect(camera.sdl_rect(&self.rect)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// A crate as simulated by the model, which falls and can be pushed sideways.
+#[derive(Clone, Debug)]
+pub struct Crate {
+    xpos: f32,
+    ypos: f32,
+    size: f32,
+    yspeed: f32,
+}
+
+impl Crate {
+    /// Speed at which the player pushes a crate, in crate sizes per second
+    pub const PUSH_SPEED: f32 = 2.5;
+
+    /// Creates a crate with its top left corner at (`x`, `y`).
+    pub fn new(x: f32, y: f32, size: f32) -> Crate {
+        Crate {
+            xpos: x,
+            ypos: y,
+            size,
+            yspeed: 0.0,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.xpos, self.ypos, self.size, self.size)
+    }
+
+    /// Moves the crate sideways by up to `push`, and lets it fall, stopping at
+    /// solid tiles, the room's edges and `solids`. Returns how far it fell.
+    pub fn update(&mut self, dt: f32, push: f32, room: &Room, solids: &[Rect]) -> f32 {
+        // In crate sizes per second, and per second squared
+        const FALL_SPEED: f32 = 15.0;
+        const FALL_ACCEL: f32 = 40.0;
+        let blocked = |rect: &Rect| {
+            rect.left() < 0.0
+                || rect.right() > room.pixel_width()
+                || has_filled_tile(room, rect)
+                || solids.iter().any(|solid| rect.overlaps(solid))
+        };
+        if push != 0.0 {
+            let pushed = Rect::new(self.xpos + push, self.ypos, self.size, self.size);
+            // Stop at whatever is in the way rather than moving only part of the way
+            if !blocked(&pushed) {
+                self.xpos += push;
+            }
+        }
+        self.yspeed = (self.yspeed + FALL_ACCEL * self.size * dt).min(FALL_SPEED * self.size);
+        let fallen = Rect::new(self.xpos, self.ypos + self.yspeed * dt, self.size, self.size);
+        if !blocked(&fallen) {
+            self.ypos += self.yspeed * dt;
+            return self.yspeed * dt;
+        }
+        // Land on top of the highest thing below
+        let floor = room.tiles_in_rect(&fallen)
+            .iter()
+            .filter(|tile| tile.kind.is_solid())
+            .map(|tile| tile.rect)
+            .chain(solids.iter().cloned().filter(|solid| fallen.overlaps(solid)))
+            .map(|rect| rect.top())
+            .fold(fallen.bottom(), f32::min);
+        let old_ypos = self.ypos;
+        self.ypos = (floor - self.size).max(self.ypos);
+        self.yspeed = 0.0;
+        self.ypos - old_ypos
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        time_delta: f32,
+    ) -> Result<(), Error> {
+        let rect = Rect::new(
+            self.xpos,
+            self.ypos + self.yspeed * time_delta,
+            self.size,
+            self.size,
+        );
+        canvas.set_draw_color(theme.crate_fill());
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        canvas.set_draw_color(theme.crate_outline());
+        canvas.draw_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+/// An enemy walking back and forth, turning around at walls and ledges.
+#[derive(Clone, Debug)]
+pub struct Enemy {
+    xpos: f32,
+    ypos: f32,
+    xspeed: f32,
+    yspeed: f32,
+    /// Number of ticks left before the enemy may turn around again
+    turn_cooldown_ticks: u32,
+}
+
+impl Enemy {
+    const WIDTH: f32 = 12.0;
+    const HEIGHT: f32 = 12.0;
+
+    /// Creates an enemy with its top left corner at (`x`, `y`).
+    pub fn new(x: f32, y: f32) -> Enemy {
+        Enemy {
+            xpos: x,
+            ypos: y,
+            xspeed: 0.0,
+            yspeed: 0.0,
+            turn_cooldown_ticks: 0,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.xpos, self.ypos, Enemy::WIDTH, Enemy::HEIGHT)
+    }
+
+    pub fn update(&mut self, dt: f32, room: &Room) {
+        const WALK_SPEED: f32 = 40.0;
+        const FALL_SPEED: f32 = 300.0;
+        const FALL_ACCEL: f32 = 300.0;
+        // Minimum time between turning around, so an enemy on a platform
+        // barely wider than itself doesn't turn around every tick
+        const TURN_COOLDOWN: f32 = 0.5;
+
+        self.turn_cooldown_ticks = self.turn_cooldown_ticks.saturating_sub(1);
+
+        let feet = Rect::new(self.xpos, self.ypos + Enemy::HEIGHT, Enemy::WIDTH, 0.5);
+        if !has_filled_tile(room, &feet) {
+            // Fall until landing on a floor
+            self.yspeed = (self.yspeed + FALL_ACCEL * dt).min(FALL_SPEED);
+            self.ypos += self.yspeed * dt;
+            for tile in room.tiles_in_rect(&self.rect()) {
+                if tile.kind.is_solid() {
+                    self.ypos = self.ypos.min(tile.rect.top() - Enemy::HEIGHT);
+                    self.yspeed = 0.0;
+                }
+            }
+            return;
+        }
+
+        if self.xspeed == 0.0 {
+            self.xspeed = WALK_SPEED;
+        }
+        let new_xpos = self.xpos + self.xspeed * dt;
+        let new_rect = Rect::new(new_xpos, self.ypos, Enemy::WIDTH, Enemy::HEIGHT);
+        let leading_x = if self.xspeed > 0.0 {
+            new_xpos + Enemy::WIDTH - 0.5
+        } else {
+            new_xpos + 0.5
+        };
+        let floor_ahead = room.tile_at_coord(leading_x, self.ypos + Enemy::HEIGHT + 0.5);
+        let out_of_room = new_xpos < 0.0 || new_xpos + Enemy::WIDTH > room.pixel_width();
+        if has_filled_tile(room, &new_rect) || !floor_ahead.kind.is_solid() || out_of_room {
+            // Turn around, or wait if the enemy turned around only just now
+            if self.turn_cooldown_ticks == 0 {
+                trace!("Enemy turns around at ({}, {})", self.xpos, self.ypos);
+                self.xspeed = -self.xspeed;
+                self.turn_cooldown_ticks = (TURN_COOLDOWN / dt).round() as u32;
+            }
+        } else {
+            self.xpos = new_xpos;
+        }
+    }
+
+    /// Renders the enemy `time_delta` seconds ahead of its simulated position.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        time_delta: f32,
+    ) -> Result<(), Error> {
+        let rect = Rect::new(
+            self.xpos + self.xspeed * time_delta,
+            self.ypos + self.yspeed * time_delta,
+            Enemy::WIDTH,
+            Enemy::HEIGHT,
+        );
+        canvas.set_draw_color(theme.enemy());
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        Ok(())
+    }
+}
+
+fn has_filled_tile(room: &Room, rect: &Rect) -> bool {
+    room.tiles_in_rect(rect)
+        .iter()
+        .any(|tile| tile.kind.is_solid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use room::TileKind;
+
+    const DT: f32 = 1.0 / 150.0;
+
+    #[test]
+    fn enemy_patrols_its_platform() {
+        let mut room = Room::new(20, 20, 16);
+        for x in 5..10 {
+            room.set_tile_at_index(x, 10, TileKind::Filled).unwrap();
+        }
+        let mut enemy = Enemy::new(80.0, 160.0 - Enemy::HEIGHT);
+        let mut turns = Vec::new();
+        for _ in 0..1500 {
+            let xspeed = enemy.xspeed;
+            enemy.update(DT, &room);
+            assert_eq!(enemy.ypos, 160.0 - Enemy::HEIGHT);
+            // Its leading edge stays above the platform
+            assert!(enemy.xpos + 0.5 >= 80.0 && enemy.xpos + Enemy::WIDTH - 0.5 <= 160.0);
+            if xspeed != 0.0 && enemy.xspeed == -xspeed {
+                turns.push((enemy.xpos, enemy.xspeed));
+            }
+        }
+        assert!(turns.len() >= 4, "turned around {} times", turns.len());
+        // Turns around within a step of the platform's edges
+        let step = 40.0 * DT;
+        for &(xpos, xspeed) in &turns {
+            if xspeed < 0.0 {
+                assert!(160.0 - (xpos + Enemy::WIDTH) < step);
+            } else {
+                assert!(xpos - 80.0 < step);
+            }
+        }
+    }
+
+    #[test]
+    fn wall_stops_pushed_crate() {
+        let mut room = Room::new(10, 10, 16);
+        for y in 0..10 {
+            room.set_tile_at_index(5, y, TileKind::Filled).unwrap();
+            room.set_tile_at_index(y, 9, TileKind::Filled).unwrap();
+        }
+        let mut crate_ = Crate::new(48.0, 128.0, 16.0);
+        let push = Crate::PUSH_SPEED * 16.0 * DT;
+        for _ in 0..150 {
+            assert_eq!(crate_.update(DT, push, &room, &[]), 0.0);
+        }
+        // Never overlaps the wall, and stops less than a push short of it
+        assert!(crate_.rect().right() <= 80.0 && crate_.rect().right() > 80.0 - push);
+        assert_eq!(crate_.rect().bottom(), 144.0);
+
+        // Open space lets it move by the full push
+        let mut crate_ = Crate::new(0.0, 128.0, 16.0);
+        crate_.update(DT, push, &room, &[]);
+        assert_eq!(crate_.rect().left(), push);
+    }
+
+    #[test]
+    fn falling_crate_lands_on_another() {
+        let mut room = Room::new(10, 10, 16);
+        for x in 0..10 {
+            room.set_tile_at_index(x, 9, TileKind::Filled).unwrap();
+        }
+        let below = Crate::new(32.0, 128.0, 16.0);
+        let mut above = Crate::new(36.0, 0.0, 16.0);
+        let mut fallen = 0.0;
+        for _ in 0..300 {
+            fallen += above.update(DT, 0.0, &room, &[below.rect()]);
+        }
+        assert_eq!(above.rect().bottom(), below.rect().top());
+        assert_eq!(fallen, 112.0);
+    }
+
+    #[test]
+    fn turret_fires_once_per_interval() {
+        let mut turret = Turret::new(TurretDef {
+            x: 0.0,
+            y: 0.0,
+            size: 16.0,
+            projectile_velocity: (128.0, 0.0),
+            interval: 0.5,
+        });
+        let shot_ticks: Vec<u32> = (1..=760).filter(|_| turret.update(DT).is_some()).collect();
+        assert_eq!(shot_ticks.len(), 10);
+        // Rounding may move a shot by a tick, but never makes them drift
+        for (i, &tick) in shot_ticks.iter().enumerate() {
+            let expected = 75 * (i as u32 + 1);
+            assert!(tick >= expected - 1 && tick <= expected + 1, "shot at {}", tick);
+        }
+    }
+
+    #[test]
+    fn projectile_despawns_at_wall_or_room_edge() {
+        let mut room = Room::new(10, 10, 16);
+        room.set_tile_at_index(6, 4, TileKind::Filled).unwrap();
+        // Fast enough to cross most of a tile per tick
+        let mut projectile = Projectile::new(8.0, 72.0, 1500.0, 0.0);
+        let mut ticks = 0;
+        loop {
+            let (path, flying) = projectile.update(DT, &room);
+            assert!(path.iter().all(|rect| rect.right() <= 96.0));
+            ticks += 1;
+            if !flying {
+                break;
+            }
+        }
+        assert_eq!(ticks, 9);
+
+        let mut projectile = Projectile::new(16.0, 8.0, -1500.0, 0.0);
+        assert!(projectile.update(DT, &room).1);
+        assert!(!projectile.update(DT, &room).1);
+    }
+}
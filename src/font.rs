@@ -0,0 +1,234 @@
+//! A tiny bitmap font for drawing text without loading any assets
+
+use failure::{err_msg, Error};
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Canvas, RenderTarget};
+
+/// Size of a glyph in font pixels
+pub const GLYPH_WIDTH: u32 = 3;
+pub const GLYPH_HEIGHT: u32 = 5;
+/// Distance from the start of one character to the next, in font pixels
+pub const ADVANCE: u32 = GLYPH_WIDTH + 1;
+/// Distance from the top of one line to the next, in font pixels
+pub const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+
+/// Returns the rows of the glyph for `c`, top to bottom, with the leftmost pixel in
+/// the highest of the three bits. Every printable ASCII character has a glyph,
+/// though letters are all drawn uppercase, and other characters are drawn as a
+/// question mark.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ';' => [0b000, 0b010, 0b000, 0b010, 0b100],
+        '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+        '`' => [0b100, 0b010, 0b000, 0b000, 0b000],
+        '^' => [0b010, 0b101, 0b000, 0b000, 0b000],
+        '~' => [0b000, 0b000, 0b011, 0b110, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '\\' => [0b100, 0b100, 0b010, 0b001, 0b001],
+        '|' => [0b010, 0b010, 0b010, 0b010, 0b010],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '[' => [0b011, 0b010, 0b010, 0b010, 0b011],
+        ']' => [0b110, 0b010, 0b010, 0b010, 0b110],
+        '{' => [0b011, 0b010, 0b110, 0b010, 0b011],
+        '}' => [0b110, 0b010, 0b011, 0b010, 0b110],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '$' => [0b011, 0b110, 0b010, 0b011, 0b110],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '&' => [0b010, 0b101, 0b010, 0b101, 0b011],
+        '@' => [0b010, 0b101, 0b111, 0b100, 0b011],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+/// Returns the width in screen pixels of `text` drawn at `scale`, which is that of
+/// its longest line.
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    text.lines()
+        .map(|line| match line.chars().count() as u32 {
+            0 => 0,
+            chars => (chars * ADVANCE - 1) * scale,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the height in screen pixels of `text` drawn at `scale`, from the top of
+/// its first line to the bottom of its last.
+pub fn text_height(text: &str, scale: u32) -> u32 {
+    match text.lines().count() as u32 {
+        0 => 0,
+        lines => ((lines - 1) * LINE_HEIGHT + GLYPH_HEIGHT) * scale,
+    }
+}
+
+/// Splits `text` into lines of at most `max_chars` characters, breaking between
+/// words where possible.
+pub fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_chars = line.chars().count();
+        if line_chars > 0 && line_chars + 1 + word.len() > max_chars {
+            lines.push(line);
+            line = String::new();
+        }
+        // Break words that don't fit on a line of their own
+        while word.len() > max_chars {
+            let rest = word.split_off(max_chars);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Draws `text` with its top left corner at (`x`, `y`) in screen coordinates, with
+/// every font pixel `scale` screen pixels in size, in the canvas's current draw
+/// color. Newlines start a new line below the first.
+pub fn render_text<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: u32,
+) -> Result<(), Error> {
+    let mut pixels = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let top = y + (line_index as u32 * LINE_HEIGHT * scale) as i32;
+        for (index, c) in line.chars().enumerate() {
+            let left = x + (index as u32 * ADVANCE * scale) as i32;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (0b100 >> column) != 0 {
+                        pixels.push(SdlRect::new(
+                            left + (column * scale) as i32,
+                            top + (row as u32 * scale) as i32,
+                            scale,
+                            scale,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    canvas.fill_rects(&pixels).map_err(err_msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_width_is_that_of_longest_line() {
+        assert_eq!(text_width("", 1), 0);
+        assert_eq!(text_width("A", 1), GLYPH_WIDTH);
+        assert_eq!(text_width("AB", 1), 7);
+        assert_eq!(text_width("AB", 3), 21);
+        assert_eq!(text_width("AB\nABCD\nA", 1), 15);
+        assert_eq!(text_width("AB\n", 1), 7);
+    }
+
+    #[test]
+    fn text_height_counts_lines() {
+        assert_eq!(text_height("", 1), 0);
+        assert_eq!(text_height("A", 1), GLYPH_HEIGHT);
+        assert_eq!(text_height("A\nB", 1), 12);
+        assert_eq!(text_height("A\nB", 2), 24);
+        // Empty lines in between count too
+        assert_eq!(text_height("A\n\nB", 1), 19);
+    }
+
+    #[test]
+    fn lowercase_letters_look_like_uppercase() {
+        for (lower, upper) in (b'a'..=b'z').zip(b'A'..=b'Z') {
+            assert_eq!(glyph(lower as char), glyph(upper as char));
+        }
+    }
+
+    #[test]
+    fn every_printable_character_has_a_glyph() {
+        let placeholder = glyph('\u{fffd}');
+        for c in (b' '..=b'~').map(char::from).filter(|&c| c != '?') {
+            assert_ne!(glyph(c), placeholder, "{:?} has no glyph", c);
+        }
+    }
+
+    #[test]
+    fn unknown_characters_are_question_marks() {
+        assert_eq!(glyph('é'), glyph('?'));
+        assert_eq!(glyph('\t'), glyph('?'));
+        assert_eq!(glyph('→'), glyph('?'));
+    }
+
+    #[test]
+    fn wrap_breaks_between_words() {
+        assert_eq!(wrap("HELLO THERE WORLD", 11), vec!["HELLO THERE", "WORLD"]);
+        assert_eq!(wrap("  A   B ", 3), vec!["A B"]);
+        assert!(wrap("", 5).is_empty());
+    }
+
+    #[test]
+    fn wrap_breaks_long_words() {
+        assert_eq!(wrap("ABCDEFG HI", 3), vec!["ABC", "DEF", "G", "HI"]);
+        assert_eq!(wrap("AB", 0), vec!["A", "B"]);
+    }
+}
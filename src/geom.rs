@@ -1,8 +1,156 @@
 //! Geometry stuff
 
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
 use sdl2::rect::Rect as SdlRect;
 
-#[derive(Debug, Clone, Copy)]
+/// A point or a displacement, e.g. a position or a speed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub fn new(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+
+    pub fn dot(self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(self) -> f32 {
+        self.x.hypot(self.y)
+    }
+
+    /// Returns the vector scaled to a length of 1, or `None` for the zero vector,
+    /// which has no direction.
+    pub fn normalize(self) -> Option<Vec2> {
+        let length = self.length();
+        if length > 0.0 {
+            Some(Vec2::new(self.x / length, self.y / length))
+        } else {
+            None
+        }
+    }
+}
+
+/// One of the four directions along the axes, with up towards negative y.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// Returns the step of a single tile in this direction.
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    pub fn is_horizontal(self) -> bool {
+        match self {
+            Direction::Left | Direction::Right => true,
+            Direction::Up | Direction::Down => false,
+        }
+    }
+}
+
+/// Index of a tile in a room, which may also lie outside of it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileCoord {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TileCoord {
+    pub fn new(x: i32, y: i32) -> TileCoord {
+        TileCoord { x, y }
+    }
+
+    /// Returns the tile of `tile_size` pixels that `point` lies in. Points on the
+    /// edge between two tiles lie in the right or lower one, also left of or above
+    /// the room's origin, so e.g. -0.5 is in tile -1 rather than in tile 0.
+    pub fn containing(point: Vec2, tile_size: u32) -> TileCoord {
+        let size = tile_size as f32;
+        TileCoord::new((point.x / size).floor() as i32, (point.y / size).floor() as i32)
+    }
+
+    /// Returns the area the tile covers for tiles of `tile_size` pixels.
+    pub fn rect(self, tile_size: u32) -> Rect {
+        let size = tile_size as f32;
+        Rect::new(self.x as f32 * size, self.y as f32 * size, size, size)
+    }
+
+    /// Returns the middle of the tile for tiles of `tile_size` pixels.
+    pub fn center(self, tile_size: u32) -> Vec2 {
+        let size = tile_size as f32;
+        Vec2::new((self.x as f32 + 0.5) * size, (self.y as f32 + 0.5) * size)
+    }
+}
+
+impl Add for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, other: Vec2) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl SubAssign for Vec2 {
+    fn sub_assign(&mut self, other: Vec2) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<f32> for Vec2 {
+    type Output = Vec2;
+
+    fn mul(self, factor: f32) -> Vec2 {
+        Vec2::new(self.x * factor, self.y * factor)
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Vec2;
+
+    fn neg(self) -> Vec2 {
+        Vec2::new(-self.x, -self.y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Rect {
     x: f32,
     y: f32,
@@ -33,6 +181,64 @@ impl Rect {
         self.y + self.h
     }
 
+    /// Returns whether the two rects overlap. Rects that only touch along an edge
+    /// or at a corner don't.
+    ///
+    /// This is exact, so it's meant for geometry. For collisions between things in
+    /// the game, use `overlaps`.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.left() < other.right() && self.right() > other.left() && self.top() < other.bottom()
+            && self.bottom() > other.top()
+    }
+
+    /// Returns whether the two rects overlap by more than a tiny margin.
+    ///
+    /// Things moved against each other only end up touching up to rounding errors,
+    /// e.g. a player standing on a floor may be sunk into it by a fraction of a
+    /// pixel. Unlike `intersects`, this doesn't count that as overlapping.
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        const MARGIN: f32 = 0.001;
+        self.left() < other.right() - MARGIN && self.right() > other.left() + MARGIN
+            && self.top() < other.bottom() - MARGIN
+            && self.bottom() > other.top() + MARGIN
+    }
+
+    /// Returns the area where the two rects overlap, if they do as far as
+    /// `intersects` is concerned.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        if !self.intersects(other) {
+            return None;
+        }
+        let left = self.left().max(other.left());
+        let top = self.top().max(other.top());
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        Some(Rect::new(left, top, right - left, bottom - top))
+    }
+
+    /// Returns whether the point (`x`, `y`) lies inside the rect. Points on the left
+    /// and top edges do, and points on the right and bottom edges don't, so that
+    /// a point on the edge between two rects placed side by side is in only one.
+    pub fn contains_point(&self, x: f32, y: f32) -> bool {
+        x >= self.left() && x < self.right() && y >= self.top() && y < self.bottom()
+    }
+
+    /// Returns whether `other` lies entirely inside the rect, which includes lying
+    /// against its edges from the inside, and being the same rect.
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        other.left() >= self.left() && other.right() <= self.right() && other.top() >= self.top()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// Returns the smallest rect containing both rects.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let left = self.left().min(other.left());
+        let top = self.top().min(other.top());
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
     pub fn sdl_rect(&self) -> SdlRect {
         SdlRect::new(
             self.x.round() as i32,
@@ -42,3 +248,448 @@ impl Rect {
         )
     }
 }
+
+/// A circle, e.g. the area within some distance of a point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl Circle {
+    pub fn new(center: Vec2, radius: f32) -> Circle {
+        debug_assert!(radius > 0.0);
+        Circle { center, radius }
+    }
+
+    /// Returns whether `point` lies inside the circle. Points on the circle itself
+    /// don't.
+    pub fn contains_point(&self, point: Vec2) -> bool {
+        (point - self.center).length() < self.radius
+    }
+
+    /// Returns whether the circle overlaps `rect`, i.e. whether the point of `rect`
+    /// closest to the center lies inside the circle. Like with `Rect::intersects`,
+    /// only touching doesn't count.
+    pub fn intersects_rect(&self, rect: &Rect) -> bool {
+        let closest = Vec2::new(
+            self.center.x.max(rect.left()).min(rect.right()),
+            self.center.y.max(rect.top()).min(rect.bottom()),
+        );
+        self.contains_point(closest)
+    }
+
+    /// Returns whether the two circles overlap. Circles that only touch don't.
+    pub fn intersects(&self, other: &Circle) -> bool {
+        (other.center - self.center).length() < self.radius + other.radius
+    }
+
+    /// Returns the smallest rect containing the circle.
+    pub fn bounding_rect(&self) -> Rect {
+        let diameter = 2.0 * self.radius;
+        Rect::new(
+            self.center.x - self.radius,
+            self.center.y - self.radius,
+            diameter,
+            diameter,
+        )
+    }
+}
+
+/// Where a moving rect first hits another rect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    /// Fraction of the displacement moved before hitting, from 0 to 1
+    pub time: f32,
+    /// Direction pointing away from the side that was hit, e.g. (0, -1) when landing
+    /// on top of the other rect
+    pub normal: Vec2,
+}
+
+/// Returns when and on which side `moving` first hits `target` while it's moved by
+/// `displacement`, if it does.
+///
+/// Like `Rect::intersects`, this only counts actually overlapping: sliding along a
+/// side or past a corner of `target` isn't a hit, but moving into a side that
+/// `moving` already touches is, at time 0. Rects that already overlap hit at time
+/// 0, with the normal along which they overlap the least, whatever the displacement.
+/// When both sides of a corner are hit at the same time, the top or bottom side counts.
+pub fn sweep(moving: &Rect, displacement: Vec2, target: &Rect) -> Option<SweepHit> {
+    if moving.intersects(target) {
+        return Some(SweepHit {
+            time: 0.0,
+            normal: overlap_normal(moving, target),
+        });
+    }
+    // Times at which the rects start and stop overlapping along each axis
+    let (entry_x, exit_x) = sweep_axis(
+        (moving.left(), moving.right()),
+        displacement.x,
+        (target.left(), target.right()),
+    )?;
+    let (entry_y, exit_y) = sweep_axis(
+        (moving.top(), moving.bottom()),
+        displacement.y,
+        (target.top(), target.bottom()),
+    )?;
+    let entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    // They only overlap if the overlaps along both axes do, for more than an instant
+    if entry >= exit || !(0.0..=1.0).contains(&entry) {
+        return None;
+    }
+    let normal = if entry_x > entry_y {
+        Vec2::new(-displacement.x.signum(), 0.0)
+    } else {
+        Vec2::new(0.0, -displacement.y.signum())
+    };
+    Some(SweepHit {
+        time: entry,
+        normal,
+    })
+}
+
+/// Returns the earliest hit of `moving` moved by `displacement` against any of the
+/// `targets`, e.g. the tiles around it. Of hits at the same time, the first one wins.
+pub fn sweep_all<'a, I>(moving: &Rect, displacement: Vec2, targets: I) -> Option<SweepHit>
+where
+    I: IntoIterator<Item = &'a Rect>,
+{
+    targets
+        .into_iter()
+        .filter_map(|target| sweep(moving, displacement, target))
+        .fold(None, |earliest: Option<SweepHit>, hit| match earliest {
+            Some(earliest) if earliest.time <= hit.time => Some(earliest),
+            _ => Some(hit),
+        })
+}
+
+/// Returns the times at which the span `moving` moved by `displacement` starts and
+/// stops overlapping the span `target`, as fractions of the displacement, or `None`
+/// if it never does. Without displacement, that's always or never.
+fn sweep_axis(moving: (f32, f32), displacement: f32, target: (f32, f32)) -> Option<(f32, f32)> {
+    let (start, end) = moving;
+    let (target_start, target_end) = target;
+    if displacement == 0.0 {
+        return if start < target_end && end > target_start {
+            Some((f32::NEG_INFINITY, f32::INFINITY))
+        } else {
+            None
+        };
+    }
+    let (entry, exit) = if displacement > 0.0 {
+        (target_start - end, target_end - start)
+    } else {
+        (target_end - start, target_start - end)
+    };
+    Some((entry / displacement, exit / displacement))
+}
+
+/// Returns the direction in which `moving` has to move the least to stop
+/// overlapping `target`.
+fn overlap_normal(moving: &Rect, target: &Rect) -> Vec2 {
+    let push_left = moving.right() - target.left();
+    let push_right = target.right() - moving.left();
+    let push_up = moving.bottom() - target.top();
+    let push_down = target.bottom() - moving.top();
+    let (push_x, normal_x) = if push_left < push_right {
+        (push_left, -1.0)
+    } else {
+        (push_right, 1.0)
+    };
+    let (push_y, normal_y) = if push_up < push_down {
+        (push_up, -1.0)
+    } else {
+        (push_down, 1.0)
+    };
+    if push_x < push_y {
+        Vec2::new(normal_x, 0.0)
+    } else {
+        Vec2::new(0.0, normal_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(rect: &Rect) -> (f32, f32, f32, f32) {
+        (rect.left(), rect.top(), rect.right(), rect.bottom())
+    }
+
+    #[test]
+    fn overlapping_rects_intersect() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(5.0, 5.0, 10.0, 10.0);
+        assert!(a.intersects(&b));
+        assert!(b.intersects(&a));
+        assert_eq!(edges(&a.intersection(&b).unwrap()), (5.0, 5.0, 10.0, 10.0));
+    }
+
+    #[test]
+    fn touching_rects_dont_intersect() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let beside = Rect::new(10.0, 0.0, 10.0, 10.0);
+        let below = Rect::new(0.0, 10.0, 10.0, 10.0);
+        let corner = Rect::new(10.0, 10.0, 10.0, 10.0);
+        for other in &[beside, below, corner] {
+            assert!(!a.intersects(other));
+            assert!(a.intersection(other).is_none());
+        }
+    }
+
+    #[test]
+    fn apart_rects_dont_intersect() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(20.0, 0.0, 10.0, 10.0);
+        assert!(!a.intersects(&b));
+        assert!(a.intersection(&b).is_none());
+    }
+
+    #[test]
+    fn contained_rect_is_the_intersection() {
+        let outer = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let inner = Rect::new(2.0, 3.0, 4.0, 5.0);
+        assert_eq!(edges(&outer.intersection(&inner).unwrap()), edges(&inner));
+    }
+
+    #[test]
+    fn overlaps_ignores_rounding_errors() {
+        let floor = Rect::new(0.0, 16.0, 16.0, 16.0);
+        let sunk = Rect::new(4.0, 0.0005, 8.0, 16.0);
+        assert!(sunk.intersects(&floor));
+        assert!(!sunk.overlaps(&floor));
+        let deep = Rect::new(4.0, 1.0, 8.0, 16.0);
+        assert!(deep.overlaps(&floor));
+    }
+
+    #[test]
+    fn contains_point_includes_left_and_top_edges_only() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        assert!(rect.contains_point(0.0, 0.0));
+        assert!(rect.contains_point(5.0, 5.0));
+        assert!(rect.contains_point(9.999, 9.999));
+        assert!(!rect.contains_point(10.0, 5.0));
+        assert!(!rect.contains_point(5.0, 10.0));
+        assert!(!rect.contains_point(-0.001, 5.0));
+    }
+
+    #[test]
+    fn contains_rect_includes_edges() {
+        let rect = Rect::new(0.0, 0.0, 10.0, 10.0);
+        assert!(rect.contains_rect(&rect));
+        assert!(rect.contains_rect(&Rect::new(0.0, 0.0, 5.0, 10.0)));
+        assert!(rect.contains_rect(&Rect::new(2.0, 2.0, 2.0, 2.0)));
+        assert!(!rect.contains_rect(&Rect::new(5.0, 5.0, 10.0, 2.0)));
+        assert!(!rect.contains_rect(&Rect::new(-1.0, 0.0, 5.0, 5.0)));
+    }
+
+    #[test]
+    fn union_covers_both_rects() {
+        let a = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::new(20.0, -5.0, 5.0, 5.0);
+        let union = a.union(&b);
+        assert_eq!(edges(&union), (0.0, -5.0, 25.0, 10.0));
+        assert!(union.contains_rect(&a));
+        assert!(union.contains_rect(&b));
+        assert_eq!(edges(&a.union(&a)), edges(&a));
+    }
+
+    #[test]
+    fn head_on_sweep_hits_at_contact() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let target = Rect::new(20.0, 0.0, 10.0, 10.0);
+        let hit = sweep(&moving, Vec2::new(20.0, 0.0), &target).unwrap();
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Vec2::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn falling_sweep_lands_on_top() {
+        let moving = Rect::new(0.0, 0.0, 8.0, 20.0);
+        let floor = Rect::new(0.0, 40.0, 16.0, 16.0);
+        let hit = sweep(&moving, Vec2::new(0.0, 40.0), &floor).unwrap();
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Vec2::new(0.0, -1.0));
+    }
+
+    #[test]
+    fn grazing_sweep_misses() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let target = Rect::new(20.0, 10.0, 10.0, 10.0);
+        assert!(sweep(&moving, Vec2::new(40.0, 0.0), &target).is_none());
+    }
+
+    #[test]
+    fn overlapping_sweep_hits_at_once() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let target = Rect::new(8.0, 0.0, 10.0, 10.0);
+        let hit = sweep(&moving, Vec2::new(0.0, 5.0), &target).unwrap();
+        assert_eq!(hit.time, 0.0);
+        assert_eq!(hit.normal, Vec2::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn still_sweep_only_hits_when_overlapping() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let apart = Rect::new(20.0, 0.0, 10.0, 10.0);
+        let touching = Rect::new(10.0, 0.0, 10.0, 10.0);
+        let overlapping = Rect::new(5.0, 0.0, 10.0, 10.0);
+        assert!(sweep(&moving, Vec2::default(), &apart).is_none());
+        assert!(sweep(&moving, Vec2::default(), &touching).is_none());
+        assert_eq!(sweep(&moving, Vec2::default(), &overlapping).unwrap().time, 0.0);
+    }
+
+    #[test]
+    fn short_or_wrong_way_sweep_misses() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let target = Rect::new(20.0, 0.0, 10.0, 10.0);
+        assert!(sweep(&moving, Vec2::new(5.0, 0.0), &target).is_none());
+        assert!(sweep(&moving, Vec2::new(-20.0, 0.0), &target).is_none());
+        assert!(sweep(&moving, Vec2::new(20.0, 30.0), &target).is_none());
+    }
+
+    #[test]
+    fn touching_sweep_hits_when_moving_in() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let target = Rect::new(10.0, 0.0, 10.0, 10.0);
+        let hit = sweep(&moving, Vec2::new(5.0, 0.0), &target).unwrap();
+        assert_eq!(hit.time, 0.0);
+        assert_eq!(hit.normal, Vec2::new(-1.0, 0.0));
+        assert!(sweep(&moving, Vec2::new(0.0, 5.0), &target).is_none());
+    }
+
+    #[test]
+    fn corner_sweep_hits_top_or_bottom() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let target = Rect::new(20.0, 20.0, 10.0, 10.0);
+        let hit = sweep(&moving, Vec2::new(20.0, 20.0), &target).unwrap();
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Vec2::new(0.0, -1.0));
+    }
+
+    #[test]
+    fn sweep_all_finds_earliest_hit() {
+        let moving = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let targets = [
+            Rect::new(50.0, 0.0, 10.0, 10.0),
+            Rect::new(20.0, 0.0, 10.0, 10.0),
+            Rect::new(0.0, 50.0, 10.0, 10.0),
+        ];
+        let hit = sweep_all(&moving, Vec2::new(80.0, 0.0), &targets).unwrap();
+        assert_eq!(hit.time, 0.125);
+        assert!(sweep_all(&moving, Vec2::new(0.0, -10.0), &targets).is_none());
+    }
+
+    fn tile_containing(x: f32, y: f32) -> TileCoord {
+        TileCoord::containing(Vec2::new(x, y), 16)
+    }
+
+    #[test]
+    fn negative_coordinates_floor_to_the_tile_before_zero() {
+        assert_eq!(tile_containing(-0.5, -0.5), TileCoord::new(-1, -1));
+        assert_eq!(tile_containing(-16.0, 0.0), TileCoord::new(-1, 0));
+        assert_eq!(tile_containing(-16.01, 0.0), TileCoord::new(-2, 0));
+        assert_eq!(tile_containing(0.0, -16.0), TileCoord::new(0, -1));
+    }
+
+    #[test]
+    fn tile_edge_lies_in_the_next_tile() {
+        assert_eq!(tile_containing(0.0, 0.0), TileCoord::new(0, 0));
+        assert_eq!(tile_containing(15.99, 15.99), TileCoord::new(0, 0));
+        assert_eq!(tile_containing(16.0, 16.0), TileCoord::new(1, 1));
+        assert_eq!(tile_containing(16.0, 8.0), TileCoord::new(1, 0));
+    }
+
+    #[test]
+    fn tile_rect_and_center_match_its_area() {
+        let tile = TileCoord::new(-1, 2);
+        assert_eq!(edges(&tile.rect(16)), (-16.0, 32.0, 0.0, 48.0));
+        assert_eq!(tile.center(16), Vec2::new(-8.0, 40.0));
+        assert_eq!(TileCoord::containing(tile.center(16), 16), tile);
+    }
+
+    #[test]
+    fn circle_contains_points_strictly_inside() {
+        let circle = Circle::new(Vec2::new(10.0, 10.0), 5.0);
+        assert!(circle.contains_point(Vec2::new(10.0, 10.0)));
+        assert!(circle.contains_point(Vec2::new(13.0, 13.0)));
+        assert!(!circle.contains_point(Vec2::new(15.0, 10.0)));
+        assert!(!circle.contains_point(Vec2::new(14.0, 14.0)));
+    }
+
+    #[test]
+    fn circle_inside_rect_intersects_it() {
+        let circle = Circle::new(Vec2::new(8.0, 8.0), 2.0);
+        assert!(circle.intersects_rect(&Rect::new(0.0, 0.0, 16.0, 16.0)));
+        let big = Circle::new(Vec2::new(8.0, 8.0), 100.0);
+        assert!(big.intersects_rect(&Rect::new(0.0, 0.0, 16.0, 16.0)));
+    }
+
+    #[test]
+    fn circle_touching_rect_side_doesnt_intersect() {
+        let rect = Rect::new(0.0, 0.0, 16.0, 16.0);
+        assert!(!Circle::new(Vec2::new(20.0, 8.0), 4.0).intersects_rect(&rect));
+        assert!(Circle::new(Vec2::new(19.9, 8.0), 4.0).intersects_rect(&rect));
+        assert!(!Circle::new(Vec2::new(8.0, -4.0), 4.0).intersects_rect(&rect));
+    }
+
+    #[test]
+    fn circle_near_rect_corner_intersects_only_within_radius() {
+        let rect = Rect::new(0.0, 0.0, 16.0, 16.0);
+        // 3-4-5 triangle from the bottom right corner
+        assert!(!Circle::new(Vec2::new(19.0, 20.0), 5.0).intersects_rect(&rect));
+        assert!(Circle::new(Vec2::new(19.0, 20.0), 5.1).intersects_rect(&rect));
+        // Within the bounding box on both axes but past the corner
+        assert!(!Circle::new(Vec2::new(20.0, 20.0), 5.0).intersects_rect(&rect));
+    }
+
+    #[test]
+    fn touching_circles_dont_intersect() {
+        let circle = Circle::new(Vec2::new(0.0, 0.0), 3.0);
+        assert!(!circle.intersects(&Circle::new(Vec2::new(6.0, 8.0), 7.0)));
+        assert!(circle.intersects(&Circle::new(Vec2::new(6.0, 8.0), 7.5)));
+        assert!(circle.intersects(&Circle::new(Vec2::new(1.0, 0.0), 1.0)));
+    }
+
+    #[test]
+    fn circle_bounding_rect_touches_circle() {
+        let circle = Circle::new(Vec2::new(10.0, 20.0), 4.0);
+        assert_eq!(edges(&circle.bounding_rect()), (6.0, 16.0, 14.0, 24.0));
+    }
+
+    const DIRECTIONS: [Direction; 4] =
+        [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    #[test]
+    fn direction_deltas_point_along_screen_axes() {
+        assert_eq!(Direction::Up.delta(), (0, -1));
+        assert_eq!(Direction::Down.delta(), (0, 1));
+        assert_eq!(Direction::Left.delta(), (-1, 0));
+        assert_eq!(Direction::Right.delta(), (1, 0));
+    }
+
+    #[test]
+    fn opposite_direction_negates_delta() {
+        for &direction in &DIRECTIONS {
+            let (dx, dy) = direction.delta();
+            assert_eq!(direction.opposite().delta(), (-dx, -dy));
+            assert_eq!(direction.opposite().opposite(), direction);
+            assert_eq!(direction.opposite().is_horizontal(), direction.is_horizontal());
+            assert_eq!(direction.is_horizontal(), dy == 0);
+        }
+    }
+
+    #[test]
+    fn direction_serializes_to_its_name() {
+        for &(direction, name) in &[
+            (Direction::Up, "\"Up\""),
+            (Direction::Down, "\"Down\""),
+            (Direction::Left, "\"Left\""),
+            (Direction::Right, "\"Right\""),
+        ] {
+            assert_eq!(::serde_json::to_string(&direction).unwrap(), name);
+            assert_eq!(::serde_json::from_str::<Direction>(name).unwrap(), direction);
+        }
+    }
+}
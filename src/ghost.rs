@@ -0,0 +1,69 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use failure::{err_msg, Error};
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, RenderTarget};
+
+use camera::Camera;
+use geom::Rect;
+use model::interpolate;
+
+/// The path the player took through a room in a completed run, one rect per tick.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Ghost {
+    pub rects: Vec<Rect>,
+}
+
+impl Ghost {
+    /// Returns the path of the file storing the best run of the room at `room_path`.
+    pub fn path_for_room(room_path: &str) -> PathBuf {
+        Path::new(room_path).with_extension("ghost.json")
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        let writer = BufWriter::new(file);
+        ::serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Ghost, Error> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let ghost = ::serde_json::from_reader(reader)?;
+        Ok(ghost)
+    }
+
+    /// Returns the number of ticks the run took.
+    pub fn ticks(&self) -> usize {
+        self.rects.len()
+    }
+
+    /// Draws the ghost `progress` of the way from where it was after `ticks - 1` ticks
+    /// of its run to where it was after `ticks` ticks, like the player is drawn.
+    /// Nothing is drawn once the run has ended.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        ticks: usize,
+        progress: f32,
+    ) -> Result<(), Error> {
+        let rect_after = |ticks: usize| ticks.checked_sub(1).and_then(|i| self.rects.get(i));
+        let new = match rect_after(ticks) {
+            Some(rect) => rect,
+            None => return Ok(()),
+        };
+        let old = rect_after(ticks - 1).unwrap_or(new);
+        let rect = Rect::new(
+            interpolate(old.left(), new.left(), progress),
+            interpolate(old.top(), new.top(), progress),
+            new.right() - new.left(),
+            new.bottom() - new.top(),
+        );
+        canvas.set_draw_color(Color::RGBA(0xff, 0xff, 0xff, 0x50));
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)
+    }
+}
@@ -0,0 +1,247 @@
+//! Textures kept between frames
+
+use std::str::FromStr;
+
+use failure::{err_msg, Error};
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Canvas, RenderTarget, Texture, TextureCreator};
+
+use camera::Camera;
+use minimap::Minimap;
+use room::Room;
+use sprite::SpriteSheet;
+use theme::Theme;
+use tileset::Tileset;
+
+/// How textures are filtered when they're drawn at a different size, which
+/// includes scaling everything up to the window.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Filter {
+    /// Repeat pixels, keeping them sharp
+    Nearest,
+    /// Blend neighbouring pixels, which blurs them
+    Linear,
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Filter, Error> {
+        match name.to_lowercase().as_str() {
+            "nearest" => Ok(Filter::Nearest),
+            "linear" => Ok(Filter::Linear),
+            _ => bail!("Unknown filter {:?}; use nearest or linear", name),
+        }
+    }
+}
+
+impl Filter {
+    /// Returns the value of SDL's render scale quality hint for this filter. SDL
+    /// applies the hint to textures when they're created, so it has to be set
+    /// before creating the canvas and any textures.
+    pub fn scale_quality(self) -> &'static str {
+        match self {
+            Filter::Nearest => "nearest",
+            Filter::Linear => "linear",
+        }
+    }
+}
+
+/// Everything besides the canvas that rendering needs: the art loaded at startup
+/// and textures kept between frames.
+pub struct RenderContext<'r, C: 'r> {
+    pub tileset: Option<Tileset<'r>>,
+    pub sprites: Option<SpriteSheet<'r>>,
+    pub theme: Theme,
+    pub room_cache: RoomCache<'r, C>,
+    pub minimap: Minimap<'r, C>,
+}
+
+impl<'r, C> RenderContext<'r, C> {
+    pub fn new(
+        texture_creator: &'r TextureCreator<C>,
+        tileset: Option<Tileset<'r>>,
+        sprites: Option<SpriteSheet<'r>>,
+        theme: Theme,
+    ) -> RenderContext<'r, C> {
+        RenderContext {
+            tileset,
+            sprites,
+            theme,
+            room_cache: RoomCache::new(texture_creator),
+            minimap: Minimap::new(texture_creator),
+        }
+    }
+}
+
+/// The tiles of the whole room drawn into a texture, so that a frame only has to
+/// copy the part the camera sees instead of drawing every tile.
+pub struct RoomCache<'r, C: 'r> {
+    texture_creator: &'r TextureCreator<C>,
+    texture: Option<Texture<'r>>,
+    /// Generation of the room the texture was drawn from, see `Room::generation`
+    generation: Option<usize>,
+    /// Whether the canvas can draw into textures at all; cleared once that fails
+    supported: bool,
+}
+
+impl<'r, C> RoomCache<'r, C> {
+    pub fn new(texture_creator: &'r TextureCreator<C>) -> RoomCache<'r, C> {
+        RoomCache {
+            texture_creator,
+            texture: None,
+            generation: None,
+            supported: true,
+        }
+    }
+
+    /// Returns the texture with the tiles of `room`, redrawing it first if the
+    /// room changed since it was last drawn, or `None` if the tiles have to be
+    /// drawn directly because `canvas` can't draw into textures.
+    pub fn update<T: RenderTarget<Context = C>>(
+        &mut self,
+        canvas: &mut Canvas<T>,
+        room: &Room,
+        tileset: Option<&Tileset>,
+        theme: &Theme,
+    ) -> Option<&Texture<'r>> {
+        if !self.supported || !canvas.render_target_supported() {
+            return None;
+        }
+        let (width, height) = (room.pixel_width() as u32, room.pixel_height() as u32);
+        let resized = match self.texture {
+            Some(ref texture) => {
+                let query = texture.query();
+                (query.width, query.height) != (width, height)
+            }
+            None => true,
+        };
+        if resized {
+            match self.texture_creator.create_texture_target(None, width, height) {
+                Ok(texture) => self.texture = Some(texture),
+                Err(e) => {
+                    warn!("Couldn't create room texture: {}; drawing tiles directly", e);
+                    self.supported = false;
+                    return None;
+                }
+            }
+            self.generation = None;
+        }
+        if self.generation != Some(room.generation()) {
+            let texture = self.texture.as_mut()?;
+            let camera = Camera::following(0.0, 0.0, width, height, room);
+            let mut result = Ok(());
+            canvas
+                .with_texture_canvas(texture, |canvas| {
+                    canvas.set_draw_color(theme.empty());
+                    canvas.clear();
+                    result = room.render_tiles(canvas, &camera, tileset, theme);
+                })
+                .map_err(Error::from)
+                .and(result)
+                .map_err(|e| {
+                    warn!("Couldn't draw room texture: {}; drawing tiles directly", e);
+                    self.supported = false;
+                })
+                .ok()?;
+            self.generation = Some(room.generation());
+        }
+        self.texture.as_ref()
+    }
+}
+
+/// The last frame drawn, kept in a texture so it can still be shown after the
+/// model has moved on, e.g. while sliding out of view when changing rooms.
+pub struct Frames<'r, C: 'r> {
+    texture_creator: &'r TextureCreator<C>,
+    current: Option<Texture<'r>>,
+    previous: Option<Texture<'r>>,
+    /// Whether the canvas can draw into textures at all; cleared once that fails
+    supported: bool,
+}
+
+impl<'r, C> Frames<'r, C> {
+    pub fn new(texture_creator: &'r TextureCreator<C>) -> Frames<'r, C> {
+        Frames {
+            texture_creator,
+            current: None,
+            previous: None,
+            supported: true,
+        }
+    }
+
+    /// Keeps the last frame drawn around as the previous frame. Returns whether
+    /// there was one.
+    pub fn keep_current(&mut self) -> bool {
+        self.previous = self.current.take();
+        self.previous.is_some()
+    }
+
+    /// Draws a frame with `draw` and puts it on `canvas`, leaving the canvas set up
+    /// the way `draw` left it.
+    ///
+    /// With `slide`, the previous frame and the new one are put at the offsets
+    /// it gives for them instead. Without render target support, `draw` draws
+    /// straight to the canvas, and there is never a previous frame.
+    pub fn render<T, F>(
+        &mut self,
+        canvas: &mut Canvas<T>,
+        slide: Option<((i32, i32), (i32, i32))>,
+        draw: F,
+    ) -> Result<(), Error>
+    where
+        T: RenderTarget<Context = C>,
+        F: FnOnce(&mut Canvas<T>) -> Result<(), Error>,
+    {
+        if !self.supported || !canvas.render_target_supported() {
+            self.previous = None;
+            return draw(canvas);
+        }
+        let (width, height) = canvas.output_size().map_err(err_msg)?;
+        let resized = match self.current {
+            Some(ref texture) => {
+                let query = texture.query();
+                (query.width, query.height) != (width, height)
+            }
+            None => true,
+        };
+        if resized {
+            match self.texture_creator.create_texture_target(None, width, height) {
+                Ok(texture) => self.current = Some(texture),
+                Err(e) => {
+                    warn!("Couldn't create frame texture: {}; drawing frames directly", e);
+                    self.supported = false;
+                    self.previous = None;
+                    return draw(canvas);
+                }
+            }
+        }
+        let texture = match self.current {
+            Some(ref mut texture) => texture,
+            None => return draw(canvas),
+        };
+        let mut result = Ok(());
+        let mut setup = (canvas.scale(), canvas.viewport());
+        canvas.with_texture_canvas(texture, |canvas| {
+            result = draw(canvas);
+            setup = (canvas.scale(), canvas.viewport());
+        })?;
+        result?;
+
+        // Copy the frame to the whole output, then set up the canvas like `draw` did
+        canvas.set_scale(1.0, 1.0).map_err(err_msg)?;
+        canvas.set_viewport(None);
+        let (old_offset, new_offset) = slide.unwrap_or(((0, 0), (0, 0)));
+        if let (Some(_), Some(previous)) = (slide, self.previous.as_ref()) {
+            let query = previous.query();
+            let rect = SdlRect::new(old_offset.0, old_offset.1, query.width, query.height);
+            canvas.copy(previous, None, rect).map_err(err_msg)?;
+        }
+        let rect = SdlRect::new(new_offset.0, new_offset.1, width, height);
+        canvas.copy(texture, None, rect).map_err(err_msg)?;
+        let ((scale_x, scale_y), viewport) = setup;
+        canvas.set_scale(scale_x, scale_y).map_err(err_msg)?;
+        canvas.set_viewport(viewport);
+        Ok(())
+    }
+}
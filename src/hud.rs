@@ -0,0 +1,112 @@
+//! Status bar showing how the run through the current room is going
+
+use failure::{err_msg, Error};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use font;
+use model::{Model, Player};
+use theme::Theme;
+
+/// Space around the contents of the bar, in logical pixels
+const PADDING: u32 = 2;
+/// Height of the bar in logical pixels
+pub const HEIGHT: u32 = font::GLYPH_HEIGHT + 2 * PADDING;
+/// Space between the items in the bar
+const GAP: i32 = 8;
+
+/// Returns where the bar goes for a view drawn at `viewport`: in the letterbox bar
+/// above the view if that fits it, and over the top of the view otherwise.
+///
+/// Both are in logical pixels relative to the whole output, like viewports are.
+pub fn bar_rect(viewport: SdlRect) -> SdlRect {
+    let top = if viewport.y() >= HEIGHT as i32 {
+        viewport.y() - HEIGHT as i32
+    } else {
+        viewport.y()
+    };
+    SdlRect::new(viewport.x(), top, viewport.width(), HEIGHT)
+}
+
+/// Draws the coins collected, the time spent in the room, the player's health
+/// and the lives left, for a canvas set up to draw the model's view.
+pub fn render_hud<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    model: &Model,
+    theme: &Theme,
+) -> Result<(), Error> {
+    let viewport = canvas.viewport();
+    let bar = bar_rect(viewport);
+    // The view's viewport clips drawing, so draw relative to the whole output
+    canvas.set_viewport(None);
+    let result = render_bar(canvas, model, theme, bar);
+    canvas.set_viewport(viewport);
+    result
+}
+
+fn render_bar<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    model: &Model,
+    theme: &Theme,
+    bar: SdlRect,
+) -> Result<(), Error> {
+    let icon_size = font::GLYPH_HEIGHT;
+    let top = bar.y() + PADDING as i32;
+    let mut x = bar.x() + PADDING as i32;
+    canvas.set_draw_color(Color::RGBA(0x00, 0x00, 0x00, 0xa0));
+    canvas.fill_rect(bar).map_err(err_msg)?;
+
+    canvas.set_draw_color(Color::RGB(0xff, 0xd0, 0x00));
+    canvas
+        .fill_rect(SdlRect::new(x, top, icon_size, icon_size))
+        .map_err(err_msg)?;
+    x += icon_size as i32 + 2;
+    let coins = model.coins().to_string();
+    canvas.set_draw_color(theme.hud_text());
+    font::render_text(canvas, &coins, x, top, 1)?;
+    x += font::text_width(&coins, 1) as i32 + GAP;
+
+    let elapsed = model.elapsed_string();
+    font::render_text(canvas, &elapsed, x, top, 1)?;
+    x += font::text_width(&elapsed, 1) as i32 + GAP;
+
+    for index in 0..Player::MAX_HEALTH {
+        let heart = SdlRect::new(x, top, icon_size, icon_size);
+        canvas.set_draw_color(Color::RGB(0xd0, 0x20, 0x20));
+        if index < model.health() {
+            canvas.fill_rect(heart).map_err(err_msg)?;
+        } else {
+            canvas.draw_rect(heart).map_err(err_msg)?;
+        }
+        x += icon_size as i32 + 1;
+    }
+
+    if let Some(lives) = model.lives() {
+        let lives = format!("LIVES {}", lives);
+        canvas.set_draw_color(theme.hud_text());
+        font::render_text(canvas, &lives, x - 1 + GAP, top, 1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_goes_above_view_if_it_fits() {
+        let bar = bar_rect(SdlRect::new(10, 40, 320, 240));
+        assert_eq!((bar.x(), bar.y()), (10, 40 - HEIGHT as i32));
+        assert_eq!((bar.width(), bar.height()), (320, HEIGHT));
+        let bar = bar_rect(SdlRect::new(0, HEIGHT as i32, 320, 240));
+        assert_eq!(bar.y(), 0);
+    }
+
+    #[test]
+    fn bar_covers_top_of_view_without_room_above() {
+        let bar = bar_rect(SdlRect::new(10, HEIGHT as i32 - 1, 320, 240));
+        assert_eq!((bar.x(), bar.y()), (10, HEIGHT as i32 - 1));
+        assert_eq!(bar.width(), 320);
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use failure::Error;
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::Keycode;
+
+/// Something the player can do by pressing a bound key.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Crouch,
+    Run,
+    Pause,
+    Reset,
+    FlipGravity,
+    LookUp,
+    Grapple,
+    Shoot,
+    SecondMoveLeft,
+    SecondMoveRight,
+    SecondJump,
+    SecondCrouch,
+}
+
+impl Action {
+    /// All actions, in the order they are listed in a bindings file.
+    pub const ALL: [Action; 15] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Crouch,
+        Action::Run,
+        Action::Pause,
+        Action::Reset,
+        Action::FlipGravity,
+        Action::LookUp,
+        Action::Grapple,
+        Action::Shoot,
+        Action::SecondMoveLeft,
+        Action::SecondMoveRight,
+        Action::SecondJump,
+        Action::SecondCrouch,
+    ];
+
+    /// Returns the action the second player performs for this action, if it's one
+    /// of theirs.
+    pub fn for_second_player(self) -> Option<Action> {
+        match self {
+            Action::SecondMoveLeft => Some(Action::MoveLeft),
+            Action::SecondMoveRight => Some(Action::MoveRight),
+            Action::SecondJump => Some(Action::Jump),
+            Action::SecondCrouch => Some(Action::Crouch),
+            _ => None,
+        }
+    }
+}
+
+/// An action starting or stopping, e.g. because its key was pressed or released.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum InputEvent {
+    Pressed(Action),
+    Released(Action),
+}
+
+/// Maps keys to the actions they perform in the game.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    actions: HashMap<Keycode, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let mut bindings = KeyBindings {
+            actions: HashMap::new(),
+        };
+        bindings.bind(Action::MoveLeft, &[Keycode::Left]);
+        bindings.bind(Action::MoveRight, &[Keycode::Right]);
+        bindings.bind(Action::Jump, &[Keycode::Up]);
+        bindings.bind(Action::Crouch, &[Keycode::Down]);
+        bindings.bind(Action::Run, &[Keycode::LShift, Keycode::Z]);
+        bindings.bind(Action::Pause, &[Keycode::P]);
+        bindings.bind(Action::Reset, &[Keycode::R, Keycode::Backspace]);
+        bindings.bind(Action::FlipGravity, &[Keycode::G]);
+        bindings.bind(Action::LookUp, &[Keycode::L]);
+        bindings.bind(Action::Grapple, &[Keycode::C]);
+        bindings.bind(Action::Shoot, &[Keycode::X]);
+        bindings.bind(Action::SecondMoveLeft, &[Keycode::A]);
+        bindings.bind(Action::SecondMoveRight, &[Keycode::D]);
+        bindings.bind(Action::SecondJump, &[Keycode::W]);
+        bindings.bind(Action::SecondCrouch, &[Keycode::S]);
+        bindings
+    }
+}
+
+impl KeyBindings {
+    /// Loads bindings from a JSON file mapping action names to lists of SDL key names,
+    /// e.g. `{"Jump": ["W", "Space"]}`.
+    ///
+    /// Actions missing from the file keep their default keys.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyBindings, Error> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let key_names: HashMap<Action, Vec<String>> = ::serde_json::from_reader(reader)?;
+        let mut bindings = KeyBindings::default();
+        for action in Action::ALL.iter() {
+            if let Some(names) = key_names.get(action) {
+                let mut keys = Vec::new();
+                for name in names {
+                    match Keycode::from_name(name) {
+                        Some(keycode) => keys.push(keycode),
+                        None => bail!("Unknown key {:?} bound to {:?}", name, action),
+                    }
+                }
+                bindings.rebind(*action, &keys);
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Binds `keys` to `action`, warning about keys that were bound to another action.
+    fn bind(&mut self, action: Action, keys: &[Keycode]) {
+        for &keycode in keys {
+            if let Some(previous) = self.actions.insert(keycode, action) {
+                if previous != action {
+                    warn!(
+                        "Key {} is bound to both {:?} and {:?}; using it for {:?}",
+                        keycode.name(),
+                        previous,
+                        action,
+                        action
+                    );
+                }
+            }
+        }
+    }
+
+    /// Binds `keys` to `action` in place of the keys it was bound to before.
+    fn rebind(&mut self, action: Action, keys: &[Keycode]) {
+        self.actions.retain(|_, bound| *bound != action);
+        self.bind(action, keys);
+    }
+
+    /// Returns the action bound to `keycode`, if any.
+    pub fn action(&self, keycode: Keycode) -> Option<Action> {
+        self.actions.get(&keycode).cloned()
+    }
+}
+
+/// Translates game controller events into actions.
+///
+/// The left stick is treated like the d-pad. It has to be pushed further to start
+/// moving than it has to be let go to stop, so that holding it near the threshold
+/// doesn't keep starting and stopping the movement.
+#[derive(Clone, Debug, Default)]
+pub struct ControllerInput {
+    /// Action performed by holding the left stick sideways
+    stick_x: Option<Action>,
+    /// Action performed by holding the left stick up or down
+    stick_y: Option<Action>,
+}
+
+impl ControllerInput {
+    /// How far the stick must be pushed to start an action
+    const PRESS_THRESHOLD: i16 = 16_000;
+    /// How far the stick must be let go to stop an action
+    const RELEASE_THRESHOLD: i16 = 10_000;
+
+    /// Returns the action bound to a controller button, if any.
+    pub fn button_action(button: Button) -> Option<Action> {
+        match button {
+            Button::DPadLeft => Some(Action::MoveLeft),
+            Button::DPadRight => Some(Action::MoveRight),
+            Button::DPadDown => Some(Action::Crouch),
+            Button::A => Some(Action::Jump),
+            Button::X => Some(Action::Run),
+            Button::B => Some(Action::Grapple),
+            Button::Y => Some(Action::Shoot),
+            Button::Start => Some(Action::Pause),
+            _ => None,
+        }
+    }
+
+    /// Returns the input events caused by moving a controller axis to `value`.
+    pub fn axis_moved(&mut self, axis: Axis, value: i16) -> Vec<InputEvent> {
+        let (held, negative, positive) = match axis {
+            Axis::LeftX => (
+                &mut self.stick_x,
+                Some(Action::MoveLeft),
+                Some(Action::MoveRight),
+            ),
+            // Pushing the stick up doesn't do anything
+            Axis::LeftY => (&mut self.stick_y, None, Some(Action::Crouch)),
+            _ => return Vec::new(),
+        };
+        let value = i32::from(value);
+        let mut events = Vec::new();
+        let still_held = match *held {
+            Some(action) if Some(action) == negative => value < -i32::from(Self::RELEASE_THRESHOLD),
+            Some(_) => value > i32::from(Self::RELEASE_THRESHOLD),
+            None => false,
+        };
+        if !still_held {
+            if let Some(action) = held.take() {
+                events.push(InputEvent::Released(action));
+            }
+            let pushed = if value < -i32::from(Self::PRESS_THRESHOLD) {
+                negative
+            } else if value > i32::from(Self::PRESS_THRESHOLD) {
+                positive
+            } else {
+                None
+            };
+            if let Some(action) = pushed {
+                events.push(InputEvent::Pressed(action));
+                *held = Some(action);
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use level::Level;
+    use model::{Model, PlayerHorizState, PlayerVertState};
+    use room::Room;
+
+    /// Returns the default bindings with the first player moved to WASD, and the
+    /// second player out of their way.
+    fn wasd_bindings() -> KeyBindings {
+        let mut bindings = KeyBindings::default();
+        bindings.rebind(Action::SecondMoveLeft, &[Keycode::J]);
+        bindings.rebind(Action::SecondMoveRight, &[Keycode::K]);
+        bindings.rebind(Action::SecondJump, &[Keycode::I]);
+        bindings.rebind(Action::SecondCrouch, &[Keycode::M]);
+        bindings.rebind(Action::MoveLeft, &[Keycode::A]);
+        bindings.rebind(Action::MoveRight, &[Keycode::D]);
+        bindings.rebind(Action::Jump, &[Keycode::W]);
+        bindings.rebind(Action::Crouch, &[Keycode::S]);
+        bindings
+    }
+
+    /// Feeds a key press or release through `bindings` into `model`, like the game does.
+    fn key(model: &mut Model, bindings: &KeyBindings, keycode: Keycode, pressed: bool) {
+        let action = bindings.action(keycode).expect("key isn't bound");
+        model.handle_input(if pressed {
+            InputEvent::Pressed(action)
+        } else {
+            InputEvent::Released(action)
+        });
+    }
+
+    fn run(model: &mut Model, millis: u64) {
+        for _ in 0..millis / 10 {
+            model.update(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn rebinding_replaces_the_old_keys() {
+        let bindings = wasd_bindings();
+        assert_eq!(bindings.action(Keycode::A), Some(Action::MoveLeft));
+        assert_eq!(bindings.action(Keycode::W), Some(Action::Jump));
+        assert_eq!(bindings.action(Keycode::Left), None);
+        assert_eq!(bindings.action(Keycode::Up), None);
+        assert_eq!(bindings.action(Keycode::LShift), Some(Action::Run));
+    }
+
+    #[test]
+    fn wasd_bindings_move_the_player() {
+        let bindings = wasd_bindings();
+        let path = "test.json";
+        let mut model = Model::new(Level::with_room(path, Room::new(40, 20, 16)), path, 150);
+        run(&mut model, 100);
+        let start = model.player().pos();
+
+        key(&mut model, &bindings, Keycode::D, true);
+        run(&mut model, 200);
+        assert_eq!(model.player().horiz_state(), PlayerHorizState::MovingRight);
+        assert!(model.player().pos().x > start.x);
+        key(&mut model, &bindings, Keycode::W, true);
+        run(&mut model, 100);
+        assert_ne!(model.player().vert_state(), PlayerVertState::Standing);
+        assert!(model.player().pos().y < start.y);
+        key(&mut model, &bindings, Keycode::W, false);
+        key(&mut model, &bindings, Keycode::D, false);
+        run(&mut model, 1000);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+
+        let right = model.player().pos().x;
+        key(&mut model, &bindings, Keycode::A, true);
+        run(&mut model, 200);
+        assert_eq!(model.player().horiz_state(), PlayerHorizState::MovingLeft);
+        assert!(model.player().pos().x < right);
+    }
+
+    #[test]
+    fn controller_buttons_map_to_actions() {
+        assert_eq!(ControllerInput::button_action(Button::A), Some(Action::Jump));
+        assert_eq!(ControllerInput::button_action(Button::Start), Some(Action::Pause));
+        assert_eq!(ControllerInput::button_action(Button::DPadLeft), Some(Action::MoveLeft));
+        assert_eq!(ControllerInput::button_action(Button::Guide), None);
+    }
+
+    #[test]
+    fn stick_has_to_be_pushed_past_press_threshold() {
+        let mut input = ControllerInput::default();
+        assert_eq!(input.axis_moved(Axis::LeftX, 12_000), vec![]);
+        assert_eq!(input.axis_moved(Axis::LeftX, 16_000), vec![]);
+        assert_eq!(
+            input.axis_moved(Axis::LeftX, 16_001),
+            vec![InputEvent::Pressed(Action::MoveRight)]
+        );
+        assert_eq!(input.axis_moved(Axis::LeftX, 32_767), vec![]);
+    }
+
+    #[test]
+    fn stick_near_threshold_doesnt_flicker() {
+        let mut input = ControllerInput::default();
+        assert_eq!(
+            input.axis_moved(Axis::LeftX, -20_000),
+            vec![InputEvent::Pressed(Action::MoveLeft)]
+        );
+        // Wobbling between the thresholds keeps holding the action
+        for &value in &[-15_000, -16_500, -10_001, -15_999, -12_000] {
+            assert_eq!(input.axis_moved(Axis::LeftX, value), vec![], "at {}", value);
+        }
+        assert_eq!(
+            input.axis_moved(Axis::LeftX, -10_000),
+            vec![InputEvent::Released(Action::MoveLeft)]
+        );
+        // And once released, it takes a full push to start again
+        for &value in &[-10_001, -15_000, -16_000] {
+            assert_eq!(input.axis_moved(Axis::LeftX, value), vec![], "at {}", value);
+        }
+    }
+
+    #[test]
+    fn flipping_stick_releases_before_pressing() {
+        let mut input = ControllerInput::default();
+        input.axis_moved(Axis::LeftX, -30_000);
+        assert_eq!(
+            input.axis_moved(Axis::LeftX, 30_000),
+            vec![
+                InputEvent::Released(Action::MoveLeft),
+                InputEvent::Pressed(Action::MoveRight),
+            ]
+        );
+        assert_eq!(
+            input.axis_moved(Axis::LeftX, 0),
+            vec![InputEvent::Released(Action::MoveRight)]
+        );
+    }
+
+    #[test]
+    fn stick_only_crouches_downwards() {
+        let mut input = ControllerInput::default();
+        assert_eq!(input.axis_moved(Axis::LeftY, -30_000), vec![]);
+        assert_eq!(
+            input.axis_moved(Axis::LeftY, 30_000),
+            vec![InputEvent::Pressed(Action::Crouch)]
+        );
+        // Moving the other axis or another stick doesn't release it
+        assert_eq!(input.axis_moved(Axis::LeftX, 0), vec![]);
+        assert_eq!(input.axis_moved(Axis::RightY, 0), vec![]);
+        assert_eq!(
+            input.axis_moved(Axis::LeftY, 5_000),
+            vec![InputEvent::Released(Action::Crouch)]
+        );
+    }
+}
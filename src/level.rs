@@ -0,0 +1,61 @@
+//! Sets of rooms connected through their exits
+
+use std::collections::HashMap;
+
+use failure::Error;
+
+use room::Room;
+
+/// All rooms that can be reached from a starting room, keyed by their file path.
+pub struct Level {
+    rooms: HashMap<String, Room>,
+}
+
+impl Level {
+    /// Creates a level containing only `room`, stored under `path`.
+    pub fn with_room(path: &str, room: Room) -> Level {
+        let mut rooms = HashMap::new();
+        rooms.insert(path.to_string(), room);
+        Level { rooms }
+    }
+
+    /// Loads the room at `path` and every room reachable through its exits.
+    ///
+    /// Exits leading to rooms that can't be loaded are removed with a warning,
+    /// so every exit in the level is guaranteed to lead somewhere.
+    pub fn load(path: &str) -> Result<Level, Error> {
+        let mut level = Level::with_room(path, Room::load(path)?);
+        let mut unvisited = vec![path.to_string()];
+        while let Some(path) = unvisited.pop() {
+            let exits = level.rooms[&path].exits().to_vec();
+            for exit in exits {
+                if level.rooms.contains_key(&exit.room) {
+                    continue;
+                }
+                match Room::load(&exit.room) {
+                    Ok(room) => {
+                        debug!("Loaded room {} linked from {}", exit.room, path);
+                        level.rooms.insert(exit.room.clone(), room);
+                        unvisited.push(exit.room);
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Couldn't load room {} linked from {}: {}; removing exit",
+                            exit.room, path, e
+                        );
+                        level.rooms.get_mut(&path).unwrap().remove_exit(exit.edge);
+                    }
+                }
+            }
+        }
+        Ok(level)
+    }
+
+    pub fn room(&self, path: &str) -> Option<&Room> {
+        self.rooms.get(path)
+    }
+
+    pub fn set_room(&mut self, path: &str, room: Room) {
+        self.rooms.insert(path.to_string(), room);
+    }
+}
@@ -0,0 +1,205 @@
+//! Darkness in cave rooms, lifted only around the players and tiles that give off light
+
+use failure::{err_msg, Error};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use camera::Camera;
+use room::{Room, TileKind};
+
+/// Number of different shades of darkness drawn, so that tiles can be drawn in batches
+const SHADES: usize = 16;
+/// Fraction of the room's light radius and falloff that tiles giving off light reach
+const TILE_LIGHT_SCALE: f32 = 0.5;
+
+/// How dark a room is, as part of the room's data.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Darkness {
+    /// Tiles around a player that are fully lit
+    pub radius: f32,
+    /// Tiles beyond `radius` over which the light fades out
+    pub falloff: f32,
+}
+
+impl Default for Darkness {
+    fn default() -> Darkness {
+        Darkness {
+            radius: 3.0,
+            falloff: 2.0,
+        }
+    }
+}
+
+impl Darkness {
+    /// Returns how lit something is `distance` tiles from a player, from 0 for
+    /// dark to 1 for fully lit.
+    pub fn light_level(&self, distance: f32) -> f32 {
+        light_level(distance, self.radius, self.falloff)
+    }
+}
+
+fn light_level(distance: f32, radius: f32, falloff: f32) -> f32 {
+    if distance <= radius {
+        1.0
+    } else if falloff <= 0.0 {
+        0.0
+    } else {
+        (1.0 - (distance - radius) / falloff).max(0.0)
+    }
+}
+
+/// Returns whether tiles of `kind` light up their surroundings in a dark room.
+pub fn gives_light(kind: TileKind) -> bool {
+    match kind {
+        TileKind::Goal | TileKind::Teleporter(_) => true,
+        TileKind::Empty | TileKind::Filled | TileKind::SlopeUp | TileKind::SlopeDown => false,
+        TileKind::GravityFlip | TileKind::Bounce | TileKind::Door => false,
+        TileKind::Blink | TileKind::BlinkOff | TileKind::Crumble => false,
+        TileKind::Switch { .. } | TileKind::ToggleBlock(_) | TileKind::ToggleBlockOff(_) => false,
+    }
+}
+
+/// How much light every tile of a room gets from the tiles that give off light.
+///
+/// Those tiles rarely change, so this is only worked out again when the room's
+/// tiles do rather than every frame.
+#[derive(Clone, Debug, Default)]
+pub struct LightMap {
+    /// Generation of the room the levels were worked out for, see `Room::generation`
+    generation: Option<usize>,
+    width: u32,
+    height: u32,
+    /// Light level of every tile, row by row
+    levels: Vec<f32>,
+}
+
+impl LightMap {
+    pub fn new(room: &Room) -> LightMap {
+        let mut light_map = LightMap::default();
+        light_map.update(room);
+        light_map
+    }
+
+    /// Works out the light levels again if the tiles of `room` changed since they
+    /// were last worked out, returning whether they did.
+    pub fn update(&mut self, room: &Room) -> bool {
+        if self.generation == Some(room.generation()) {
+            return false;
+        }
+        let (width, height) = (room.width(), room.height());
+        let mut levels: Vec<f32> = vec![0.0; (width * height) as usize];
+        if let Some(darkness) = room.darkness() {
+            let radius = darkness.radius * TILE_LIGHT_SCALE;
+            let falloff = darkness.falloff * TILE_LIGHT_SCALE;
+            let reach = (radius + falloff).ceil() as i32;
+            for y in 0..height as i32 {
+                for x in 0..width as i32 {
+                    if !gives_light(room.tile_at_index(x, y).kind) {
+                        continue;
+                    }
+                    let rows = (y - reach).max(0)..(y + reach + 1).min(height as i32);
+                    for lit_y in rows {
+                        let columns = (x - reach).max(0)..(x + reach + 1).min(width as i32);
+                        for lit_x in columns {
+                            let (dx, dy) = ((lit_x - x) as f32, (lit_y - y) as f32);
+                            let level = light_level(dx.hypot(dy), radius, falloff);
+                            let index = (lit_y as u32 * width + lit_x as u32) as usize;
+                            levels[index] = levels[index].max(level);
+                        }
+                    }
+                }
+            }
+        }
+        *self = LightMap {
+            generation: Some(room.generation()),
+            width,
+            height,
+            levels,
+        };
+        true
+    }
+
+    /// Returns the light level of the tile at the given index, which is 0 outside
+    /// the room.
+    pub fn level_at(&self, x: u32, y: u32) -> f32 {
+        if x >= self.width || y >= self.height {
+            return 0.0;
+        }
+        self.levels[(y * self.width + x) as usize]
+    }
+}
+
+/// Darkens the tiles of `room` that `camera` sees, as far as neither `light_map`
+/// nor the players at `players`, in room coordinates, light them up. Does nothing
+/// in rooms that aren't dark.
+///
+/// Each tile gets a single shade from the distance of its center, which follows the
+/// players smoothly enough since it's worked out from where they're drawn.
+pub fn render_darkness<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    camera: &Camera,
+    room: &Room,
+    light_map: &LightMap,
+    players: &[(f32, f32)],
+) -> Result<(), Error> {
+    let darkness = match room.darkness() {
+        Some(darkness) => darkness,
+        None => return Ok(()),
+    };
+    let size = room.tile_size() as f32;
+    let mut shades = vec![Vec::new(); SHADES];
+    let (columns, rows) = room.visible_tiles(camera);
+    for y in rows {
+        for x in columns.clone() {
+            let center_x = (x as f32 + 0.5) * size;
+            let center_y = (y as f32 + 0.5) * size;
+            let level = players
+                .iter()
+                .map(|&(player_x, player_y)| {
+                    let distance = (center_x - player_x).hypot(center_y - player_y) / size;
+                    darkness.light_level(distance)
+                })
+                .fold(light_map.level_at(x, y), f32::max);
+            let shade = ((1.0 - level) * (SHADES - 1) as f32).round() as usize;
+            if shade > 0 {
+                let rect = room.tile_at_index(x as i32, y as i32).rect;
+                shades[shade].push(camera.sdl_rect(&rect));
+            }
+        }
+    }
+    for (shade, rects) in shades.iter().enumerate() {
+        if rects.is_empty() {
+            continue;
+        }
+        let alpha = (shade as f32 / (SHADES - 1) as f32 * 255.0).round() as u8;
+        canvas.set_draw_color(Color::RGBA(0x00, 0x00, 0x00, alpha));
+        canvas.fill_rects(rects as &[SdlRect]).map_err(err_msg)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_map_is_only_worked_out_again_when_tiles_change() {
+        let mut value = ::serde_json::to_value(Room::new(10, 10, 16)).unwrap();
+        value["darkness"] = ::serde_json::to_value(Darkness::default()).unwrap();
+        let mut room: Room = ::serde_json::from_value(value).unwrap();
+        room.set_tile_at_index(2, 2, TileKind::Goal).unwrap();
+        let mut light_map = LightMap::new(&room);
+        assert_eq!(light_map.level_at(2, 3), 1.0);
+        assert_eq!(light_map.level_at(8, 3), 0.0);
+        assert!(!light_map.update(&room));
+        // A copy of the room has the same tiles
+        assert!(!light_map.update(&room.clone()));
+
+        room.set_tile_at_index(8, 2, TileKind::Goal).unwrap();
+        assert!(light_map.update(&room));
+        assert_eq!(light_map.level_at(8, 3), 1.0);
+        assert!(!light_map.update(&room));
+    }
+}
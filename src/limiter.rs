@@ -0,0 +1,177 @@
+//! Keeping frames evenly spaced when the frame rate is limited
+
+use std::cmp;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use floating_duration::TimeFormat;
+
+/// Waits out the rest of each frame so that frames start on a fixed schedule.
+///
+/// Sleeping tends to overshoot, so it sleeps until shortly before the end of the
+/// frame and spins for the rest. How long before is adapted to how much sleeping
+/// has overshot so far.
+#[derive(Clone, Debug)]
+pub struct FrameLimiter {
+    frame_duration: Duration,
+    /// When the current frame should end, once it has started
+    deadline: Option<Instant>,
+    /// Time before the deadline at which to stop sleeping and start spinning
+    margin: Duration,
+}
+
+impl FrameLimiter {
+    /// Margin to start with, which covers typical oversleeping
+    const INITIAL_MARGIN: Duration = Duration::from_millis(2);
+    /// Bounds of the margin, so that a single long oversleep can't make it spin forever
+    const MIN_MARGIN: Duration = Duration::from_micros(500);
+    const MAX_MARGIN: Duration = Duration::from_millis(5);
+
+    pub fn new(frame_duration: Duration) -> FrameLimiter {
+        FrameLimiter {
+            frame_duration,
+            deadline: None,
+            margin: FrameLimiter::INITIAL_MARGIN,
+        }
+    }
+
+    /// Returns when the frame starting at `frame_started` should end, and
+    /// remembers it.
+    ///
+    /// Frames follow on from the previous frame's deadline rather than from when
+    /// they actually started, so that the time spent between frames doesn't add
+    /// up. A frame that starts after the previous deadline plus a whole frame
+    /// starts a new schedule instead, so that long frames aren't followed by a
+    /// burst of frames catching up.
+    pub fn start_frame(&mut self, frame_started: Instant) -> Instant {
+        let deadline = match self.deadline {
+            Some(previous) if frame_started < previous + self.frame_duration => {
+                previous + self.frame_duration
+            }
+            _ => frame_started + self.frame_duration,
+        };
+        self.deadline = Some(deadline);
+        deadline
+    }
+
+    /// Returns how long to sleep at `now` before spinning until `deadline`, if at all.
+    pub fn sleep_time(&self, now: Instant, deadline: Instant) -> Option<Duration> {
+        if now + self.margin >= deadline {
+            return None;
+        }
+        Some(deadline - self.margin - now)
+    }
+
+    /// Adapts the margin to a sleep meant to end at `expected` having ended at `woke`.
+    ///
+    /// The margin grows straight away to cover longer oversleeps, and shrinks
+    /// slowly back towards shorter ones, so that little time is spent spinning.
+    pub fn record_sleep(&mut self, expected: Instant, woke: Instant) {
+        let oversleep = if woke > expected {
+            woke - expected
+        } else {
+            Duration::from_secs(0)
+        };
+        // Keep some room on top of the oversleep, since it varies
+        let wanted = oversleep + oversleep / 2;
+        self.margin = if wanted > self.margin {
+            wanted
+        } else {
+            self.margin - (self.margin - wanted) / 8
+        };
+        self.margin = cmp::min(
+            cmp::max(self.margin, FrameLimiter::MIN_MARGIN),
+            FrameLimiter::MAX_MARGIN,
+        );
+    }
+
+    /// Waits until the end of the frame that started at `frame_started`.
+    pub fn wait(&mut self, frame_started: Instant) {
+        let deadline = self.start_frame(frame_started);
+        let now = Instant::now();
+        if now >= deadline {
+            trace!("Frame is {} late", TimeFormat(now - deadline));
+            return;
+        }
+        if let Some(sleep_time) = self.sleep_time(now, deadline) {
+            trace!("Frame is {} early; sleeping", TimeFormat(deadline - now));
+            thread::sleep(sleep_time);
+            self.record_sleep(now + sleep_time, Instant::now());
+        }
+        while Instant::now() < deadline {
+            thread::yield_now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn first_frame_ends_a_frame_after_it_started() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(ms(16));
+        assert_eq!(limiter.start_frame(start), start + ms(16));
+    }
+
+    #[test]
+    fn frames_follow_on_from_previous_deadline() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(ms(16));
+        limiter.start_frame(start);
+        // Starting a little late doesn't push back the schedule
+        assert_eq!(limiter.start_frame(start + ms(17)), start + ms(32));
+        assert_eq!(limiter.start_frame(start + ms(32)), start + ms(48));
+    }
+
+    #[test]
+    fn long_frame_starts_new_schedule() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(ms(16));
+        limiter.start_frame(start);
+        assert_eq!(limiter.start_frame(start + ms(40)), start + ms(56));
+        assert_eq!(limiter.start_frame(start + ms(57)), start + ms(72));
+    }
+
+    #[test]
+    fn sleeps_until_margin_before_deadline() {
+        let start = Instant::now();
+        let limiter = FrameLimiter::new(ms(16));
+        let deadline = start + ms(16);
+        assert_eq!(limiter.sleep_time(start, deadline), Some(ms(14)));
+        assert_eq!(limiter.sleep_time(start + ms(13), deadline), Some(ms(1)));
+        assert_eq!(limiter.sleep_time(start + ms(14), deadline), None);
+        assert_eq!(limiter.sleep_time(start + ms(20), deadline), None);
+    }
+
+    #[test]
+    fn margin_grows_with_oversleep_up_to_maximum() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(ms(16));
+        limiter.record_sleep(start, start + ms(2));
+        assert_eq!(limiter.margin, ms(3));
+        limiter.record_sleep(start, start + ms(100));
+        assert_eq!(limiter.margin, FrameLimiter::MAX_MARGIN);
+    }
+
+    #[test]
+    fn margin_shrinks_slowly_down_to_minimum() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(ms(16));
+        limiter.record_sleep(start, start);
+        let margin = limiter.margin;
+        assert_eq!(margin, ms(2) - ms(2) / 8);
+        // Waking early counts as not oversleeping at all
+        limiter.record_sleep(start + ms(1), start);
+        assert_eq!(limiter.margin, margin - margin / 8);
+        for _ in 0..100 {
+            limiter.record_sleep(start, start);
+        }
+        assert_eq!(limiter.margin, FrameLimiter::MIN_MARGIN);
+    }
+}
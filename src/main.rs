@@ -11,25 +11,64 @@ extern crate serde_derive;
 extern crate serde_json;
 #[macro_use]
 extern crate structopt;
+extern crate toml;
 
+pub mod camera;
+pub mod difficulty;
 pub mod editor;
+pub mod effects;
+pub mod entity;
+pub mod font;
 pub mod geom;
+pub mod hud;
+pub mod graphics;
+pub mod ghost;
+pub mod input;
+pub mod level;
+pub mod light;
+pub mod limiter;
+pub mod minimap;
 pub mod model;
+pub mod physics;
+pub mod redraw;
+pub mod replay;
 pub mod room;
+pub mod sprite;
+pub mod stats;
+pub mod theme;
+pub mod tileset;
+pub mod transition;
 
+use std::path::Path;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use failure::{err_msg, Error};
-use floating_duration::TimeFormat;
+use floating_duration::{TimeAsFloat, TimeFormat};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use sdl2::surface::Surface;
 use sdl2::video::FullscreenType;
 use structopt::StructOpt;
 
-use editor::Editor;
+use difficulty::Difficulty;
+use editor::{Editor, Macro};
+use graphics::{Filter, Frames, RenderContext};
+use input::{ControllerInput, InputEvent, KeyBindings};
 use model::Model;
+use physics::PhysicsConfig;
+use redraw::RedrawTracker;
+use replay::Replay;
+use level::Level;
+use limiter::FrameLimiter;
 use room::Room;
+use sprite::SpriteSheet;
+use stats::{FrameTime, FrameTimes, RateCounter};
+use theme::Theme;
+use tileset::Tileset;
+use transition::RoomTransition;
 
 #[derive(Debug, StructOpt)]
 pub struct Options {
@@ -39,6 +78,62 @@ pub struct Options {
                 help = "Limit frame rate to at most <fps>, or 0 for unlimited")]
     pub fps: u32,
     #[structopt(short = "v", long = "vsync", help = "Enable vsync")] pub vsync: bool,
+    #[structopt(long = "scale", default_value = "1",
+                help = "Make the window <factor> times the size of the view, from 1 to 8")]
+    pub scale: u32,
+    #[structopt(long = "view-width", default_value = "640",
+                help = "Show at most <pixels> of the room horizontally")]
+    pub view_width: u32,
+    #[structopt(long = "view-height", default_value = "480",
+                help = "Show at most <pixels> of the room vertically")]
+    pub view_height: u32,
+    #[structopt(long = "tps", default_value = "150",
+                help = "Simulate the game at <tps> ticks per second")]
+    pub tps: u32,
+    #[structopt(long = "max-catch-up", default_value = "250",
+                help = "Simulate at most <ms> of game time per frame, or 0 for unlimited")]
+    pub max_catch_up: u64,
+    #[structopt(long = "peek-distance", default_value = "4",
+                help = "Move the camera <tiles> up or down when looking ahead")]
+    pub peek_distance: f32,
+    #[structopt(long = "camera-lead", default_value = "2",
+                help = "Keep the camera <tiles> ahead of the player in the direction they face")]
+    pub camera_lead: f32,
+    #[structopt(long = "screen-shake", default_value = "1",
+                help = "Scale the shaking on hard landings and deaths by <factor>; 0 for none")]
+    pub screen_shake: f32,
+    #[structopt(long = "no-darkness", help = "Show dark rooms fully lit")]
+    pub no_darkness: bool,
+    #[structopt(long = "no-afterimages",
+                help = "Don't draw a trail behind the player while sprinting")]
+    pub no_afterimages: bool,
+    #[structopt(long = "fps-in-title", help = "Show the frame rate in the window title")]
+    pub fps_in_title: bool,
+    #[structopt(long = "minimap-entities",
+                help = "Show coins, keys and enemies on the minimap")]
+    pub minimap_entities: bool,
+    #[structopt(long = "players", default_value = "1",
+                help = "Play with <players> players; the second one moves with WASD")]
+    pub players: u32,
+    #[structopt(long = "filter", default_value = "nearest",
+                help = "Scale the view up with nearest or linear filtering")]
+    pub filter: Filter,
+    #[structopt(long = "difficulty", default_value = "normal",
+                help = "Play on easy, normal or hard difficulty")]
+    pub difficulty: Difficulty,
+    #[structopt(long = "lives",
+                help = "Override the difficulty's starting lives, or 0 for infinite")]
+    pub lives: Option<u32>,
+    #[structopt(long = "tileset",
+                help = "Draw tiles with the tileset described by the JSON file <file>")]
+    pub tileset: Option<String>,
+    #[structopt(long = "sprites",
+                help = "Draw the player with the sprite sheet described by the JSON file <file>")]
+    pub sprites: Option<String>,
+    #[structopt(long = "record", help = "Record all input into the replay file <file>")]
+    pub record: Option<String>,
+    #[structopt(long = "replay", help = "Play back the input from the replay file <file>")]
+    pub replay: Option<String>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -50,10 +145,50 @@ enum Mode {
 /// Runs the game.
 pub fn run(options: &Options) -> Result<(), Error> {
     debug!("Running game with {:?}", options);
+    ensure!(
+        options.view_width > 0 && options.view_height > 0,
+        "View size must not be zero"
+    );
+    ensure!(
+        options.scale >= 1 && options.scale <= 8,
+        "Window scale must be between 1 and 8"
+    );
+    ensure!(
+        options.tps >= 10 && options.tps <= 1000,
+        "Tick rate must be between 10 and 1000 ticks per second"
+    );
+    ensure!(
+        options.peek_distance.is_finite() && options.peek_distance >= 0.0,
+        "Peek distance must be a non-negative number of tiles"
+    );
+    ensure!(
+        options.camera_lead.is_finite() && options.camera_lead >= 0.0,
+        "Camera lead must be a non-negative number of tiles"
+    );
+    ensure!(
+        options.screen_shake.is_finite() && options.screen_shake >= 0.0,
+        "Screen shake must be a non-negative factor"
+    );
+    ensure!(
+        options.players == 1 || options.players == 2,
+        "There can be only 1 or 2 players"
+    );
+    ensure!(
+        options.record.is_none() || options.replay.is_none(),
+        "Can't record and play back a replay at the same time"
+    );
     let sdl = sdl2::init().map_err(err_msg)?;
     let video = sdl.video().map_err(err_msg)?;
     let mut event_pump = sdl.event_pump().map_err(err_msg)?;
-    let mut window_builder = video.window("Grot", 640, 480);
+    let controller_subsystem = sdl.game_controller().map_err(err_msg)?;
+    // Controllers are opened when SDL reports them as added, which also happens at startup
+    let mut controllers = Vec::new();
+    let mut controller_input = ControllerInput::default();
+    // This has to come before creating the canvas and textures, so they all use it
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", options.filter.scale_quality());
+    let (window_width, window_height) = (640 * options.scale, 480 * options.scale);
+    let mut window_builder = video.window("Grot", window_width, window_height);
+    window_builder.resizable();
     if options.fullscreen {
         window_builder.fullscreen_desktop();
     }
@@ -63,30 +198,118 @@ pub fn run(options: &Options) -> Result<(), Error> {
         canvas_builder = canvas_builder.present_vsync();
     }
     let mut canvas = canvas_builder.build()?;
+    // Everything drawn with an alpha below 255 is meant to be see-through
+    canvas.set_blend_mode(BlendMode::Blend);
+    let texture_creator = canvas.texture_creator();
+    let tileset = options.tileset.as_ref().and_then(|path| {
+        Tileset::load(&texture_creator, path)
+            .map_err(|e| warn!("Couldn't load tileset {}: {}; drawing plain tiles", path, e))
+            .ok()
+    });
+    let sprites = options.sprites.as_ref().and_then(|path| {
+        SpriteSheet::load(&texture_creator, path)
+            .map_err(|e| warn!("Couldn't load sprites {}: {}; drawing a plain player", path, e))
+            .ok()
+    });
+    let theme = if Path::new("theme.json").exists() {
+        let theme = Theme::load("theme.json")
+            .map_err(|e| format_err!("Invalid theme in theme.json: {}", e))?;
+        debug!("Loaded {:?}", theme);
+        theme
+    } else {
+        Theme::default()
+    };
+    let mut render_context = RenderContext::new(&texture_creator, tileset, sprites, theme);
 
     let mut game_mode = Mode::Run;
-    let room = Room::load("room.json")
+    let level = Level::load("room.json")
         .map_err(|e| {
             warn!(
                 "Couldn't load room.json: {}; falling back to default room",
                 e
             )
         })
-        .unwrap_or_default();
-    let mut model = Model::new(room.clone());
-    let mut editor = Editor::new(room);
+        .unwrap_or_else(|_| Level::with_room("room.json", Room::default()));
+    let mut model = Model::new(level, "room.json", options.tps);
+    let mut editor = Editor::new(model.room_path(), model.unchanged_room().clone());
+    model.set_view_size(options.view_width, options.view_height);
+    model.set_peek_distance(options.peek_distance);
+    model.set_camera_lead(options.camera_lead);
+    model.set_shake_intensity(options.screen_shake);
+    model.set_afterimages(!options.no_afterimages);
+    model.set_darkness(!options.no_darkness);
+    model.set_second_player(options.players == 2);
+    model.set_difficulty(options.difficulty);
+    if let Some(lives) = options.lives {
+        model.set_starting_lives(match lives {
+            0 => None,
+            lives => Some(lives),
+        });
+    }
+    model.set_max_catch_up(match options.max_catch_up {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    });
+    let physics_paths = ["physics.json", "physics.toml"];
+    if let Some(path) = physics_paths.iter().find(|path| Path::new(path).exists()) {
+        let physics = PhysicsConfig::load(path)
+            .map_err(|e| format_err!("Invalid physics config in {}: {}", path, e))?;
+        debug!("Loaded {:?}", physics);
+        model.set_physics(physics);
+    }
+    if let Some(ref path) = options.replay {
+        let replay = Replay::load(path)
+            .map_err(|e| format_err!("Couldn't load replay from {}: {}", path, e))?;
+        model.start_playback(replay)?;
+    }
+    if options.record.is_some() {
+        model.start_recording();
+    }
+    editor.set_view_size(options.view_width, options.view_height);
+    let key_bindings = if Path::new("bindings.json").exists() {
+        KeyBindings::load("bindings.json")
+            .map_err(|e| format_err!("Invalid key bindings in bindings.json: {}", e))?
+    } else {
+        KeyBindings::default()
+    };
+    match Macro::load("macro.json") {
+        Ok(editor_macro) => editor.set_macro(editor_macro),
+        Err(e) => debug!("No editor macro loaded from macro.json: {}", e),
+    }
+
+    let mut frame_limiter = match options.fps {
+        0 => None,
+        fps => Some(FrameLimiter::new(Duration::from_secs(1) / fps)),
+    };
 
-    let limit_fps = options.fps != 0;
-    let frame_duration = Duration::from_secs(1)
-        .checked_div(options.fps)
-        .unwrap_or_default();
+    let mut show_rates = false;
+    let mut show_frame_times = false;
+    let mut frame_times = FrameTimes::default();
+    // The editor only changes when something happens, so it's drawn at a low rate otherwise
+    let mut editor_redraw = RedrawTracker::new(Duration::from_millis(100));
+    let mut screenshot_requested = false;
+    let mut show_debug = false;
+    let mut show_collisions = false;
+    let mut show_hud = true;
+    let mut show_minimap = false;
+    let mut frames = Frames::new(&texture_creator);
+    let mut transition: Option<RoomTransition> = None;
+    let mut frame_rate = RateCounter::default();
+    let mut tick_rate = RateCounter::default();
+    let mut last_total_ticks = model.total_ticks();
+    // Setting the title every frame is slow and makes some window managers flicker
+    const TITLE_INTERVAL: Duration = Duration::from_millis(250);
+    let mut title_updated: Option<Instant> = None;
 
     debug!("Running main loop");
+    let started = Instant::now();
     let mut last_update_time = Instant::now();
     loop {
         trace!("Start new frame");
         let frame_started = Instant::now();
         for event in event_pump.poll_iter() {
+            // Any event might change what's shown, e.g. by resizing the window
+            editor_redraw.force();
             match event {
                 // Close window or press Escape to quit
                 Event::Quit { .. }
@@ -94,8 +317,13 @@ pub fn run(options: &Options) -> Result<(), Error> {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => {
+                    save_recording(&mut model, options)?;
                     debug!("Saving room");
-                    editor.room().save("room.json")?;
+                    editor.save()?;
+                    if !editor.current_macro().commands.is_empty() {
+                        debug!("Saving editor macro");
+                        editor.current_macro().save("macro.json")?;
+                    }
                     debug!("Quitting");
                     return Ok(());
                 }
@@ -115,6 +343,9 @@ pub fn run(options: &Options) -> Result<(), Error> {
                     window
                         .set_fullscreen(new_fullscreen_state)
                         .map_err(err_msg)?;
+                    if new_fullscreen_state == FullscreenType::Off {
+                        window.set_size(window_width, window_height)?;
+                    }
                 }
 
                 // Switch between Run and Edit mode with E
@@ -125,31 +356,114 @@ pub fn run(options: &Options) -> Result<(), Error> {
                 } => {
                     game_mode = match game_mode {
                         Mode::Run => {
-                            // Make sure no player movement keys are pressed anymore,
+                            // Make sure no player actions are held anymore,
                             // as their key release events won't be received by the model
-                            model.key_released(Keycode::Left);
-                            model.key_released(Keycode::Right);
-                            model.key_released(Keycode::Up);
+                            model.release_actions();
+                            // Editing the room would make the recording impossible to play back
+                            save_recording(&mut model, options)?;
                             Mode::Edit
                         }
                         Mode::Edit => {
                             // Clone the editor's room to play in the model
-                            model.set_room(editor.room().clone());
+                            model.set_room(editor.path(), editor.room().clone());
                             Mode::Run
                         }
                     };
                     debug!("Switched to game mode {:?}", game_mode);
                 }
 
+                // Slow down, speed up or reset the simulation speed with F5, F6 and F7
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } if game_mode == Mode::Run
+                    && [Keycode::F5, Keycode::F6, Keycode::F7].contains(&keycode) =>
+                {
+                    let time_scale = match keycode {
+                        Keycode::F5 => model.time_scale() / 2.0,
+                        Keycode::F6 => model.time_scale() * 2.0,
+                        _ => 1.0,
+                    };
+                    model.set_time_scale(time_scale);
+                    info!("Simulation speed: {}×", model.time_scale());
+                }
+
+                // Show or hide the player's physics state with F3
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    repeat: false,
+                    ..
+                } => {
+                    show_debug = !show_debug;
+                }
+
+                // Show or hide what the player's collision checks saw with F4
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    repeat: false,
+                    ..
+                } if game_mode == Mode::Run =>
+                {
+                    show_collisions = !show_collisions;
+                }
+
+                // Show or hide the status bar with F2
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    repeat: false,
+                    ..
+                } => {
+                    show_hud = !show_hud;
+                }
+
+                // Show or hide the minimap with Tab
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    repeat: false,
+                    ..
+                } => {
+                    show_minimap = !show_minimap;
+                }
+
+                // Show or hide the frame and tick rates with F10
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    repeat: false,
+                    ..
+                } => {
+                    show_rates = !show_rates;
+                }
+
+                // Show or hide the graph of recent frame times with F11
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => {
+                    show_frame_times = !show_frame_times;
+                }
+
+                // Save what's on screen with F12, once this frame is drawn
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => {
+                    screenshot_requested = true;
+                }
+
                 // Any other keypress goes to the model or editor depending on game mode;
                 // the editor receives key repeat events while the model does not.
                 Event::KeyDown {
                     keycode: Some(keycode),
                     repeat: false,
                     ..
-                } if game_mode == Mode::Run =>
+                } if game_mode == Mode::Run && transition.is_none() =>
                 {
-                    model.key_pressed(keycode)
+                    if let Some(action) = key_bindings.action(keycode) {
+                        model.action_pressed(action)
+                    }
                 }
                 Event::KeyDown {
                     keycode: Some(keycode),
@@ -159,13 +473,53 @@ pub fn run(options: &Options) -> Result<(), Error> {
                     editor.key_pressed(keycode)
                 }
 
+                // Controller input goes to the model if it is active
+                Event::ControllerButtonDown { button, .. }
+                    if game_mode == Mode::Run && transition.is_none() =>
+                {
+                    if let Some(action) = ControllerInput::button_action(button) {
+                        model.handle_input(InputEvent::Pressed(action))
+                    }
+                }
+                Event::ControllerButtonUp { button, .. } if game_mode == Mode::Run => {
+                    if let Some(action) = ControllerInput::button_action(button) {
+                        model.handle_input(InputEvent::Released(action))
+                    }
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    for input_event in controller_input.axis_moved(axis, value) {
+                        if game_mode == Mode::Run {
+                            model.handle_input(input_event);
+                        }
+                    }
+                }
+
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            debug!("Opened controller {}", controller.name());
+                            controllers.push(controller);
+                        }
+                        Err(e) => warn!("Couldn't open controller {}: {}", which, e),
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    debug!("Controller {} disconnected", which);
+                    controllers.retain(|controller| controller.instance_id() != which);
+                    // Release everything, since the controller's release events won't arrive
+                    controller_input = ControllerInput::default();
+                    model.release_actions();
+                }
+
                 // Any key release goes to the model if it is active
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
                 } if game_mode == Mode::Run =>
                 {
-                    model.key_released(keycode)
+                    if let Some(action) = key_bindings.action(keycode) {
+                        model.action_released(action)
+                    }
                 }
 
                 _ => trace!("Unhandled event of type {:?}", event),
@@ -176,36 +530,190 @@ pub fn run(options: &Options) -> Result<(), Error> {
         let time_passed = update_time - last_update_time;
         last_update_time = update_time;
 
+        let transition_finished = match transition {
+            Some(transition) => transition.is_finished(update_time),
+            None => false,
+        };
+        if transition_finished {
+            transition = None;
+        }
+
         // Do model or editor stuff depending on which is active
-        match game_mode {
+        if game_mode == Mode::Run && transition.is_none() {
+            // Update model with the time passed since the previous update
+            trace!("Time passed for model update: {}", TimeFormat(time_passed));
+            model.update(time_passed);
+            if let Some(room_change) = model.take_room_change() {
+                debug!("Entered room {}", room_change.room);
+                // Let the editor follow the player. Its edits are kept in the level
+                // for the rest of the session, but only saving writes them to disk.
+                if editor.has_unsaved_changes() {
+                    warn!("Leaving room {} with unsaved changes", editor.path());
+                }
+                editor.set_room(model.room_path(), model.unchanged_room().clone());
+                // Hold the model while the old room slides out of view
+                if frames.keep_current() {
+                    transition = Some(RoomTransition::new(room_change.edge, update_time));
+                }
+            }
+        }
+        if game_mode == Mode::Edit {
+            let shown = (
+                editor.room().generation(),
+                editor.cursor(),
+                show_rates,
+                show_frame_times,
+            );
+            if !editor_redraw.needs_redraw(shown, update_time) {
+                // Wait a little rather than spinning, but not so long that input lags
+                thread::sleep(Duration::from_millis(5));
+                continue;
+            }
+        }
+        let render_started = Instant::now();
+        let slide = match transition {
+            Some(transition) => {
+                let output_size = canvas.output_size().map_err(err_msg)?;
+                Some(transition.offsets(update_time, output_size))
+            }
+            None => None,
+        };
+        frames.render(&mut canvas, slide, |canvas| match game_mode {
             Mode::Run => {
-                // Update model with the time passed since the previous update
-                trace!("Time passed for model update: {}", TimeFormat(time_passed));
-                model.update(time_passed);
-
-                model.render(&mut canvas)?;
-                canvas.present();
+                model.render(canvas, &mut render_context)?;
+                if show_hud {
+                    hud::render_hud(canvas, &model, &render_context.theme)?;
+                }
+                if show_minimap {
+                    model.render_minimap(canvas, &mut render_context, options.minimap_entities)?;
+                }
+                if show_collisions {
+                    model.render_collisions(canvas, &render_context.theme)?;
+                }
+                if show_debug {
+                    model.render_debug(canvas, &render_context.theme)?;
+                }
+                Ok(())
             }
             Mode::Edit => {
-                editor.render(&mut canvas)?;
-                canvas.present();
+                // The editor has no simulated time, so tiles animate in wall time
+                let clock = (update_time - started).as_fractional_secs() as f32;
+                editor.render(canvas, &mut render_context, clock)
             }
+        })?;
+        frame_rate.record(update_time, 1);
+        tick_rate.record(update_time, model.total_ticks() - last_total_ticks);
+        last_total_ticks = model.total_ticks();
+        if show_rates {
+            let (fps, tps) = (frame_rate.per_second(), tick_rate.per_second());
+            stats::render_rates(&mut canvas, fps, tps, &render_context.theme)?;
         }
+        if show_frame_times {
+            stats::render_frame_times(&mut canvas, &frame_times, &render_context.theme)?;
+        }
+        let title_due = match title_updated {
+            Some(time) => update_time >= time + TITLE_INTERVAL,
+            None => true,
+        };
+        if title_due {
+            title_updated = Some(update_time);
+            let (room_path, unsaved) = match game_mode {
+                Mode::Run => (model.room_path(), false),
+                Mode::Edit => (editor.path(), editor.has_unsaved_changes()),
+            };
+            let fps = if options.fps_in_title {
+                Some(frame_rate.per_second())
+            } else {
+                None
+            };
+            let title = window_title(&game_mode, room_path, unsaved, fps);
+            if canvas.window().title() != title {
+                canvas.window_mut().set_title(&title)?;
+            }
+        }
+        if screenshot_requested {
+            screenshot_requested = false;
+            match save_screenshot(&mut canvas) {
+                Ok(path) => info!("Saved screenshot to {}", path),
+                Err(e) => error!("Couldn't save screenshot: {}", e),
+            }
+        }
+        // Presenting may wait for vsync, so it doesn't count as rendering
+        let render_finished = Instant::now();
+        canvas.present();
 
         let frame_finished = Instant::now();
         let frame_process_time = frame_finished - frame_started;
         trace!("Processing frame took {}", TimeFormat(frame_process_time));
-        if limit_fps {
-            if frame_process_time < frame_duration {
-                let sleep_duration = frame_duration - frame_process_time;
-                trace!("Frame is {} early; sleeping", TimeFormat(sleep_duration));
-                thread::sleep(sleep_duration);
-            } else {
-                let lateness = frame_process_time - frame_duration;
-                trace!("Frame is {} late", TimeFormat(lateness));
-            }
+        if let Some(ref mut frame_limiter) = frame_limiter {
+            frame_limiter.wait(frame_started);
         }
+        frame_times.record(FrameTime {
+            update: render_started - update_time,
+            render: render_finished - render_started,
+            total: Instant::now() - frame_started,
+        });
+    }
+}
+
+/// Stops recording input, if the model was, and saves the replay to the path in `options`.
+fn save_recording(model: &mut Model, options: &Options) -> Result<(), Error> {
+    if let (Some(replay), Some(path)) = (model.stop_recording(), options.record.as_ref()) {
+        debug!("Saving replay to {}", path);
+        replay.save(path)?;
     }
+    Ok(())
+}
+
+/// Saves the whole window, letterbox bars included, to a BMP file named after the
+/// current time in the working directory, and returns its path.
+///
+/// This has to happen before presenting, after which the frame is gone.
+fn save_screenshot<T: RenderTarget>(canvas: &mut Canvas<T>) -> Result<String, Error> {
+    let path = screenshot_path(SystemTime::now());
+    // Read the whole output rather than just the letterboxed view
+    let (scale, viewport) = (canvas.scale(), canvas.viewport());
+    canvas.set_scale(1.0, 1.0).map_err(err_msg)?;
+    canvas.set_viewport(None);
+    let (width, height) = canvas.output_size().map_err(err_msg)?;
+    let format = PixelFormatEnum::RGB24;
+    let pixels = canvas.read_pixels(None, format);
+    canvas.set_scale(scale.0, scale.1).map_err(err_msg)?;
+    canvas.set_viewport(viewport);
+    let mut pixels = pixels.map_err(err_msg)?;
+    let pitch = row_pitch(width, format);
+    let surface = Surface::from_data(&mut pixels, width, height, pitch, format).map_err(err_msg)?;
+    surface.save_bmp(&path).map_err(err_msg)?;
+    Ok(path)
+}
+
+/// Returns the name of a screenshot taken at `time`, which sorts by the time taken.
+fn screenshot_path(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "screenshot-{}-{:03}.bmp",
+        since_epoch.as_secs(),
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Returns the number of bytes in a row of `width` pixels of `format`, as read by
+/// `Canvas::read_pixels`, which packs the rows without padding.
+fn row_pitch(width: u32, format: PixelFormatEnum) -> u32 {
+    width * format.byte_size_per_pixel() as u32
+}
+
+/// Returns the window title for playing or editing the room at `room_path` in
+/// `mode`, marking unsaved edits with an asterisk, and with the frame rate if given.
+fn window_title(mode: &Mode, room_path: &str, unsaved: bool, fps: Option<u64>) -> String {
+    let mut title = format!("Grot - {:?} - {}", mode, room_path);
+    if unsaved {
+        title.push('*');
+    }
+    if let Some(fps) = fps {
+        title.push_str(&format!(" - {} FPS", fps));
+    }
+    title
 }
 
 fn main() {
@@ -223,3 +731,83 @@ fn main() {
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_path_sorts_by_time_taken() {
+        let time = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+        assert_eq!(screenshot_path(time(1_500_000_000_042)), "screenshot-1500000000-042.bmp");
+        let mut paths: Vec<String> = [1_500_000_001_000, 1_500_000_000_999, 1_500_000_000_005]
+            .iter()
+            .map(|&millis| screenshot_path(time(millis)))
+            .collect();
+        paths.sort();
+        assert_eq!(paths[0], "screenshot-1500000000-005.bmp");
+        assert_eq!(paths[2], "screenshot-1500000001-000.bmp");
+    }
+
+    #[test]
+    fn row_pitch_counts_bytes_per_row() {
+        assert_eq!(row_pitch(640, PixelFormatEnum::RGB24), 1920);
+        assert_eq!(row_pitch(641, PixelFormatEnum::RGB24), 1923);
+        assert_eq!(row_pitch(640, PixelFormatEnum::ARGB8888), 2560);
+    }
+
+    #[test]
+    fn filter_parses_either_name_in_any_case() {
+        assert_eq!("nearest".parse::<Filter>().unwrap(), Filter::Nearest);
+        assert_eq!("Linear".parse::<Filter>().unwrap(), Filter::Linear);
+        assert!("bilinear".parse::<Filter>().is_err());
+        assert!("".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn filter_option_defaults_to_nearest() {
+        let options = Options::from_iter_safe(&["grot"]).unwrap();
+        assert_eq!(options.filter, Filter::Nearest);
+        assert_eq!(options.filter.scale_quality(), "nearest");
+        let options = Options::from_iter_safe(&["grot", "--filter", "linear"]).unwrap();
+        assert_eq!(options.filter, Filter::Linear);
+        assert_eq!(options.filter.scale_quality(), "linear");
+    }
+
+    #[test]
+    fn unknown_filter_option_is_rejected() {
+        assert!(Options::from_iter_safe(&["grot", "--filter", "smooth"]).is_err());
+    }
+
+    #[test]
+    fn window_title_shows_mode_and_room() {
+        assert_eq!(
+            window_title(&Mode::Run, "rooms/start.json", false, None),
+            "Grot - Run - rooms/start.json"
+        );
+        assert_eq!(
+            window_title(&Mode::Edit, "rooms/start.json", false, None),
+            "Grot - Edit - rooms/start.json"
+        );
+    }
+
+    #[test]
+    fn window_title_marks_unsaved_edits() {
+        assert_eq!(
+            window_title(&Mode::Edit, "room.json", true, None),
+            "Grot - Edit - room.json*"
+        );
+    }
+
+    #[test]
+    fn window_title_ends_with_frame_rate() {
+        assert_eq!(
+            window_title(&Mode::Edit, "room.json", true, Some(59)),
+            "Grot - Edit - room.json* - 59 FPS"
+        );
+        assert_eq!(
+            window_title(&Mode::Run, "room.json", false, Some(0)),
+            "Grot - Run - room.json - 0 FPS"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Small overview of the whole room
+
+use failure::{err_msg, Error};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::surface::Surface;
+
+use geom::Rect;
+use room::{self, Room, TileKind};
+use theme::Theme;
+
+/// Most screen pixels a tile takes up on the minimap
+const MAX_TILE_SCALE: f32 = 2.0;
+
+/// The room drawn with a pixel per tile, which is only redrawn when the room's
+/// tiles change.
+pub struct Minimap<'r, C: 'r> {
+    texture_creator: &'r TextureCreator<C>,
+    texture: Option<Texture<'r>>,
+    /// Generation of the room the texture was drawn from, see `Room::generation`
+    generation: Option<usize>,
+}
+
+impl<'r, C> Minimap<'r, C> {
+    pub fn new(texture_creator: &'r TextureCreator<C>) -> Minimap<'r, C> {
+        Minimap {
+            texture_creator,
+            texture: None,
+            generation: None,
+        }
+    }
+
+    /// Returns the texture with a pixel for every tile of `room`, redrawing it first
+    /// if the room changed since it was last drawn.
+    pub fn update(&mut self, room: &Room, theme: &Theme) -> Result<&Texture<'r>, Error> {
+        if self.texture.is_none() || self.generation != Some(room.generation()) {
+            let mut surface =
+                Surface::new(room.width(), room.height(), PixelFormatEnum::RGB24).map_err(err_msg)?;
+            surface.fill_rect(None, theme.empty()).map_err(err_msg)?;
+            for y in 0..room.height() as i32 {
+                for x in 0..room.width() as i32 {
+                    let kind = room.tile_at_index(x, y).kind;
+                    if kind != TileKind::Empty {
+                        let pixel = SdlRect::new(x, y, 1, 1);
+                        surface
+                            .fill_rect(pixel, room::tile_color(kind, theme))
+                            .map_err(err_msg)?;
+                    }
+                }
+            }
+            self.texture = Some(self.texture_creator.create_texture_from_surface(&surface)?);
+            self.generation = Some(room.generation());
+        }
+        self.texture
+            .as_ref()
+            .ok_or_else(|| err_msg("Minimap texture is missing"))
+    }
+}
+
+/// Returns the size in screen pixels of the minimap of a room of `room_tiles`
+/// tiles, at most two pixels per tile but shrunk to fit in `max_size`.
+pub fn size(room_tiles: (u32, u32), max_size: (u32, u32)) -> (u32, u32) {
+    let (width, height) = (room_tiles.0.max(1) as f32, room_tiles.1.max(1) as f32);
+    let scale = MAX_TILE_SCALE
+        .min(max_size.0 as f32 / width)
+        .min(max_size.1 as f32 / height);
+    (
+        ((width * scale).round() as u32).max(1),
+        ((height * scale).round() as u32).max(1),
+    )
+}
+
+/// Converts `rect` in the coordinates of a room of `room_size` pixels to where it
+/// is on a minimap covering `area`, at least a pixel in size.
+pub fn map_rect(rect: &Rect, room_size: (f32, f32), area: SdlRect) -> SdlRect {
+    let scale_x = area.width() as f32 / room_size.0.max(1.0);
+    let scale_y = area.height() as f32 / room_size.1.max(1.0);
+    let left = (rect.left() * scale_x).round() as i32;
+    let top = (rect.top() * scale_y).round() as i32;
+    let right = (rect.right() * scale_x).round() as i32;
+    let bottom = (rect.bottom() * scale_y).round() as i32;
+    SdlRect::new(
+        area.x() + left,
+        area.y() + top,
+        (right - left).max(1) as u32,
+        (bottom - top).max(1) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_is_at_most_two_pixels_per_tile() {
+        assert_eq!(size((40, 20), (200, 200)), (80, 40));
+    }
+
+    #[test]
+    fn size_shrinks_to_fit_keeping_the_aspect_ratio() {
+        assert_eq!(size((200, 50), (100, 100)), (100, 25));
+        assert_eq!(size((0, 0), (100, 100)), (2, 2));
+    }
+
+    #[test]
+    fn map_rect_scales_into_the_area() {
+        let area = SdlRect::new(100, 50, 80, 40);
+        let rect = map_rect(&Rect::new(32.0, 16.0, 16.0, 32.0), (320.0, 160.0), area);
+        assert_eq!(rect, SdlRect::new(108, 54, 4, 8));
+    }
+
+    #[test]
+    fn map_rect_keeps_small_rects_visible() {
+        let area = SdlRect::new(0, 0, 80, 40);
+        let rect = map_rect(&Rect::new(40.0, 40.0, 1.0, 1.0), (320.0, 160.0), area);
+        assert_eq!(rect, SdlRect::new(10, 10, 1, 1));
+    }
+}
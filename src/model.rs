@@ -1,15 +1,33 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::iter;
+use std::mem;
 use std::time::Duration;
 
 use failure::{err_msg, Error};
-use floating_duration::TimeAsFloat;
-use sdl2::keyboard::Keycode;
+use floating_duration::{TimeAsFloat, TimeFormat};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect as SdlRect;
 use sdl2::render::{Canvas, RenderTarget};
 
-use room::{Room, TileKind};
-
-const TICKS_PER_SECOND: u32 = 150;
+use camera::{self, Camera, Peek};
+use difficulty::Difficulty;
+use entity::{Coin, Crate, Crusher, Enemy, Entity, Key, Platform, Projectile, Sign, Turret};
+use effects::{Burst, Fade, ParticleKind, Particles, Shake, Trail};
+use font;
+use minimap;
+use geom::{Direction, Rect, Vec2};
+use ghost::Ghost;
+use graphics::RenderContext;
+use input::{Action, InputEvent};
+use level::Level;
+use light::{self, LightMap};
+use physics::PhysicsConfig;
+use replay::Replay;
+use room::{Edge, Room, Tile, TileKind};
+use sprite::{Animation, SpriteSheet};
+use theme::Theme;
 
 /// Game model.
 ///
@@ -20,280 +38,5900 @@ const TICKS_PER_SECOND: u32 = 150;
 pub struct Model {
     frame_duration: Duration,
     time_since_last_tick: Duration,
+    /// Input events that happened since the last tick, waiting to be applied
+    input_queue: Vec<InputEvent>,
+    replay_mode: ReplayMode,
+    /// Number of ticks simulated since recording or playback started
+    replay_tick: u32,
+    /// Most time simulated in a single call to `update`, if limited
+    max_catch_up: Option<Duration>,
+    /// Factor applied to the time passed, to run the simulation slower or faster
+    time_scale: f64,
     player: Player,
     old_player: Player,
+    /// Player controlled by the `Second*` actions, when playing with two
+    second_player: Option<Player>,
+    old_second_player: Player,
+    level: Level,
+    /// Working copy of the current room
     room: Room,
+    room_path: String,
+    room_change: Option<RoomChange>,
+    platforms: Vec<Platform>,
+    coins: Vec<Coin>,
+    enemies: Vec<Enemy>,
+    crushers: Vec<Crusher>,
+    crates: Vec<Crate>,
+    turrets: Vec<Turret>,
+    projectiles: Vec<Projectile>,
+    /// Projectiles shot by the player
+    player_projectiles: Vec<Projectile>,
+    /// Number of ticks left before the player can shoot again
+    shot_cooldown_ticks: u32,
+    particles: Particles,
+    /// Fade to black and back around the first player's respawn
+    fade: Fade,
+    /// Copies of the player drawn behind them while they sprint
+    trail: Trail,
+    /// Whether to draw the trail at all
+    afterimages: bool,
+    /// Light the room's glowing tiles give off, if the room is dark
+    light_map: LightMap,
+    /// Whether to draw dark rooms dark, rather than fully lit
+    darkness: bool,
+    /// Jolting of the view after a hard landing or a death
+    shake: Shake,
+    /// How strongly the view shakes, where 0 turns shaking off
+    shake_intensity: f32,
+    signs: Vec<Sign>,
+    /// Index of the sign whose message is shown, if any
+    shown_sign: Option<usize>,
+    /// Index of the sign whose message was dismissed, so that an automatic sign
+    /// doesn't show it again until the player has left it
+    dismissed_sign: Option<usize>,
+    keys: Vec<Key>,
+    /// Number of keys picked up since entering the current room and not yet used
+    keys_held: u32,
+    /// Indices of door tiles opened in the previous tick, to be emptied at the
+    /// start of the next one
+    opening_doors: Vec<(u32, u32)>,
+    /// Indices of the blink tiles in the current room, so that they can be updated
+    /// without going through every tile each tick
+    blink_tiles: Vec<(u32, u32)>,
+    /// Indices of the toggle blocks in the current room, for the same reason
+    toggle_blocks: Vec<(u32, u32)>,
+    /// Number of ticks each crumble tile has been stood on, keyed by tile index
+    crumbling: HashMap<(u32, u32), u32>,
+    /// Number of ticks left until each collapsed crumble tile comes back, keyed by tile index
+    crumbled: HashMap<(u32, u32), u32>,
+    /// Switch channels that were flipped an odd number of times
+    flipped_channels: Vec<u32>,
+    /// Indices of the switches touched in the previous tick
+    touching_switches: Vec<(u32, u32)>,
+    /// Indices of the one-shot switches that have been used
+    used_switches: Vec<(u32, u32)>,
+    /// Number of coins collected since entering the current room
+    coins_collected: u32,
+    /// Number of ticks simulated since entering the current room, until reaching the goal
+    timer_ticks: u32,
+    /// Number of ticks simulated since entering the current room
+    room_ticks: u32,
+    /// Number of ticks simulated since the game started
+    total_ticks: u64,
+    /// Summary of the run through the current room once its goal is reached,
+    /// which stops the game until the players move on
+    results: Option<RoomResults>,
+    /// Number of deaths since entering or restarting the current room
+    attempt_deaths: u32,
+    /// Whether the player touched a gravity flip tile in the previous tick
+    touching_gravity_flip: bool,
+    /// Whether the first and second player were inside a teleporter in the previous tick
+    in_teleporter: bool,
+    second_in_teleporter: bool,
+    /// Player rects for each tick of the current run through the room
+    run_rects: Vec<Rect>,
+    /// Best completed run through the current room, if any
+    ghost: Option<Ghost>,
+    paused: bool,
+    deaths: u32,
+    /// Number of ticks left before the player respawns after dying, or 0 while alive
+    dying_ticks: u32,
+    /// Number of ticks dying takes in total
+    death_ticks: u32,
+    /// Number of ticks left before the second player respawns after dying, or 0
+    /// while alive
+    second_dying_ticks: u32,
+    /// Number of lives left, or `None` for infinitely many
+    lives: Option<u32>,
+    /// Number of lives to start with after a game over
+    starting_lives: Option<u32>,
+    /// Whether the players ran out of lives, stopping the game until it's restarted
+    game_over: bool,
+    view_width: u32,
+    view_height: u32,
+    /// Physics config before adjusting it for the difficulty
+    physics: PhysicsConfig,
+    difficulty: Difficulty,
+    look_up_held: bool,
+    /// Direction the player is peeking in and for how many ticks, if any
+    peek: Option<Peek>,
+    peek_ticks: u32,
+    /// Number of tiles the camera moves when peeking
+    peek_distance: f32,
+    /// Current vertical camera offset in pixels, easing towards the peek target
+    peek_offset: f32,
+    /// Number of tiles the camera looks ahead of the player in the direction they face
+    camera_lead: f32,
+    /// Point the camera centers on, easing towards its target every tick, and
+    /// where it was the tick before
+    camera_center: (f32, f32),
+    old_camera_center: (f32, f32),
+    /// Index of the camera zone the player is in, if any
+    camera_zone: Option<usize>,
+    /// Offset from the current camera target to where the camera was when switching
+    /// camera zones, which shrinks to nothing over `zone_blend_total` ticks
+    zone_blend_offset: (f32, f32),
+    zone_blend_ticks: u32,
+    zone_blend_total: u32,
 }
 
 impl Model {
-    pub fn new(room: Room) -> Model {
+    /// Creates a model playing in the room stored under `room_path` in `level`,
+    /// simulating `ticks_per_second` ticks per second.
+    pub fn new(level: Level, room_path: &str, ticks_per_second: u32) -> Model {
         let player = Player::new();
-        Model {
-            frame_duration: Duration::from_secs(1) / TICKS_PER_SECOND,
+        let room = level.room(room_path).cloned().unwrap_or_else(|| {
+            warn!("Room {} is not in the level; using default room", room_path);
+            Room::default()
+        });
+        let mut model = Model {
+            frame_duration: Duration::from_secs(1) / ticks_per_second,
             time_since_last_tick: Duration::new(0, 0),
+            input_queue: Vec::new(),
+            replay_mode: ReplayMode::Off,
+            replay_tick: 0,
+            max_catch_up: Some(Duration::from_millis(250)),
+            time_scale: 1.0,
             player,
             old_player: player,
+            second_player: None,
+            old_second_player: player,
+            level,
             room,
+            room_path: room_path.to_string(),
+            room_change: None,
+            platforms: Vec::new(),
+            coins: Vec::new(),
+            enemies: Vec::new(),
+            crushers: Vec::new(),
+            crates: Vec::new(),
+            turrets: Vec::new(),
+            projectiles: Vec::new(),
+            player_projectiles: Vec::new(),
+            shot_cooldown_ticks: 0,
+            particles: Particles::default(),
+            fade: Fade::Clear,
+            trail: Trail::default(),
+            afterimages: true,
+            light_map: LightMap::default(),
+            darkness: true,
+            shake: Shake::none(),
+            shake_intensity: 1.0,
+            signs: Vec::new(),
+            shown_sign: None,
+            dismissed_sign: None,
+            keys: Vec::new(),
+            keys_held: 0,
+            opening_doors: Vec::new(),
+            blink_tiles: Vec::new(),
+            toggle_blocks: Vec::new(),
+            crumbling: HashMap::new(),
+            crumbled: HashMap::new(),
+            flipped_channels: Vec::new(),
+            touching_switches: Vec::new(),
+            used_switches: Vec::new(),
+            coins_collected: 0,
+            timer_ticks: 0,
+            room_ticks: 0,
+            total_ticks: 0,
+            results: None,
+            attempt_deaths: 0,
+            touching_gravity_flip: false,
+            in_teleporter: false,
+            second_in_teleporter: false,
+            run_rects: Vec::new(),
+            ghost: None,
+            paused: false,
+            deaths: 0,
+            dying_ticks: 0,
+            death_ticks: 1,
+            second_dying_ticks: 0,
+            lives: None,
+            starting_lives: None,
+            game_over: false,
+            view_width: 640,
+            view_height: 480,
+            physics: PhysicsConfig::default(),
+            difficulty: Difficulty::Normal,
+            look_up_held: false,
+            peek: None,
+            peek_ticks: 0,
+            peek_distance: 4.0,
+            peek_offset: 0.0,
+            camera_lead: 2.0,
+            camera_center: (0.0, 0.0),
+            old_camera_center: (0.0, 0.0),
+            camera_zone: None,
+            zone_blend_offset: (0.0, 0.0),
+            zone_blend_ticks: 0,
+            zone_blend_total: 0,
+        };
+        model.load_entities();
+        model.respawn();
+        model
+    }
+
+    pub fn room(&self) -> &Room {
+        &self.room
+    }
+
+    /// Returns the current room as it was when entering it, without changes made
+    /// by playing in it, like opened doors.
+    pub fn unchanged_room(&self) -> &Room {
+        self.level.room(&self.room_path).unwrap_or(&self.room)
+    }
+
+    pub fn room_path(&self) -> &str {
+        &self.room_path
+    }
+
+    /// Replaces the physics config, respawning the player so its size is updated.
+    pub fn set_physics(&mut self, physics: PhysicsConfig) {
+        self.physics = physics;
+        self.respawn();
+    }
+
+    /// Adds a second player controlled by the `Second*` actions, or removes them.
+    ///
+    /// The players don't collide with each other. Only the first player reaches
+    /// the goal, leads the way into other rooms and flips gravity by key.
+    pub fn set_second_player(&mut self, enabled: bool) {
+        self.second_player = if enabled { Some(Player::new()) } else { None };
+        self.respawn();
+    }
+
+    /// Sets the difficulty and restarts the current room with its starting lives.
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        debug!("Difficulty: {:?}", difficulty);
+        self.difficulty = difficulty;
+        self.set_starting_lives(difficulty.starting_lives());
+        self.reset();
+    }
+
+    /// Returns the physics config adjusted for the difficulty, in pixels of the
+    /// current room's tile size.
+    fn scaled_physics(&self) -> PhysicsConfig {
+        self.difficulty
+            .apply(&self.physics)
+            .scaled(self.room.tile_size())
+    }
+
+    /// Sets how many lives the players start with, or gives them infinitely many
+    /// when `None`.
+    pub fn set_starting_lives(&mut self, lives: Option<u32>) {
+        self.starting_lives = lives;
+        self.lives = lives;
+    }
+
+    /// Returns how many lives are left, or `None` if they're infinite.
+    pub fn lives(&self) -> Option<u32> {
+        self.lives
+    }
+
+    /// Returns whether the players ran out of lives. The game stays stopped until
+    /// it's restarted with the reset action.
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// Sets how many tiles the camera moves up or down when the player peeks.
+    pub fn set_peek_distance(&mut self, tiles: f32) {
+        self.peek_distance = tiles;
+    }
+
+    /// Sets how many tiles the camera looks ahead of the player.
+    pub fn set_camera_lead(&mut self, tiles: f32) {
+        self.camera_lead = tiles;
+    }
+
+    /// Sets whether copies of the player trail behind them while they sprint.
+    pub fn set_afterimages(&mut self, enabled: bool) {
+        self.afterimages = enabled;
+        if !enabled {
+            self.trail.clear();
         }
     }
 
-    pub fn set_room(&mut self, room: Room) {
-        self.room = room;
+    /// Sets whether rooms that are meant to be dark are drawn dark.
+    pub fn set_darkness(&mut self, enabled: bool) {
+        self.darkness = enabled;
     }
 
-    pub fn key_pressed(&mut self, keycode: Keycode) {
-        match keycode {
-            Keycode::Left => self.player.set_horiz_state(PlayerHorizState::MovingLeft),
-            Keycode::Right => self.player.set_horiz_state(PlayerHorizState::MovingRight),
-            Keycode::Up if self.player.vert_state() == PlayerVertState::Standing => {
-                self.player.set_vert_state(PlayerVertState::Jumping)
-            }
-            _ => (),
+    /// Sets how strongly the view shakes on hard landings and deaths, as a factor of
+    /// the normal strength. 0 turns shaking off.
+    pub fn set_shake_intensity(&mut self, intensity: f32) {
+        self.shake_intensity = intensity;
+        if intensity <= 0.0 {
+            self.shake = Shake::none();
         }
     }
 
-    pub fn key_released(&mut self, keycode: Keycode) {
-        match keycode {
-            Keycode::Left if self.player.horiz_state() == PlayerHorizState::MovingLeft => {
-                self.player
-                    .set_horiz_state(PlayerHorizState::StopMovingLeft)
-            }
-            Keycode::Right if self.player.horiz_state() == PlayerHorizState::MovingRight => {
-                self.player
-                    .set_horiz_state(PlayerHorizState::StopMovingRight)
-            }
-            Keycode::Up if self.player.vert_state() == PlayerVertState::Jumping => {
-                self.player.set_vert_state(PlayerVertState::Falling)
-            }
-            _ => (),
+    /// Sets the maximum size in pixels of the part of the room that is shown at once.
+    pub fn set_view_size(&mut self, width: u32, height: u32) {
+        self.view_width = width;
+        self.view_height = height;
+    }
+
+    /// Returns the room change that happened since the last call, if any.
+    pub fn take_room_change(&mut self) -> Option<RoomChange> {
+        self.room_change.take()
+    }
+
+    /// Returns how many coins have been collected since entering the current room.
+    ///
+    /// Coins stay collected when the player dies, but are put back when the
+    /// room is entered again.
+    pub fn coins(&self) -> u32 {
+        self.coins_collected
+    }
+
+    /// Returns how many keys the player holds, which are lost when leaving or
+    /// restarting the room.
+    pub fn keys(&self) -> u32 {
+        self.keys_held
+    }
+
+    /// Returns the simulated time spent in the current room until reaching its goal.
+    ///
+    /// The timer restarts when entering a room, but keeps running when the player dies.
+    pub fn elapsed(&self) -> Duration {
+        self.frame_duration * self.timer_ticks
+    }
+
+    /// Returns `elapsed()` formatted as minutes, seconds and milliseconds.
+    pub fn elapsed_string(&self) -> String {
+        format_duration(self.elapsed())
+    }
+
+    pub fn is_goal_reached(&self) -> bool {
+        self.results.is_some()
+    }
+
+    /// Returns the first player.
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    /// Returns which sides of the first player are touching something solid.
+    pub fn player_probes(&self) -> Probes {
+        self.player.probes(&self.room, &self.solid_entity_rects())
+    }
+
+    /// Returns what the first player's collision checks found during the last tick.
+    pub fn player_collisions(&self) -> &CollisionRecord {
+        &self.player.collisions
+    }
+
+    /// Returns the tile the first player is standing on, if they're standing on one.
+    pub fn standing_tile(&self) -> Option<Tile> {
+        if self.player.vert_state() != PlayerVertState::Standing {
+            return None;
         }
+        self.room
+            .tiles_in_rect(&self.player.feet_rect())
+            .into_iter()
+            .find(|tile| tile.kind.is_solid())
     }
 
-    pub fn update(&mut self, time_passed: Duration) {
-        // what happens with inputs
-        // when the game fps is higher than the model fps?
-        // answer: all inputs SHOULD BE processed at the start of a new model tick
-        self.time_since_last_tick += time_passed;
-        while self.time_since_last_tick >= self.frame_duration {
-            self.time_since_last_tick -= self.frame_duration;
-            let time_delta = self.frame_duration.as_fractional_secs() as f32;
-            self.old_player = self.player;
-            self.player.update(time_delta, &self.room);
-            let room_width = self.room.width() as f32 * self.room.tile_size() as f32;
-            if self.player.xpos >= room_width {
-                self.room = Room::default();
-                self.player.xpos -= room_width;
+    /// Returns the number of ticks simulated since the game started.
+    pub fn total_ticks(&self) -> u64 {
+        self.total_ticks
+    }
+
+    /// Returns the message of the sign the player is reading, if any.
+    pub fn active_message(&self) -> Option<&str> {
+        self.shown_sign.map(|index| self.signs[index].text())
+    }
+
+    /// Returns the summary of the run through the current room if its goal was reached.
+    pub fn results(&self) -> Option<RoomResults> {
+        self.results
+    }
+
+    pub fn health(&self) -> u32 {
+        self.player.health()
+    }
+
+    /// Returns how many times the players have died.
+    pub fn deaths(&self) -> u32 {
+        self.deaths
+    }
+
+    /// Replaces the room stored under `path` in the level and starts playing in it.
+    pub fn set_room(&mut self, path: &str, room: Room) {
+        self.level.set_room(path, room.clone());
+        self.room = room;
+        self.room_path = path.to_string();
+        self.load_entities();
+        self.respawn();
+    }
+
+    /// Sets up the simulated entities from the current room's entity list.
+    fn load_entities(&mut self) {
+        self.platforms.clear();
+        self.coins.clear();
+        self.enemies.clear();
+        self.crushers.clear();
+        self.crates.clear();
+        self.turrets.clear();
+        self.projectiles.clear();
+        self.player_projectiles.clear();
+        self.shot_cooldown_ticks = 0;
+        self.particles.clear();
+        self.trail.clear();
+        self.light_map.update(&self.room);
+        self.fade = Fade::Clear;
+        self.shake = Shake::none();
+        self.signs.clear();
+        self.shown_sign = None;
+        self.dismissed_sign = None;
+        self.keys.clear();
+        self.keys_held = 0;
+        self.opening_doors.clear();
+        self.blink_tiles = self.room
+            .tile_indices(|kind| kind == TileKind::Blink || kind == TileKind::BlinkOff);
+        self.toggle_blocks = self.room.tile_indices(|kind| kind.toggle_channel().is_some());
+        self.crumbling.clear();
+        self.crumbled.clear();
+        self.flipped_channels.clear();
+        self.touching_switches.clear();
+        self.used_switches.clear();
+        self.coins_collected = 0;
+        self.timer_ticks = 0;
+        self.room_ticks = 0;
+        self.camera_zone = None;
+        self.zone_blend_ticks = 0;
+        self.results = None;
+        self.attempt_deaths = 0;
+        self.run_rects.clear();
+        let ghost_path = Ghost::path_for_room(&self.room_path);
+        self.ghost = if ghost_path.exists() {
+            Ghost::load(&ghost_path)
+                .map_err(|e| warn!("Couldn't load ghost from {}: {}", ghost_path.display(), e))
+                .ok()
+        } else {
+            None
+        };
+        for entity in self.room.entities() {
+            match *entity {
+                Entity::Platform(ref def) => self.platforms.push(Platform::new(def.clone())),
+                Entity::Coin { x, y } => self.coins.push(Coin::new(x, y)),
+                Entity::Enemy { x, y } => self.enemies.push(Enemy::new(x, y)),
+                Entity::Crusher(ref def) => self.crushers.push(Crusher::new(def.clone())),
+                Entity::Key { x, y } => self.keys.push(Key::new(x, y)),
+                Entity::Crate { x, y, size } => self.crates.push(Crate::new(x, y, size)),
+                Entity::Turret(ref def) => self.turrets.push(Turret::new(def.clone())),
+                Entity::Sign(ref def) => self.signs.push(Sign::new(def.clone())),
             }
         }
     }
 
-    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<(), Error> {
-        let mut render_player = self.old_player;
-        let time_delta = self.time_since_last_tick.as_fractional_secs() as f32;
-        self.room.render(canvas)?;
-        render_player.xpos += self.player.xspeed * time_delta;
-        render_player.ypos += self.player.yspeed * time_delta;
-        render_player.render(canvas)?;
-        Ok(())
+    /// Puts the players at the room's spawn point with zero speed.
+    pub fn respawn(&mut self) {
+        self.respawn_first_player();
+        self.respawn_second_player();
     }
-}
 
-#[derive(Clone, Copy)]
-pub struct Player {
-    horiz_state: PlayerHorizState,
-    vert_state: PlayerVertState,
-    xpos: f32,
-    ypos: f32,
-    xspeed: f32,
-    yspeed: f32,
-    width: f32,
-    height: f32,
-}
+    fn respawn_first_player(&mut self) {
+        self.dying_ticks = 0;
+        let physics = self.scaled_physics();
+        self.player.respawn(&self.room, &physics);
+        // Don't interpolate from the previous position across the room
+        self.old_player = self.player;
+        self.trail.clear();
+    }
 
-impl Player {
-    pub fn new() -> Player {
-        Player {
-            horiz_state: PlayerHorizState::Idle,
-            vert_state: PlayerVertState::Falling,
-            xpos: 20.0,
-            ypos: 10.0,
-            xspeed: 0.0,
-            yspeed: 0.0,
-            width: 8.0,
-            height: 20.0,
+    /// Puts the second player, if any, a couple of tiles beside the spawn point,
+    /// or on it if neither side has room for them.
+    fn respawn_second_player(&mut self) {
+        const SPAWN_OFFSET_TILES: f32 = 2.0;
+        self.second_dying_ticks = 0;
+        let physics = self.scaled_physics();
+        let platform_rects = self.solid_entity_rects();
+        let room = &self.room;
+        if let Some(ref mut second) = self.second_player {
+            second.respawn(room, &physics);
+            let spawn_x = second.pos.x;
+            let offset = SPAWN_OFFSET_TILES * room.tile_size() as f32;
+            let (ypos, width, height) = (second.pos.y, second.width, second.height);
+            second.pos.x = [spawn_x + offset, spawn_x - offset]
+                .iter()
+                .cloned()
+                .find(|&x| {
+                    let rect = Rect::new(x, ypos, width, height);
+                    rect.left() >= 0.0
+                        && rect.right() <= room.pixel_width()
+                        && solids_in_rect(&rect, room, &platform_rects).is_empty()
+                })
+                .unwrap_or(spawn_x);
+            self.old_second_player = *second;
         }
     }
 
-    pub fn horiz_state(&self) -> PlayerHorizState {
-        self.horiz_state
+    pub fn is_paused(&self) -> bool {
+        self.paused
     }
 
-    pub fn set_horiz_state(&mut self, state: PlayerHorizState) {
-        if self.horiz_state == state {
-            return;
+    /// Pauses or unpauses the simulation.
+    ///
+    /// All actions are considered released when pausing, so that no movement
+    /// keys get stuck if they're let go while paused.
+    pub fn toggle_pause(&mut self) {
+        if !self.paused {
+            self.release_actions();
         }
-        self.horiz_state = state;
-        debug!("Player horiz state is now {:?}", self.horiz_state);
+        self.paused = !self.paused;
+        debug!("Paused: {}", self.paused);
     }
 
-    pub fn vert_state(&self) -> PlayerVertState {
-        self.vert_state
+    /// Releases all actions that control the player.
+    pub fn release_actions(&mut self) {
+        for &action in Action::ALL.iter() {
+            self.action_released(action);
+        }
     }
 
-    pub fn set_vert_state(&mut self, state: PlayerVertState) {
-        if self.vert_state == state {
+    pub fn handle_input(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Pressed(action) => self.action_pressed(action),
+            InputEvent::Released(action) => self.action_released(action),
+        }
+    }
+
+    /// Queues an action's key press, to be applied at the start of the next tick.
+    ///
+    /// Pausing takes effect immediately, since it doesn't affect the simulation.
+    /// Keys that move on from the results or the game over screen go through the
+    /// queue too, so that they're recorded along with the rest.
+    pub fn action_pressed(&mut self, action: Action) {
+        if self.game_over || self.results.is_some() {
+            self.input_queue.push(InputEvent::Pressed(action));
             return;
         }
-        self.vert_state = state;
-        debug!("Player vert state is now {:?}", self.vert_state);
+        if action == Action::Pause {
+            self.toggle_pause();
+        } else if !self.paused {
+            self.input_queue.push(InputEvent::Pressed(action));
+        }
+    }
+
+    /// Queues an action's key release, to be applied at the start of the next tick.
+    pub fn action_released(&mut self, action: Action) {
+        if !self.paused {
+            self.input_queue.push(InputEvent::Released(action));
+        }
+    }
+
+    /// Starts recording all input from the start of the current room.
+    pub fn start_recording(&mut self) {
+        let path = self.room_path.clone();
+        self.restart_room(&path);
+        debug!("Recording input in room {}", path);
+        self.replay_mode = ReplayMode::Recording(Replay {
+            room: path,
+            ticks_per_second: self.ticks_per_second().round() as u32,
+            inputs: Vec::new(),
+            ticks: 0,
+        });
+    }
+
+    /// Stops recording, returning the recorded input if a recording was running.
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        match mem::replace(&mut self.replay_mode, ReplayMode::Off) {
+            ReplayMode::Recording(mut replay) => {
+                replay.ticks = self.replay_tick;
+                debug!(
+                    "Recorded {} input events over {} ticks",
+                    replay.inputs.len(),
+                    replay.ticks
+                );
+                Some(replay)
+            }
+            other => {
+                self.replay_mode = other;
+                None
+            }
+        }
+    }
+
+    /// Restarts the replay's room and plays back its input, ignoring any other
+    /// input until it's done.
+    pub fn start_playback(&mut self, replay: Replay) -> Result<(), Error> {
+        ensure!(
+            self.level.room(&replay.room).is_some(),
+            "Replay starts in room {} which is not in the level",
+            replay.room
+        );
+        if (f64::from(replay.ticks_per_second) - self.ticks_per_second()).abs() > 0.5 {
+            warn!(
+                "Replay was recorded at {} ticks per second, not {}; it will play back differently",
+                replay.ticks_per_second,
+                self.ticks_per_second()
+            );
+        }
+        self.restart_room(&replay.room);
+        debug!(
+            "Playing back {} input events over {} ticks",
+            replay.inputs.len(),
+            replay.end_tick()
+        );
+        self.replay_mode = ReplayMode::Playing { replay, next: 0 };
+        Ok(())
+    }
+
+    pub fn is_playing_back(&self) -> bool {
+        match self.replay_mode {
+            ReplayMode::Playing { .. } => true,
+            ReplayMode::Off | ReplayMode::Recording(_) => false,
+        }
+    }
+
+    fn ticks_per_second(&self) -> f64 {
+        1.0 / self.frame_duration.as_fractional_secs()
+    }
+
+    /// Starts playing the room stored under `path` in the level from scratch.
+    fn restart_room(&mut self, path: &str) {
+        if let Some(room) = self.level.room(path).cloned() {
+            self.room = room;
+            self.room_path = path.to_string();
+        }
+        self.input_queue.clear();
+        self.time_since_last_tick = Duration::new(0, 0);
+        self.replay_tick = 0;
+        self.deaths = 0;
+        self.lives = self.starting_lives;
+        self.game_over = false;
+        self.reset();
+    }
+
+    /// Restarts the run through the current room.
+    ///
+    /// The player respawns with full health, and coins, keys, doors, enemies,
+    /// platforms and the timer are put back as they were when entering the room. The death count
+    /// and any recording or playback carry on.
+    pub fn reset(&mut self) {
+        debug!("Resetting room {}", self.room_path);
+        // Close the doors opened during the run
+        if let Some(room) = self.level.room(&self.room_path).cloned() {
+            self.room = room;
+        }
+        self.load_entities();
+        self.respawn();
     }
 
-    pub fn update(&mut self, dt: f32, room: &Room) {
-        const WALK_SPEED: f32 = 120.0; // Maximum walk speed, in pixels per second
-        const WALK_TIME: f32 = 0.2; // Time to go from 0 to `WALK_SPEED`, in seconds
-        const WALK_ACCEL: f32 = WALK_SPEED / WALK_TIME;
-        const STOP_TIME: f32 = 0.3; // Time to go from `WALK_SPEED` back to 0
-        const STOP_ACCEL: f32 = WALK_SPEED / STOP_TIME;
-        const FALL_SPEED: f32 = 300.0;
-        const FALL_TIME: f32 = 1.0;
-        const FALL_ACCEL: f32 = FALL_SPEED / FALL_TIME;
-        const JUMP_SPEED: f32 = -130.0;
-        const JUMP_TIME: f32 = 0.1;
-        const JUMP_ACCEL: f32 = JUMP_SPEED / JUMP_TIME;
-
-        let (xaccel, xminspeed, xmaxspeed) = match self.horiz_state {
-            PlayerHorizState::Idle => (0.0, 0.0, 0.0),
-            PlayerHorizState::MovingLeft => (-WALK_ACCEL, -WALK_SPEED, WALK_SPEED),
-            PlayerHorizState::MovingRight => (WALK_ACCEL, -WALK_SPEED, WALK_SPEED),
-            PlayerHorizState::StopMovingLeft => (STOP_ACCEL, -WALK_SPEED, 0.0),
-            PlayerHorizState::StopMovingRight => (-STOP_ACCEL, 0.0, WALK_SPEED),
+    /// Moves on from the results of the current room to the room its first exit
+    /// leads to, or restarts it if it has no exits.
+    fn leave_results(&mut self) {
+        let exit = match self.room.exits().first() {
+            Some(exit) => exit.clone(),
+            None => {
+                self.reset();
+                return;
+            }
         };
-        let yaccel = match self.vert_state {
-            PlayerVertState::Standing => 0.0,
-            PlayerVertState::Falling => FALL_ACCEL,
-            PlayerVertState::Jumping => JUMP_ACCEL,
+        let room = match self.level.room(&exit.room) {
+            Some(room) => room.clone(),
+            None => {
+                error!("Exit leads to room {} which is not in the level", exit.room);
+                self.reset();
+                return;
+            }
         };
+        debug!("Moving on to room {}", exit.room);
+        self.room = room;
+        self.room_path = exit.room.clone();
+        self.load_entities();
+        self.respawn();
+        self.room_change = Some(RoomChange {
+            room: exit.room,
+            edge: exit.edge,
+        });
+    }
 
-        // Calculate new speed based on acceleration
-        self.xspeed = (self.xspeed + xaccel * dt).min(xmaxspeed).max(xminspeed);
-        self.yspeed = (self.yspeed + yaccel * dt).min(FALL_SPEED).max(JUMP_SPEED);
+    /// Restarts the current room with the starting number of lives.
+    fn restart_after_game_over(&mut self) {
+        debug!("Restarting after game over");
+        self.game_over = false;
+        self.lives = self.starting_lives;
+        self.time_since_last_tick = Duration::new(0, 0);
+        self.reset();
+    }
 
-        // Calculate new position based on speed
-        self.xpos += self.xspeed * dt;
-        self.ypos += self.yspeed * dt;
+    /// Applies all queued input events in the order they happened.
+    ///
+    /// While recording, the events are also stored with the current tick. While
+    /// playing back, the recorded events for the current tick are applied instead.
+    fn apply_input(&mut self) {
+        let mut events: Vec<InputEvent> = self.input_queue.drain(..).collect();
+        let tick = self.replay_tick;
+        self.replay_tick += 1;
+        let mut finished = false;
+        match self.replay_mode {
+            ReplayMode::Off => (),
+            ReplayMode::Recording(ref mut replay) => {
+                replay
+                    .inputs
+                    .extend(events.iter().map(|&event| (tick, event)));
+            }
+            ReplayMode::Playing {
+                ref replay,
+                ref mut next,
+            } => {
+                events = replay.inputs[*next..]
+                    .iter()
+                    .take_while(|&&(event_tick, _)| event_tick == tick)
+                    .map(|&(_, event)| event)
+                    .collect();
+                *next += events.len();
+                finished = tick + 1 >= replay.end_tick();
+            }
+        }
+        if finished {
+            debug!("Replay finished after {} ticks", tick + 1);
+            self.replay_mode = ReplayMode::Off;
+        }
+        for event in events {
+            match event {
+                // Only the keys that move on work on the game over and results screens
+                InputEvent::Pressed(action) if self.game_over => {
+                    if action == Action::Reset {
+                        self.restart_after_game_over();
+                    }
+                }
+                InputEvent::Pressed(action) if self.results.is_some() => {
+                    if action == Action::Jump {
+                        self.leave_results();
+                    }
+                }
+                // Keep the players still while the view fades back in after a respawn
+                InputEvent::Pressed(_) if self.fade.is_fading_in() => (),
+                InputEvent::Pressed(action) => self.apply_action_pressed(action),
+                InputEvent::Released(action) => self.apply_action_released(action),
+            }
+        }
+    }
 
-        // Change horizontal state to idle when player has stopped moving
-        if self.xspeed == 0.0 {
-            self.set_horiz_state(PlayerHorizState::Idle);
+    fn apply_action_pressed(&mut self, action: Action) {
+        if let Some(action) = action.for_second_player() {
+            if let Some(ref mut second) = self.second_player {
+                second.action_pressed(action, &self.room);
+            }
+            return;
+        }
+        // Only restarting works while dying
+        if self.is_dying() && action != Action::Reset {
+            return;
         }
+        if action == Action::LookUp {
+            self.look_up_held = true;
+            self.read_sign();
+        }
+        match action {
+            Action::Reset => self.reset(),
+            Action::Shoot if self.room.allows_shooting() => self.shoot(),
+            Action::FlipGravity if self.room.allows_gravity_flip_key() => {
+                self.player.flip_gravity()
+            }
+            _ => self.player.action_pressed(action, &self.room),
+        }
+    }
 
-        // Change vertical state to falling when player has reached maximum jump speed
-        if self.yspeed == JUMP_SPEED {
-            self.set_vert_state(PlayerVertState::Falling);
+    fn apply_action_released(&mut self, action: Action) {
+        if let Some(action) = action.for_second_player() {
+            if let Some(ref mut second) = self.second_player {
+                second.action_released(action);
+            }
+            return;
+        }
+        if action == Action::LookUp {
+            self.look_up_held = false;
         }
+        self.player.action_released(action);
+    }
 
-        // Stop horizontal movement when walking into a wall
-        if self.xspeed > 0.0 {
-            let tile1_right = room.tile_at_coord(self.xpos + self.width, self.ypos + 0.5);
-            let tile2_right =
-                room.tile_at_coord(self.xpos + self.width, self.ypos + self.height - 0.5);
-            if (tile1_right.kind == TileKind::Filled
-                && room.tile_at_index(tile1_right.x - 1, tile1_right.y).kind == TileKind::Empty)
-                || (tile2_right.kind == TileKind::Filled
-                    && room.tile_at_index(tile2_right.x - 1, tile2_right.y).kind == TileKind::Empty)
-            {
-                trace!("Player hit wall to the right");
-                self.xspeed = 0.0;
-                self.xpos = tile1_right.rect.left() - self.width;
-            }
-        } else if self.xspeed < 0.0 {
-            let tile1_left = room.tile_at_coord(self.xpos, self.ypos + 0.5);
-            let tile2_left = room.tile_at_coord(self.xpos, self.ypos + self.height - 0.5);
-            if (tile1_left.kind == TileKind::Filled
-                && room.tile_at_index(tile1_left.x + 1, tile1_left.y).kind == TileKind::Empty)
-                || (tile2_left.kind == TileKind::Filled
-                    && room.tile_at_index(tile2_left.x + 1, tile2_left.y).kind == TileKind::Empty)
-            {
-                trace!("Player hit wall to the left");
-                self.xspeed = 0.0;
-                self.xpos = tile1_left.rect.right();
-            }
-        }
-
-        if self.yspeed >= 0.0 {
-            // Handle presence or absence of floor below player
-            let tile1_below = room.tile_at_coord(self.xpos + 0.5, self.ypos + self.height);
-            let tile2_below =
-                room.tile_at_coord(self.xpos + self.width - 0.5, self.ypos + self.height);
-            match (tile1_below.kind, tile2_below.kind) {
-                // Stand if either tile is filled
-                (TileKind::Filled, _) | (_, TileKind::Filled) => {
-                    if self.yspeed > 0.0 {
-                        trace!("Player hit floor");
-                        self.set_vert_state(PlayerVertState::Standing);
-                        self.yspeed = 0.0;
-                        self.ypos = tile1_below.rect.top() - self.height;
+    /// Limits how much time a single call to `update` simulates, or removes the limit
+    /// when `None`. Time passed beyond the limit is dropped, so that a long hiccup
+    /// doesn't freeze the game while it catches up.
+    pub fn set_max_catch_up(&mut self, max_catch_up: Option<Duration>) {
+        self.max_catch_up = max_catch_up;
+    }
+
+    /// Sets the factor by which time passes slower or faster in the simulation,
+    /// clamped to between 1/16 and 4. Ticks keep simulating the same amount of
+    /// time, so only the number of ticks per second of real time changes.
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        const MIN_TIME_SCALE: f64 = 1.0 / 16.0;
+        const MAX_TIME_SCALE: f64 = 4.0;
+        self.time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    pub fn update(&mut self, time_passed: Duration) {
+        if self.paused {
+            // Discard the time passed, so none of it is simulated after unpausing
+            return;
+        }
+        let scaled_secs = time_passed.as_fractional_secs() * self.time_scale;
+        let scaled_nanos = (scaled_secs * 1e9).round() as u64;
+        self.time_since_last_tick += Duration::new(
+            scaled_nanos / 1_000_000_000,
+            (scaled_nanos % 1_000_000_000) as u32,
+        );
+        if let Some(max_catch_up) = self.max_catch_up {
+            if self.time_since_last_tick > max_catch_up {
+                warn!(
+                    "Model is {} behind; skipping ahead to catch up at most {}",
+                    TimeFormat(self.time_since_last_tick),
+                    TimeFormat(max_catch_up)
+                );
+                self.time_since_last_tick = max_catch_up;
+            }
+        }
+        while self.time_since_last_tick >= self.frame_duration {
+            self.time_since_last_tick -= self.frame_duration;
+            let time_delta = self.frame_duration.as_fractional_secs() as f32;
+            let (tick_start_player, tick_start_second) = (self.player, self.second_player);
+            // Apply input at the start of a tick, so its effect doesn't depend on the frame rate
+            self.apply_input();
+            if self.game_over || self.results.is_some() {
+                // Only wait for the key that moves on, with everything standing still
+                self.old_player = self.player;
+                if let Some(second) = self.second_player {
+                    self.old_second_player = second;
+                }
+                self.old_camera_center = self.camera_center;
+                continue;
+            }
+            self.fade.tick();
+            self.shake.tick();
+            if self.dying_ticks > 0 {
+                self.dying_ticks -= 1;
+                if self.dying_ticks == 0 {
+                    if self.lives == Some(0) {
+                        debug!("Game over");
+                        self.game_over = true;
+                        return;
                     }
+                    self.respawn_hazards();
+                    self.respawn_first_player();
+                    self.fade = Fade::fading_in(self.seconds_to_ticks(FADE_TIME));
                 }
-                // Fall if standing and both tiles are empty
-                (TileKind::Empty, TileKind::Empty) => {
-                    if self.yspeed == 0.0 {
-                        trace!("Player fall from ledge");
-                        if self.vert_state == PlayerVertState::Standing {
-                            self.set_vert_state(PlayerVertState::Falling);
-                        }
+            }
+            if self.second_dying_ticks > 0 {
+                self.second_dying_ticks -= 1;
+                if self.second_dying_ticks == 0 {
+                    if self.lives == Some(0) {
+                        debug!("Game over");
+                        self.game_over = true;
+                        return;
                     }
+                    self.respawn_hazards();
+                    self.respawn_second_player();
                 }
             }
-        } else {
-            // Stop vertical movement when hitting a ceiling
-            let tile1_above = room.tile_at_coord(self.xpos + 0.5, self.ypos);
-            let tile2_above = room.tile_at_coord(self.xpos + self.width - 0.5, self.ypos);
-            match (tile1_above.kind, tile2_above.kind) {
-                (TileKind::Filled, _) | (_, TileKind::Filled) => {
-                    trace!("Player hit ceiling");
-                    self.set_vert_state(PlayerVertState::Falling);
-                    self.yspeed = 0.0;
-                    self.ypos = tile1_above.rect.bottom();
+            self.open_doors();
+            self.update_blink_tiles();
+            self.update_toggle_blocks();
+            self.room_ticks += 1;
+            self.total_ticks += 1;
+            self.old_player = self.player;
+            if let Some(second) = self.second_player {
+                self.old_second_player = second;
+            }
+            self.update_platforms(time_delta);
+            let (crushed, second_crushed) = self.update_crushers(time_delta);
+            if crushed {
+                self.kill_player();
+            }
+            if second_crushed {
+                self.kill_second_player();
+            }
+            let (crushed, second_crushed) = self.update_crates(time_delta);
+            if crushed {
+                debug!("Player was hit by a falling crate");
+                self.kill_player();
+            }
+            if second_crushed {
+                self.kill_second_player();
+            }
+            let platform_rects = self.solid_entity_rects();
+            if !self.is_dying() && !self.player.unstick(&self.room, &platform_rects) {
+                warn!("Player is stuck inside a wall; respawning");
+                self.respawn_first_player();
+            }
+            let second_stuck = match self.second_player {
+                Some(ref mut second) if self.second_dying_ticks == 0 => {
+                    !second.unstick(&self.room, &platform_rects)
+                }
+                _ => false,
+            };
+            if second_stuck {
+                warn!("Second player is stuck inside a wall; respawning");
+                self.respawn_second_player();
+            }
+            let physics = self.scaled_physics();
+            if !self.is_dying() {
+                self.player
+                    .update(time_delta, &self.room, &platform_rects, &physics);
+            }
+            if let Some(ref mut second) = self.second_player {
+                if self.second_dying_ticks == 0 {
+                    second.update(time_delta, &self.room, &platform_rects, &physics);
                 }
-                _ => (),
+            }
+            for enemy in &mut self.enemies {
+                enemy.update(time_delta, &self.room);
+            }
+            self.particles.update(time_delta);
+            // Only leave a trail when actually going faster than walking
+            let trailing = self.afterimages && !self.is_dying() && self.player.sprinting
+                && self.player.vel.x.abs() > physics.walk_speed;
+            self.trail.update(if trailing { Some(self.player.rect()) } else { None });
+            let (player, second_player) = (self.player, self.second_player);
+            self.react_to_movement(&tick_start_player, &player, &physics);
+            if let (Some(before), Some(after)) = (tick_start_second, second_player) {
+                self.react_to_movement(&before, &after, &physics);
+            }
+            let (shot, second_shot) = self.update_turrets(time_delta);
+            self.update_player_projectiles(time_delta);
+            self.update_crumble_tiles();
+            self.collect_coins();
+            self.collect_keys();
+            self.touch_doors();
+            self.touch_gravity_flip_tiles();
+            self.touch_switches();
+            self.update_signs();
+            if teleport(&mut self.player, &self.room, &mut self.in_teleporter) {
+                self.old_player = self.player;
+            }
+            if let Some(ref mut second) = self.second_player {
+                if teleport(second, &self.room, &mut self.second_in_teleporter) {
+                    self.old_second_player = *second;
+                }
+            }
+            self.light_map.update(&self.room);
+            self.update_peek(time_delta);
+            self.update_camera_zone();
+            self.update_camera(time_delta);
+            self.timer_ticks += 1;
+            self.run_rects.push(self.player.rect());
+            if self.room
+                .tiles_in_rect(&self.player.rect())
+                .iter()
+                .any(|tile| tile.kind == TileKind::Goal)
+            {
+                debug!("Reached goal in {}", self.elapsed_string());
+                self.save_best_run();
+                self.results = Some(RoomResults {
+                    time: self.elapsed(),
+                    coins: self.coins_collected,
+                    total_coins: self.coins.len() as u32,
+                    deaths: self.attempt_deaths,
+                });
+                return;
+            }
+            let second_died = match self.second_player {
+                Some(ref mut second) if self.second_dying_ticks == 0 => {
+                    let old_second = self.old_second_player;
+                    let contact = handle_enemy_contact(&mut self.enemies, &old_second, second);
+                    let died = match contact.or(second_shot) {
+                        Some(source_x) => second.hurt(source_x),
+                        None => false,
+                    };
+                    // Without an exit of their own, leaving the room is as good as falling out
+                    let room_rect =
+                        Rect::new(0.0, 0.0, self.room.pixel_width(), self.room.pixel_height());
+                    died || !second.rect().overlaps(&room_rect)
+                }
+                _ => false,
+            };
+            if second_died {
+                self.kill_second_player();
+            }
+            if self.is_dying() {
+                continue;
+            }
+            let contact =
+                handle_enemy_contact(&mut self.enemies, &self.old_player, &mut self.player);
+            let died = match contact.or(shot) {
+                Some(source_x) => self.player.hurt(source_x),
+                None => false,
+            };
+            if died {
+                self.kill_player();
+            } else if let Some(edge) = self.exited_edge() {
+                self.change_room(edge);
+            } else if self.player.pos.y > self.room.pixel_height()
+                || (self.player.is_gravity_flipped()
+                    && self.player.pos.y + self.player.height < 0.0)
+            {
+                self.kill_player();
             }
         }
+    }
 
-        trace!(
-            "Player accel: ({}, {}), speed: ({}, {}), pos: ({}, {})",
-            xaccel,
-            yaccel,
-            self.xspeed,
-            self.yspeed,
-            self.xpos,
-            self.ypos
-        );
+    /// Moves the camera up or down while the player stands still holding look up or
+    /// down, easing back once released. Moving cancels peeking immediately.
+    fn update_peek(&mut self, dt: f32) {
+        // Fraction of the remaining distance the camera moves per second
+        const PEEK_EASE_RATE: f32 = 6.0;
+        let standing_still = self.player.vert_state() == PlayerVertState::Standing
+            && self.player.horiz_state() == PlayerHorizState::Idle;
+        let peek = if !standing_still {
+            None
+        } else if self.look_up_held {
+            Some(Peek::Up)
+        } else if self.player.is_crouch_held() {
+            Some(Peek::Down)
+        } else {
+            None
+        };
+        if peek != self.peek {
+            self.peek = peek;
+            self.peek_ticks = 0;
+        }
+        self.peek_ticks += 1;
+        let held_time = self.peek_ticks as f32 * dt;
+        let distance = self.peek_distance * self.room.tile_size() as f32;
+        let target = camera::peek_offset(self.peek, held_time, distance);
+        let horiz_state = self.player.horiz_state();
+        let moving = horiz_state == PlayerHorizState::MovingLeft
+            || horiz_state == PlayerHorizState::MovingRight;
+        self.peek_offset = if moving {
+            0.0
+        } else {
+            self.peek_offset + (target - self.peek_offset) * (PEEK_EASE_RATE * dt).min(1.0)
+        };
     }
 
-    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<(), Error> {
-        let x = self.xpos.round() as i32;
-        let y = self.ypos.round() as i32;
-        let w = self.width.round() as u32;
-        let h = self.height.round() as u32;
-        canvas.set_draw_color(Color::RGB(0xff, 0xff, 0xff));
-        canvas.fill_rect(SdlRect::new(x, y, w, h)).map_err(err_msg)?;
-        Ok(())
+    /// Flips gravity when the player starts touching a gravity flip tile.
+    fn touch_gravity_flip_tiles(&mut self) {
+        let touching = self.room
+            .tiles_in_rect(&self.player.rect())
+            .iter()
+            .any(|tile| tile.kind == TileKind::GravityFlip);
+        if touching && !self.touching_gravity_flip {
+            self.player.flip_gravity();
+        }
+        self.touching_gravity_flip = touching;
     }
-}
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum PlayerHorizState {
-    Idle,
-    MovingLeft,
-    MovingRight,
-    StopMovingLeft,
-    StopMovingRight,
-}
+    /// Stores the run that just reached the goal as the room's ghost if it beats the current one.
+    fn save_best_run(&mut self) {
+        if let Some(ref ghost) = self.ghost {
+            if ghost.ticks() <= self.run_rects.len() {
+                return;
+            }
+        }
+        let ghost = Ghost {
+            rects: self.run_rects.clone(),
+        };
+        let ghost_path = Ghost::path_for_room(&self.room_path);
+        debug!("New best run; saving ghost to {}", ghost_path.display());
+        if let Err(e) = ghost.save(&ghost_path) {
+            warn!("Couldn't save ghost to {}: {}", ghost_path.display(), e);
+        }
+        self.ghost = Some(ghost);
+    }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum PlayerVertState {
-    Standing,
-    Falling,
-    Jumping,
+    /// Returns the edge with an exit that the player's center has crossed, if any.
+    fn exited_edge(&self) -> Option<Edge> {
+        let center_x = self.player.pos.x + self.player.width / 2.0;
+        let center_y = self.player.pos.y + self.player.height / 2.0;
+        let edge = if center_x < 0.0 {
+            Edge::Left
+        } else if center_x >= self.room.pixel_width() {
+            Edge::Right
+        } else if center_y < 0.0 {
+            Edge::Top
+        } else if center_y >= self.room.pixel_height() {
+            Edge::Bottom
+        } else {
+            return None;
+        };
+        self.room.exit(edge).map(|_| edge)
+    }
+
+    /// Moves the player through the exit at `edge` into the linked room,
+    /// keeping their speed and their position along the edge.
+    fn change_room(&mut self, edge: Edge) {
+        let path = match self.room.exit(edge) {
+            Some(exit) => exit.room.clone(),
+            None => return,
+        };
+        let new_room = match self.level.room(&path) {
+            Some(room) => room.clone(),
+            None => {
+                error!("Exit leads to room {} which is not in the level", path);
+                return;
+            }
+        };
+        let shift = match edge {
+            Edge::Left => Vec2::new(new_room.pixel_width(), 0.0),
+            Edge::Right => Vec2::new(-self.room.pixel_width(), 0.0),
+            // Enter with the whole player inside the new room rather than partly below it.
+            // Like walking off a ledge, they fall back out unless they go up far enough.
+            Edge::Top => {
+                let new_ypos = (self.player.pos.y + new_room.pixel_height())
+                    .min(new_room.pixel_height() - self.player.height);
+                Vec2::new(0.0, new_ypos - self.player.pos.y)
+            }
+            Edge::Bottom => Vec2::new(0.0, -self.room.pixel_height()),
+        };
+        debug!("Player exits through {:?} edge into room {}", edge, path);
+        self.player.pos += shift;
+        self.player.release_grapple();
+        // Move the previous position along so interpolation stays continuous
+        self.old_player.pos += shift;
+        // Bring the second player along, wherever they were
+        if let Some(ref mut second) = self.second_player {
+            second.pos = self.player.pos;
+            self.old_second_player = *second;
+        }
+        self.room = new_room;
+        self.room_path = path.clone();
+        self.load_entities();
+        self.room_change = Some(RoomChange { room: path, edge });
+    }
+
+    /// Moves the platforms along their paths, carrying the players who
+    /// stand on one and pushing the others out of the way.
+    fn update_platforms(&mut self, dt: f32) {
+        for platform in &mut self.platforms {
+            let old_rect = platform.rect();
+            let (dx, dy) = platform.update(dt);
+            let rect = platform.rect();
+            for player in iter::once(&mut self.player).chain(self.second_player.as_mut()) {
+                if player.is_standing_on(&old_rect) {
+                    player.carry(dx, &rect, &self.room);
+                } else if player.rect().overlaps(&rect) {
+                    player.push_out(dx, dy, &rect);
+                }
+            }
+        }
+    }
+
+    fn collect_keys(&mut self) {
+        let player_rects: Vec<Rect> = iter::once(&self.player)
+            .chain(self.second_player.as_ref())
+            .map(|player| player.rect())
+            .collect();
+        for key in &mut self.keys {
+            let key_rect = key.rect();
+            if !key.is_collected() && player_rects.iter().any(|rect| rect.overlaps(&key_rect)) {
+                key.collect();
+                self.keys_held += 1;
+                debug!("Picked up key; holding {}", self.keys_held);
+            }
+        }
+    }
+
+    /// Uses up a held key for each door a player touches from the side, if there are
+    /// enough. A door is a group of door tiles next to each other, which all open
+    /// together, so that a door taller than a tile doesn't take several keys.
+    ///
+    /// The doors only open at the start of the next tick, so that the tiles don't
+    /// change while the players are being collided with them.
+    fn touch_doors(&mut self) {
+        // How close a player has to be to a door to touch it
+        const REACH: f32 = 1.0;
+        let players = iter::once(&self.player).chain(self.second_player.as_ref());
+        for player in players {
+            let rect = player.rect();
+            // Only reach sideways, so that standing on a door doesn't open it
+            let reach_rect = Rect::new(
+                rect.left() - REACH,
+                rect.top(),
+                rect.right() - rect.left() + 2.0 * REACH,
+                rect.bottom() - rect.top(),
+            );
+            for tile in self.room.tiles_in_rect(&reach_rect) {
+                let index = (tile.x as u32, tile.y as u32);
+                if self.keys_held == 0 {
+                    return;
+                }
+                if tile.kind == TileKind::Door && !self.opening_doors.contains(&index) {
+                    self.keys_held -= 1;
+                    self.opening_doors.extend(self.room.connected_tiles(index.0, index.1));
+                    debug!("Opened door at {:?}; holding {} keys", index, self.keys_held);
+                }
+            }
+        }
+    }
+
+    /// Empties the door tiles opened in the previous tick.
+    fn open_doors(&mut self) {
+        for (x, y) in self.opening_doors.drain(..) {
+            self.room
+                .set_tile_at_index(x, y, TileKind::Empty)
+                .unwrap_or_else(|error| error!("{}", error));
+        }
+    }
+
+    /// Makes the blink tiles solid or intangible as their cycle says.
+    ///
+    /// A blink tile that should become solid while a player overlaps it waits
+    /// until they're clear of it, so they never get stuck inside.
+    fn update_blink_tiles(&mut self) {
+        let time = self.room_ticks as f32 * self.frame_duration.as_fractional_secs() as f32;
+        let (solid, _) = self.room.blink_state(time);
+        let player_rects: Vec<Rect> = iter::once(&self.player)
+            .chain(self.second_player.as_ref())
+            .map(|player| player.rect())
+            .collect();
+        for &(x, y) in &self.blink_tiles {
+            let tile = self.room.tile_at_index(x as i32, y as i32);
+            let kind = match tile.kind {
+                TileKind::Blink if !solid => TileKind::BlinkOff,
+                TileKind::BlinkOff
+                    if solid && !player_rects.iter().any(|rect| rect.overlaps(&tile.rect)) =>
+                {
+                    TileKind::Blink
+                }
+                _ => continue,
+            };
+            self.room
+                .set_tile_at_index(x, y, kind)
+                .unwrap_or_else(|error| error!("{}", error));
+        }
+    }
+
+    /// Flips the channel of each switch a player starts touching, unless it's a
+    /// one-shot switch that was used already. The toggle blocks change at the
+    /// start of the next tick.
+    fn touch_switches(&mut self) {
+        let mut touching = Vec::new();
+        for player in iter::once(&self.player).chain(self.second_player.as_ref()) {
+            for tile in self.room.tiles_in_rect(&player.rect()) {
+                let index = (tile.x as u32, tile.y as u32);
+                if let TileKind::Switch { channel, one_shot } = tile.kind {
+                    if touching.contains(&index) {
+                        continue;
+                    }
+                    touching.push(index);
+                    let was_touching = self.touching_switches.contains(&index);
+                    if was_touching || self.used_switches.contains(&index) {
+                        continue;
+                    }
+                    debug!("Switch at {:?} flips channel {}", index, channel);
+                    match self.flipped_channels.iter().position(|&c| c == channel) {
+                        Some(position) => {
+                            self.flipped_channels.remove(position);
+                        }
+                        None => self.flipped_channels.push(channel),
+                    }
+                    if one_shot {
+                        self.used_switches.push(index);
+                    }
+                }
+            }
+        }
+        self.touching_switches = touching;
+    }
+
+    /// Makes the toggle blocks solid or intangible as their channel says.
+    ///
+    /// Like blink tiles, a toggle block that should become solid while a player
+    /// overlaps it waits until they're clear of it.
+    fn update_toggle_blocks(&mut self) {
+        let player_rects: Vec<Rect> = iter::once(&self.player)
+            .chain(self.second_player.as_ref())
+            .map(|player| player.rect())
+            .collect();
+        let original_room = match self.level.room(&self.room_path) {
+            Some(room) => room,
+            None => return,
+        };
+        for &(x, y) in &self.toggle_blocks {
+            let tile = self.room.tile_at_index(x as i32, y as i32);
+            let channel = match tile.kind.toggle_channel() {
+                Some(channel) => channel,
+                None => continue,
+            };
+            let solid_at_start = original_room.tile_at_index(x as i32, y as i32).kind.is_solid();
+            let solid = solid_at_start != self.flipped_channels.contains(&channel);
+            let kind = match tile.kind {
+                TileKind::ToggleBlock(_) if !solid => TileKind::ToggleBlockOff(channel),
+                TileKind::ToggleBlockOff(_)
+                    if solid && !player_rects.iter().any(|rect| rect.overlaps(&tile.rect)) =>
+                {
+                    TileKind::ToggleBlock(channel)
+                }
+                _ => continue,
+            };
+            self.room
+                .set_tile_at_index(x, y, kind)
+                .unwrap_or_else(|error| error!("{}", error));
+        }
+    }
+
+    /// Collapses crumble tiles that have been stood on for long enough, and brings
+    /// collapsed ones back if the room says so and no player is in the way.
+    fn update_crumble_tiles(&mut self) {
+        let crumble_ticks = self.seconds_to_ticks(CRUMBLE_TIME);
+        let regenerate_ticks = self.seconds_to_ticks(CRUMBLE_REGENERATE_TIME);
+        // Bring tiles back before collapsing others, so a tile that collapses now isn't
+        // counted down already
+        if self.room.crumble_regenerates() {
+            let player_rects: Vec<Rect> = iter::once(&self.player)
+                .chain(self.second_player.as_ref())
+                .map(|player| player.rect())
+                .collect();
+            let room = &mut self.room;
+            self.crumbled.retain(|&(x, y), ticks_left| {
+                *ticks_left = ticks_left.saturating_sub(1);
+                let tile_rect = room.tile_at_index(x as i32, y as i32).rect;
+                if *ticks_left > 0 || player_rects.iter().any(|rect| rect.overlaps(&tile_rect)) {
+                    return true;
+                }
+                room.set_tile_at_index(x, y, TileKind::Crumble)
+                    .unwrap_or_else(|error| error!("{}", error));
+                false
+            });
+        }
+        let players: Vec<Player> = iter::once(self.player)
+            .chain(self.second_player)
+            .filter(|player| player.vert_state() == PlayerVertState::Standing)
+            .collect();
+        let mut stood_on = Vec::new();
+        for player in &players {
+            for tile in self.room.tiles_in_rect(&player.feet_rect()) {
+                let index = (tile.x as u32, tile.y as u32);
+                if tile.kind == TileKind::Crumble && !stood_on.contains(&index) {
+                    stood_on.push(index);
+                }
+            }
+        }
+        for index in stood_on {
+            let ticks = self.crumbling.entry(index).or_insert(0);
+            *ticks += 1;
+            if *ticks >= crumble_ticks {
+                debug!("Crumble tile at {:?} collapses", index);
+                self.crumbling.remove(&index);
+                self.crumbled.insert(index, regenerate_ticks);
+                self.room
+                    .set_tile_at_index(index.0, index.1, TileKind::Empty)
+                    .unwrap_or_else(|error| error!("{}", error));
+            }
+        }
+    }
+
+    /// Returns the number of ticks closest to `seconds`, but at least 1.
+    fn seconds_to_ticks(&self, seconds: f32) -> u32 {
+        ((f64::from(seconds) * self.ticks_per_second()).round() as u32).max(1)
+    }
+
+    /// Moves the crushers along their cycles, pushing the players out of the way.
+    ///
+    /// Returns whether the first and the second player were crushed, which is when
+    /// there isn't enough free space behind them to be pushed into.
+    fn update_crushers(&mut self, dt: f32) -> (bool, bool) {
+        let mut crushed = (false, false);
+        for index in 0..self.crushers.len() {
+            let (dx, dy) = self.crushers[index].update(dt);
+            let rect = self.crushers[index].rect();
+            // Everything solid besides this crusher that a player can be pinched against
+            let solids: Vec<Rect> = self.platforms
+                .iter()
+                .map(|platform| platform.rect())
+                .chain(
+                    self.crushers
+                        .iter()
+                        .enumerate()
+                        .filter(|&(other, _)| other != index)
+                        .map(|(_, crusher)| crusher.rect()),
+                )
+                .collect();
+            crushed.0 |= push_or_crush(&mut self.player, dx, dy, &rect, &self.room, &solids);
+            if let Some(ref mut second) = self.second_player {
+                crushed.1 |= push_or_crush(second, dx, dy, &rect, &self.room, &solids);
+            }
+        }
+        crushed
+    }
+
+    /// Returns the rects of platforms, crushers, crates and turrets, which are solid like
+    /// filled tiles.
+    fn solid_entity_rects(&self) -> Vec<Rect> {
+        self.platforms
+            .iter()
+            .map(|platform| platform.rect())
+            .chain(self.crushers.iter().map(|crusher| crusher.rect()))
+            .chain(self.crates.iter().map(|crate_| crate_.rect()))
+            .chain(self.turrets.iter().map(|turret| turret.rect()))
+            .collect()
+    }
+
+    /// Fires a projectile from the player in the direction they're facing, unless
+    /// too many are flying already or the last shot was too recent.
+    fn shoot(&mut self) {
+        // Most projectiles shot by the player in the air at once
+        const MAX_PROJECTILES: usize = 2;
+        // Seconds between shots
+        const COOLDOWN: f32 = 0.25;
+        // Speed of the projectiles in tiles per second
+        const SPEED: f32 = 16.0;
+        if self.shot_cooldown_ticks > 0 || self.player_projectiles.len() >= MAX_PROJECTILES {
+            return;
+        }
+        let speed = SPEED * self.room.tile_size() as f32;
+        let xspeed = self.player.facing().delta().0 as f32 * speed;
+        let (x, y) = self.player.center();
+        self.player_projectiles.push(Projectile::new(x, y, xspeed, 0.0));
+        self.shot_cooldown_ticks = self.seconds_to_ticks(COOLDOWN);
+    }
+
+    /// Moves the player's projectiles, removing those that hit a filled tile or leave
+    /// the room, and defeating the first enemy each one hits.
+    fn update_player_projectiles(&mut self, dt: f32) {
+        self.shot_cooldown_ticks = self.shot_cooldown_ticks.saturating_sub(1);
+        let projectiles: Vec<Projectile> = self.player_projectiles.drain(..).collect();
+        for mut projectile in projectiles {
+            let (path, flying) = projectile.update(dt, &self.room);
+            // Follow the path in order, so the projectile hits the first enemy in its way
+            let enemies = &self.enemies;
+            let hit = path.iter()
+                .filter_map(|rect| enemies.iter().position(|enemy| rect.overlaps(&enemy.rect())))
+                .next();
+            if let Some(index) = hit {
+                debug!("Player shot an enemy");
+                self.enemies.remove(index);
+            } else if flying {
+                self.player_projectiles.push(projectile);
+            }
+        }
+    }
+
+    /// Lets the turrets fire and moves their projectiles, removing those that hit a
+    /// filled tile, leave the room or hit a player.
+    ///
+    /// Returns the horizontal position of the projectile that hit the first and the
+    /// second player, if any.
+    fn update_turrets(&mut self, dt: f32) -> (Option<f32>, Option<f32>) {
+        for turret in &mut self.turrets {
+            if let Some(projectile) = turret.update(dt) {
+                self.projectiles.push(projectile);
+            }
+        }
+        let player_rect = if self.is_dying() {
+            None
+        } else {
+            Some(self.player.rect())
+        };
+        let second_rect = match self.second_player {
+            Some(second) if !self.is_second_dying() => Some(second.rect()),
+            _ => None,
+        };
+        let mut hit = (None, None);
+        let projectiles: Vec<Projectile> = self.projectiles.drain(..).collect();
+        for mut projectile in projectiles {
+            let (path, flying) = projectile.update(dt, &self.room);
+            let hit_rect = |player_rect: Option<Rect>| {
+                let player_rect = player_rect?;
+                path.iter().find(|rect| rect.overlaps(&player_rect)).cloned()
+            };
+            if let Some(rect) = hit_rect(player_rect) {
+                debug!("Player was shot");
+                hit.0 = Some((rect.left() + rect.right()) / 2.0);
+            } else if let Some(rect) = hit_rect(second_rect) {
+                debug!("Second player was shot");
+                hit.1 = Some((rect.left() + rect.right()) / 2.0);
+            } else if flying {
+                self.projectiles.push(projectile);
+            }
+        }
+        hit
+    }
+
+    /// Moves the crates the players walk into sideways, and lets all crates fall.
+    ///
+    /// Returns whether the first and the second player were hit by a falling
+    /// crate, which kills them.
+    fn update_crates(&mut self, dt: f32) -> (bool, bool) {
+        let mut hit = (false, false);
+        for index in 0..self.crates.len() {
+            let rect = self.crates[index].rect();
+            let push_distance = Crate::PUSH_SPEED * (rect.right() - rect.left()) * dt;
+            let push = iter::once(&self.player)
+                .chain(self.second_player.as_ref())
+                .map(|player| player.push_direction(&rect) * push_distance)
+                .find(|&push| push != 0.0)
+                .unwrap_or(0.0);
+            let solids: Vec<Rect> = self.platforms
+                .iter()
+                .map(|platform| platform.rect())
+                .chain(self.crushers.iter().map(|crusher| crusher.rect()))
+                .chain(
+                    self.crates
+                        .iter()
+                        .enumerate()
+                        .filter(|&(other, _)| other != index)
+                        .map(|(_, crate_)| crate_.rect()),
+                )
+                .collect();
+            let fallen = self.crates[index].update(dt, push, &self.room, &solids);
+            let rect = self.crates[index].rect();
+            if fallen > 0.0 {
+                hit.0 |= !self.is_dying() && self.player.rect().overlaps(&rect);
+                if let Some(ref second) = self.second_player {
+                    hit.1 |= !self.is_second_dying() && second.rect().overlaps(&rect);
+                }
+            }
+        }
+        hit
+    }
+
+    fn collect_coins(&mut self) {
+        let player_rects: Vec<Rect> = iter::once(&self.player)
+            .chain(self.second_player.as_ref())
+            .map(|player| player.rect())
+            .collect();
+        for coin in &mut self.coins {
+            let area = coin.pickup_area();
+            if !coin.is_collected() && player_rects.iter().any(|rect| area.intersects_rect(rect)) {
+                coin.collect();
+                self.coins_collected += 1;
+                debug!("Collected coin; {} so far", self.coins_collected);
+            }
+        }
+    }
+
+    /// Switches to the camera zone the player is in, blending from where the camera
+    /// was over a moment so that it doesn't snap to the new framing.
+    fn update_camera_zone(&mut self) {
+        // Seconds it takes to blend between the framing of two zones
+        const ZONE_BLEND_TIME: f32 = 0.4;
+        let (x, y) = self.player.center();
+        let zone = self.room.camera_zone_at(x, y);
+        self.zone_blend_ticks = self.zone_blend_ticks.saturating_sub(1);
+        if zone == self.camera_zone {
+            return;
+        }
+        let (focus_x, focus_y) = self.camera_focus(&self.player, self.second_player.as_ref());
+        let (old_x, old_y) = self.camera_target(focus_x, focus_y);
+        self.camera_zone = zone;
+        // Frame the room right away when entering it
+        if self.room_ticks <= 1 {
+            return;
+        }
+        let (new_x, new_y) = self.camera_target(focus_x, focus_y);
+        self.zone_blend_offset = (old_x - new_x, old_y - new_y);
+        self.zone_blend_total = self.seconds_to_ticks(ZONE_BLEND_TIME);
+        self.zone_blend_ticks = self.zone_blend_total;
+    }
+
+    /// Eases the camera towards its target ahead of the player, or puts it there
+    /// right away when entering a room.
+    fn update_camera(&mut self, dt: f32) {
+        let (focus_x, focus_y) = self.camera_focus(&self.player, self.second_player.as_ref());
+        let target = self.camera_target(focus_x, focus_y);
+        let direction = self.player.facing().delta().0 as f32;
+        let lead = self.camera_lead * self.room.tile_size() as f32;
+        let view_size = (self.view_width, self.view_height);
+        let room_size = (self.room.pixel_width(), self.room.pixel_height());
+        if self.room_ticks <= 1 {
+            self.camera_center =
+                camera::follow_smoothly(target, direction, lead, target, 0.0, view_size, room_size);
+            self.old_camera_center = self.camera_center;
+            return;
+        }
+        self.old_camera_center = self.camera_center;
+        self.camera_center = camera::follow_smoothly(
+            target,
+            direction,
+            lead,
+            self.camera_center,
+            dt,
+            view_size,
+            room_size,
+        );
+    }
+
+    /// Returns the point the camera follows: the point between both players, so they
+    /// stay in view as long as they're close enough, moved by peeking.
+    fn camera_focus(&self, player: &Player, second: Option<&Player>) -> (f32, f32) {
+        let (mut focus_x, mut focus_y) = player.center();
+        if let Some(second) = second {
+            let (second_x, second_y) = second.center();
+            focus_x = (focus_x + second_x) / 2.0;
+            focus_y = (focus_y + second_y) / 2.0;
+        }
+        (focus_x, focus_y + self.peek_offset)
+    }
+
+    /// Returns the point the camera centers on for the focus point (`x`, `y`) in the
+    /// current camera zone.
+    fn camera_target(&self, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = match self.camera_zone {
+            Some(index) => self.room.camera_zones()[index].target(x, y),
+            None => (x, y),
+        };
+        if self.zone_blend_ticks == 0 {
+            return (x, y);
+        }
+        let blend = self.zone_blend_ticks as f32 / self.zone_blend_total as f32;
+        (
+            x + self.zone_blend_offset.0 * blend,
+            y + self.zone_blend_offset.1 * blend,
+        )
+    }
+
+    /// Returns the index of the sign the player overlaps whose center is closest to
+    /// theirs, if any.
+    fn nearest_sign(&self) -> Option<usize> {
+        let player_rect = self.player.rect();
+        let (player_x, player_y) = self.player.center();
+        let distance = |rect: &Rect| {
+            let x = (rect.left() + rect.right()) / 2.0 - player_x;
+            let y = (rect.top() + rect.bottom()) / 2.0 - player_y;
+            x * x + y * y
+        };
+        self.signs
+            .iter()
+            .enumerate()
+            .filter(|&(_, sign)| sign.rect().overlaps(&player_rect))
+            .min_by(|&(_, a), &(_, b)| {
+                distance(&a.rect())
+                    .partial_cmp(&distance(&b.rect()))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// Shows the message of the nearest sign, or dismisses the one that's shown.
+    fn read_sign(&mut self) {
+        if let Some(index) = self.shown_sign.take() {
+            self.dismissed_sign = Some(index);
+        } else {
+            self.shown_sign = self.nearest_sign();
+        }
+    }
+
+    /// Hides the shown message once the player has walked away from its sign, and
+    /// shows the message of an automatic sign when they reach it.
+    fn update_signs(&mut self) {
+        let nearest = if self.is_dying() {
+            None
+        } else {
+            self.nearest_sign()
+        };
+        if self.shown_sign != nearest {
+            self.shown_sign = None;
+        }
+        if self.dismissed_sign != nearest {
+            self.dismissed_sign = None;
+        }
+        if let Some(index) = nearest {
+            if self.signs[index].is_automatic() && self.dismissed_sign.is_none() {
+                self.shown_sign = Some(index);
+            }
+        }
+    }
+
+    /// Starts the player's death, after which they respawn once `DEATH_TIME` has passed.
+    ///
+    /// Does nothing if the player is dying already, so that a death is only
+    /// counted once when several things kill the player at the same time.
+    fn kill_player(&mut self) {
+        if self.is_dying() {
+            return;
+        }
+        self.deaths += 1;
+        self.attempt_deaths += 1;
+        debug!("Player died; {} deaths so far", self.deaths);
+        self.lose_life();
+        let (x, y) = self.player.center();
+        self.spawn_death_particles(x, y);
+        self.start_shake(DEATH_SHAKE);
+        self.dying_ticks = self.seconds_to_ticks(DEATH_TIME);
+        self.death_ticks = self.dying_ticks;
+        self.fade = Fade::fading_out(self.seconds_to_ticks(FADE_TIME), self.dying_ticks);
+    }
+
+    /// Returns whether the player died and is waiting to respawn.
+    pub fn is_dying(&self) -> bool {
+        self.dying_ticks > 0
+    }
+
+    /// Returns whether the second player died and is waiting to respawn.
+    fn is_second_dying(&self) -> bool {
+        self.second_dying_ticks > 0
+    }
+
+    /// Starts the second player's death like `kill_player` does for the first
+    /// player, but without fading out the view, which the first player still uses.
+    fn kill_second_player(&mut self) {
+        if self.is_second_dying() {
+            return;
+        }
+        let second = match self.second_player {
+            Some(second) => second,
+            None => return,
+        };
+        self.deaths += 1;
+        self.attempt_deaths += 1;
+        debug!("Second player died; {} deaths so far", self.deaths);
+        self.lose_life();
+        let (x, y) = second.center();
+        self.spawn_death_particles(x, y);
+        self.start_shake(DEATH_SHAKE);
+        self.second_dying_ticks = self.seconds_to_ticks(DEATH_TIME);
+        self.death_ticks = self.second_dying_ticks;
+    }
+
+    /// Kicks up dust when `after` jumped or landed hard during the tick it started
+    /// as `before`, and shakes the view on a hard landing.
+    fn react_to_movement(
+        &mut self,
+        before: &Player,
+        after: &Player,
+        physics: &PhysicsConfig,
+    ) {
+        // Fraction of the maximum fall speed a landing needs to kick up dust
+        const HARD_LANDING: f32 = 0.6;
+        let jumped = (after.vert_state == PlayerVertState::Jumping
+            && before.vert_state != PlayerVertState::Jumping)
+            || (before.air_jump_available && !after.air_jump_available);
+        let landed = after.vert_state == PlayerVertState::Standing
+            && before.vert_state != PlayerVertState::Standing
+            && before.vel.y.abs() >= physics.fall_speed * HARD_LANDING;
+        let count = match (jumped, landed) {
+            (true, _) => 6,
+            (false, true) => 10,
+            (false, false) => return,
+        };
+        if landed {
+            self.start_shake(LANDING_SHAKE);
+        }
+        let feet = after.feet_rect();
+        let x = (feet.left() + feet.right()) / 2.0;
+        // Fan out sideways and away from the floor, which is above when gravity is flipped
+        let angles = if after.is_gravity_flipped() {
+            (0.1 * PI, 0.9 * PI)
+        } else {
+            (-0.1 * PI, -0.9 * PI)
+        };
+        let burst = Burst {
+            count,
+            angles,
+            speed: 3.0 * self.room.tile_size() as f32,
+            lifetime: self.seconds_to_ticks(0.3),
+            kind: ParticleKind::Dust,
+        };
+        self.particles.burst(x, feet.top(), &burst);
+    }
+
+    /// Shakes the view by up to `tiles` tiles, scaled by the shake intensity, unless
+    /// it's already shaking harder.
+    fn start_shake(&mut self, tiles: f32) {
+        if self.shake_intensity <= 0.0 {
+            return;
+        }
+        let strength = tiles * self.room.tile_size() as f32 * self.shake_intensity;
+        let shake = Shake::new(strength, self.seconds_to_ticks(SHAKE_TIME), self.total_ticks);
+        if shake.is_stronger_than(&self.shake) {
+            self.shake = shake;
+        }
+    }
+
+    /// Scatters particles in every direction from where a player died.
+    fn spawn_death_particles(&mut self, x: f32, y: f32) {
+        let burst = Burst {
+            count: 16,
+            angles: (0.0, 2.0 * PI),
+            speed: 8.0 * self.room.tile_size() as f32,
+            lifetime: self.seconds_to_ticks(0.5),
+            kind: ParticleKind::Death,
+        };
+        self.particles.burst(x, y, &burst);
+    }
+
+    /// Puts defeated enemies back where they started, if the difficulty says so.
+    fn respawn_hazards(&mut self) {
+        if !self.difficulty.respawns_hazards() {
+            return;
+        }
+        self.enemies.clear();
+        for entity in self.room.entities() {
+            if let Entity::Enemy { x, y } = *entity {
+                self.enemies.push(Enemy::new(x, y));
+            }
+        }
+    }
+
+    /// Takes away a life if they're limited.
+    fn lose_life(&mut self) {
+        if let Some(ref mut lives) = self.lives {
+            *lives = lives.saturating_sub(1);
+            debug!("{} lives left", lives);
+        }
+    }
+
+    /// Returns the players where they're drawn and the camera following them.
+    fn render_view(&self) -> (Player, Option<Player>, Camera) {
+        // Interpolate between the last two ticks rather than extrapolating from the old
+        // position, so the player is never drawn somewhere it couldn't have been
+        let time_delta = self.time_since_last_tick.as_fractional_secs() as f32;
+        let progress = time_delta / self.frame_duration.as_fractional_secs() as f32;
+        let render_player = self.player.interpolated(&self.old_player, progress);
+        let render_second = self.second_player
+            .map(|second| second.interpolated(&self.old_second_player, progress));
+        // Interpolate the camera too, so scrolling is smooth between ticks
+        let (old_x, old_y) = self.old_camera_center;
+        let (new_x, new_y) = self.camera_center;
+        let camera = Camera::following(
+            interpolate(old_x, new_x, progress),
+            interpolate(old_y, new_y, progress),
+            self.view_width,
+            self.view_height,
+            &self.room,
+        );
+        // Shake after keeping the camera inside the room, so it shakes at the edges too
+        let (shake_x, shake_y) = self.shake.offset(progress);
+        (render_player, render_second, camera.shifted(shake_x, shake_y))
+    }
+
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        context: &mut RenderContext<T::Context>,
+    ) -> Result<(), Error> {
+        let time_delta = self.time_since_last_tick.as_fractional_secs() as f32;
+        let progress = time_delta / self.frame_duration.as_fractional_secs() as f32;
+        let (render_player, render_second, camera) = self.render_view();
+        // Animate tiles in simulated time, so they stop while paused and match replays
+        let clock = self.total_ticks as f32 * self.frame_duration.as_fractional_secs() as f32
+            + time_delta;
+        self.room.render(canvas, &camera, context, clock)?;
+        let theme = &context.theme;
+        self.render_blink_warning(canvas, &camera, theme)?;
+        self.render_crumbling(canvas, &camera, theme)?;
+        self.render_switch_states(canvas, &camera, theme)?;
+        for platform in &self.platforms {
+            platform.render(canvas, &camera, theme, time_delta)?;
+        }
+        for crusher in &self.crushers {
+            crusher.render(canvas, &camera, theme, time_delta)?;
+        }
+        for crate_ in &self.crates {
+            crate_.render(canvas, &camera, theme, time_delta)?;
+        }
+        for turret in &self.turrets {
+            turret.render(canvas, &camera, theme)?;
+        }
+        for projectile in self.projectiles.iter().chain(&self.player_projectiles) {
+            projectile.render(canvas, &camera, theme, time_delta)?;
+        }
+        for sign in &self.signs {
+            sign.render(canvas, &camera, theme)?;
+        }
+        for coin in &self.coins {
+            coin.render(canvas, &camera, theme)?;
+        }
+        for key in &self.keys {
+            key.render(canvas, &camera, theme)?;
+        }
+        for enemy in &self.enemies {
+            enemy.render(canvas, &camera, theme, time_delta)?;
+        }
+        if let Some(ref ghost) = self.ghost {
+            // Follow the simulated time rather than wall time, so the race is fair at any
+            // frame rate
+            if self.results.is_none() {
+                ghost.render(canvas, &camera, self.timer_ticks as usize, progress)?;
+            }
+        }
+        if let Some(second) = render_second {
+            if self.is_second_dying() {
+                let time_left = self.second_dying_ticks as f32 / self.death_ticks as f32;
+                second.render_dying(canvas, &camera, theme, time_left)?;
+            } else {
+                // Keep the second player a plain rect, so they can be told apart
+                second.render(canvas, &camera, theme, theme.second_player(), None)?;
+            }
+        }
+        self.trail.render(canvas, &camera, theme.afterimage())?;
+        if self.is_dying() {
+            let time_left = self.dying_ticks as f32 / self.death_ticks as f32;
+            render_player.render_dying(canvas, &camera, theme, time_left)?;
+        } else {
+            let sprites = context.sprites.as_ref();
+            render_player.render(canvas, &camera, theme, theme.player(), sprites)?;
+        }
+        self.particles.render(canvas, &camera, theme, time_delta)?;
+        if self.darkness {
+            let mut lights = vec![render_player.center()];
+            lights.extend(render_second.map(|second| second.center()));
+            light::render_darkness(canvas, &camera, &self.room, &self.light_map, &lights)?;
+        }
+        let darkness = self.fade.darkness(progress);
+        if darkness > 0.0 {
+            let (width, height) = camera.logical_size();
+            let mut color = theme.fade();
+            color.a = (darkness * 255.0).round() as u8;
+            canvas.set_draw_color(color);
+            canvas
+                .fill_rect(SdlRect::new(0, 0, width, height))
+                .map_err(err_msg)?;
+        }
+        if let Some(message) = self.active_message() {
+            // Keep the message out of the way of the player
+            let player_screen_y = render_player.center().1 - camera.top();
+            let at_top = player_screen_y > self.view_height as f32 / 2.0;
+            render_message_box(canvas, &camera, theme, message, at_top)?;
+        }
+        if let Some(ref results) = self.results {
+            render_results_overlay(canvas, &camera, theme, results)?;
+        } else if self.game_over {
+            render_game_over_overlay(canvas, &camera, theme)?;
+        } else if self.paused {
+            render_pause_overlay(canvas, &camera, theme)?;
+        }
+        Ok(())
+    }
+
+    /// Draws an overview of the room in the top right corner of the view, with the
+    /// players as bright dots and the part of the room in view outlined. Coins,
+    /// keys and enemies are only shown with `show_entities`.
+    pub fn render_minimap<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        context: &mut RenderContext<T::Context>,
+        show_entities: bool,
+    ) -> Result<(), Error> {
+        const MARGIN: u32 = 4;
+        let (render_player, render_second, camera) = self.render_view();
+        let (view_width, view_height) = camera.logical_size();
+        let (width, height) = minimap::size(
+            (self.room.width(), self.room.height()),
+            (view_width / 4, view_height / 3),
+        );
+        let area = SdlRect::new(
+            view_width as i32 - (width + MARGIN) as i32,
+            MARGIN as i32,
+            width,
+            height,
+        );
+        let texture = context.minimap.update(&self.room, &context.theme)?;
+        canvas.copy(texture, None, area).map_err(err_msg)?;
+
+        let room_size = (self.room.pixel_width(), self.room.pixel_height());
+        if show_entities {
+            let theme = &context.theme;
+            let dots: [(Color, Vec<Rect>); 3] = [
+                (theme.coin(), self.coins.iter().map(Coin::rect).collect()),
+                (theme.key(), self.keys.iter().map(Key::rect).collect()),
+                (theme.enemy(), self.enemies.iter().map(Enemy::rect).collect()),
+            ];
+            for &(color, ref rects) in &dots {
+                let rects: Vec<SdlRect> = rects
+                    .iter()
+                    .map(|rect| minimap::map_rect(rect, room_size, area))
+                    .collect();
+                canvas.set_draw_color(color);
+                canvas.fill_rects(&rects).map_err(err_msg)?;
+            }
+        }
+        let view = Rect::new(
+            camera.left(),
+            camera.top(),
+            view_width as f32,
+            view_height as f32,
+        );
+        canvas.set_draw_color(context.theme.minimap_view());
+        canvas
+            .draw_rect(minimap::map_rect(&view, room_size, area))
+            .map_err(err_msg)?;
+        let players = [
+            (Some(render_player), context.theme.player()),
+            (render_second, context.theme.second_player()),
+        ];
+        for &(player, color) in &players {
+            let player = match player {
+                Some(player) => player,
+                None => continue,
+            };
+            let dot = minimap::map_rect(&player.rect(), room_size, area);
+            // Make the player stand out even in small rooms
+            let (x, y) = (dot.center().x(), dot.center().y());
+            canvas.set_draw_color(color);
+            canvas
+                .fill_rect(SdlRect::new(x - 1, y - 1, 3, 3))
+                .map_err(err_msg)?;
+        }
+        Ok(())
+    }
+
+    /// Draws what the first player's collision checks saw during the last tick: the
+    /// collision rect, the area of each check, and the tiles in those areas, red if
+    /// they're solid and green if not.
+    ///
+    /// Everything is drawn where the checks happened rather than interpolated, so it
+    /// may lag slightly behind the player.
+    pub fn render_collisions<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        let (_, _, camera) = self.render_view();
+        let probes = self.player.collisions.probes();
+        for probe in &probes {
+            for tile in self.room.tiles_in_rect(&probe.rect) {
+                let color = if tile.kind.is_solid() {
+                    theme.collision_solid()
+                } else {
+                    theme.collision_free()
+                };
+                canvas.set_draw_color(color);
+                canvas.fill_rect(camera.sdl_rect(&tile.rect)).map_err(err_msg)?;
+            }
+        }
+        canvas.set_draw_color(theme.collision_outline());
+        canvas
+            .draw_rect(camera.sdl_rect(&self.player.rect()))
+            .map_err(err_msg)?;
+        for probe in &probes {
+            let color = match (probe.kind, probe.hit) {
+                (_, true) => theme.probe_hit(),
+                (ProbeKind::Horizontal, false) => theme.probe_horizontal(),
+                (ProbeKind::Vertical, false) => theme.probe_vertical(),
+                (ProbeKind::Floor, false) => theme.probe_floor(),
+            };
+            canvas.set_draw_color(color);
+            // Thin probes would round to nothing, so make them at least a pixel high
+            let mut rect = camera.sdl_rect(&probe.rect);
+            rect.set_height(rect.height().max(1));
+            canvas.draw_rect(rect).map_err(err_msg)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the player's collision rect and lists their physics state in the top
+    /// right corner, for finding out why a move didn't work.
+    pub fn render_debug<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        const PADDING: u32 = 2;
+        let (render_player, _, camera) = self.render_view();
+        canvas.set_draw_color(theme.debug_outline());
+        canvas
+            .draw_rect(camera.sdl_rect(&render_player.rect()))
+            .map_err(err_msg)?;
+        let (x, y) = self.player.position();
+        let (xspeed, yspeed) = self.player.speed();
+        let probes = self.player_probes();
+        let yes_no = |hit: bool| if hit { "YES" } else { "NO" };
+        let lines = [
+            format!("POS {:.1} {:.1}", x, y),
+            format!("SPEED {:.1} {:.1}", xspeed, yspeed),
+            format!("{:?}", self.player.horiz_state()),
+            format!("{:?}", self.player.vert_state()),
+            format!("FLOOR {} CEILING {}", yes_no(probes.floor), yes_no(probes.ceiling)),
+            format!(
+                "WALL LEFT {} RIGHT {}",
+                yes_no(probes.left_wall),
+                yes_no(probes.right_wall)
+            ),
+            match self.standing_tile() {
+                Some(tile) => format!("TILE {} {}", tile.x, tile.y),
+                None => "TILE -".to_string(),
+            },
+            format!("TICK {}", self.total_ticks),
+        ];
+        let width = lines
+            .iter()
+            .map(|line| font::text_width(line, 1))
+            .max()
+            .unwrap_or(0) + 2 * PADDING;
+        let height = lines.len() as u32 * font::LINE_HEIGHT + 2 * PADDING - 2;
+        let left = camera.logical_size().0 as i32 - width as i32;
+        canvas.set_draw_color(theme.dim());
+        canvas
+            .fill_rect(SdlRect::new(left, 0, width, height))
+            .map_err(err_msg)?;
+        canvas.set_draw_color(theme.debug_text());
+        for (index, line) in lines.iter().enumerate() {
+            let top = (PADDING + index as u32 * font::LINE_HEIGHT) as i32;
+            font::render_text(canvas, line, left + PADDING as i32, top, 1)?;
+        }
+        Ok(())
+    }
+
+    /// Marks the switches whose channel is flipped with a dot in the middle, and
+    /// crosses out the used up one-shot switches.
+    fn render_switch_states<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        for y in 0..self.room.height() as i32 {
+            for x in 0..self.room.width() as i32 {
+                let tile = self.room.tile_at_index(x, y);
+                let channel = match tile.kind {
+                    TileKind::Switch { channel, .. } => channel,
+                    _ => continue,
+                };
+                if !camera.sees(&tile.rect) {
+                    continue;
+                }
+                let (left, top) = (tile.rect.left(), tile.rect.top());
+                let size = tile.rect.right() - left;
+                if self.flipped_channels.contains(&channel) {
+                    let offset = size * 3.0 / 8.0;
+                    let dot = Rect::new(left + offset, top + offset, size / 4.0, size / 4.0);
+                    canvas.set_draw_color(theme.switch_flipped());
+                    canvas.fill_rect(camera.sdl_rect(&dot)).map_err(err_msg)?;
+                }
+                if self.used_switches.contains(&(x as u32, y as u32)) {
+                    let rect = camera.sdl_rect(&tile.rect);
+                    canvas.set_draw_color(theme.switch_used());
+                    canvas
+                        .draw_line(rect.top_left(), rect.bottom_right())
+                        .map_err(err_msg)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Darkens crumble tiles the longer they've been stood on.
+    fn render_crumbling<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        let crumble_ticks = self.seconds_to_ticks(CRUMBLE_TIME);
+        for (&(x, y), &ticks) in &self.crumbling {
+            let mut color = theme.crumbling();
+            color.a = (0xc0 * ticks / crumble_ticks).min(0xc0) as u8;
+            let rect = self.room.tile_at_index(x as i32, y as i32).rect;
+            canvas.set_draw_color(color);
+            canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+        }
+        Ok(())
+    }
+
+    /// Shades the blink tiles during the last moments before they change.
+    fn render_blink_warning<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        const WARNING_TIME: f32 = 0.5;
+        let time = self.room_ticks as f32 * self.frame_duration.as_fractional_secs() as f32;
+        let (solid, time_left) = self.room.blink_state(time);
+        if time_left > WARNING_TIME {
+            return Ok(());
+        }
+        // Fade solid tiles out and intangible tiles in
+        let color = if solid {
+            theme.blink_warning_solid()
+        } else {
+            theme.blink_warning_off()
+        };
+        canvas.set_draw_color(color);
+        for y in 0..self.room.height() as i32 {
+            for x in 0..self.room.width() as i32 {
+                let tile = self.room.tile_at_index(x, y);
+                let is_blink = tile.kind == TileKind::Blink || tile.kind == TileKind::BlinkOff;
+                if is_blink && camera.sees(&tile.rect) {
+                    canvas.fill_rect(camera.sdl_rect(&tile.rect)).map_err(err_msg)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Seconds a crumble tile can be stood on before it collapses
+const CRUMBLE_TIME: f32 = 0.5;
+/// Seconds the view shakes for after a hard landing or a death
+const SHAKE_TIME: f32 = 0.25;
+/// Tiles the view shakes by at most after a hard landing
+const LANDING_SHAKE: f32 = 0.125;
+/// Tiles the view shakes by at most after a death
+const DEATH_SHAKE: f32 = 0.25;
+/// Seconds between a player dying and respawning
+const DEATH_TIME: f32 = 0.6;
+/// Seconds the view takes to fade to black before a respawn, and back after it
+const FADE_TIME: f32 = 0.3;
+/// Seconds until a collapsed crumble tile comes back, in rooms where they do
+const CRUMBLE_REGENERATE_TIME: f32 = 3.0;
+
+#[derive(Clone, Copy)]
+pub struct Player {
+    horiz_state: PlayerHorizState,
+    vert_state: PlayerVertState,
+    /// Position of the top left corner
+    pos: Vec2,
+    /// Speed in pixels per second
+    vel: Vec2,
+    width: f32,
+    height: f32,
+    /// Height of the player when not crouching
+    stand_height: f32,
+    /// Size the player is drawn with when standing, centered on the collision box
+    visual_width: f32,
+    visual_height: f32,
+    /// Number of ticks left in which the player may still jump after walking off a ledge
+    coyote_ticks: u32,
+    /// Whether the jump was released early, applying extra gravity until the apex
+    jump_cut: bool,
+    /// Whether the air jump has not been used since last standing on the ground
+    air_jump_available: bool,
+    /// The wall the player is currently sliding down, if any
+    wall_side: Option<WallSide>,
+    /// Whether a wall jump should be performed at the start of the next tick
+    wall_jump_requested: bool,
+    /// Number of ticks left in which horizontal input is ignored after a wall jump
+    wall_jump_lockout_ticks: u32,
+    /// Whether to bounce up at the start of the next tick after stomping an enemy
+    bounce_requested: bool,
+    health: u32,
+    /// Number of ticks left in which the player can't be hurt
+    invincible_ticks: u32,
+    /// Horizontal center of what hurt the player, to be knocked back from at the
+    /// start of the next tick
+    knockback_from: Option<f32>,
+    sprinting: bool,
+    crouch_held: bool,
+    crouching: bool,
+    jump_held: bool,
+    /// Direction of the last horizontal movement input, which is always horizontal
+    facing: Direction,
+    /// 1 when gravity pulls the player down, or -1 when it's flipped to pull up
+    gravity: f32,
+    /// Point the player is swinging from on the grappling hook, if any
+    grapple_anchor: Option<(f32, f32)>,
+    /// Distance from the anchor the player's center is kept within while swinging
+    grapple_length: f32,
+    animation: Animation,
+    /// Seconds of simulated time since the current animation started
+    animation_time: f32,
+    /// What the collision checks of the last update found, for debugging
+    collisions: CollisionRecord,
+}
+
+impl Player {
+    pub const MAX_HEALTH: u32 = 3;
+
+    pub fn new() -> Player {
+        Player {
+            horiz_state: PlayerHorizState::Idle,
+            vert_state: PlayerVertState::Falling,
+            pos: Vec2::new(20.0, 10.0),
+            vel: Vec2::new(0.0, 0.0),
+            width: 8.0,
+            height: 20.0,
+            stand_height: 20.0,
+            visual_width: 8.0,
+            visual_height: 20.0,
+            coyote_ticks: 0,
+            jump_cut: false,
+            air_jump_available: false,
+            wall_side: None,
+            wall_jump_requested: false,
+            wall_jump_lockout_ticks: 0,
+            bounce_requested: false,
+            health: Player::MAX_HEALTH,
+            invincible_ticks: 0,
+            knockback_from: None,
+            sprinting: false,
+            crouch_held: false,
+            crouching: false,
+            jump_held: false,
+            facing: Direction::Right,
+            gravity: 1.0,
+            grapple_anchor: None,
+            grapple_length: 0.0,
+            animation: Animation::Idle,
+            animation_time: 0.0,
+            collisions: CollisionRecord::default(),
+        }
+    }
+
+    /// Puts the player on top of the room's spawn tile, horizontally centered,
+    /// with zero speed.
+    ///
+    /// Which keys are held is kept, so that e.g. sprinting continues after respawning.
+    pub fn respawn(&mut self, room: &Room, physics: &PhysicsConfig) {
+        let (sprinting, crouch_held, jump_held) =
+            (self.sprinting, self.crouch_held, self.jump_held);
+        *self = Player::new();
+        self.sprinting = sprinting;
+        self.crouch_held = crouch_held;
+        self.jump_held = jump_held;
+        self.width = physics.width;
+        self.height = physics.height;
+        self.stand_height = physics.height;
+        self.visual_width = physics.visual_width();
+        self.visual_height = physics.visual_height();
+        let spawn_rect = room.spawn_tile().rect;
+        self.pos.x = spawn_rect.left() + (room.tile_size() as f32 - self.width) / 2.0;
+        self.pos.y = spawn_rect.top() - self.height;
+    }
+
+    pub fn horiz_state(&self) -> PlayerHorizState {
+        self.horiz_state
+    }
+
+    pub fn set_horiz_state(&mut self, state: PlayerHorizState) {
+        if self.horiz_state == state {
+            return;
+        }
+        self.horiz_state = state;
+        debug!("Player horiz state is now {:?}", self.horiz_state);
+        match state {
+            PlayerHorizState::MovingLeft => self.facing = Direction::Left,
+            PlayerHorizState::MovingRight => self.facing = Direction::Right,
+            _ => (),
+        }
+    }
+
+    /// Returns the direction the player last started moving in, which is either
+    /// left or right.
+    pub fn facing(&self) -> Direction {
+        self.facing
+    }
+
+    pub fn vert_state(&self) -> PlayerVertState {
+        self.vert_state
+    }
+
+    pub fn set_vert_state(&mut self, state: PlayerVertState) {
+        if self.vert_state == state {
+            return;
+        }
+        self.vert_state = state;
+        debug!("Player vert state is now {:?}", self.vert_state);
+    }
+
+    pub fn set_sprinting(&mut self, sprinting: bool) {
+        if self.sprinting == sprinting {
+            return;
+        }
+        self.sprinting = sprinting;
+        debug!("Player sprinting is now {}", self.sprinting);
+    }
+
+    pub fn is_crouching(&self) -> bool {
+        self.crouching
+    }
+
+    /// Sets whether the crouch key is held.
+    ///
+    /// The player crouches at the next update if standing, and stands back up
+    /// once the key is released and there is enough headroom.
+    pub fn set_crouch_held(&mut self, held: bool) {
+        self.crouch_held = held;
+    }
+
+    pub fn is_crouch_held(&self) -> bool {
+        self.crouch_held
+    }
+
+    /// Sets whether the jump key is held, which makes bounce tiles launch higher.
+    pub fn set_jump_held(&mut self, held: bool) {
+        self.jump_held = held;
+    }
+
+    /// Returns whether a jump may be started right now.
+    ///
+    /// This is the case while standing, and for a short while after walking off a ledge.
+    pub fn can_jump(&self) -> bool {
+        self.vert_state == PlayerVertState::Standing
+            || (self.vert_state == PlayerVertState::Falling && self.coyote_ticks > 0)
+    }
+
+    pub fn jump(&mut self) {
+        if self.vert_state == PlayerVertState::Falling {
+            // Jumping during coyote time behaves exactly like a grounded jump
+            trace!("Player coyote jump with {} ticks left", self.coyote_ticks);
+            self.vel.y = 0.0;
+        }
+        self.coyote_ticks = 0;
+        self.set_vert_state(PlayerVertState::Jumping);
+    }
+
+    pub fn can_air_jump(&self) -> bool {
+        self.vert_state != PlayerVertState::Standing && self.air_jump_available
+    }
+
+    /// Performs a second jump in mid-air, which uses up the air jump until landing.
+    pub fn air_jump(&mut self) {
+        // Reset vertical speed rather than adding to it, so the jump is the same as a grounded one
+        self.air_jump_available = false;
+        self.jump_cut = false;
+        self.vel.y = 0.0;
+        self.set_vert_state(PlayerVertState::Jumping);
+    }
+
+    pub fn is_gravity_flipped(&self) -> bool {
+        self.gravity < 0.0
+    }
+
+    /// Turns gravity upside down, or back again.
+    ///
+    /// With flipped gravity the player falls up, stands on ceilings and jumps down.
+    pub fn flip_gravity(&mut self) {
+        self.gravity = -self.gravity;
+        debug!("Player gravity flipped: {}", self.is_gravity_flipped());
+        self.jump_cut = false;
+        self.coyote_ticks = 0;
+        if self.vert_state != PlayerVertState::WallSliding {
+            self.set_vert_state(PlayerVertState::Falling);
+        }
+    }
+
+    /// Applies the press of an action that moves this player.
+    fn action_pressed(&mut self, action: Action, room: &Room) {
+        if action == Action::Jump {
+            self.set_jump_held(true);
+        }
+        match action {
+            Action::MoveLeft => self.set_horiz_state(PlayerHorizState::MovingLeft),
+            Action::MoveRight => self.set_horiz_state(PlayerHorizState::MovingRight),
+            Action::Jump if self.is_swinging() => self.release_grapple(),
+            Action::Jump if self.can_jump() => self.jump(),
+            Action::Jump if self.vert_state() == PlayerVertState::WallSliding => self.wall_jump(),
+            Action::Jump if room.allows_double_jump() && self.can_air_jump() => self.air_jump(),
+            Action::Run => self.set_sprinting(true),
+            Action::Crouch => self.set_crouch_held(true),
+            Action::Grapple => self.fire_grapple(room),
+            _ => (),
+        }
+    }
+
+    /// Applies the release of an action that moves this player.
+    fn action_released(&mut self, action: Action) {
+        if action == Action::Jump {
+            self.set_jump_held(false);
+        }
+        match action {
+            Action::MoveLeft if self.horiz_state() == PlayerHorizState::MovingLeft => {
+                self.set_horiz_state(PlayerHorizState::StopMovingLeft)
+            }
+            Action::MoveRight if self.horiz_state() == PlayerHorizState::MovingRight => {
+                self.set_horiz_state(PlayerHorizState::StopMovingRight)
+            }
+            Action::Jump if self.vert_state() == PlayerVertState::Jumping => self.cut_jump(),
+            Action::Run => self.set_sprinting(false),
+            Action::Crouch => self.set_crouch_held(false),
+            Action::Grapple => self.release_grapple(),
+            _ => (),
+        }
+    }
+
+    /// Fires the grappling hook diagonally up in the facing direction, starting to
+    /// swing from the first filled tile it hits within range. A miss does nothing.
+    pub fn fire_grapple(&mut self, room: &Room) {
+        // Range of the grappling hook in tiles
+        const GRAPPLE_RANGE: f32 = 6.0;
+        let (x, y) = self.center();
+        let dx = self.facing.delta().0 as f32;
+        let range = GRAPPLE_RANGE * room.tile_size() as f32;
+        // Up is against gravity
+        let (anchor_x, anchor_y) = match room.raycast(x, y, dx, -self.gravity, range) {
+            Some(hit) => hit.point,
+            None => {
+                trace!("Player grapple missed");
+                return;
+            }
+        };
+        debug!("Player grappled at ({}, {})", anchor_x, anchor_y);
+        self.grapple_anchor = Some((anchor_x, anchor_y));
+        self.grapple_length = ((anchor_x - x).powi(2) + (anchor_y - y).powi(2)).sqrt();
+        self.jump_cut = false;
+        if self.vert_state == PlayerVertState::Jumping {
+            self.set_vert_state(PlayerVertState::Falling);
+        }
+    }
+
+    pub fn is_swinging(&self) -> bool {
+        self.grapple_anchor.is_some()
+    }
+
+    /// Lets go of the grappling hook, keeping the current speed.
+    pub fn release_grapple(&mut self) {
+        if self.grapple_anchor.take().is_some() {
+            trace!("Player released grapple");
+        }
+    }
+
+    /// Jumps up and away from the wall the player is sliding down.
+    ///
+    /// The jump itself is performed at the start of the next update.
+    pub fn wall_jump(&mut self) {
+        self.wall_jump_requested = true;
+    }
+
+    pub fn health(&self) -> u32 {
+        self.health
+    }
+
+    /// Returns the position of the player's top left corner.
+    pub fn position(&self) -> (f32, f32) {
+        (self.pos.x, self.pos.y)
+    }
+
+    /// Returns the position of the player's top left corner as a vector.
+    pub fn pos(&self) -> Vec2 {
+        self.pos
+    }
+
+    /// Returns the player's speed in pixels per second as a vector.
+    pub fn vel(&self) -> Vec2 {
+        self.vel
+    }
+
+    pub fn speed(&self) -> (f32, f32) {
+        (self.vel.x, self.vel.y)
+    }
+
+    /// Returns which sides of the player are touching a filled tile or one of the
+    /// `platforms`, where the floor is whatever gravity pulls the player towards.
+    pub fn probes(&self, room: &Room, platforms: &[Rect]) -> Probes {
+        // How far beyond the player's sides to look
+        const REACH: f32 = 0.5;
+        let touching = |rect: Rect| !solids_in_rect(&rect, room, platforms).is_empty();
+        let head_y = if self.is_gravity_flipped() {
+            self.pos.y + self.height
+        } else {
+            self.pos.y - REACH
+        };
+        Probes {
+            floor: self.has_floor_below(room, platforms),
+            ceiling: touching(Rect::new(self.pos.x, head_y, self.width, REACH)),
+            left_wall: touching(Rect::new(self.pos.x - REACH, self.pos.y, REACH, self.height)),
+            right_wall: touching(Rect::new(
+                self.pos.x + self.width,
+                self.pos.y,
+                REACH,
+                self.height,
+            )),
+        }
+    }
+
+    /// Takes one point of damage from something centered horizontally at
+    /// `source_x`, unless the player is still invincible from being hurt
+    /// earlier. Returns whether the player has run out of health.
+    ///
+    /// The player is knocked back away from the source at the start of the
+    /// next update, and is invincible for a while after that.
+    pub fn hurt(&mut self, source_x: f32) -> bool {
+        if self.invincible_ticks > 0 || self.knockback_from.is_some() {
+            return false;
+        }
+        self.health = self.health.saturating_sub(1);
+        debug!("Player hurt; health is now {}", self.health);
+        self.knockback_from = Some(source_x);
+        self.health == 0
+    }
+
+    /// Bounces up after stomping an enemy.
+    ///
+    /// The bounce itself is performed at the start of the next update.
+    pub fn bounce(&mut self) {
+        self.bounce_requested = true;
+    }
+
+    /// Ends a jump early because the jump key was released.
+    pub fn cut_jump(&mut self) {
+        self.set_vert_state(PlayerVertState::Falling);
+        if self.vel.y * self.gravity < 0.0 {
+            self.jump_cut = true;
+        }
+    }
+
+    /// Returns whether the player is standing on top of `rect`.
+    pub fn is_standing_on(&self, rect: &Rect) -> bool {
+        self.vert_state == PlayerVertState::Standing
+            && !self.is_gravity_flipped()
+            && (self.pos.y + self.height - rect.top()).abs() < 0.5
+            && self.pos.x < rect.right() && self.pos.x + self.width > rect.left()
+    }
+
+    /// Returns 1 or -1 if the player is walking right or left into the side of
+    /// `rect`, so as to push it, or 0 otherwise.
+    fn push_direction(&self, rect: &Rect) -> f32 {
+        const REACH: f32 = 0.5;
+        let beside = self.pos.y < rect.bottom() && self.pos.y + self.height > rect.top();
+        let right = self.pos.x + self.width;
+        match self.horiz_state {
+            PlayerHorizState::MovingRight if beside && (right - rect.left()).abs() < REACH => 1.0,
+            PlayerHorizState::MovingLeft if beside && (self.pos.x - rect.right()).abs() < REACH => {
+                -1.0
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Moves the player along with the platform they're standing on, which has
+    /// moved horizontally by `dx` to `platform`.
+    pub fn carry(&mut self, dx: f32, platform: &Rect, room: &Room) {
+        self.move_horizontally(dx, room, &[]);
+        self.pos.y = platform.top() - self.height;
+    }
+
+    /// Pushes the player out of `platform`, which has moved by (`dx`, `dy`) into them.
+    pub fn push_out(&mut self, dx: f32, dy: f32, platform: &Rect) {
+        if dx.abs() >= dy.abs() {
+            if dx > 0.0 {
+                self.pos.x = platform.right();
+            } else {
+                self.pos.x = platform.left() - self.width;
+            }
+        } else if dy < 0.0 {
+            // Moving up into the player lifts them onto the platform
+            self.pos.y = platform.top() - self.height;
+            self.vel.y = self.vel.y.min(0.0);
+            if self.vert_state != PlayerVertState::Jumping && !self.is_gravity_flipped() {
+                self.set_vert_state(PlayerVertState::Standing);
+            }
+        } else {
+            self.pos.y = platform.bottom();
+            self.vel.y = self.vel.y.max(0.0);
+        }
+    }
+
+    /// Moves the player out of any solids it overlaps, e.g. after spawning inside a
+    /// wall, to the nearest free position in the direction opposite of gravity,
+    /// sideways or along gravity, in that order of preference.
+    ///
+    /// Returns `false` if there's no free position within three tiles.
+    pub fn unstick(&mut self, room: &Room, platforms: &[Rect]) -> bool {
+        if solids_in_rect(&self.rect(), room, platforms).is_empty() {
+            return true;
+        }
+        for distance in 1..=3 * room.tile_size() {
+            let distance = distance as f32;
+            let up = -distance * self.gravity;
+            for &(dx, dy) in &[(0.0, up), (-distance, 0.0), (distance, 0.0), (0.0, -up)] {
+                let rect = Rect::new(self.pos.x + dx, self.pos.y + dy, self.width, self.height);
+                if solids_in_rect(&rect, room, platforms).is_empty() {
+                    debug!("Player was stuck; moved by ({}, {})", dx, dy);
+                    self.pos.x += dx;
+                    self.pos.y += dy;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Advances the player by `dt` seconds, colliding with the room's tiles and the `platforms`.
+    pub fn update(&mut self, dt: f32, room: &Room, platforms: &[Rect], physics: &PhysicsConfig) {
+        self.collisions = CollisionRecord::default();
+        self.coyote_ticks = self.coyote_ticks.saturating_sub(1);
+        self.wall_jump_lockout_ticks = self.wall_jump_lockout_ticks.saturating_sub(1);
+
+        if self.wall_jump_requested {
+            self.wall_jump_requested = false;
+            if let Some(wall_side) = self.wall_side.take() {
+                trace!("Player wall jump off {:?} wall", wall_side);
+                self.vel.x = match wall_side {
+                    WallSide::Left => physics.wall_jump_xspeed,
+                    WallSide::Right => -physics.wall_jump_xspeed,
+                };
+                self.vel.y = physics.wall_jump_yspeed * self.gravity;
+                self.wall_jump_lockout_ticks = (physics.wall_jump_lockout / dt).round() as u32;
+                self.set_vert_state(PlayerVertState::Falling);
+            }
+        }
+
+        // Crouch or stand up, keeping the feet in place
+        if self.crouch_held && !self.crouching && self.vert_state == PlayerVertState::Standing {
+            trace!("Player crouch");
+            self.crouching = true;
+            if !self.is_gravity_flipped() {
+                self.pos.y += self.stand_height - physics.crouch_height;
+            }
+            self.height = physics.crouch_height;
+        } else if !self.crouch_held && self.crouching {
+            let stand_top = if self.is_gravity_flipped() {
+                self.pos.y
+            } else {
+                self.pos.y + self.height - self.stand_height
+            };
+            let stand_rect = Rect::new(self.pos.x, stand_top, self.width, self.stand_height);
+            if solids_in_rect(&stand_rect, room, platforms).is_empty() {
+                trace!("Player stand up");
+                self.crouching = false;
+                self.pos.y = stand_top;
+                self.height = self.stand_height;
+            }
+        }
+
+        self.invincible_ticks = self.invincible_ticks.saturating_sub(1);
+        if let Some(source_x) = self.knockback_from.take() {
+            self.vel.x = if self.pos.x + self.width / 2.0 < source_x {
+                -physics.knockback_xspeed
+            } else {
+                physics.knockback_xspeed
+            };
+            self.vel.y = physics.knockback_yspeed * self.gravity;
+            self.jump_cut = false;
+            self.set_vert_state(PlayerVertState::Falling);
+            self.invincible_ticks = (physics.invincible_time / dt).round() as u32;
+            self.release_grapple();
+        }
+
+        if self.bounce_requested {
+            self.bounce_requested = false;
+            self.vel.y = physics.jump_speed * physics.stomp_bounce * self.gravity;
+            self.jump_cut = false;
+            self.set_vert_state(PlayerVertState::Falling);
+        }
+
+        let walk_multiplier = if self.crouching {
+            physics.crouch_multiplier
+        } else if self.sprinting {
+            physics.sprint_multiplier
+        } else {
+            1.0
+        };
+        // Control is weaker in the air; only the acceleration changes when landing,
+        // so the speed carries over smoothly
+        let (walk_accel, stop_accel) = if self.vert_state == PlayerVertState::Standing {
+            (physics.walk_accel(), physics.stop_accel())
+        } else {
+            (physics.air_walk_accel(), physics.air_stop_accel())
+        };
+        let walk_speed = physics.walk_speed * walk_multiplier;
+        let walk_accel = walk_accel * walk_multiplier;
+        let (xaccel, xminspeed, xmaxspeed) = if self.wall_jump_lockout_ticks > 0 {
+            // Keep the momentum of a wall jump regardless of horizontal input
+            (0.0, -physics.walk_speed, physics.walk_speed)
+        } else if self.is_swinging() {
+            // Let the swing carry the player, with horizontal input only pumping it
+            let xaccel = match self.horiz_state {
+                PlayerHorizState::MovingLeft => -walk_accel,
+                PlayerHorizState::MovingRight => walk_accel,
+                _ => 0.0,
+            };
+            (xaccel, -physics.fall_speed, physics.fall_speed)
+        } else {
+            match self.horiz_state {
+                PlayerHorizState::Idle => (0.0, 0.0, 0.0),
+                PlayerHorizState::MovingLeft => (-walk_accel, -walk_speed, walk_speed),
+                PlayerHorizState::MovingRight => (walk_accel, -walk_speed, walk_speed),
+                PlayerHorizState::StopMovingLeft => (stop_accel, -physics.walk_speed, 0.0),
+                PlayerHorizState::StopMovingRight => (-stop_accel, 0.0, physics.walk_speed),
+            }
+        };
+        // Holding down while falling falls faster, which stops as soon as it's released
+        let fast_falling = self.crouch_held && self.vert_state == PlayerVertState::Falling;
+        let fall_multiplier = if fast_falling {
+            physics.fast_fall_multiplier
+        } else {
+            1.0
+        };
+        // Vertical acceleration and speeds are along gravity, so the limits work the same
+        // when it's flipped
+        let yaccel = match self.vert_state {
+            PlayerVertState::Standing => 0.0,
+            PlayerVertState::Falling if self.jump_cut => {
+                physics.fall_accel() * physics.jump_cut_gravity.max(fall_multiplier)
+            }
+            PlayerVertState::Falling => physics.fall_accel() * fall_multiplier,
+            PlayerVertState::WallSliding => physics.fall_accel(),
+            PlayerVertState::Jumping => physics.jump_accel(),
+        };
+        let fall_speed = match self.vert_state {
+            PlayerVertState::WallSliding => physics.wall_slide_speed,
+            _ => physics.fall_speed * fall_multiplier,
+        };
+
+        // Calculate new speed based on acceleration, decelerating rather than snapping back
+        // when above the speed limit, e.g. after letting go of the sprint key. The distance
+        // covered is worked out along with it, since a limit can be reached partway through
+        // the tick.
+        let (xspeed, xdistance) = if self.vel.x > xmaxspeed {
+            accelerate_capped(self.vel.x, -stop_accel, xmaxspeed, dt)
+        } else if self.vel.x < xminspeed {
+            accelerate_capped(self.vel.x, stop_accel, xminspeed, dt)
+        } else if xaccel > 0.0 {
+            accelerate_capped(self.vel.x, xaccel, xmaxspeed, dt)
+        } else {
+            accelerate_capped(self.vel.x, xaccel, xminspeed, dt)
+        };
+        self.vel.x = xspeed;
+        let yspeed_along = self.vel.y * self.gravity;
+        let (yspeed_along, ydistance_along) = match self.vert_state {
+            PlayerVertState::Falling if yspeed_along > fall_speed => {
+                // Slow down gradually rather than snapping back after letting go of fast-fall
+                accelerate_capped(yspeed_along, -physics.fall_accel(), fall_speed, dt)
+            }
+            PlayerVertState::Jumping => {
+                let (speed, rise, time_left) =
+                    accelerate(yspeed_along, yaccel, physics.jump_speed, dt);
+                if time_left > 0.0 {
+                    // Having reached full jump speed, fall for the rest of the tick
+                    self.set_vert_state(PlayerVertState::Falling);
+                }
+                let (speed, fall) =
+                    accelerate_capped(speed, physics.fall_accel(), fall_speed, time_left);
+                (speed, rise + fall)
+            }
+            PlayerVertState::Falling if self.jump_cut && yspeed_along < 0.0 => {
+                // The extra gravity stops at the apex, and normal gravity takes over from there
+                let (speed, rise, time_left) = accelerate(yspeed_along, yaccel, 0.0, dt);
+                let fall_accel = physics.fall_accel() * fall_multiplier;
+                let (speed, fall) = accelerate_capped(speed, fall_accel, fall_speed, time_left);
+                (speed, rise + fall)
+            }
+            _ => accelerate_capped(yspeed_along, yaccel, fall_speed, dt),
+        };
+        self.vel.y = yspeed_along * self.gravity;
+
+        // Stop applying jump cut gravity once the apex of the jump has been reached
+        if self.jump_cut
+            && (self.vel.y * self.gravity >= 0.0 || self.vert_state != PlayerVertState::Falling)
+        {
+            self.jump_cut = false;
+        }
+
+        // Change horizontal state to idle when player has stopped moving
+        if self.vel.x == 0.0 && self.wall_jump_lockout_ticks == 0 {
+            self.set_horiz_state(PlayerHorizState::Idle);
+        }
+
+        // Move by the average speed over the tick, so that the distance travelled doesn't
+        // depend on the tick rate
+        let mut xspeed = xdistance / dt;
+        let mut yspeed = ydistance_along / dt * self.gravity;
+
+        // Move in sub-steps no larger than half a tile or half the player's size, checking
+        // collisions after each one, so that fast movement can't skip over tiles
+        let max_step = (room.tile_size() as f32).min(self.width).min(self.height) / 2.0;
+        let distance = (xspeed * dt).abs().max((yspeed * dt).abs());
+        let steps = (distance / max_step).ceil().max(1.0) as u32;
+        let step_dt = dt / steps as f32;
+        let coyote_ticks = (physics.coyote_time / dt).round() as u32;
+        let mut wall_hit = None;
+        let mut landing_speed = 0.0;
+        for _ in 0..steps {
+            // Resolve collisions one axis at a time
+            let dx = xspeed * step_dt;
+            if let Some(side) = self.move_horizontally(dx, room, platforms) {
+                wall_hit = Some(side);
+                xspeed = 0.0;
+            }
+            if self.vert_state == PlayerVertState::Standing
+                && !self.is_gravity_flipped()
+                && !self.has_solid_floor_below(room, platforms)
+            {
+                // Follow slopes down while walking, rather than walking off into the air
+                self.land_on_slope(room, dx.abs() + 0.5);
+            }
+            let dy = yspeed * step_dt;
+            let was_standing = self.vert_state == PlayerVertState::Standing;
+            let speed_before = self.vel.y;
+            self.move_vertically(dy, room, platforms, coyote_ticks);
+            if self.vel.y >= 0.0 && !self.is_gravity_flipped() {
+                self.land_on_slope(room, 0.0);
+            }
+            if self.vert_state == PlayerVertState::Standing && !was_standing {
+                landing_speed = speed_before.abs();
+            }
+            if self.vel.y == 0.0 {
+                yspeed = 0.0;
+            }
+        }
+        self.constrain_to_grapple(room, platforms, coyote_ticks);
+        self.animate(dt);
+
+        if self.vert_state == PlayerVertState::Standing && self.is_on_bounce_tile(room) {
+            self.launch(landing_speed, physics);
+        }
+
+        // Slide down a wall when pressing into it while airborne
+        let pressing_into_wall = match (wall_hit, self.horiz_state) {
+            (Some(WallSide::Left), PlayerHorizState::MovingLeft)
+            | (Some(WallSide::Right), PlayerHorizState::MovingRight) => true,
+            // Reaching the opposite wall during a wall jump also counts, so narrow shafts can
+            // be climbed
+            (Some(_), _) => self.wall_jump_lockout_ticks > 0,
+            (None, _) => false,
+        };
+        match self.vert_state {
+            PlayerVertState::Falling | PlayerVertState::WallSliding if pressing_into_wall => {
+                if self.vert_state == PlayerVertState::Falling {
+                    self.set_vert_state(PlayerVertState::WallSliding);
+                    self.air_jump_available = true;
+                    self.jump_cut = false;
+                    self.wall_jump_lockout_ticks = 0;
+                }
+                self.wall_side = wall_hit;
+            }
+            PlayerVertState::WallSliding => {
+                self.set_vert_state(PlayerVertState::Falling);
+                self.wall_side = None;
+            }
+            _ => (),
+        }
+
+        trace!(
+            "Player accel: ({}, {}), speed: ({}, {}), pos: ({}, {})",
+            xaccel,
+            yaccel,
+            self.vel.x,
+            self.vel.y,
+            self.pos.x,
+            self.pos.y
+        );
+    }
+
+    /// Advances the animation clock, starting over when the animation changes.
+    fn animate(&mut self, dt: f32) {
+        let animation = match self.vert_state {
+            PlayerVertState::Standing if self.vel.x != 0.0 => Animation::Walk,
+            PlayerVertState::Standing => Animation::Idle,
+            PlayerVertState::Jumping => Animation::Jump,
+            PlayerVertState::Falling if self.vel.y * self.gravity < 0.0 => Animation::Jump,
+            PlayerVertState::Falling | PlayerVertState::WallSliding => Animation::Fall,
+        };
+        if animation == self.animation {
+            self.animation_time += dt;
+        } else {
+            self.animation = animation;
+            self.animation_time = 0.0;
+        }
+    }
+
+    /// Keeps the player's center within the grappling hook's length of its anchor
+    /// while swinging, removing the speed away from the anchor so that only the speed
+    /// along the swing remains.
+    ///
+    /// The correction is moved like any other movement, so tiles still stop the player.
+    fn constrain_to_grapple(&mut self, room: &Room, platforms: &[Rect], coyote_ticks: u32) {
+        let (anchor_x, anchor_y) = match self.grapple_anchor {
+            Some(anchor) => anchor,
+            None => return,
+        };
+        let (x, y) = self.center();
+        let (offset_x, offset_y) = (x - anchor_x, y - anchor_y);
+        let distance = (offset_x * offset_x + offset_y * offset_y).sqrt();
+        if distance <= self.grapple_length {
+            return;
+        }
+        let normal = Vec2::new(offset_x / distance, offset_y / distance);
+        let outward_speed = self.vel.dot(normal);
+        if outward_speed > 0.0 {
+            self.vel -= normal * outward_speed;
+        }
+        let excess = distance - self.grapple_length;
+        let max_step = (room.tile_size() as f32).min(self.width).min(self.height) / 2.0;
+        let steps = (excess / max_step).ceil().max(1.0) as u32;
+        for _ in 0..steps {
+            self.move_horizontally(-normal.x * excess / steps as f32, room, platforms);
+            self.move_vertically(-normal.y * excess / steps as f32, room, platforms, coyote_ticks);
+        }
+    }
+
+    /// Returns a copy of this player `progress` of the way from where they were in
+    /// `old`, the state of the previous tick.
+    fn interpolated(&self, old: &Player, progress: f32) -> Player {
+        let mut player = *self;
+        player.pos = Vec2::new(
+            interpolate(old.pos.x, self.pos.x, progress),
+            interpolate(old.pos.y, self.pos.y, progress),
+        );
+        player
+    }
+
+    fn center(&self) -> (f32, f32) {
+        (self.pos.x + self.width / 2.0, self.pos.y + self.height / 2.0)
+    }
+
+    /// Returns the player's collision rect.
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.x, self.pos.y, self.width, self.height)
+    }
+
+    /// Moves horizontally by `dx` and pushes the player out of any filled tiles
+    /// or platforms that were entered, returning which wall was hit.
+    fn move_horizontally(&mut self, dx: f32, room: &Room, platforms: &[Rect]) -> Option<WallSide> {
+        self.pos.x += dx;
+        let mut wall_hit = None;
+        let solids = solids_in_rect(&self.rect(), room, platforms);
+        self.collisions.record(CollisionProbe {
+            kind: ProbeKind::Horizontal,
+            rect: self.rect(),
+            hit: !solids.is_empty(),
+        });
+        for solid in solids {
+            if dx > 0.0 {
+                self.pos.x = self.pos.x.min(solid.left() - self.width);
+                wall_hit = Some(WallSide::Right);
+            } else if dx < 0.0 {
+                self.pos.x = self.pos.x.max(solid.right());
+                wall_hit = Some(WallSide::Left);
+            }
+        }
+        // The left and right edges of the room act as walls unless there is an exit
+        if self.pos.x < 0.0 && room.exit(Edge::Left).is_none() {
+            self.pos.x = 0.0;
+            wall_hit = Some(WallSide::Left);
+        } else if self.pos.x + self.width > room.pixel_width() && room.exit(Edge::Right).is_none() {
+            self.pos.x = room.pixel_width() - self.width;
+            wall_hit = Some(WallSide::Right);
+        }
+        if let Some(side) = wall_hit {
+            trace!("Player hit wall to the {:?}", side);
+            self.vel.x = 0.0;
+        }
+        wall_hit
+    }
+
+    /// Moves vertically by `dy` and pushes the player out of any filled tiles
+    /// or platforms that were entered, landing on floors and stopping at
+    /// ceilings. Falls when standing without a floor below.
+    ///
+    /// `coyote_ticks` is the number of ticks a jump is still allowed after walking off a ledge.
+    fn move_vertically(&mut self, dy: f32, room: &Room, platforms: &[Rect], coyote_ticks: u32) {
+        self.pos.y += dy;
+        let mut solids = solids_in_rect(&self.rect(), room, platforms);
+        self.collisions.record(CollisionProbe {
+            kind: ProbeKind::Vertical,
+            rect: self.rect(),
+            hit: !solids.is_empty(),
+        });
+        if dy * self.gravity < 0.0 && !solids.is_empty() {
+            // Slide around the corner of a ceiling that was only barely clipped
+            if let Some(nudge) = self.corner_correction(&solids, room, platforms) {
+                trace!("Player corner correction by {}", nudge);
+                self.pos.x += nudge;
+                solids.clear();
+            }
+        }
+        let mut hit_below = false;
+        let mut hit_above = false;
+        for solid in solids {
+            if dy > 0.0 {
+                self.pos.y = self.pos.y.min(solid.top() - self.height);
+                hit_below = true;
+            } else if dy < 0.0 {
+                self.pos.y = self.pos.y.max(solid.bottom());
+                hit_above = true;
+            }
+        }
+        // The floor is whatever gravity pulls the player towards
+        let (hit_floor, hit_ceiling) = if self.is_gravity_flipped() {
+            (hit_above, hit_below)
+        } else {
+            (hit_below, hit_above)
+        };
+        if hit_floor {
+            trace!("Player hit floor");
+            self.land();
+        } else if hit_ceiling {
+            trace!("Player hit ceiling");
+            self.set_vert_state(PlayerVertState::Falling);
+            self.vel.y = 0.0;
+        } else if self.vert_state == PlayerVertState::Standing {
+            let has_floor = self.has_floor_below(room, platforms);
+            self.collisions.record(CollisionProbe {
+                kind: ProbeKind::Floor,
+                rect: self.feet_rect(),
+                hit: has_floor,
+            });
+            if !has_floor {
+                trace!("Player fall from ledge");
+                self.set_vert_state(PlayerVertState::Falling);
+                self.coyote_ticks = coyote_ticks;
+            }
+        }
+    }
+
+    /// Returns how far to move the player horizontally to get out of the `solids` it
+    /// has bumped its head into, if that is only a few pixels and the space there is free.
+    fn corner_correction(&self, solids: &[Rect], room: &Room, platforms: &[Rect]) -> Option<f32> {
+        const MAX_CORRECTION: f32 = 3.0;
+        let right_nudge = solids
+            .iter()
+            .map(|solid| solid.right() - self.pos.x)
+            .fold(0.0, f32::max);
+        let left_nudge = solids
+            .iter()
+            .map(|solid| solid.left() - (self.pos.x + self.width))
+            .fold(0.0, f32::min);
+        let mut nudges = [right_nudge, left_nudge];
+        nudges.sort_by(|a, b| a.abs().partial_cmp(&b.abs()).unwrap_or(Ordering::Equal));
+        nudges.iter().cloned().find(|&nudge| {
+            let xpos = self.pos.x + nudge;
+            let rect = Rect::new(xpos, self.pos.y, self.width, self.height);
+            nudge.abs() <= MAX_CORRECTION
+                && xpos >= 0.0
+                && xpos + self.width <= room.pixel_width()
+                && solids_in_rect(&rect, room, platforms).is_empty()
+        })
+    }
+
+    fn is_on_bounce_tile(&self, room: &Room) -> bool {
+        let feet = self.feet_rect();
+        room.tiles_in_rect(&feet)
+            .iter()
+            .any(|tile| tile.kind == TileKind::Bounce && feet.overlaps(&tile.rect))
+    }
+
+    /// Launches the player away from a bounce tile it landed on with `landing_speed`.
+    fn launch(&mut self, landing_speed: f32, physics: &PhysicsConfig) {
+        let mut speed = (landing_speed * physics.bounce_factor).max(physics.bounce_min_speed);
+        if self.jump_held {
+            speed *= physics.bounce_jump_multiplier;
+        }
+        let speed = speed.min(physics.bounce_max_speed);
+        trace!("Player bounce with speed {}", speed);
+        self.vel.y = -speed * self.gravity;
+        self.jump_cut = false;
+        self.air_jump_available = true;
+        self.set_vert_state(PlayerVertState::Falling);
+    }
+
+    fn land(&mut self) {
+        self.set_vert_state(PlayerVertState::Standing);
+        self.air_jump_available = true;
+        self.wall_side = None;
+        self.vel.y = 0.0;
+    }
+
+    /// Puts the player's feet on the surface of a slope tile if they're below it, or
+    /// at most `reach` pixels above it.
+    ///
+    /// Slopes are only solid from above, so they're ignored by the other collision checks.
+    fn land_on_slope(&mut self, room: &Room, reach: f32) {
+        if let Some(surface) = self.slope_surface_below(room, reach) {
+            self.pos.y = surface - self.height;
+            if self.vert_state != PlayerVertState::Standing {
+                trace!("Player landed on slope");
+                self.land();
+            }
+        }
+    }
+
+    /// Returns the highest slope surface below the player's bottom edge that is at
+    /// most `reach` pixels below its feet, and at most a tile above them.
+    ///
+    /// Every tile along the bottom edge is checked, so slopes are found even when
+    /// the player is several tiles wide.
+    fn slope_surface_below(&self, room: &Room, reach: f32) -> Option<f32> {
+        let bottom = self.pos.y + self.height;
+        let feet = Rect::new(self.pos.x, bottom - 0.001, self.width, reach + 0.002);
+        room.tiles_in_rect(&feet)
+            .iter()
+            .filter_map(|tile| {
+                // The surface is straight, so its highest point under the player is at one of
+                // its edges
+                let left = tile.surface_at(self.pos.x)?;
+                let right = tile.surface_at(self.pos.x + self.width)?;
+                Some(left.min(right))
+            })
+            .filter(|&surface| surface <= bottom + reach)
+            .fold(None, |highest: Option<f32>, surface| {
+                Some(highest.map_or(surface, |highest| highest.min(surface)))
+            })
+    }
+
+    /// Returns whether there is a filled tile, platform or slope directly below the player's feet.
+    fn has_floor_below(&self, room: &Room, platforms: &[Rect]) -> bool {
+        self.has_solid_floor_below(room, platforms)
+            || (!self.is_gravity_flipped() && self.slope_surface_below(room, 0.5).is_some())
+    }
+
+    /// Returns whether there is a filled tile or platform directly below the player's feet,
+    /// or above their head when gravity is flipped.
+    fn has_solid_floor_below(&self, room: &Room, platforms: &[Rect]) -> bool {
+        !solids_in_rect(&self.feet_rect(), room, platforms).is_empty()
+    }
+
+    /// Returns a thin rect directly below the player's feet, or above their head
+    /// when gravity is flipped.
+    fn feet_rect(&self) -> Rect {
+        let feet_y = if self.is_gravity_flipped() {
+            self.pos.y - 0.5
+        } else {
+            self.pos.y + self.height
+        };
+        Rect::new(self.pos.x, feet_y, self.width, 0.5)
+    }
+
+    /// Draws the player with the current frame of their animation from `sprites`,
+    /// or as a rect in `color` without a sprite sheet.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        color: Color,
+        sprites: Option<&SpriteSheet>,
+    ) -> Result<(), Error> {
+        // Blink while invincible
+        const BLINK_TICKS: u32 = 8;
+        if self.invincible_ticks / BLINK_TICKS % 2 == 1 {
+            return Ok(());
+        }
+        // Shrink the drawn height along with the collision box when crouching
+        let visual_height = self.visual_height * self.height / self.stand_height;
+        let visual_left = self.pos.x + (self.width - self.visual_width) / 2.0;
+        let visual_top = self.pos.y + (self.height - visual_height) / 2.0;
+        let visual_rect = Rect::new(visual_left, visual_top, self.visual_width, visual_height);
+        if let Some((anchor_x, anchor_y)) = self.grapple_anchor {
+            let (x, y) = self.center();
+            canvas.set_draw_color(theme.grapple_rope());
+            canvas
+                .draw_line(camera.sdl_point(x, y), camera.sdl_point(anchor_x, anchor_y))
+                .map_err(err_msg)?;
+        }
+        let sprite = sprites.and_then(|sprites| {
+            let source = sprites.source_rect(self.animation, self.animation_time)?;
+            Some((sprites, source))
+        });
+        if let Some((sprites, source)) = sprite {
+            // The frames face right and stand on the floor
+            let flip_horizontal = self.facing == Direction::Left;
+            return canvas
+                .copy_ex(
+                    sprites.texture(),
+                    source,
+                    camera.sdl_rect(&visual_rect),
+                    0.0,
+                    None,
+                    flip_horizontal,
+                    self.is_gravity_flipped(),
+                )
+                .map_err(err_msg);
+        }
+        canvas.set_draw_color(color);
+        canvas
+            .fill_rect(camera.sdl_rect(&visual_rect))
+            .map_err(err_msg)?;
+        // Draw a notch near the top on the side the player is facing
+        const NOTCH_SIZE: f32 = 3.0;
+        let notch_x = if self.facing == Direction::Left {
+            visual_left
+        } else {
+            visual_left + self.visual_width - NOTCH_SIZE
+        };
+        // Draw it upside down along with the player when gravity is flipped
+        let notch_y = if self.is_gravity_flipped() {
+            visual_top + visual_height - 2.0 - NOTCH_SIZE
+        } else {
+            visual_top + 2.0
+        };
+        let notch = Rect::new(notch_x, notch_y, NOTCH_SIZE, NOTCH_SIZE);
+        canvas.set_draw_color(theme.player_notch());
+        canvas
+            .fill_rect(camera.sdl_rect(&notch))
+            .map_err(err_msg)?;
+        Ok(())
+    }
+
+    /// Draws the player dying as a red flash shrinking towards their center, with
+    /// `time_left` going from 1 at the moment of death to 0 when they respawn.
+    pub fn render_dying<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        theme: &Theme,
+        time_left: f32,
+    ) -> Result<(), Error> {
+        let width = self.visual_width * time_left;
+        let height = self.visual_height * self.height / self.stand_height * time_left;
+        let rect = Rect::new(
+            self.pos.x + (self.width - width) / 2.0,
+            self.pos.y + (self.height - height) / 2.0,
+            width,
+            height,
+        );
+        canvas.set_draw_color(theme.dying());
+        canvas.fill_rect(camera.sdl_rect(&rect)).map_err(err_msg)
+    }
+}
+
+/// Dims the screen and shows the results as rows of squares: one per coin in the
+/// room, filled in for those collected, and one per death.
+fn render_results_overlay<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    camera: &Camera,
+    theme: &Theme,
+    results: &RoomResults,
+) -> Result<(), Error> {
+    // Most squares drawn in a row, so that a lot of deaths still fit on screen
+    const MAX_SQUARES: u32 = 20;
+    let (width, height) = camera.logical_size();
+    canvas.set_draw_color(theme.dim());
+    canvas
+        .fill_rect(SdlRect::new(0, 0, width, height))
+        .map_err(err_msg)?;
+    let size = (width / 40).max(2);
+    let left = (width / 2) as i32 - (MAX_SQUARES * size) as i32 / 2;
+    let coins_top = (height / 2) as i32 - 2 * size as i32;
+    let deaths_top = (height / 2) as i32 + size as i32;
+    for i in 0..results.total_coins.min(MAX_SQUARES) {
+        let square = SdlRect::new(left + (i * size) as i32, coins_top, size - 1, size - 1);
+        if i < results.coins {
+            canvas.set_draw_color(theme.coin());
+            canvas.fill_rect(square).map_err(err_msg)?;
+        } else {
+            canvas.set_draw_color(theme.results_missed_coin());
+            canvas.draw_rect(square).map_err(err_msg)?;
+        }
+    }
+    canvas.set_draw_color(theme.results_death());
+    for i in 0..results.deaths.min(MAX_SQUARES) {
+        let square = SdlRect::new(left + (i * size) as i32, deaths_top, size - 1, size - 1);
+        canvas.fill_rect(square).map_err(err_msg)?;
+    }
+    Ok(())
+}
+
+/// Draws `message` in a box along the top or bottom of the screen, wrapped to fit.
+fn render_message_box<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    camera: &Camera,
+    theme: &Theme,
+    message: &str,
+    at_top: bool,
+) -> Result<(), Error> {
+    let (width, height) = camera.logical_size();
+    let scale = (width / 160).max(1);
+    let padding = 2 * scale;
+    let margin = 4 * scale;
+    let box_width = width.saturating_sub(2 * margin);
+    let max_chars = (box_width.saturating_sub(2 * padding) / (font::ADVANCE * scale)) as usize;
+    let lines = font::wrap(message, max_chars);
+    let box_height = lines.len() as u32 * font::LINE_HEIGHT * scale + 2 * padding - 2 * scale;
+    let box_top = if at_top {
+        margin as i32
+    } else {
+        height as i32 - (margin + box_height) as i32
+    };
+    canvas.set_draw_color(theme.message_box());
+    canvas
+        .fill_rect(SdlRect::new(margin as i32, box_top, box_width, box_height))
+        .map_err(err_msg)?;
+    canvas.set_draw_color(theme.message_text());
+    for (index, line) in lines.iter().enumerate() {
+        let line_top = box_top + (padding + index as u32 * font::LINE_HEIGHT * scale) as i32;
+        font::render_text(canvas, line, (margin + padding) as i32, line_top, scale)?;
+    }
+    Ok(())
+}
+
+/// Tints the screen red and shows a cross in the middle.
+fn render_game_over_overlay<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    camera: &Camera,
+    theme: &Theme,
+) -> Result<(), Error> {
+    let (width, height) = camera.logical_size();
+    canvas.set_draw_color(theme.game_over_tint());
+    canvas
+        .fill_rect(SdlRect::new(0, 0, width, height))
+        .map_err(err_msg)?;
+    let size = (height / 6).max(1) as i32;
+    let (center_x, center_y) = (width as i32 / 2, height as i32 / 2);
+    canvas.set_draw_color(theme.overlay_symbol());
+    canvas
+        .draw_line(
+            (center_x - size / 2, center_y - size / 2),
+            (center_x + size / 2, center_y + size / 2),
+        )
+        .map_err(err_msg)?;
+    canvas
+        .draw_line(
+            (center_x + size / 2, center_y - size / 2),
+            (center_x - size / 2, center_y + size / 2),
+        )
+        .map_err(err_msg)?;
+    Ok(())
+}
+
+/// Dims the screen and shows a pause symbol in the middle.
+fn render_pause_overlay<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    camera: &Camera,
+    theme: &Theme,
+) -> Result<(), Error> {
+    let (width, height) = camera.logical_size();
+    canvas.set_draw_color(theme.dim());
+    canvas
+        .fill_rect(SdlRect::new(0, 0, width, height))
+        .map_err(err_msg)?;
+    let bar_width = (width / 40).max(1);
+    let bar_height = (height / 8).max(1);
+    let center_x = width as i32 / 2;
+    let top = (height - bar_height) as i32 / 2;
+    canvas.set_draw_color(theme.overlay_symbol());
+    for &x in &[center_x - 2 * bar_width as i32, center_x + bar_width as i32] {
+        canvas
+            .fill_rect(SdlRect::new(x, top, bar_width, bar_height))
+            .map_err(err_msg)?;
+    }
+    Ok(())
+}
+
+/// Formats a duration like `1:02.345`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{}:{:02}.{:03}",
+        secs / 60,
+        secs % 60,
+        duration.subsec_millis()
+    )
+}
+
+/// Returns the value `progress` of the way from `old` to `new`, where `progress`
+/// is clamped to the range from 0 to 1.
+pub fn interpolate(old: f32, new: f32, progress: f32) -> f32 {
+    old + (new - old) * progress.clamp(0.0, 1.0)
+}
+
+/// Changes `speed` by `accel` per second for up to `dt` seconds, stopping once it
+/// reaches `limit`, or snapping to `limit` if it's already past it.
+///
+/// Returns the new speed, the distance covered until then, and how much of `dt`
+/// was left after reaching the limit.
+fn accelerate(speed: f32, accel: f32, limit: f32, dt: f32) -> (f32, f32, f32) {
+    let time = if accel == 0.0 {
+        dt
+    } else {
+        ((limit - speed) / accel).max(0.0).min(dt)
+    };
+    let new_speed = if time < dt { limit } else { speed + accel * dt };
+    (new_speed, (speed + new_speed) / 2.0 * time, dt - time)
+}
+
+/// Like `accelerate`, but keeps going at the limit for the rest of `dt`, returning
+/// the new speed and the whole distance covered.
+fn accelerate_capped(speed: f32, accel: f32, limit: f32, dt: f32) -> (f32, f32) {
+    let (speed, distance, time_left) = accelerate(speed, accel, limit, dt);
+    (speed, distance + speed * time_left)
+}
+
+/// Defeats enemies `player` stomped on, given where they were in the previous tick.
+/// If the player touched an enemy in any other way, returns the horizontal center
+/// of the enemy that hurt them.
+///
+/// A stomp is when the player moves down onto an enemy with their feet
+/// above its vertical center. The player's movement during the whole tick
+/// is considered, so a fast fall can't skip over an enemy.
+fn handle_enemy_contact(
+    enemies: &mut Vec<Enemy>,
+    old_player: &Player,
+    player: &mut Player,
+) -> Option<f32> {
+    let old_rect = old_player.rect();
+    let rect = player.rect();
+    let swept_rect = Rect::new(
+        old_rect.left().min(rect.left()),
+        old_rect.top().min(rect.top()),
+        old_rect.right().max(rect.right()) - old_rect.left().min(rect.left()),
+        old_rect.bottom().max(rect.bottom()) - old_rect.top().min(rect.top()),
+    );
+    let moving_down = rect.top() > old_rect.top();
+    let mut stomped = false;
+    let mut hurt_by = None;
+    enemies.retain(|enemy| {
+        let enemy_rect = enemy.rect();
+        let enemy_center = (enemy_rect.top() + enemy_rect.bottom()) / 2.0;
+        if moving_down && old_rect.bottom() <= enemy_center && swept_rect.overlaps(&enemy_rect) {
+            debug!("Player stomped an enemy");
+            stomped = true;
+            false
+        } else {
+            if rect.overlaps(&enemy_rect) {
+                debug!("Player touched an enemy");
+                hurt_by = Some((enemy_rect.left() + enemy_rect.right()) / 2.0);
+            }
+            true
+        }
+    });
+    if stomped {
+        player.bounce();
+        None
+    } else {
+        hurt_by
+    }
+}
+
+/// Moves `player` to the linked teleporter when they enter one, keeping their
+/// speed and their position relative to the teleporter.
+///
+/// `was_inside` tracks whether they were inside a teleporter in the previous tick,
+/// so that they have to leave the destination before it can teleport them back,
+/// even when standing still on it.
+/// Returns whether they were teleported.
+fn teleport(player: &mut Player, room: &Room, was_inside: &mut bool) -> bool {
+    let rect = player.rect();
+    // The player is taller than a tile, so only their horizontal extent has to be inside
+    let entered = room.tiles_in_rect(&rect).into_iter().find(|tile| {
+        rect.left() >= tile.rect.left()
+            && rect.right() <= tile.rect.right()
+            && room.teleporter_destination(tile).is_some()
+    });
+    let already_inside = *was_inside;
+    *was_inside = entered.is_some();
+    let tile = match entered {
+        Some(ref tile) if !already_inside => tile,
+        _ => return false,
+    };
+    let destination = match room.teleporter_destination(tile) {
+        Some(destination) => destination,
+        None => return false,
+    };
+    debug!(
+        "Teleporting from ({}, {}) to ({}, {})",
+        tile.x, tile.y, destination.x, destination.y
+    );
+    player.pos += Vec2::new(
+        destination.rect.left() - tile.rect.left(),
+        destination.rect.top() - tile.rect.top(),
+    );
+    player.release_grapple();
+    true
+}
+
+/// Pushes `player` out of `crusher`, which has moved by (`dx`, `dy`), if it moved
+/// into them. Returns whether that pushed them into a solid tile or one of the
+/// `solids`, i.e. whether they're crushed.
+fn push_or_crush(
+    player: &mut Player,
+    dx: f32,
+    dy: f32,
+    crusher: &Rect,
+    room: &Room,
+    solids: &[Rect],
+) -> bool {
+    if !player.rect().overlaps(crusher) {
+        return false;
+    }
+    player.push_out(dx, dy, crusher);
+    !solids_in_rect(&player.rect(), room, solids).is_empty()
+}
+
+/// Returns the rects of all filled tiles and platforms overlapping `rect`.
+///
+/// Tiles that only overlap by a rounding error don't count, so that walking along a
+/// floor never hits the floor tiles as walls, however thick the walls around are.
+fn solids_in_rect(rect: &Rect, room: &Room, platforms: &[Rect]) -> Vec<Rect> {
+    let mut solids: Vec<Rect> = room.tiles_in_rect(rect)
+        .into_iter()
+        .filter(|tile| tile.kind.is_solid() && rect.overlaps(&tile.rect))
+        .map(|tile| tile.rect)
+        .collect();
+    solids.extend(platforms.iter().filter(|platform| rect.overlaps(platform)));
+    solids
+}
+
+/// Whether input is being recorded or played back.
+#[derive(Clone, Debug)]
+enum ReplayMode {
+    Off,
+    Recording(Replay),
+    Playing {
+        replay: Replay,
+        /// Index of the next input to apply
+        next: usize,
+    },
+}
+
+/// Summary of a completed run through a room, measured in simulated time.
+#[derive(Clone, Copy, Debug)]
+pub struct RoomResults {
+    /// Time taken to reach the goal
+    pub time: Duration,
+    pub coins: u32,
+    /// Number of coins in the room
+    pub total_coins: u32,
+    /// Number of deaths since entering or restarting the room
+    pub deaths: u32,
+}
+
+/// Describes the player moving from one room to another.
+#[derive(Clone, Debug)]
+pub struct RoomChange {
+    /// File path of the room that was entered
+    pub room: String,
+    /// Edge of the previous room through which it was left
+    pub edge: Edge,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PlayerHorizState {
+    Idle,
+    MovingLeft,
+    MovingRight,
+    StopMovingLeft,
+    StopMovingRight,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PlayerVertState {
+    Standing,
+    Falling,
+    Jumping,
+    WallSliding,
+}
+
+/// Which of the player's collision checks a probe was made by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProbeKind {
+    /// Checking for walls after moving sideways
+    Horizontal,
+    /// Checking for floors and ceilings after moving up or down
+    Vertical,
+    /// Checking for a floor below the feet while standing
+    Floor,
+}
+
+impl ProbeKind {
+    /// Number of kinds of probes
+    const COUNT: usize = 3;
+
+    fn index(self) -> usize {
+        match self {
+            ProbeKind::Horizontal => 0,
+            ProbeKind::Vertical => 1,
+            ProbeKind::Floor => 2,
+        }
+    }
+}
+
+/// An area a collision check looked for solids in, and whether it found any.
+#[derive(Clone, Copy, Debug)]
+pub struct CollisionProbe {
+    pub kind: ProbeKind,
+    pub rect: Rect,
+    pub hit: bool,
+}
+
+/// The last probe of each kind a player's update made, to show what collision saw.
+///
+/// Keeping only one probe per kind keeps recording cheap enough to always do.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CollisionRecord {
+    probes: [Option<CollisionProbe>; ProbeKind::COUNT],
+}
+
+impl CollisionRecord {
+    fn record(&mut self, probe: CollisionProbe) {
+        self.probes[probe.kind.index()] = Some(probe);
+    }
+
+    /// Returns the recorded probes, in the order of their kinds.
+    pub fn probes(&self) -> Vec<CollisionProbe> {
+        self.probes.iter().filter_map(|&probe| probe).collect()
+    }
+}
+
+/// Which sides of the player are touching something solid.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Probes {
+    pub floor: bool,
+    pub ceiling: bool,
+    pub left_wall: bool,
+    pub right_wall: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WallSide {
+    Left,
+    Right,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use camera::{CameraMode, CameraZone};
+    use room::BLINK_TIME;
+
+    const ROOM_PATH: &str = "test.json";
+    const TICKS_PER_SECOND: u32 = 150;
+
+    fn model_in(room: Room) -> Model {
+        Model::new(Level::with_room(ROOM_PATH, room), ROOM_PATH, TICKS_PER_SECOND)
+    }
+
+    fn tick(model: &mut Model, ticks: u32) {
+        for _ in 0..ticks {
+            let frame_duration = model.frame_duration;
+            model.update(frame_duration);
+        }
+    }
+
+    fn press(model: &mut Model, action: Action) {
+        model.handle_input(InputEvent::Pressed(action));
+    }
+
+    fn release(model: &mut Model, action: Action) {
+        model.handle_input(InputEvent::Released(action));
+    }
+
+    /// Returns a model with the player standing on a four tile wide ledge, high
+    /// above the floor.
+    fn model_on_ledge() -> Model {
+        let mut room = Room::new(10, 10, 16);
+        for x in 0..4 {
+            room.set_tile_at_index(x, 4, TileKind::Filled).unwrap();
+        }
+        room.set_spawn(1, 4).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        model
+    }
+
+    /// Walks the player right until they've walked off the ledge.
+    fn walk_off_ledge(model: &mut Model) {
+        press(model, Action::MoveRight);
+        for _ in 0..TICKS_PER_SECOND {
+            tick(model, 1);
+            if model.player().vert_state() == PlayerVertState::Falling {
+                return;
+            }
+        }
+        panic!("Player never walked off the ledge");
+    }
+
+    fn coyote_window(model: &Model) -> u32 {
+        (model.physics.coyote_time * TICKS_PER_SECOND as f32).round() as u32
+    }
+
+    #[test]
+    fn jump_right_after_walking_off_ledge_fires() {
+        let mut model = model_on_ledge();
+        walk_off_ledge(&mut model);
+        tick(&mut model, 3);
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Jumping);
+        assert!(model.player().vel().y < 0.0);
+    }
+
+    #[test]
+    fn jump_at_end_of_coyote_window_fires() {
+        let mut model = model_on_ledge();
+        walk_off_ledge(&mut model);
+        let window = coyote_window(&model);
+        tick(&mut model, window - 2);
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Jumping);
+    }
+
+    #[test]
+    fn jump_after_coyote_window_does_nothing() {
+        let mut model = model_on_ledge();
+        walk_off_ledge(&mut model);
+        let window = coyote_window(&model);
+        tick(&mut model, window + 1);
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Falling);
+        assert!(model.player().vel().y > 0.0);
+    }
+
+    #[test]
+    fn no_coyote_time_after_jumping() {
+        let mut model = model_on_ledge();
+        press(&mut model, Action::Jump);
+        for _ in 0..coyote_window(&model) {
+            tick(&mut model, 1);
+            assert_ne!(model.player().vert_state(), PlayerVertState::Standing);
+            assert!(!model.player().can_jump());
+        }
+    }
+
+    #[test]
+    fn player_at_high_fall_speed_lands_on_thin_floor() {
+        let mut room = Room::new(4, 40, 16);
+        room.set_spawn(1, 2).unwrap();
+        let mut model = model_in(room);
+        model.set_physics(PhysicsConfig {
+            fall_speed: 1000.0,
+            fall_time: 0.02,
+            ..PhysicsConfig::default()
+        });
+        let mut fastest_tick = 0.0f32;
+        for _ in 0..TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            fastest_tick = fastest_tick.max(model.player().vel().y / TICKS_PER_SECOND as f32);
+        }
+        // Fast enough to pass the player's height and the floor tile in a single tick
+        assert!(fastest_tick > 20.0 + 16.0, "only fell {} pixels per tick", fastest_tick);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        assert_eq!(model.player().rect().bottom(), 39.0 * 16.0);
+    }
+
+    /// Moves the player around the ledge room by holding keys for a while.
+    fn play_scripted_run(model: &mut Model) {
+        press(model, Action::MoveRight);
+        tick(model, 20);
+        press(model, Action::Jump);
+        tick(model, 7);
+        release(model, Action::Jump);
+        tick(model, 30);
+        release(model, Action::MoveRight);
+        press(model, Action::MoveLeft);
+        tick(model, 11);
+        release(model, Action::MoveLeft);
+        // Keep going for a while after the last input
+        tick(model, 25);
+    }
+
+    #[test]
+    fn replay_ends_in_recorded_state() {
+        let mut model = model_on_ledge();
+        model.start_recording();
+        play_scripted_run(&mut model);
+        let recorded = model.player;
+        let replay = model.stop_recording().unwrap();
+        assert_ne!(recorded.pos, model_on_ledge().player.pos);
+
+        model.start_playback(replay.clone()).unwrap();
+        assert_ne!(model.player.pos, recorded.pos);
+        tick(&mut model, replay.end_tick() - 1);
+        assert!(model.is_playing_back());
+        // Keys pressed during playback are ignored
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        assert!(!model.is_playing_back());
+        assert_eq!(model.player.pos.x.to_bits(), recorded.pos.x.to_bits());
+        assert_eq!(model.player.pos.y.to_bits(), recorded.pos.y.to_bits());
+        assert_eq!(model.player.vel, recorded.vel);
+        assert_eq!(model.player.vert_state, recorded.vert_state);
+    }
+
+    /// Returns a falling player with its top left corner at (`x`, `y`).
+    fn player_at(x: f32, y: f32) -> Player {
+        let mut player = Player::new();
+        player.pos = Vec2::new(x, y);
+        player
+    }
+
+    /// Returns a room with a floor and filled tiles at the given indices.
+    fn room_with_tiles(tiles: &[(u32, u32)]) -> Room {
+        let mut room = Room::new(6, 8, 16);
+        for &(x, y) in tiles {
+            room.toggle_tile_at_index(x, y, TileKind::Filled).unwrap();
+        }
+        room
+    }
+
+    #[test]
+    fn landing_with_one_foot_snaps_to_that_tile() {
+        // Only the right foot is above the filled tile
+        let room = room_with_tiles(&[(2, 5)]);
+        let mut player = player_at(28.0, 58.0);
+        player.move_vertically(4.0, &room, &[], 0);
+        assert_eq!(player.pos.y, 80.0 - 20.0);
+        assert_eq!(player.vert_state, PlayerVertState::Standing);
+
+        // Only the left foot is, with the right one outside the room
+        let room = room_with_tiles(&[(5, 5)]);
+        let mut player = player_at(92.0, 58.0);
+        player.move_vertically(4.0, &room, &[], 0);
+        assert_eq!(player.pos.y, 60.0);
+        assert_eq!(player.vert_state, PlayerVertState::Standing);
+    }
+
+    #[test]
+    fn landing_on_tiles_of_different_rows_snaps_to_the_higher_one() {
+        let room = room_with_tiles(&[(1, 4), (2, 5)]);
+        let mut player = player_at(28.0, 40.0);
+        player.move_vertically(22.0, &room, &[], 0);
+        assert_eq!(player.pos.y, 64.0 - 20.0);
+
+        // Same with the tiles swapped around
+        let room = room_with_tiles(&[(1, 5), (2, 4)]);
+        let mut player = player_at(28.0, 40.0);
+        player.move_vertically(22.0, &room, &[], 0);
+        assert_eq!(player.pos.y, 44.0);
+    }
+
+    #[test]
+    fn landing_on_platform_and_tile_snaps_to_the_higher_one() {
+        let room = room_with_tiles(&[(2, 5)]);
+        let platforms = [Rect::new(0.0, 70.0, 32.0, 4.0)];
+        let mut player = player_at(28.0, 40.0);
+        player.move_vertically(22.0, &room, &platforms, 0);
+        assert_eq!(player.pos.y, 70.0 - 20.0);
+    }
+
+    #[test]
+    fn bumping_head_with_one_side_snaps_below_that_tile() {
+        let room = room_with_tiles(&[(2, 1)]);
+        // Too far under the tile to be corrected around its corner
+        let mut player = player_at(28.0, 34.0);
+        player.vel.y = -100.0;
+        player.move_vertically(-4.0, &room, &[], 0);
+        assert_eq!(player.pos, Vec2::new(28.0, 32.0));
+        assert_eq!(player.vel.y, 0.0);
+        assert_eq!(player.vert_state, PlayerVertState::Falling);
+    }
+
+    #[test]
+    fn bumping_head_on_tiles_of_different_rows_snaps_below_the_lower_one() {
+        let room = room_with_tiles(&[(1, 1), (2, 2)]);
+        let mut player = player_at(28.0, 50.0);
+        player.move_vertically(-20.0, &room, &[], 0);
+        assert_eq!(player.pos.y, 48.0);
+    }
+
+    #[test]
+    fn landing_with_flipped_gravity_snaps_below_the_lower_ceiling_tile() {
+        let room = room_with_tiles(&[(1, 1), (2, 2)]);
+        let mut player = player_at(28.0, 50.0);
+        player.flip_gravity();
+        player.move_vertically(-20.0, &room, &[], 0);
+        assert_eq!(player.pos.y, 48.0);
+        assert_eq!(player.vert_state, PlayerVertState::Standing);
+    }
+
+    /// Returns how high the player rises with `physics` when jumping from the floor
+    /// and holding the jump key for `hold_ticks` ticks, or for the whole jump if `None`.
+    fn jump_height(physics: PhysicsConfig, hold_ticks: Option<u32>) -> f32 {
+        let mut model = model_in(Room::new(10, 30, 16));
+        model.set_physics(physics);
+        tick(&mut model, 1);
+        let start = model.player().pos().y;
+        let mut peak = start;
+        press(&mut model, Action::Jump);
+        for ticks in 0..2 * TICKS_PER_SECOND {
+            if Some(ticks) == hold_ticks {
+                release(&mut model, Action::Jump);
+            }
+            tick(&mut model, 1);
+            peak = peak.min(model.player().pos().y);
+        }
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        start - peak
+    }
+
+    #[test]
+    fn short_hop_peaks_lower_than_full_jump() {
+        let short = jump_height(PhysicsConfig::default(), Some(3));
+        let full = jump_height(PhysicsConfig::default(), None);
+        assert!(short > 0.0);
+        assert!(short < full / 2.0, "short hop {} vs full jump {}", short, full);
+    }
+
+    /// Taps jump every few ticks from the moment the player leaves the floor until
+    /// they land again, returning how many jumps started in that time.
+    fn jumps_in_one_airtime(model: &mut Model) -> u32 {
+        // Ticks between presses, which is long enough for every jump to reach full speed
+        const TAP_PERIOD: u32 = 20;
+        let mut jumps = 0;
+        let mut was_jumping = false;
+        for ticks in 0..5 * TICKS_PER_SECOND {
+            match ticks % TAP_PERIOD {
+                0 => press(model, Action::Jump),
+                15 => release(model, Action::Jump),
+                _ => (),
+            }
+            tick(model, 1);
+            let jumping = model.player().vert_state() == PlayerVertState::Jumping;
+            if jumping && !was_jumping {
+                jumps += 1;
+            }
+            was_jumping = jumping;
+            if ticks > 0 && model.player().vert_state() == PlayerVertState::Standing {
+                release(model, Action::Jump);
+                return jumps;
+            }
+        }
+        panic!("Player never landed");
+    }
+
+    /// Returns `room` with one of its boolean settings, e.g. `"double_jump"`, turned on.
+    fn with_setting(room: Room, setting: &str) -> Room {
+        let mut value = ::serde_json::to_value(&room).unwrap();
+        value[setting] = true.into();
+        ::serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn double_jump_allows_two_jumps_per_airtime() {
+        let mut model = model_in(with_setting(Room::new(10, 30, 16), "double_jump"));
+        tick(&mut model, 1);
+        assert_eq!(jumps_in_one_airtime(&mut model), 2);
+        // Landing gives the air jump back
+        assert_eq!(jumps_in_one_airtime(&mut model), 2);
+    }
+
+    #[test]
+    fn no_air_jump_without_double_jump() {
+        let mut model = model_in(Room::new(10, 30, 16));
+        tick(&mut model, 1);
+        assert_eq!(jumps_in_one_airtime(&mut model), 1);
+    }
+
+    #[test]
+    fn wall_slide_ends_standing_on_floor() {
+        let mut room = Room::new(10, 20, 16);
+        for y in 0..19 {
+            room.set_tile_at_index(5, y, TileKind::Filled).unwrap();
+        }
+        room.set_spawn(3, 19).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        press(&mut model, Action::MoveRight);
+        press(&mut model, Action::Jump);
+        let mut slid = false;
+        for _ in 0..2 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            match model.player().vert_state() {
+                PlayerVertState::WallSliding => slid = true,
+                PlayerVertState::Standing if slid => break,
+                _ => (),
+            }
+        }
+        assert!(slid);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        assert_eq!(model.player().wall_side, None);
+        // Jumping from the floor is a normal jump rather than a wall jump off the wall beside it
+        release(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Jumping);
+        assert!(model.player().vel().x >= 0.0);
+    }
+
+    #[test]
+    fn wall_jumps_climb_one_tile_wide_shaft() {
+        const SHAFT_X: u32 = 4;
+        const SHAFT_TOP: u32 = 8;
+        let mut room = Room::new(10, 30, 16);
+        for x in 0..10 {
+            for y in SHAFT_TOP..29 {
+                if x != SHAFT_X {
+                    room.set_tile_at_index(x, y, TileKind::Filled).unwrap();
+                }
+            }
+        }
+        room.set_spawn(SHAFT_X, 29).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        press(&mut model, Action::MoveRight);
+        press(&mut model, Action::Jump);
+        while model.player().vert_state() != PlayerVertState::WallSliding {
+            assert!(model.total_ticks() < u64::from(TICKS_PER_SECOND), "Player never hit a wall");
+            tick(&mut model, 1);
+        }
+        let shaft_top = (SHAFT_TOP * 16) as f32;
+        for _ in 0..10 * TICKS_PER_SECOND {
+            if model.player().vert_state() == PlayerVertState::WallSliding {
+                press(&mut model, Action::Jump);
+            } else {
+                release(&mut model, Action::Jump);
+            }
+            tick(&mut model, 1);
+            if model.player().pos().y + model.player().height < shaft_top {
+                return;
+            }
+        }
+        panic!("Player only climbed to {}", model.player().pos().y);
+    }
+
+    /// Returns how far the player gets in a second of holding right on a long floor.
+    fn distance_walked_in_a_second(sprinting: bool) -> f32 {
+        let mut model = model_in(Room::new(40, 10, 16));
+        tick(&mut model, 1);
+        let start = model.player().pos().x;
+        if sprinting {
+            press(&mut model, Action::Run);
+        }
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND);
+        model.player().pos().x - start
+    }
+
+    #[test]
+    fn sprinting_covers_more_distance_than_walking() {
+        let walked = distance_walked_in_a_second(false);
+        let sprinted = distance_walked_in_a_second(true);
+        assert!(walked > 0.0);
+        assert!(sprinted > walked * 1.3, "sprinted {} vs walked {}", sprinted, walked);
+    }
+
+    /// Returns a model with a one tile high tunnel from the fifth to the eighth
+    /// tile, which the player only fits through when crouching.
+    fn model_before_tunnel() -> Model {
+        let mut room = Room::new(12, 10, 16);
+        for x in 4..8 {
+            room.set_tile_at_index(x, 7, TileKind::Filled).unwrap();
+        }
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        model
+    }
+
+    #[test]
+    fn standing_player_cant_walk_into_low_tunnel() {
+        let mut model = model_before_tunnel();
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, 2 * TICKS_PER_SECOND);
+        assert_eq!(model.player().pos().x + model.player().width, 4.0 * 16.0);
+    }
+
+    #[test]
+    fn crouching_player_walks_through_low_tunnel() {
+        let mut model = model_before_tunnel();
+        press(&mut model, Action::Crouch);
+        tick(&mut model, 1);
+        assert!(model.player().is_crouching());
+        let feet = model.player().pos().y + model.player().height;
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, 3 * TICKS_PER_SECOND);
+        assert!(model.player().pos().x > 8.0 * 16.0);
+        assert_eq!(model.player().pos().y + model.player().height, feet);
+    }
+
+    #[test]
+    fn crouching_player_doesnt_stand_up_under_ceiling() {
+        let mut model = model_before_tunnel();
+        press(&mut model, Action::Crouch);
+        press(&mut model, Action::MoveRight);
+        while model.player().pos().x < 5.0 * 16.0 {
+            tick(&mut model, 1);
+        }
+        release(&mut model, Action::MoveRight);
+        release(&mut model, Action::Crouch);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert!(model.player().is_crouching());
+        assert!(model.player().rect().top() >= 8.0 * 16.0);
+        // Leaving the tunnel with the key released stands the player up again
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, 2 * TICKS_PER_SECOND);
+        assert!(!model.player().is_crouching());
+        assert_eq!(model.player().height, 20.0);
+        assert_eq!(model.player().rect().bottom(), 9.0 * 16.0);
+    }
+
+    #[test]
+    fn left_edge_of_room_stops_player() {
+        let mut model = model_in(Room::new(10, 10, 16));
+        press(&mut model, Action::MoveLeft);
+        for _ in 0..5 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            assert!(model.player().pos().x >= 0.0);
+        }
+        assert_eq!(model.player().pos().x, 0.0);
+        assert_eq!(model.player().vel().x, 0.0);
+        // Nothing to interpolate between while pressed against the edge
+        assert_eq!(model.old_player.pos().x, 0.0);
+    }
+
+    #[test]
+    fn falling_into_pit_respawns_player() {
+        let mut room = Room::new(10, 10, 16);
+        for x in 3..6 {
+            room.set_tile_at_index(x, 9, TileKind::Empty).unwrap();
+        }
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        let spawn = model.player().pos();
+        press(&mut model, Action::MoveRight);
+        let mut fell = false;
+        for _ in 0..5 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            if model.is_dying() {
+                fell = true;
+                release(&mut model, Action::MoveRight);
+            } else if fell {
+                break;
+            }
+        }
+        assert!(fell, "Player never fell out of the room");
+        assert_eq!(model.deaths(), 1);
+        assert_eq!(model.player().pos(), spawn);
+        assert_eq!(model.player().vel(), Vec2::new(0.0, 0.0));
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        assert_eq!(model.player().horiz_state(), PlayerHorizState::Idle);
+    }
+
+    #[test]
+    fn set_room_puts_player_at_spawn_standing_still() {
+        let mut model = model_in(Room::new(10, 10, 16));
+        press(&mut model, Action::MoveRight);
+        press(&mut model, Action::Jump);
+        tick(&mut model, 10);
+        assert_ne!(model.player().vel(), Vec2::new(0.0, 0.0));
+        let mut room = Room::new(12, 10, 16);
+        room.set_spawn(6, 5).unwrap();
+        model.set_room(ROOM_PATH, room);
+        let player = model.player();
+        assert_eq!(player.pos(), Vec2::new(6.0 * 16.0 + 4.0, 5.0 * 16.0 - 20.0));
+        assert_eq!(player.vel(), Vec2::new(0.0, 0.0));
+        assert_eq!(player.vert_state(), PlayerVertState::Falling);
+        assert_eq!(model.old_player.pos(), player.pos());
+    }
+
+    /// Returns `room` with an exit at `edge` leading to the room stored under `path`.
+    fn with_exit(room: Room, edge: Edge, path: &str) -> Room {
+        let mut value = ::serde_json::to_value(&room).unwrap();
+        let exit = ::room::Exit {
+            edge,
+            room: path.to_string(),
+        };
+        value["exits"]
+            .as_array_mut()
+            .unwrap()
+            .push(::serde_json::to_value(exit).unwrap());
+        ::serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn walking_out_of_room_enters_next_room_at_same_speed() {
+        const NEXT_ROOM_PATH: &str = "next.json";
+        let room = with_exit(Room::new(10, 10, 16), Edge::Right, NEXT_ROOM_PATH);
+        let mut level = Level::with_room(ROOM_PATH, room);
+        level.set_room(NEXT_ROOM_PATH, Room::new(10, 10, 16));
+        let mut model = Model::new(level, ROOM_PATH, TICKS_PER_SECOND);
+        tick(&mut model, 1);
+        press(&mut model, Action::MoveRight);
+        for _ in 0..3 * TICKS_PER_SECOND {
+            let speed = model.player().vel().x;
+            tick(&mut model, 1);
+            if let Some(change) = model.take_room_change() {
+                assert_eq!(change.room, NEXT_ROOM_PATH);
+                assert_eq!(change.edge, Edge::Right);
+                assert_eq!(model.room_path(), NEXT_ROOM_PATH);
+                let player = model.player();
+                assert!(player.pos().x + player.width / 2.0 < 1.0, "entered at {:?}", player.pos());
+                assert!(speed > 0.0);
+                assert_eq!(player.vel().x, speed);
+                assert_eq!(player.vert_state(), PlayerVertState::Standing);
+                return;
+            }
+        }
+        panic!("Player never left the room");
+    }
+
+    /// Returns a model with a platform moving back and forth along `waypoints`,
+    /// and the player standing on its left end.
+    fn model_on_platform(waypoints: Vec<(f32, f32)>) -> Model {
+        let mut model = model_in(Room::new(20, 20, 16));
+        let (x, y) = waypoints[0];
+        model.platforms.push(Platform::new(::entity::PlatformDef {
+            width: 32.0,
+            height: 8.0,
+            waypoints,
+            speed: 32.0,
+        }));
+        model.player.pos = Vec2::new(x + 4.0, y - model.player.height);
+        tick(&mut model, 1);
+        assert!(model.player().is_standing_on(&model.platforms[0].rect()));
+        model
+    }
+
+    #[test]
+    fn platform_carries_player_along() {
+        let mut model = model_on_platform(vec![(64.0, 160.0), (160.0, 160.0)]);
+        let offset = model.player().pos().x - model.platforms[0].rect().left();
+        for _ in 0..TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            let platform = model.platforms[0].rect();
+            assert!(model.player().is_standing_on(&platform));
+            assert!((model.player().pos().x - platform.left() - offset).abs() < 0.01);
+        }
+        assert!(model.platforms[0].rect().left() > 64.0 + 16.0);
+    }
+
+    #[test]
+    fn player_rides_platform_through_turnaround() {
+        let mut model = model_on_platform(vec![(64.0, 160.0), (96.0, 160.0)]);
+        let start = model.player().pos().x;
+        let mut furthest = start;
+        // There and back again
+        for _ in 0..2 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            furthest = furthest.max(model.player().pos().x);
+            assert!(model.player().is_standing_on(&model.platforms[0].rect()));
+        }
+        assert!((furthest - start - 32.0).abs() < 0.5, "went {} far", furthest - start);
+        assert!((model.player().pos().x - start).abs() < 0.5);
+    }
+
+    #[test]
+    fn rising_platform_lifts_player() {
+        let mut model = model_on_platform(vec![(64.0, 240.0), (64.0, 160.0)]);
+        tick(&mut model, TICKS_PER_SECOND);
+        let platform = model.platforms[0].rect();
+        assert!(platform.top() < 240.0 - 16.0);
+        assert!(model.player().is_standing_on(&platform));
+        assert_eq!(model.player().rect().bottom(), platform.top());
+    }
+
+    #[test]
+    fn platform_rising_into_falling_player_lifts_them_onto_it() {
+        let mut model = model_in(Room::new(20, 20, 16));
+        model.platforms.push(Platform::new(::entity::PlatformDef {
+            width: 32.0,
+            height: 8.0,
+            waypoints: vec![(64.0, 240.0), (64.0, 100.0)],
+            speed: 300.0,
+        }));
+        model.player.pos = Vec2::new(70.0, 220.0);
+        model.player.vert_state = PlayerVertState::Falling;
+        tick(&mut model, 1);
+        let platform = model.platforms[0].rect();
+        assert_eq!(model.player().rect().bottom(), platform.top());
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+    }
+
+    #[test]
+    fn each_coin_is_counted_once() {
+        let mut room = Room::new(12, 10, 16);
+        for &x in &[3, 5, 7] {
+            room.toggle_coin_at_index(x, 8).unwrap();
+        }
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        press(&mut model, Action::MoveRight);
+        let mut coins = 0;
+        for _ in 0..2 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            assert!(model.coins() - coins <= 1);
+            coins = model.coins();
+        }
+        assert_eq!(model.coins(), 3);
+        // Walking back over where they were doesn't count them again
+        release(&mut model, Action::MoveRight);
+        press(&mut model, Action::MoveLeft);
+        tick(&mut model, 2 * TICKS_PER_SECOND);
+        assert_eq!(model.coins(), 3);
+        assert!(model.coins.iter().all(|coin| coin.is_collected()));
+    }
+
+    #[test]
+    fn falling_onto_enemy_stomps_it() {
+        let mut enemies = vec![Enemy::new(20.0, 100.0)];
+        let old_player = player_at(22.0, 78.0);
+        let mut player = player_at(22.0, 83.0);
+        assert_eq!(handle_enemy_contact(&mut enemies, &old_player, &mut player), None);
+        assert!(enemies.is_empty());
+        assert!(player.bounce_requested);
+    }
+
+    #[test]
+    fn walking_into_enemy_hurts() {
+        let mut enemies = vec![Enemy::new(20.0, 100.0)];
+        let old_player = player_at(10.0, 92.0);
+        let mut player = player_at(13.0, 92.0);
+        assert_eq!(handle_enemy_contact(&mut enemies, &old_player, &mut player), Some(26.0));
+        assert_eq!(enemies.len(), 1);
+        assert!(!player.bounce_requested);
+    }
+
+    #[test]
+    fn falling_onto_enemy_from_below_its_center_hurts() {
+        let mut enemies = vec![Enemy::new(20.0, 100.0)];
+        // Feet start just below the enemy's center
+        let old_player = player_at(22.0, 86.5);
+        let mut player = player_at(22.0, 88.0);
+        assert_eq!(handle_enemy_contact(&mut enemies, &old_player, &mut player), Some(26.0));
+        assert_eq!(enemies.len(), 1);
+        // Feet exactly at the center still stomp
+        let old_player = player_at(22.0, 86.0);
+        assert_eq!(handle_enemy_contact(&mut enemies, &old_player, &mut player), None);
+        assert!(enemies.is_empty());
+    }
+
+    #[test]
+    fn falling_past_enemy_in_one_tick_stomps_it() {
+        let mut enemies = vec![Enemy::new(20.0, 100.0)];
+        let old_player = player_at(22.0, 70.0);
+        let mut player = player_at(22.0, 115.0);
+        assert!(!player.rect().overlaps(&enemies[0].rect()));
+        assert_eq!(handle_enemy_contact(&mut enemies, &old_player, &mut player), None);
+        assert!(enemies.is_empty());
+    }
+
+    #[test]
+    fn jumping_into_enemy_from_below_hurts() {
+        let mut enemies = vec![Enemy::new(20.0, 100.0)];
+        let old_player = player_at(22.0, 115.0);
+        let mut player = player_at(22.0, 110.0);
+        assert_eq!(handle_enemy_contact(&mut enemies, &old_player, &mut player), Some(26.0));
+        assert_eq!(enemies.len(), 1);
+    }
+
+    #[test]
+    fn stomp_wins_over_touching_another_enemy() {
+        let mut enemies = vec![Enemy::new(20.0, 100.0), Enemy::new(27.0, 88.0)];
+        let old_player = player_at(22.0, 78.0);
+        let mut player = player_at(22.0, 83.0);
+        assert!(player.rect().overlaps(&enemies[1].rect()));
+        assert_eq!(handle_enemy_contact(&mut enemies, &old_player, &mut player), None);
+        assert_eq!(enemies.len(), 1);
+    }
+
+    #[test]
+    fn player_is_invincible_for_a_while_after_being_hurt() {
+        let room = Room::new(10, 10, 16);
+        let physics = PhysicsConfig::default().scaled(16);
+        let dt = 1.0 / TICKS_PER_SECOND as f32;
+        let mut player = Player::new();
+        player.respawn(&room, &physics);
+        assert!(!player.hurt(0.0));
+        assert_eq!(player.health(), Player::MAX_HEALTH - 1);
+        // Nothing hurts until the knockback is applied either
+        assert!(!player.hurt(0.0));
+        player.update(dt, &room, &[], &physics);
+        assert!(player.vel().x > 0.0);
+        let invincible_ticks = (physics.invincible_time * TICKS_PER_SECOND as f32).round() as u32;
+        for _ in 0..invincible_ticks {
+            assert!(!player.hurt(0.0));
+            assert_eq!(player.health(), Player::MAX_HEALTH - 1);
+            player.update(dt, &room, &[], &physics);
+        }
+        assert!(!player.hurt(0.0));
+        assert_eq!(player.health(), Player::MAX_HEALTH - 2);
+    }
+
+    #[test]
+    fn touching_two_enemies_at_once_hurts_once() {
+        let mut model = model_in(Room::new(10, 10, 16));
+        tick(&mut model, 1);
+        let feet = model.player().rect().bottom();
+        let x = model.player().pos().x;
+        model.enemies.push(Enemy::new(x + 2.0, feet - 12.0));
+        model.enemies.push(Enemy::new(x - 4.0, feet - 12.0));
+        tick(&mut model, 1);
+        assert_eq!(model.health(), Player::MAX_HEALTH - 1);
+        tick(&mut model, TICKS_PER_SECOND / 2);
+        assert_eq!(model.health(), Player::MAX_HEALTH - 1);
+    }
+
+    #[test]
+    fn replays_take_the_same_number_of_ticks() {
+        let mut model = model_on_ledge();
+        model.start_recording();
+        play_scripted_run(&mut model);
+        let recorded_time = model.elapsed();
+        let replay = model.stop_recording().unwrap();
+        assert_eq!(recorded_time, model.frame_duration * replay.ticks);
+        for _ in 0..2 {
+            model.start_playback(replay.clone()).unwrap();
+            assert_eq!(model.elapsed(), Duration::new(0, 0));
+            let start_ticks = model.total_ticks();
+            while model.is_playing_back() {
+                tick(&mut model, 1);
+            }
+            assert_eq!(model.total_ticks() - start_ticks, u64::from(replay.ticks));
+            assert_eq!(model.elapsed(), recorded_time);
+        }
+    }
+
+    #[test]
+    fn pausing_freezes_the_player() {
+        let mut model = model_in(Room::new(20, 10, 16));
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, 20);
+        // Part of a tick is left over when pausing
+        model.update(model.frame_duration / 2);
+        let (before, ticks) = (model.player, model.total_ticks());
+        model.toggle_pause();
+        for _ in 0..10 {
+            model.update(Duration::from_secs(1));
+        }
+        assert_eq!(model.total_ticks(), ticks);
+        model.toggle_pause();
+        assert_eq!(model.player.pos, before.pos);
+        assert_eq!(model.player.vel, before.vel);
+        // Only the part of a tick left over from before pausing is added to the time passed
+        // since unpausing
+        let frame_duration = model.frame_duration;
+        model.update(frame_duration);
+        assert_eq!(model.total_ticks(), ticks + 1);
+        model.update(frame_duration / 2);
+        assert_eq!(model.total_ticks(), ticks + 2);
+        // Keys held when pausing were let go
+        assert_eq!(model.player().horiz_state(), PlayerHorizState::StopMovingRight);
+    }
+
+    #[test]
+    fn loaded_jump_speed_changes_jump_height() {
+        let path = ::std::env::temp_dir().join("grot-test-jump-physics.json");
+        ::std::fs::write(&path, r#"{ "jump_speed": -11.5 }"#).unwrap();
+        let physics = PhysicsConfig::load(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        let default_height = jump_height(PhysicsConfig::default(), None);
+        let height = jump_height(physics, None);
+        // Jumping about 1.4 times as fast reaches about twice as high
+        let ratio = height / default_height;
+        assert!(ratio > 1.8 && ratio < 2.2, "jumped {} vs {}", height, default_height);
+    }
+
+    /// Plays the same input at `ticks_per_second`, returning where the player was
+    /// in mid-air and where they ended up.
+    fn play_timed_script(ticks_per_second: u32) -> (Vec2, Vec2) {
+        let level = Level::with_room(ROOM_PATH, Room::new(40, 20, 16));
+        let mut model = Model::new(level, ROOM_PATH, ticks_per_second);
+        let ticks = |seconds: f32| (seconds * ticks_per_second as f32).round() as u32;
+        tick(&mut model, ticks(0.1));
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, ticks(0.5));
+        press(&mut model, Action::Jump);
+        tick(&mut model, ticks(0.25));
+        release(&mut model, Action::Jump);
+        release(&mut model, Action::MoveRight);
+        press(&mut model, Action::MoveLeft);
+        tick(&mut model, ticks(0.2));
+        let in_air = model.player().pos();
+        assert_ne!(model.player().vert_state(), PlayerVertState::Standing);
+        release(&mut model, Action::MoveLeft);
+        tick(&mut model, ticks(1.5));
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        (in_air, model.player().pos())
+    }
+
+    #[test]
+    fn same_input_moves_alike_at_any_tick_rate() {
+        let (slow_in_air, slow_end) = play_timed_script(60);
+        let (fast_in_air, fast_end) = play_timed_script(240);
+        for &(slow, fast) in &[(slow_in_air, fast_in_air), (slow_end, fast_end)] {
+            assert!((slow.x - fast.x).abs() < 0.01, "{:?} vs {:?}", slow, fast);
+            assert!((slow.y - fast.y).abs() < 0.01, "{:?} vs {:?}", slow, fast);
+        }
+    }
+
+    #[test]
+    fn interpolated_player_stays_between_old_and_new_positions() {
+        let old = player_at(10.0, 40.0);
+        let new = player_at(16.0, 31.0);
+        for &progress in &[-0.5, 0.0, 0.25, 0.5, 0.9, 1.0, 1.5] {
+            let pos = new.interpolated(&old, progress).pos;
+            assert!(pos.x >= 10.0 && pos.x <= 16.0, "{} at {}", pos.x, progress);
+            assert!(pos.y >= 31.0 && pos.y <= 40.0, "{} at {}", pos.y, progress);
+            assert_eq!(pos.x, interpolate(10.0, 16.0, progress));
+        }
+        assert_eq!(new.interpolated(&old, 0.0).pos, old.pos);
+        assert_eq!(new.interpolated(&old, 1.0).pos, new.pos);
+    }
+
+    /// Plays the same timed input through `update` calls of the given lengths in
+    /// milliseconds, repeated for a second, returning the final player.
+    fn play_in_chunks(chunk_millis: &[u64]) -> (u64, Player) {
+        let mut model = model_in(Room::new(40, 20, 16));
+        let inputs = [
+            (100, InputEvent::Pressed(Action::MoveRight)),
+            (300, InputEvent::Pressed(Action::Jump)),
+            (500, InputEvent::Released(Action::Jump)),
+            (500, InputEvent::Released(Action::MoveRight)),
+        ];
+        let mut elapsed = 0;
+        for &chunk in chunk_millis.iter().cycle() {
+            for &(at, event) in &inputs {
+                if at == elapsed {
+                    model.handle_input(event);
+                }
+            }
+            if elapsed == 1000 {
+                break;
+            }
+            model.update(Duration::from_millis(chunk));
+            elapsed += chunk;
+        }
+        (model.total_ticks(), model.player)
+    }
+
+    #[test]
+    fn same_input_in_any_update_pattern_ends_alike() {
+        let (ticks, player) = play_in_chunks(&[1]);
+        assert!(player.pos.x > 0.0 && player.vel.y != 0.0);
+        for chunks in &[&[5][..], &[20], &[3, 17], &[100]] {
+            let (other_ticks, other) = play_in_chunks(chunks);
+            assert_eq!(other_ticks, ticks, "{:?}", chunks);
+            assert_eq!(other.pos, player.pos, "{:?}", chunks);
+            assert_eq!(other.vel, player.vel, "{:?}", chunks);
+            assert_eq!(other.vert_state, player.vert_state, "{:?}", chunks);
+        }
+    }
+
+    #[test]
+    fn player_keeps_facing_left_after_stopping() {
+        let mut model = model_in(Room::new(10, 10, 16));
+        tick(&mut model, 1);
+        assert_eq!(model.player().facing(), Direction::Right);
+        press(&mut model, Action::MoveLeft);
+        tick(&mut model, 20);
+        release(&mut model, Action::MoveLeft);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert_eq!(model.player().vel().x, 0.0);
+        assert_eq!(model.player().facing(), Direction::Left);
+
+        model.respawn();
+        assert_eq!(model.player().facing(), Direction::Right);
+    }
+
+    /// Returns how many ticks it takes the player to turn from walking right at full
+    /// speed to moving left, either on the ground or high up in the air.
+    fn turnaround_ticks(airborne: bool) -> u32 {
+        let mut model = model_in(Room::new(10, 200, 16));
+        tick(&mut model, 1);
+        if airborne {
+            model.player.pos.y = 16.0;
+        }
+        model.player.vel.x = model.scaled_physics().walk_speed;
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, 1);
+        release(&mut model, Action::MoveRight);
+        press(&mut model, Action::MoveLeft);
+        for ticks in 1..TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            if model.player().vel().x < 0.0 {
+                let still_airborne = model.player().vert_state() != PlayerVertState::Standing;
+                assert_eq!(still_airborne, airborne, "Player landed or took off");
+                return ticks;
+            }
+        }
+        panic!("Player never turned around");
+    }
+
+    #[test]
+    fn turning_around_takes_longer_in_the_air() {
+        let on_ground = turnaround_ticks(false);
+        let in_air = turnaround_ticks(true);
+        assert!(on_ground > 1);
+        assert!(in_air > on_ground + 5, "{} vs {} ticks", in_air, on_ground);
+    }
+
+    #[test]
+    fn reset_restarts_the_run_but_keeps_the_death_count() {
+        let mut room = Room::new(12, 10, 16);
+        for &x in &[3, 5, 7] {
+            room.toggle_coin_at_index(x, 8).unwrap();
+        }
+        let mut model = model_in(room);
+        model.set_starting_lives(Some(3));
+        tick(&mut model, 1);
+        let spawn = model.player().pos();
+        model.kill_player();
+        tick(&mut model, TICKS_PER_SECOND);
+        assert_eq!((model.deaths(), model.lives()), (1, Some(2)));
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND);
+        model.player.health -= 1;
+        assert_eq!(model.coins(), 3);
+
+        model.reset();
+        // The run starts over...
+        assert_eq!(model.player().pos(), spawn);
+        assert_eq!(model.player().vel(), Vec2::new(0.0, 0.0));
+        assert_eq!(model.old_player.pos(), spawn);
+        assert_eq!(model.player().health(), Player::MAX_HEALTH);
+        assert_eq!(model.coins(), 0);
+        assert!(model.coins.iter().all(|coin| !coin.is_collected()));
+        assert_eq!(model.elapsed(), Duration::new(0, 0));
+        // ...but the deaths so far still count
+        assert_eq!((model.deaths(), model.lives()), (1, Some(2)));
+    }
+
+    /// Walks the player into a step, jumps onto it and off the other side, returning
+    /// where they were after each tick.
+    fn trajectory_with_physics(physics: PhysicsConfig) -> Vec<Vec2> {
+        let mut room = Room::new(20, 10, 16);
+        for &(x, y) in &[(6, 8), (7, 8), (7, 7), (12, 5)] {
+            room.set_tile_at_index(x, y, TileKind::Filled).unwrap();
+        }
+        let mut model = model_in(room);
+        model.set_physics(physics);
+        let mut positions = Vec::new();
+        let mut run = |model: &mut Model, ticks| {
+            for _ in 0..ticks {
+                tick(model, 1);
+                positions.push(model.player().pos());
+            }
+        };
+        press(&mut model, Action::MoveRight);
+        run(&mut model, TICKS_PER_SECOND / 2);
+        press(&mut model, Action::Jump);
+        run(&mut model, TICKS_PER_SECOND / 2);
+        release(&mut model, Action::Jump);
+        run(&mut model, TICKS_PER_SECOND);
+        press(&mut model, Action::Jump);
+        run(&mut model, 2 * TICKS_PER_SECOND);
+        positions
+    }
+
+    #[test]
+    fn visual_size_doesnt_change_collisions() {
+        let physics = PhysicsConfig::default();
+        let expected = trajectory_with_physics(physics.clone());
+        for &(width, height) in &[(0.1, 0.1), (3.0, 0.5), (0.5, 3.0)] {
+            let trajectory = trajectory_with_physics(PhysicsConfig {
+                visual_width: Some(physics.width * width),
+                visual_height: Some(physics.height * height),
+                ..physics.clone()
+            });
+            assert!(trajectory == expected, "Visual size {}x{} moved differently", width, height);
+        }
+    }
+
+    #[test]
+    fn walking_up_slope_follows_its_surface() {
+        let mut room = Room::new(16, 10, 16);
+        room.set_tile_at_index(4, 8, TileKind::SlopeUp).unwrap();
+        for x in 5..16 {
+            room.set_tile_at_index(x, 8, TileKind::Filled).unwrap();
+        }
+        room.set_spawn(1, 9).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        press(&mut model, Action::MoveRight);
+        let mut on_slope = 0;
+        for _ in 0..TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            let player = model.player();
+            assert_eq!(player.vert_state(), PlayerVertState::Standing);
+            let (right, bottom) = (player.rect().right(), player.rect().bottom());
+            if right > 64.0 && right < 80.0 {
+                // The highest point of the slope under the player is below its right edge
+                let surface = 144.0 - (right - 64.0);
+                assert!((bottom - surface).abs() < 0.01, "{} at {}", bottom, right);
+                on_slope += 1;
+            }
+        }
+        assert!(on_slope > 5, "Only {} ticks on the slope", on_slope);
+        assert_eq!(model.player().rect().bottom(), 128.0);
+    }
+
+    #[test]
+    fn flipping_gravity_mid_fall_lands_on_ceiling() {
+        let mut room = with_setting(Room::new(10, 10, 16), "gravity_flip_key");
+        for x in 0..10 {
+            room.set_tile_at_index(x, 0, TileKind::Filled).unwrap();
+        }
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        model.player.pos.y = 48.0;
+        tick(&mut model, 10);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Falling);
+        assert!(model.player().vel().y > 0.0);
+
+        press(&mut model, Action::FlipGravity);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        assert_eq!(model.player().vel().y, 0.0);
+        assert_eq!(model.player().rect().top(), 16.0);
+    }
+
+    #[test]
+    fn grazing_ceiling_corner_nudges_player_past_it() {
+        // The player's left edge overlaps the tile by 2 pixels
+        let room = room_with_tiles(&[(2, 2)]);
+        let mut player = player_at(46.0, 50.0);
+        player.move_vertically(-4.0, &room, &[], 0);
+        assert_eq!(player.pos, Vec2::new(48.0, 46.0));
+
+        // And from the other side, with the right edge overlapping it
+        let mut player = player_at(26.0, 50.0);
+        player.move_vertically(-4.0, &room, &[], 0);
+        assert_eq!(player.pos, Vec2::new(24.0, 46.0));
+    }
+
+    #[test]
+    fn hitting_ceiling_squarely_isnt_nudged() {
+        let room = room_with_tiles(&[(2, 2)]);
+        let mut player = player_at(36.0, 50.0);
+        player.move_vertically(-4.0, &room, &[], 0);
+        assert_eq!(player.pos, Vec2::new(36.0, 48.0));
+
+        // Overlapping by more than a few pixels isn't a graze either
+        let mut player = player_at(42.0, 50.0);
+        player.move_vertically(-4.0, &room, &[], 0);
+        assert_eq!(player.pos, Vec2::new(42.0, 48.0));
+    }
+
+    #[test]
+    fn player_stands_on_tile_narrower_than_them() {
+        for &kind in &[TileKind::Filled, TileKind::SlopeUp, TileKind::SlopeDown] {
+            // The 8x20 pixel player spans three tiles, and only the middle one is under
+            // their feet
+            let mut room = Room::new(8, 12, 4);
+            room.set_tile_at_index(2, 6, kind).unwrap();
+            let mut model = model_in(room);
+            model.set_physics(PhysicsConfig {
+                width: 2.0,
+                height: 5.0,
+                ..PhysicsConfig::default()
+            });
+            tick(&mut model, 1);
+            model.player.pos = Vec2::new(5.0, 0.0);
+            tick(&mut model, TICKS_PER_SECOND / 2);
+            assert_eq!(model.player().vert_state(), PlayerVertState::Standing, "on {:?}", kind);
+            assert_eq!(model.player().rect().bottom(), 24.0, "on {:?}", kind);
+            assert_eq!(model.player().pos().x, 5.0, "on {:?}", kind);
+        }
+    }
+
+    /// Walks the player right into a two tile high wall `thickness` tiles thick,
+    /// returning the model once they have had time to reach it.
+    fn walk_into_wall(thickness: u32) -> Model {
+        let mut room = Room::new(16, 10, 16);
+        for x in 8..8 + thickness {
+            for y in 7..9 {
+                room.set_tile_at_index(x, y, TileKind::Filled).unwrap();
+            }
+        }
+        let mut model = model_in(room);
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, 2 * TICKS_PER_SECOND);
+        model
+    }
+
+    #[test]
+    fn walls_of_any_thickness_stop_player() {
+        for &thickness in &[1, 3] {
+            let model = walk_into_wall(thickness);
+            assert_eq!(model.player().rect().right(), 128.0, "{} thick", thickness);
+            assert_eq!(model.player().vel().x, 0.0, "{} thick", thickness);
+            assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        }
+    }
+
+    #[test]
+    fn walking_along_floor_doesnt_snag() {
+        let mut model = model_in(Room::new(40, 10, 16));
+        tick(&mut model, 1);
+        let ypos = model.player().pos().y;
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND / 2);
+        let walk_speed = model.scaled_physics().walk_speed;
+        for _ in 0..TICKS_PER_SECOND {
+            let xpos = model.player().pos().x;
+            tick(&mut model, 1);
+            assert_eq!(model.player().pos().y, ypos);
+            assert_eq!(model.player().vel().x, walk_speed);
+            assert!(model.player().pos().x > xpos);
+        }
+    }
+
+    /// Walks the player right and jumps onto a two tile high step, returning where
+    /// they were after each tick in tiles.
+    fn tile_trajectory(tile_size: u32) -> Vec<Vec2> {
+        let mut room = Room::new(16, 10, tile_size);
+        for x in 8..16 {
+            for y in 7..9 {
+                room.set_tile_at_index(x, y, TileKind::Filled).unwrap();
+            }
+        }
+        room.set_spawn(1, 9).unwrap();
+        let mut model = model_in(room);
+        let mut positions = Vec::new();
+        press(&mut model, Action::MoveRight);
+        for ticks in 0..2 * TICKS_PER_SECOND {
+            if ticks == TICKS_PER_SECOND / 2 {
+                press(&mut model, Action::Jump);
+            }
+            tick(&mut model, 1);
+            positions.push(model.player().pos() * (1.0 / tile_size as f32));
+        }
+        let bottom = model.player().rect().bottom() / tile_size as f32;
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        assert_eq!(bottom, 7.0, "Didn't make it onto the step at tile size {}", tile_size);
+        positions
+    }
+
+    #[test]
+    fn player_moves_alike_in_tiles_at_any_tile_size() {
+        let small = tile_trajectory(8);
+        let large = tile_trajectory(32);
+        for (tick, (small, large)) in small.iter().zip(&large).enumerate() {
+            let distance = (*small - *large).length();
+            assert!(distance < 0.001, "{:?} vs {:?} after {} ticks", small, large, tick);
+        }
+    }
+
+    /// Returns how fast the player is launched off a bounce tile in tiles per second,
+    /// after falling onto it from high up or walking onto it.
+    fn bounce_speed(physics: PhysicsConfig, fall: bool, hold_jump: bool) -> f32 {
+        let mut room = Room::new(10, 40, 16);
+        room.set_spawn(1, 39).unwrap();
+        room.set_tile_at_index(5, 39, TileKind::Bounce).unwrap();
+        let mut model = model_in(room);
+        model.set_physics(physics);
+        tick(&mut model, 1);
+        if fall {
+            model.player.pos = Vec2::new(84.0, 16.0);
+            // Wait out the coyote time, so holding jump doesn't jump
+            tick(&mut model, TICKS_PER_SECOND / 4);
+            assert_eq!(model.player().vert_state(), PlayerVertState::Falling);
+        } else {
+            press(&mut model, Action::MoveRight);
+        }
+        if hold_jump {
+            press(&mut model, Action::Jump);
+        }
+        for _ in 0..3 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            if model.player().vel().y < 0.0 {
+                return -model.player().vel().y / 16.0;
+            }
+        }
+        panic!("Player never bounced");
+    }
+
+    #[test]
+    fn bounce_tile_launches_player_by_their_landing_speed() {
+        // Falling from high up lands at full fall speed
+        let physics = PhysicsConfig::default();
+        let fall_launch = physics.fall_speed * physics.bounce_factor;
+        assert_eq!(bounce_speed(physics.clone(), true, false), fall_launch);
+        assert_eq!(
+            bounce_speed(physics.clone(), true, true),
+            fall_launch * physics.bounce_jump_multiplier
+        );
+        // Walking on gives a small hop
+        assert_eq!(bounce_speed(physics.clone(), false, false), physics.bounce_min_speed);
+        // And launches are capped
+        let bouncy = PhysicsConfig {
+            bounce_factor: 2.0,
+            ..physics.clone()
+        };
+        assert_eq!(bounce_speed(bouncy, true, false), physics.bounce_max_speed);
+    }
+
+    /// Returns a room with a wall filling the given columns from top to bottom.
+    fn room_with_wall(columns: ::std::ops::Range<u32>) -> Room {
+        let mut room = Room::new(8, 8, 16);
+        for x in columns {
+            for y in 0..7 {
+                room.set_tile_at_index(x, y, TileKind::Filled).unwrap();
+            }
+        }
+        room
+    }
+
+    #[test]
+    fn player_one_pixel_in_floor_is_pushed_up() {
+        let room = Room::new(8, 8, 16);
+        let mut player = player_at(20.0, 93.0);
+        assert!(player.unstick(&room, &[]));
+        assert_eq!(player.pos, Vec2::new(20.0, 92.0));
+    }
+
+    #[test]
+    fn player_half_a_tile_in_wall_is_pushed_out_sideways() {
+        let room = room_with_wall(3..4);
+        let mut player = player_at(48.0, 40.0);
+        assert!(player.unstick(&room, &[]));
+        assert_eq!(player.pos, Vec2::new(40.0, 40.0));
+    }
+
+    #[test]
+    fn player_deep_in_wall_is_pushed_out_of_the_nearer_side() {
+        let room = room_with_wall(2..5);
+        let mut player = player_at(60.0, 40.0);
+        assert!(player.unstick(&room, &[]));
+        assert_eq!(player.pos, Vec2::new(80.0, 40.0));
+    }
+
+    #[test]
+    fn player_with_no_free_space_nearby_stays_stuck() {
+        let room = room_with_wall(0..8);
+        let mut player = player_at(60.0, 40.0);
+        assert!(!player.unstick(&room, &[]));
+        assert_eq!(player.pos, Vec2::new(60.0, 40.0));
+    }
+
+    /// Returns how many ticks the player takes to fall to the ground from high up.
+    fn ticks_to_fall(fast_fall: bool) -> u32 {
+        let mut model = model_in(Room::new(10, 40, 16));
+        tick(&mut model, 1);
+        model.player.pos.y = 16.0;
+        tick(&mut model, 1);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Falling);
+        if fast_fall {
+            press(&mut model, Action::Crouch);
+        }
+        for ticks in 1..5 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            if model.player().vert_state() == PlayerVertState::Standing {
+                return ticks;
+            }
+        }
+        panic!("Player never landed");
+    }
+
+    #[test]
+    fn fast_falling_reaches_the_ground_sooner() {
+        let normal = ticks_to_fall(false);
+        let fast = ticks_to_fall(true);
+        assert!(fast < normal * 3 / 4, "{} vs {} ticks", fast, normal);
+    }
+
+    /// Plays timed input for both players, where either player's input can be left
+    /// out, returning where each player was after every tick.
+    fn two_player_trajectories(first: bool, second: bool) -> (Vec<Vec2>, Vec<Vec2>) {
+        let mut room = Room::new(40, 10, 16);
+        room.set_spawn(20, 9).unwrap();
+        let mut model = model_in(room);
+        model.set_second_player(true);
+        let script = [
+            (10, Action::MoveRight, true),
+            (15, Action::SecondMoveLeft, true),
+            (40, Action::Jump, true),
+            (41, Action::SecondJump, true),
+            (60, Action::SecondMoveLeft, false),
+            (60, Action::SecondMoveRight, true),
+            (70, Action::Jump, false),
+            (90, Action::MoveRight, false),
+            (100, Action::SecondJump, false),
+            (110, Action::SecondCrouch, true),
+        ];
+        let (mut firsts, mut seconds) = (Vec::new(), Vec::new());
+        for ticks in 0..2 * TICKS_PER_SECOND {
+            for &(at, action, pressed) in &script {
+                let played = if action.for_second_player().is_some() {
+                    second
+                } else {
+                    first
+                };
+                if at == ticks && played {
+                    if pressed {
+                        press(&mut model, action);
+                    } else {
+                        release(&mut model, action);
+                    }
+                }
+            }
+            tick(&mut model, 1);
+            firsts.push(model.player().pos());
+            seconds.push(model.second_player.unwrap().pos());
+        }
+        (firsts, seconds)
+    }
+
+    #[test]
+    fn two_players_dont_interfere() {
+        let (both_first, both_second) = two_player_trajectories(true, true);
+        let (only_first, idle_second) = two_player_trajectories(true, false);
+        let (idle_first, only_second) = two_player_trajectories(false, true);
+        assert!(both_first == only_first);
+        assert!(both_second == only_second);
+        assert!(both_first != idle_first);
+        assert!(both_second != idle_second);
+    }
+
+    #[test]
+    fn crusher_pushes_player_in_open_space() {
+        let room = Room::new(8, 8, 16);
+        let mut player = player_at(40.0, 92.0);
+        let crusher = Rect::new(12.0, 80.0, 32.0, 32.0);
+        assert!(!push_or_crush(&mut player, 4.0, 0.0, &crusher, &room, &[]));
+        assert_eq!(player.pos, Vec2::new(44.0, 92.0));
+    }
+
+    #[test]
+    fn crusher_crushes_player_against_wall() {
+        let room = room_with_tiles(&[(3, 6)]);
+        let mut player = player_at(40.0, 92.0);
+        let crusher = Rect::new(12.0, 80.0, 32.0, 32.0);
+        assert!(push_or_crush(&mut player, 4.0, 0.0, &crusher, &room, &[]));
+    }
+
+    #[test]
+    fn crusher_crushes_player_under_low_ceiling() {
+        // The ceiling leaves 16 pixels above the crusher, less than the player's height
+        let room = room_with_tiles(&[(1, 4), (2, 4), (3, 4)]);
+        let mut player = player_at(24.0, 80.0);
+        let crusher = Rect::new(16.0, 96.0, 32.0, 16.0);
+        assert!(push_or_crush(&mut player, 0.0, -4.0, &crusher, &room, &[]));
+
+        // Without the ceiling, the player is lifted onto the crusher
+        let room = Room::new(6, 8, 16);
+        let mut player = player_at(24.0, 80.0);
+        assert!(!push_or_crush(&mut player, 0.0, -4.0, &crusher, &room, &[]));
+        assert_eq!(player.pos, Vec2::new(24.0, 76.0));
+        assert_eq!(player.vert_state, PlayerVertState::Standing);
+    }
+
+    /// Returns a model with a two tile high door in front of the player, and a key
+    /// on the way there if `with_key` is set.
+    fn model_before_door(with_key: bool) -> Model {
+        let mut room = Room::new(12, 10, 16);
+        room.set_spawn(1, 9).unwrap();
+        for y in 7..9 {
+            room.set_tile_at_index(6, y, TileKind::Door).unwrap();
+        }
+        if with_key {
+            room.toggle_key_at_index(3, 8).unwrap();
+        }
+        let mut model = model_in(room);
+        press(&mut model, Action::MoveRight);
+        model
+    }
+
+    #[test]
+    fn door_blocks_player_without_key() {
+        let mut model = model_before_door(false);
+        tick(&mut model, 2 * TICKS_PER_SECOND);
+        assert_eq!(model.player().rect().right(), 96.0);
+        assert_eq!(model.room().tile_at_index(6, 8).kind, TileKind::Door);
+    }
+
+    #[test]
+    fn key_opens_door() {
+        let mut model = model_before_door(true);
+        let mut picked_up = false;
+        for _ in 0..2 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            picked_up |= model.keys() == 1;
+        }
+        assert!(picked_up, "Player never picked up the key");
+        assert_eq!(model.keys(), 0);
+        assert!(model.player().rect().left() > 112.0);
+        // One key opens all the tiles of the door
+        assert_eq!(model.room().tile_at_index(6, 8).kind, TileKind::Empty);
+        assert_eq!(model.room().tile_at_index(6, 7).kind, TileKind::Empty);
+    }
+
+    #[test]
+    fn blink_tiles_switch_every_blink_time() {
+        let mut room = Room::new(10, 10, 16);
+        room.set_tile_at_index(7, 5, TileKind::Blink).unwrap();
+        let mut model = model_in(room);
+        let blink_ticks = BLINK_TIME as u32 * TICKS_PER_SECOND;
+        let mut switches = Vec::new();
+        let mut solid = true;
+        for ticks in 1..=5 * blink_ticks {
+            tick(&mut model, 1);
+            let now_solid = model.room().tile_at_index(7, 5).kind == TileKind::Blink;
+            if now_solid != solid {
+                switches.push(ticks);
+                solid = now_solid;
+            }
+        }
+        assert_eq!(switches.len(), 4, "{:?}", switches);
+        for (phase, &ticks) in switches.iter().enumerate() {
+            // The switch happens in the tick starting once the time is up, or one tick
+            // later since the frame duration is rounded down to whole nanoseconds
+            let expected = (phase as u32 + 1) * blink_ticks + 1;
+            assert!(ticks == expected || ticks == expected + 1, "Switched in tick {}", ticks);
+        }
+    }
+
+    #[test]
+    fn blink_tile_waits_for_player_to_leave_before_turning_solid() {
+        let mut room = Room::new(10, 10, 16);
+        room.set_tile_at_index(3, 8, TileKind::Blink).unwrap();
+        let mut model = model_in(room);
+        let blink_ticks = BLINK_TIME as u32 * TICKS_PER_SECOND;
+        tick(&mut model, blink_ticks + 10);
+        assert_eq!(model.room().tile_at_index(3, 8).kind, TileKind::BlinkOff);
+
+        // Stand where the blink tile is while it would turn solid
+        model.player.pos.x = 52.0;
+        tick(&mut model, blink_ticks);
+        let pos = model.player().pos();
+        assert_eq!(model.room().tile_at_index(3, 8).kind, TileKind::BlinkOff);
+        tick(&mut model, 10);
+        assert_eq!(model.room().tile_at_index(3, 8).kind, TileKind::BlinkOff);
+        assert_eq!(model.player().pos(), pos);
+
+        press(&mut model, Action::MoveLeft);
+        tick(&mut model, TICKS_PER_SECOND / 2);
+        assert_eq!(model.room().tile_at_index(3, 8).kind, TileKind::Blink);
+        assert!(!model.player().rect().overlaps(&model.room().tile_at_index(3, 8).rect));
+    }
+
+    #[test]
+    fn teleporter_moves_player_to_its_partner_once() {
+        let mut room = Room::new(16, 10, 16);
+        room.set_spawn(1, 9).unwrap();
+        room.toggle_teleporter_at_index(4, 8).unwrap();
+        room.toggle_teleporter_at_index(12, 8).unwrap();
+        let mut model = model_in(room);
+        press(&mut model, Action::MoveRight);
+        let mut teleports = 0;
+        for _ in 0..3 * TICKS_PER_SECOND {
+            let xpos = model.player().pos().x;
+            tick(&mut model, 1);
+            let offset = model.player().pos().x - xpos;
+            if offset.abs() > 16.0 {
+                // Eight tiles along, plus the bit walked in that tick
+                assert!((128.0..130.0).contains(&offset), "Moved by {}", offset);
+                teleports += 1;
+                // Stop on the destination pad
+                release(&mut model, Action::MoveRight);
+                model.player.vel.x = 0.0;
+            }
+        }
+        assert_eq!(teleports, 1);
+        let pad = model.room().tile_at_index(12, 8).rect;
+        let rect = model.player().rect();
+        assert!(rect.left() >= pad.left() && rect.right() <= pad.right());
+    }
+
+    #[test]
+    fn dying_player_ignores_input_for_the_whole_death_time() {
+        let mut model = model_in(Room::new(10, 10, 16));
+        tick(&mut model, 1);
+        let spawn = model.player().pos();
+        model.player.pos.x += 40.0;
+        let died_at = model.player().pos();
+        model.kill_player();
+        let death_ticks = (DEATH_TIME * TICKS_PER_SECOND as f32).round() as u32;
+        press(&mut model, Action::MoveRight);
+        press(&mut model, Action::Jump);
+        for _ in 1..death_ticks {
+            tick(&mut model, 1);
+            assert!(model.is_dying());
+            assert_eq!(model.player().pos(), died_at);
+        }
+        // Pausing holds the countdown
+        model.toggle_pause();
+        tick(&mut model, TICKS_PER_SECOND);
+        model.toggle_pause();
+        assert!(model.is_dying());
+
+        tick(&mut model, 1);
+        assert!(!model.is_dying());
+        assert_eq!(model.deaths(), 1);
+        assert_eq!(model.player().pos(), spawn);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert_eq!(model.player().pos(), spawn);
+    }
+
+    /// Kills the player and waits until they've respawned or the game is over.
+    fn die(model: &mut Model) {
+        model.kill_player();
+        tick(model, TICKS_PER_SECOND);
+    }
+
+    #[test]
+    fn running_out_of_lives_stops_the_game_until_restarting() {
+        let mut model = model_in(Room::new(10, 10, 16));
+        model.set_starting_lives(Some(2));
+        tick(&mut model, 1);
+        let spawn = model.player().pos();
+        die(&mut model);
+        assert_eq!(model.lives(), Some(1));
+        assert!(!model.is_game_over());
+        die(&mut model);
+        assert_eq!(model.lives(), Some(0));
+        assert!(model.is_game_over());
+
+        // Nothing but restarting does anything now
+        let pos = model.player().pos();
+        press(&mut model, Action::MoveRight);
+        press(&mut model, Action::Jump);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert_eq!(model.player().pos(), pos);
+        assert!(model.is_game_over());
+
+        press(&mut model, Action::Reset);
+        tick(&mut model, 1);
+        assert!(!model.is_game_over());
+        assert_eq!((model.lives(), model.deaths()), (Some(2), 2));
+        assert_eq!(model.player().pos(), spawn);
+    }
+
+    #[test]
+    fn reaching_goal_sums_up_the_run() {
+        let mut room = Room::new(12, 10, 16);
+        room.set_spawn(1, 9).unwrap();
+        room.set_tile_at_index(9, 8, TileKind::Goal).unwrap();
+        for &(x, y) in &[(3, 8), (5, 8), (5, 2)] {
+            room.toggle_coin_at_index(x, y).unwrap();
+        }
+        // The ghost of the run is saved next to the room
+        let path = ::std::env::temp_dir().join("grot-test-results.json");
+        let path = path.to_str().unwrap();
+        let mut model = Model::new(Level::with_room(path, room), path, TICKS_PER_SECOND);
+        tick(&mut model, 1);
+        model.kill_player();
+        tick(&mut model, TICKS_PER_SECOND);
+        let time_before = model.elapsed();
+        press(&mut model, Action::MoveRight);
+        let mut ticks = 0;
+        while model.results().is_none() {
+            assert!(ticks < 2 * TICKS_PER_SECOND, "Player never reached the goal");
+            tick(&mut model, 1);
+            ticks += 1;
+        }
+        ::std::fs::remove_file(Ghost::path_for_room(path)).unwrap();
+
+        let results = model.results().unwrap();
+        assert_eq!(results.time, time_before + model.frame_duration * ticks);
+        assert_eq!((results.coins, results.total_coins), (2, 3));
+        assert_eq!(results.deaths, 1);
+        // Nothing moves on until the results are dismissed
+        tick(&mut model, TICKS_PER_SECOND);
+        assert_eq!(model.elapsed(), results.time);
+    }
+
+    /// Returns how many ticks a second of real time in chunks of 10 milliseconds runs
+    /// at `time_scale`.
+    fn ticks_in_a_second(time_scale: f64) -> u64 {
+        let mut model = model_in(Room::new(10, 10, 16));
+        model.set_time_scale(time_scale);
+        for _ in 0..100 {
+            model.update(Duration::from_millis(10));
+        }
+        model.total_ticks()
+    }
+
+    #[test]
+    fn time_scale_changes_how_many_ticks_run() {
+        assert_eq!(ticks_in_a_second(1.0), u64::from(TICKS_PER_SECOND));
+        assert_eq!(ticks_in_a_second(0.5), u64::from(TICKS_PER_SECOND / 2));
+        assert_eq!(ticks_in_a_second(2.0), u64::from(TICKS_PER_SECOND * 2));
+        // Out of range scales are clamped
+        assert_eq!(ticks_in_a_second(100.0), u64::from(TICKS_PER_SECOND * 4));
+    }
+
+    /// Returns a model with the player standing on a row of crumble tiles, and
+    /// another crumble tile out of reach.
+    fn model_on_crumble_tiles(regenerates: bool) -> Model {
+        let mut room = Room::new(12, 10, 16);
+        if regenerates {
+            room = with_setting(room, "crumble_regenerates");
+        }
+        for &x in &[2, 3, 4, 9] {
+            room.set_tile_at_index(x, 5, TileKind::Crumble).unwrap();
+        }
+        room.set_spawn(3, 5).unwrap();
+        model_in(room)
+    }
+
+    #[test]
+    fn crumble_tile_collapses_after_being_stood_on() {
+        let mut model = model_on_crumble_tiles(false);
+        let crumble_ticks = (CRUMBLE_TIME * TICKS_PER_SECOND as f32).round() as u32;
+        tick(&mut model, crumble_ticks - 1);
+        assert_eq!(model.room().tile_at_index(3, 5).kind, TileKind::Crumble);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        tick(&mut model, 1);
+        assert_eq!(model.room().tile_at_index(3, 5).kind, TileKind::Empty);
+        // Only the tile under the player's feet collapsed
+        assert_eq!(model.room().tile_at_index(2, 5).kind, TileKind::Crumble);
+        tick(&mut model, 2);
+        assert_eq!(model.player().vert_state(), PlayerVertState::Falling);
+
+        // Without regeneration, it stays gone, and the untouched one never decays
+        tick(&mut model, 10 * TICKS_PER_SECOND);
+        assert_eq!(model.room().tile_at_index(3, 5).kind, TileKind::Empty);
+        assert_eq!(model.room().tile_at_index(9, 5).kind, TileKind::Crumble);
+    }
+
+    #[test]
+    fn crumble_tile_comes_back_in_rooms_where_they_regenerate() {
+        let mut model = model_on_crumble_tiles(true);
+        let crumble_ticks = (CRUMBLE_TIME * TICKS_PER_SECOND as f32).round() as u32;
+        let regenerate_ticks = (CRUMBLE_REGENERATE_TIME * TICKS_PER_SECOND as f32).round() as u32;
+        tick(&mut model, crumble_ticks);
+        assert_eq!(model.room().tile_at_index(3, 5).kind, TileKind::Empty);
+        tick(&mut model, regenerate_ticks - 1);
+        assert_eq!(model.room().tile_at_index(3, 5).kind, TileKind::Empty);
+        tick(&mut model, 1);
+        assert_eq!(model.room().tile_at_index(3, 5).kind, TileKind::Crumble);
+    }
+
+    /// Holds right towards a three tile high ledge, jumping whenever the player is
+    /// stopped below it, with a crate in front of it if `with_crate` is set.
+    /// Returns the model after a few seconds.
+    fn climb_towards_ledge(with_crate: bool) -> Model {
+        let mut room = Room::new(16, 10, 16);
+        for x in 10..16 {
+            for y in 6..9 {
+                room.set_tile_at_index(x, y, TileKind::Filled).unwrap();
+            }
+        }
+        room.set_spawn(1, 9).unwrap();
+        if with_crate {
+            room.toggle_crate_at_index(9, 8).unwrap();
+        }
+        let mut model = model_in(room);
+        press(&mut model, Action::MoveRight);
+        for _ in 0..4 * TICKS_PER_SECOND {
+            tick(&mut model, 1);
+            let player = model.player();
+            let below_ledge = player.rect().bottom() > 96.0;
+            if player.vert_state() == PlayerVertState::Standing
+                && player.vel().x == 0.0
+                && below_ledge
+            {
+                press(&mut model, Action::Jump);
+                tick(&mut model, TICKS_PER_SECOND / 4);
+                release(&mut model, Action::Jump);
+            }
+        }
+        model
+    }
+
+    #[test]
+    fn crate_in_front_of_ledge_is_a_step_up() {
+        let model = climb_towards_ledge(true);
+        let crate_rect = model.crates[0].rect();
+        assert_eq!((crate_rect.left(), crate_rect.top()), (144.0, 128.0));
+        assert_eq!(model.player().vert_state(), PlayerVertState::Standing);
+        assert_eq!(model.player().rect().bottom(), 96.0);
+
+        // The ledge is too high to reach without it
+        let model = climb_towards_ledge(false);
+        assert!(model.player().rect().bottom() > 96.0);
+    }
+
+    /// Walks right towards a wall of channel 1 toggle blocks, starting on the
+    /// channel's switch if `on_switch` is set. Returns the model after a second.
+    fn walk_at_toggle_blocks(on_switch: bool) -> Model {
+        let mut room = Room::new(10, 10, 16);
+        for y in 0..9 {
+            room.set_tile_at_index(5, y, TileKind::ToggleBlock(1)).unwrap();
+        }
+        let switch = TileKind::Switch {
+            channel: 1,
+            one_shot: false,
+        };
+        room.set_tile_at_index(1, 8, switch).unwrap();
+        room.set_spawn(if on_switch { 1 } else { 3 }, 9).unwrap();
+        let mut model = model_in(room);
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND);
+        model
+    }
+
+    #[test]
+    fn switch_makes_its_blocks_intangible_on_next_tick() {
+        let mut room = Room::new(10, 10, 16);
+        room.set_tile_at_index(5, 8, TileKind::ToggleBlock(1)).unwrap();
+        room.set_tile_at_index(6, 8, TileKind::ToggleBlock(2)).unwrap();
+        room.set_spawn(1, 9).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        model.flipped_channels.push(1);
+        let solid = |model: &Model, x| model.room.tile_at_index(x, 8).kind.is_solid();
+        assert!(solid(&model, 5));
+        tick(&mut model, 1);
+        assert!(!solid(&model, 5));
+        // Blocks of other channels are left alone
+        assert!(solid(&model, 6));
+
+        let model = walk_at_toggle_blocks(false);
+        assert!(model.player().rect().right() <= 80.0);
+        let model = walk_at_toggle_blocks(true);
+        assert!(model.player().rect().left() >= 96.0);
+    }
+
+    #[test]
+    fn toggle_block_waits_for_player_to_leave_before_turning_solid() {
+        let mut room = Room::new(10, 10, 16);
+        room.set_tile_at_index(1, 8, TileKind::ToggleBlockOff(1)).unwrap();
+        room.set_spawn(1, 9).unwrap();
+        let mut model = model_in(room);
+        model.flipped_channels.push(1);
+        tick(&mut model, TICKS_PER_SECOND / 2);
+        assert_eq!(model.room.tile_at_index(1, 8).kind, TileKind::ToggleBlockOff(1));
+        assert_eq!(model.player().rect().bottom(), 144.0);
+
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND / 2);
+        assert!(model.player().rect().left() > 32.0);
+        assert_eq!(model.room.tile_at_index(1, 8).kind, TileKind::ToggleBlock(1));
+    }
+
+    #[test]
+    fn turret_shot_hurts_player_in_its_way() {
+        let mut room = Room::new(10, 10, 16);
+        room.set_tile_at_index(9, 8, TileKind::Filled).unwrap();
+        room.toggle_turret_at_index(8, 8).unwrap();
+        room.set_spawn(2, 9).unwrap();
+        let mut model = model_in(room);
+        // The first shot is fired after two seconds, give or take a tick
+        tick(&mut model, 2 * TICKS_PER_SECOND - 2);
+        assert!(model.projectiles.is_empty());
+        tick(&mut model, 3);
+        assert_eq!(model.projectiles.len(), 1);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert!(model.projectiles.is_empty());
+        assert_eq!(model.health(), Player::MAX_HEALTH - 1);
+    }
+
+    #[test]
+    fn grapple_hooks_nearest_filled_tile_diagonally_up() {
+        let mut room = Room::new(12, 12, 16);
+        room.set_tile_at_index(5, 5, TileKind::Filled).unwrap();
+        room.set_tile_at_index(8, 2, TileKind::Filled).unwrap();
+        room.set_tile_at_index(1, 7, TileKind::Filled).unwrap();
+        let mut player = player_at(36.0, 130.0);
+        assert_eq!(player.center(), (40.0, 140.0));
+
+        // The nearer tile is hit on its underside, where the diagonal enters it
+        player.fire_grapple(&room);
+        assert_eq!(player.grapple_anchor, Some((84.0, 96.0)));
+        assert_eq!(player.grapple_length, 44.0 * 2.0f32.sqrt());
+
+        player.release_grapple();
+        player.facing = Direction::Left;
+        player.fire_grapple(&room);
+        assert_eq!(player.grapple_anchor, Some((28.0, 128.0)));
+
+        // The farther tile is out of range, so the shot misses
+        room.set_tile_at_index(5, 5, TileKind::Empty).unwrap();
+        let mut player = player_at(36.0, 130.0);
+        player.fire_grapple(&room);
+        assert!(!player.is_swinging());
+    }
+
+    #[test]
+    fn releasing_grapple_keeps_speed_along_the_swing() {
+        let mut room = Room::new(16, 12, 16);
+        for x in 0..16 {
+            room.set_tile_at_index(x, 6, TileKind::Filled).unwrap();
+        }
+        room.set_spawn(3, 11).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        while model.player().vel().y < 0.0 {
+            tick(&mut model, 1);
+        }
+        release(&mut model, Action::Jump);
+        press(&mut model, Action::Grapple);
+        tick(&mut model, 1);
+        let (anchor_x, anchor_y) = model.player.grapple_anchor.unwrap();
+        assert_eq!(anchor_y, 112.0);
+
+        tick(&mut model, TICKS_PER_SECOND / 4);
+        let (x, y) = model.player.center();
+        let offset = Vec2::new(x - anchor_x, y - anchor_y);
+        // Swinging below the anchor on a taut rope
+        assert!(offset.y > 0.0);
+        assert!((offset.length() - model.player.grapple_length).abs() < 0.01);
+        let vel = model.player().vel();
+        assert!(vel.length() > 10.0);
+        assert!(vel.dot(offset).abs() / (vel.length() * offset.length()) < 0.01);
+        release(&mut model, Action::Grapple);
+        tick(&mut model, 1);
+        assert!(!model.player.is_swinging());
+        // Keeps going the same way, give or take a tick of gravity and air friction
+        assert!((model.player().vel() - vel).length() < vel.length() / 10.0);
+    }
+
+    /// Returns a model with the player standing left of a camera zone with `mode`
+    /// covering the right half of the room.
+    fn model_beside_zone(mode: CameraMode) -> Model {
+        let zone = CameraZone {
+            rect: Rect::new(320.0, 0.0, 320.0, 480.0),
+            mode,
+        };
+        let mut value = ::serde_json::to_value(Room::new(40, 30, 16)).unwrap();
+        value["camera_zones"] = ::serde_json::to_value(vec![zone]).unwrap();
+        let mut room: Room = ::serde_json::from_value(value).unwrap();
+        room.set_spawn(10, 29).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        model
+    }
+
+    /// Returns the point the camera is currently easing towards.
+    fn camera_target(model: &Model) -> (f32, f32) {
+        let (x, y) = model.camera_focus(&model.player, model.second_player.as_ref());
+        model.camera_target(x, y)
+    }
+
+    #[test]
+    fn camera_zone_frames_player_as_its_mode_says() {
+        let modes = [
+            (CameraMode::Follow, (404.0, 454.0)),
+            (CameraMode::LockX, (480.0, 454.0)),
+            (CameraMode::LockY, (404.0, 240.0)),
+            (CameraMode::Locked, (480.0, 240.0)),
+        ];
+        for &(mode, target) in &modes {
+            let mut model = model_beside_zone(mode);
+            assert_eq!(camera_target(&model), model.player.center());
+            model.player.pos.x = 400.0;
+            tick(&mut model, TICKS_PER_SECOND);
+            assert_eq!(model.player.center(), (404.0, 454.0));
+            assert_eq!(camera_target(&model), target, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn camera_blends_into_zone_over_blend_time() {
+        let mut model = model_beside_zone(CameraMode::Locked);
+        model.player.pos.x = 400.0;
+        tick(&mut model, 1);
+        // Starts out where it was headed outside the zone
+        assert_eq!(camera_target(&model), (404.0, 454.0));
+        let blend_ticks = model.seconds_to_ticks(0.4);
+        let mut last = camera_target(&model);
+        for _ in 1..blend_ticks {
+            tick(&mut model, 1);
+            let target = camera_target(&model);
+            assert!(target.0 > last.0 && target.0 < 480.0);
+            assert!(target.1 < last.1 && target.1 > 240.0);
+            last = target;
+        }
+        tick(&mut model, 1);
+        assert_eq!(camera_target(&model), (480.0, 240.0));
+    }
+
+    /// Returns a model in a room where the player may shoot, standing on the floor
+    /// left of a wall in column `wall_x`.
+    fn model_shooting_at_wall(wall_x: u32) -> Model {
+        let mut room = with_setting(Room::new(30, 10, 16), "shooting");
+        for y in 0..9 {
+            room.set_tile_at_index(wall_x, y, TileKind::Filled).unwrap();
+        }
+        room.set_spawn(2, 9).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 1);
+        model
+    }
+
+    fn shoot(model: &mut Model) {
+        press(model, Action::Shoot);
+        tick(model, 1);
+        release(model, Action::Shoot);
+    }
+
+    #[test]
+    fn player_has_at_most_two_projectiles_flying() {
+        let mut model = model_shooting_at_wall(29);
+        shoot(&mut model);
+        // Still cooling down
+        shoot(&mut model);
+        assert_eq!(model.player_projectiles.len(), 1);
+        for _ in 0..3 {
+            tick(&mut model, TICKS_PER_SECOND / 4);
+            shoot(&mut model);
+        }
+        assert_eq!(model.player_projectiles.len(), 2);
+
+        // Shooting isn't allowed in rooms without the setting
+        let mut model = model_in(Room::new(30, 10, 16));
+        shoot(&mut model);
+        assert!(model.player_projectiles.is_empty());
+    }
+
+    #[test]
+    fn player_projectile_despawns_at_wall() {
+        let mut model = model_shooting_at_wall(10);
+        shoot(&mut model);
+        let mut ticks = 1;
+        while let Some(projectile) = model.player_projectiles.first().cloned() {
+            assert!(projectile.rect().right() <= 160.0);
+            tick(&mut model, 1);
+            ticks += 1;
+        }
+        // Gone on the tick it reaches the wall, which is 16 tiles per second away
+        let distance = 160.0 - (model.player.center().0 + Projectile::SIZE / 2.0);
+        let expected = (distance / (16.0 * 16.0) * TICKS_PER_SECOND as f32).ceil() as u32;
+        assert_eq!(ticks, expected);
+    }
+
+    #[test]
+    fn player_projectile_defeats_first_enemy_in_its_way() {
+        let mut model = model_shooting_at_wall(29);
+        model.enemies.push(Enemy::new(200.0, 144.0 - 12.0));
+        model.enemies.push(Enemy::new(120.0, 144.0 - 12.0));
+        shoot(&mut model);
+        tick(&mut model, TICKS_PER_SECOND / 2);
+        assert_eq!(model.enemies.len(), 1);
+        assert!(model.enemies[0].rect().left() > 150.0);
+        assert!(model.player_projectiles.is_empty());
+    }
+
+    #[test]
+    fn player_animation_follows_what_they_do() {
+        let mut model = model_in(Room::new(20, 30, 16));
+        tick(&mut model, TICKS_PER_SECOND / 2);
+        assert_eq!(model.player.animation, Animation::Idle);
+
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, 1);
+        assert_eq!(model.player.animation, Animation::Walk);
+        assert_eq!(model.player.animation_time, 0.0);
+        // The clock runs on simulated time
+        tick(&mut model, 30);
+        let dt = model.frame_duration.as_fractional_secs() as f32;
+        assert!((model.player.animation_time - 30.0 * dt).abs() < 1e-4);
+
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        assert_eq!(model.player.animation, Animation::Jump);
+        while model.player().vel().y < 0.0 {
+            tick(&mut model, 1);
+        }
+        tick(&mut model, 1);
+        assert_eq!(model.player.animation, Animation::Fall);
+    }
+
+    #[test]
+    fn jumping_landing_hard_and_dying_spawn_particles() {
+        let mut room = Room::new(10, 30, 16);
+        room.set_spawn(2, 29).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, TICKS_PER_SECOND);
+        // Standing and walking don't
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND / 4);
+        assert!(model.particles.is_empty());
+
+        press(&mut model, Action::Jump);
+        tick(&mut model, 1);
+        assert_eq!(model.particles.len(), 6);
+        release(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert!(model.particles.is_empty());
+
+        // Falling ten tiles lands hard
+        model.player.pos.y -= 160.0;
+        while model.player().vert_state() != PlayerVertState::Standing {
+            tick(&mut model, 1);
+        }
+        assert_eq!(model.particles.len(), 10);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert!(model.particles.is_empty());
+
+        model.kill_player();
+        assert_eq!(model.particles.len(), 16);
+    }
+
+    fn probe(model: &Model, kind: ProbeKind) -> Option<CollisionProbe> {
+        model
+            .player_collisions()
+            .probes()
+            .into_iter()
+            .find(|probe| probe.kind == kind)
+    }
+
+    #[test]
+    fn standing_player_probes_the_floor_below_their_feet() {
+        let mut room = room_with_tiles(&[]);
+        room.set_spawn(1, 7).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 30);
+        let kinds: Vec<ProbeKind> = model
+            .player_collisions()
+            .probes()
+            .iter()
+            .map(|probe| probe.kind)
+            .collect();
+        assert_eq!(kinds, [ProbeKind::Horizontal, ProbeKind::Vertical, ProbeKind::Floor]);
+        let floor = probe(&model, ProbeKind::Floor).unwrap();
+        assert!(floor.hit);
+        assert_eq!(floor.rect.top(), 112.0);
+        assert_eq!(floor.rect.left(), model.player().rect().left());
+        assert!(!probe(&model, ProbeKind::Horizontal).unwrap().hit);
+        assert!(!probe(&model, ProbeKind::Vertical).unwrap().hit);
+    }
+
+    #[test]
+    fn walking_into_a_wall_records_where_it_was_entered() {
+        let mut room = room_with_tiles(&[(4, 6)]);
+        room.set_spawn(1, 7).unwrap();
+        let mut model = model_in(room);
+        press(&mut model, Action::MoveRight);
+        tick(&mut model, TICKS_PER_SECOND);
+        assert_eq!(model.player().rect().right(), 64.0);
+        let wall = probe(&model, ProbeKind::Horizontal).unwrap();
+        assert!(wall.hit);
+        // The probe is the rect before the player was pushed back out of the wall
+        assert!(wall.rect.right() > 64.0);
+    }
+
+    #[test]
+    fn falling_player_makes_no_floor_probe() {
+        let mut room = room_with_tiles(&[]);
+        room.set_spawn(1, 3).unwrap();
+        let mut model = model_in(room);
+        tick(&mut model, 2);
+        assert!(probe(&model, ProbeKind::Floor).is_none());
+        let vertical = probe(&model, ProbeKind::Vertical).unwrap();
+        assert!(!vertical.hit);
+        assert_eq!(vertical.rect.top(), model.player().rect().top());
+    }
+
+    /// Runs, jumps over a step, sprints into a wall and jumps back, returning where
+    /// the player was and how fast they moved every 25 ticks.
+    fn record_trajectory() -> Vec<(Vec2, Vec2)> {
+        let mut room = Room::new(20, 12, 16);
+        for &(x, y) in &[(6, 10), (7, 10), (7, 9), (12, 6), (13, 6), (14, 6), (18, 10), (18, 9)] {
+            room.toggle_tile_at_index(x, y, TileKind::Filled).unwrap();
+        }
+        room.set_spawn(1, 11).unwrap();
+        let mut model = model_in(room);
+        let steps: &[(&[Action], &[Action], u32)] = &[
+            (&[], &[], 20),
+            (&[Action::MoveRight], &[], 60),
+            (&[Action::Jump], &[], 25),
+            (&[], &[Action::Jump], 60),
+            (&[Action::Jump], &[], 80),
+            (&[Action::Run], &[Action::Jump], 90),
+            (&[Action::MoveLeft], &[Action::MoveRight], 30),
+            (&[Action::Jump], &[], 40),
+            (&[], &[Action::Jump, Action::MoveLeft, Action::Run], 60),
+        ];
+        let mut trajectory = Vec::new();
+        let mut ticks_run = 0;
+        for &(pressed, released, ticks) in steps {
+            for &action in pressed {
+                press(&mut model, action);
+            }
+            for &action in released {
+                release(&mut model, action);
+            }
+            for _ in 0..ticks {
+                tick(&mut model, 1);
+                ticks_run += 1;
+                if ticks_run % 25 == 0 {
+                    trajectory.push((model.player().pos(), model.player().vel()));
+                }
+            }
+        }
+        trajectory
+    }
+
+    #[test]
+    fn trajectory_is_bit_for_bit_the_same() {
+        // Keeping the position and speed in `Vec2`s moved the player exactly as before
+        // along this run. Only record it again when changing how the player moves.
+        let expected = [
+            (20.333338, 156.0, 19.999998, 0.0),
+            (32.000015, 156.0, 119.99999, 0.0),
+            (51.999996, 156.0, 120.0, 0.0),
+            (72.000015, 145.33334, 120.0, -120.0),
+            (92.00009, 129.50002, 120.0, -70.0),
+            (112.00017, 122.00001, 120.0, -20.0),
+            (132.00024, 122.83334, 120.0, 29.999998),
+            (152.00032, 132.00002, 120.0, 79.99999),
+            (172.0004, 149.5, 120.0, 129.99998),
+            (192.53378, 156.0, 151.99998, 0.0),
+            (223.70042, 156.0, 192.0, 0.0),
+            (255.7004, 156.0, 192.0, 0.0),
+            (280.0, 156.0, 0.0, 0.0),
+            (275.20004, 156.0, -95.99999, 0.0),
+            (248.00003, 153.11113, -192.0, -86.66665),
+            (216.00006, 134.83334, -192.0, -90.0),
+            (185.77783, 124.00001, -165.33344, -40.0),
+            (161.00003, 121.50001, -132.00023, 9.999999),
+        ];
+        let trajectory: Vec<(f32, f32, f32, f32)> = record_trajectory()
+            .iter()
+            .map(|&(pos, vel)| (pos.x, pos.y, vel.x, vel.y))
+            .collect();
+        assert_eq!(trajectory, expected);
+    }
 }
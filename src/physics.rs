@@ -0,0 +1,340 @@
+//! Tunable constants for player movement
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use failure::Error;
+
+/// Constants controlling how the player moves.
+///
+/// Lengths are in tiles, speeds in tiles per second and times in seconds, so
+/// the player moves the same in rooms with any tile size. Negative vertical
+/// speeds point up.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    /// Width of the player's collision box
+    pub width: f32,
+    /// Height of the player's collision box when standing
+    pub height: f32,
+    /// Width the player is drawn with, if different from `width`
+    pub visual_width: Option<f32>,
+    /// Height the player is drawn with when standing, if different from `height`
+    pub visual_height: Option<f32>,
+    /// Maximum walk speed
+    pub walk_speed: f32,
+    /// Time to go from 0 to `walk_speed`
+    pub walk_time: f32,
+    /// Time to go from `walk_speed` back to 0
+    pub stop_time: f32,
+    /// Time to go from 0 to `walk_speed` while airborne
+    pub air_walk_time: f32,
+    /// Time to go from `walk_speed` back to 0 while airborne
+    pub air_stop_time: f32,
+    /// Factor applied to walk speed and acceleration while sprinting
+    pub sprint_multiplier: f32,
+    pub crouch_height: f32,
+    /// Factor applied to walk speed and acceleration while crouching
+    pub crouch_multiplier: f32,
+    pub fall_speed: f32,
+    /// Time to go from 0 to `fall_speed`
+    pub fall_time: f32,
+    pub jump_speed: f32,
+    /// Time to go from 0 to `jump_speed` while holding jump
+    pub jump_time: f32,
+    /// Gravity multiplier after releasing a jump early
+    pub jump_cut_gravity: f32,
+    /// Factor applied to gravity and fall speed while holding down in the air
+    pub fast_fall_multiplier: f32,
+    /// Time after walking off a ledge in which a jump is still allowed
+    pub coyote_time: f32,
+    /// Maximum fall speed while sliding down a wall
+    pub wall_slide_speed: f32,
+    /// Horizontal speed away from the wall when wall jumping
+    pub wall_jump_xspeed: f32,
+    pub wall_jump_yspeed: f32,
+    /// Time after a wall jump in which horizontal input is ignored
+    pub wall_jump_lockout: f32,
+    /// Fraction of `jump_speed` to bounce up with after stomping an enemy
+    pub stomp_bounce: f32,
+    /// Fraction of the landing speed to be launched back up with by a bounce tile
+    pub bounce_factor: f32,
+    /// Launch speed of a bounce tile when landing on it slowly or walking onto it
+    pub bounce_min_speed: f32,
+    /// Highest launch speed of a bounce tile
+    pub bounce_max_speed: f32,
+    /// Factor applied to the launch speed when holding jump at the moment of the bounce
+    pub bounce_jump_multiplier: f32,
+    /// Time after being hurt in which the player can't be hurt again
+    pub invincible_time: f32,
+    pub knockback_xspeed: f32,
+    pub knockback_yspeed: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> PhysicsConfig {
+        PhysicsConfig {
+            width: 0.5,
+            height: 1.25,
+            visual_width: None,
+            visual_height: None,
+            walk_speed: 7.5,
+            walk_time: 0.2,
+            stop_time: 0.3,
+            air_walk_time: 0.35,
+            air_stop_time: 0.6,
+            sprint_multiplier: 1.6,
+            crouch_height: 0.75,
+            crouch_multiplier: 0.5,
+            fall_speed: 18.75,
+            fall_time: 1.0,
+            jump_speed: -8.125,
+            jump_time: 0.1,
+            jump_cut_gravity: 3.0,
+            fast_fall_multiplier: 2.0,
+            coyote_time: 0.09,
+            wall_slide_speed: 3.75,
+            wall_jump_xspeed: 6.25,
+            wall_jump_yspeed: -8.125,
+            wall_jump_lockout: 0.15,
+            stomp_bounce: 0.6,
+            bounce_factor: 0.8,
+            bounce_min_speed: 4.0,
+            bounce_max_speed: 25.0,
+            bounce_jump_multiplier: 1.5,
+            invincible_time: 1.0,
+            knockback_xspeed: 5.0,
+            knockback_yspeed: -5.0,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    /// Loads a config from a JSON file, or from a TOML file if its extension is
+    /// `.toml`. Values missing from the file keep their defaults.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PhysicsConfig, Error> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let is_toml = path.extension().and_then(|ext| ext.to_str()) == Some("toml");
+        let config: PhysicsConfig = if is_toml {
+            let mut text = String::new();
+            BufReader::new(file).read_to_string(&mut text)?;
+            ::toml::from_str(&text)?
+        } else {
+            ::serde_json::from_reader(BufReader::new(file))?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that all values are usable, so that the simulation can't
+    /// produce infinite or NaN positions.
+    pub fn validate(&self) -> Result<(), Error> {
+        let values = [
+            ("width", self.width),
+            ("height", self.height),
+            ("visual_width", self.visual_width()),
+            ("visual_height", self.visual_height()),
+            ("walk_speed", self.walk_speed),
+            ("walk_time", self.walk_time),
+            ("stop_time", self.stop_time),
+            ("air_walk_time", self.air_walk_time),
+            ("air_stop_time", self.air_stop_time),
+            ("sprint_multiplier", self.sprint_multiplier),
+            ("crouch_height", self.crouch_height),
+            ("crouch_multiplier", self.crouch_multiplier),
+            ("fall_speed", self.fall_speed),
+            ("fall_time", self.fall_time),
+            ("jump_speed", self.jump_speed),
+            ("jump_time", self.jump_time),
+            ("jump_cut_gravity", self.jump_cut_gravity),
+            ("fast_fall_multiplier", self.fast_fall_multiplier),
+            ("coyote_time", self.coyote_time),
+            ("wall_slide_speed", self.wall_slide_speed),
+            ("wall_jump_xspeed", self.wall_jump_xspeed),
+            ("wall_jump_yspeed", self.wall_jump_yspeed),
+            ("wall_jump_lockout", self.wall_jump_lockout),
+            ("stomp_bounce", self.stomp_bounce),
+            ("bounce_factor", self.bounce_factor),
+            ("bounce_min_speed", self.bounce_min_speed),
+            ("bounce_max_speed", self.bounce_max_speed),
+            ("bounce_jump_multiplier", self.bounce_jump_multiplier),
+            ("invincible_time", self.invincible_time),
+            ("knockback_xspeed", self.knockback_xspeed),
+            ("knockback_yspeed", self.knockback_yspeed),
+        ];
+        for &(name, value) in &values {
+            ensure!(value.is_finite(), "Physics value {} must be a finite number", name);
+        }
+        let positive = [
+            ("width", self.width),
+            ("height", self.height),
+            ("visual_width", self.visual_width()),
+            ("visual_height", self.visual_height()),
+            ("walk_speed", self.walk_speed),
+            ("walk_time", self.walk_time),
+            ("stop_time", self.stop_time),
+            ("air_walk_time", self.air_walk_time),
+            ("air_stop_time", self.air_stop_time),
+            ("sprint_multiplier", self.sprint_multiplier),
+            ("crouch_height", self.crouch_height),
+            ("crouch_multiplier", self.crouch_multiplier),
+            ("fall_speed", self.fall_speed),
+            ("fall_time", self.fall_time),
+            ("jump_time", self.jump_time),
+            ("jump_cut_gravity", self.jump_cut_gravity),
+            ("fast_fall_multiplier", self.fast_fall_multiplier),
+            ("wall_slide_speed", self.wall_slide_speed),
+            ("bounce_min_speed", self.bounce_min_speed),
+            ("bounce_max_speed", self.bounce_max_speed),
+            ("bounce_jump_multiplier", self.bounce_jump_multiplier),
+        ];
+        for &(name, value) in &positive {
+            ensure!(value > 0.0, "Physics value {} must be greater than 0, not {}", name, value);
+        }
+        let not_negative = [
+            ("coyote_time", self.coyote_time),
+            ("wall_jump_lockout", self.wall_jump_lockout),
+            ("stomp_bounce", self.stomp_bounce),
+            ("bounce_factor", self.bounce_factor),
+            ("invincible_time", self.invincible_time),
+        ];
+        for &(name, value) in &not_negative {
+            ensure!(value >= 0.0, "Physics value {} must not be negative, not {}", name, value);
+        }
+        ensure!(
+            self.jump_speed < 0.0,
+            "Physics value jump_speed must be negative (upwards), not {}",
+            self.jump_speed
+        );
+        Ok(())
+    }
+
+    /// Returns a copy of the config with all lengths and speeds converted from
+    /// tiles to pixels of size `tile_size`.
+    pub fn scaled(&self, tile_size: u32) -> PhysicsConfig {
+        let scale = tile_size as f32;
+        PhysicsConfig {
+            width: self.width * scale,
+            height: self.height * scale,
+            visual_width: self.visual_width.map(|width| width * scale),
+            visual_height: self.visual_height.map(|height| height * scale),
+            walk_speed: self.walk_speed * scale,
+            crouch_height: self.crouch_height * scale,
+            fall_speed: self.fall_speed * scale,
+            jump_speed: self.jump_speed * scale,
+            wall_slide_speed: self.wall_slide_speed * scale,
+            wall_jump_xspeed: self.wall_jump_xspeed * scale,
+            wall_jump_yspeed: self.wall_jump_yspeed * scale,
+            knockback_xspeed: self.knockback_xspeed * scale,
+            knockback_yspeed: self.knockback_yspeed * scale,
+            bounce_min_speed: self.bounce_min_speed * scale,
+            bounce_max_speed: self.bounce_max_speed * scale,
+            ..self.clone()
+        }
+    }
+
+    pub fn visual_width(&self) -> f32 {
+        self.visual_width.unwrap_or(self.width)
+    }
+
+    pub fn visual_height(&self) -> f32 {
+        self.visual_height.unwrap_or(self.height)
+    }
+
+    pub fn walk_accel(&self) -> f32 {
+        self.walk_speed / self.walk_time
+    }
+
+    pub fn stop_accel(&self) -> f32 {
+        self.walk_speed / self.stop_time
+    }
+
+    pub fn air_walk_accel(&self) -> f32 {
+        self.walk_speed / self.air_walk_time
+    }
+
+    pub fn air_stop_accel(&self) -> f32 {
+        self.walk_speed / self.air_stop_time
+    }
+
+    pub fn fall_accel(&self) -> f32 {
+        self.fall_speed / self.fall_time
+    }
+
+    pub fn jump_accel(&self) -> f32 {
+        self.jump_speed / self.jump_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    fn config_with(change: fn(&mut PhysicsConfig)) -> PhysicsConfig {
+        let mut config = PhysicsConfig::default();
+        change(&mut config);
+        config
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        PhysicsConfig::default().validate().unwrap();
+        PhysicsConfig::default().scaled(16).validate().unwrap();
+    }
+
+    #[test]
+    fn zero_values_are_rejected() {
+        assert!(config_with(|config| config.walk_time = 0.0).validate().is_err());
+        assert!(config_with(|config| config.height = 0.0).validate().is_err());
+        assert!(config_with(|config| config.jump_speed = 0.0).validate().is_err());
+        // Some things may be turned off entirely
+        config_with(|config| config.coyote_time = 0.0).validate().unwrap();
+    }
+
+    #[test]
+    fn negative_values_are_rejected() {
+        assert!(config_with(|config| config.fall_speed = -1.0).validate().is_err());
+        assert!(config_with(|config| config.coyote_time = -0.1).validate().is_err());
+        assert!(config_with(|config| config.visual_width = Some(-1.0)).validate().is_err());
+        // Except for speeds pointing up
+        assert!(config_with(|config| config.jump_speed = 8.0).validate().is_err());
+    }
+
+    #[test]
+    fn nan_and_infinite_values_are_rejected() {
+        assert!(config_with(|config| config.width = f32::NAN).validate().is_err());
+        assert!(config_with(|config| config.jump_speed = f32::NAN).validate().is_err());
+        assert!(config_with(|config| config.knockback_xspeed = f32::NAN).validate().is_err());
+        assert!(config_with(|config| config.fall_time = f32::INFINITY).validate().is_err());
+    }
+
+    #[test]
+    fn config_loads_from_json_or_toml() {
+        let dir = env::temp_dir();
+        let json_path = dir.join("grot-test-physics.json");
+        let toml_path = dir.join("grot-test-physics.toml");
+        fs::write(&json_path, r#"{ "jump_speed": -10.0, "coyote_time": 0.0 }"#).unwrap();
+        fs::write(&toml_path, "jump_speed = -10.0\ncoyote_time = 0.0\n").unwrap();
+        for path in &[json_path, toml_path] {
+            let config = PhysicsConfig::load(path).unwrap();
+            assert_eq!(config.jump_speed, -10.0);
+            assert_eq!(config.coyote_time, 0.0);
+            assert_eq!(config.walk_speed, PhysicsConfig::default().walk_speed);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_config_fails_to_load() {
+        let path = env::temp_dir().join("grot-test-invalid-physics.toml");
+        fs::write(&path, "walk_time = -0.2\n").unwrap();
+        let result = PhysicsConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Skipping frames that would look the same as the last one
+
+use std::time::{Duration, Instant};
+
+/// Decides whether a frame needs to be drawn, from a summary `S` of what it shows
+/// that is equal for frames that look the same.
+///
+/// Frames are still drawn every so often while nothing changes, for things that
+/// change on their own, like animations.
+#[derive(Clone, Debug)]
+pub struct RedrawTracker<S> {
+    /// What the last frame drawn showed, and when it was drawn
+    last: Option<(S, Instant)>,
+    /// Whether something the summary doesn't cover changed since then
+    forced: bool,
+    /// Longest time between frames while nothing changes
+    idle_interval: Duration,
+}
+
+impl<S: PartialEq> RedrawTracker<S> {
+    pub fn new(idle_interval: Duration) -> RedrawTracker<S> {
+        RedrawTracker {
+            last: None,
+            forced: false,
+            idle_interval,
+        }
+    }
+
+    /// Makes the next frame be drawn whatever it shows, e.g. after an event that
+    /// may have changed something.
+    pub fn force(&mut self) {
+        self.forced = true;
+    }
+
+    /// Returns whether a frame showing `shown` has to be drawn at `now`, and if so
+    /// remembers it as the last frame drawn.
+    pub fn needs_redraw(&mut self, shown: S, now: Instant) -> bool {
+        let needed = match self.last {
+            Some((ref last_shown, drawn)) => {
+                self.forced || *last_shown != shown || now >= drawn + self.idle_interval
+            }
+            None => true,
+        };
+        if needed {
+            self.last = Some((shown, now));
+            self.forced = false;
+        }
+        needed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for a room generation, cursor position and overlay toggle
+    type Shown = (usize, (i32, i32), bool);
+
+    const SHOWN: Shown = (1, (0, 0), false);
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn first_frame_is_drawn() {
+        let mut tracker = RedrawTracker::new(ms(100));
+        assert!(tracker.needs_redraw(SHOWN, Instant::now()));
+    }
+
+    #[test]
+    fn unchanged_frame_is_skipped() {
+        let start = Instant::now();
+        let mut tracker = RedrawTracker::new(ms(100));
+        tracker.needs_redraw(SHOWN, start);
+        assert!(!tracker.needs_redraw(SHOWN, start + ms(10)));
+        assert!(!tracker.needs_redraw(SHOWN, start + ms(99)));
+    }
+
+    #[test]
+    fn any_change_is_drawn() {
+        let start = Instant::now();
+        for &changed in &[(2, (0, 0), false), (1, (1, 0), false), (1, (0, 0), true)] {
+            let mut tracker = RedrawTracker::new(ms(100));
+            tracker.needs_redraw(SHOWN, start);
+            assert!(tracker.needs_redraw(changed, start + ms(1)), "{:?}", changed);
+            assert!(!tracker.needs_redraw(changed, start + ms(2)), "{:?}", changed);
+            // Changing back counts as a change too
+            assert!(tracker.needs_redraw(SHOWN, start + ms(3)), "{:?}", changed);
+        }
+    }
+
+    #[test]
+    fn forced_frame_is_drawn_once() {
+        let start = Instant::now();
+        let mut tracker = RedrawTracker::new(ms(100));
+        tracker.needs_redraw(SHOWN, start);
+        tracker.force();
+        assert!(tracker.needs_redraw(SHOWN, start + ms(1)));
+        assert!(!tracker.needs_redraw(SHOWN, start + ms(2)));
+    }
+
+    #[test]
+    fn idle_frames_are_drawn_every_interval() {
+        let start = Instant::now();
+        let mut tracker = RedrawTracker::new(ms(100));
+        tracker.needs_redraw(SHOWN, start);
+        assert!(tracker.needs_redraw(SHOWN, start + ms(100)));
+        // The interval counts from the last frame drawn
+        assert!(!tracker.needs_redraw(SHOWN, start + ms(150)));
+        assert!(tracker.needs_redraw(SHOWN, start + ms(200)));
+    }
+}
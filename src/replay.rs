@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use failure::Error;
+
+use input::InputEvent;
+
+/// Player input recorded tick by tick, so that a run can be played back exactly.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Replay {
+    /// File path of the room the recording started in
+    pub room: String,
+    /// Tick rate of the model that made the recording
+    pub ticks_per_second: u32,
+    /// Input events, each with the index of the tick at whose start it was applied
+    pub inputs: Vec<(u32, InputEvent)>,
+    /// Number of ticks the recording ran for, which may go on past the last input
+    #[serde(default)]
+    pub ticks: u32,
+}
+
+impl Replay {
+    /// Returns the number of ticks to play back, which for recordings that didn't
+    /// store it is until the last input.
+    pub fn end_tick(&self) -> u32 {
+        let last_input = self.inputs.last().map(|&(tick, _)| tick + 1).unwrap_or(0);
+        self.ticks.max(last_input)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        let writer = BufWriter::new(file);
+        ::serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, Error> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let replay = ::serde_json::from_reader(reader)?;
+        Ok(replay)
+    }
+}
@@ -1,13 +1,24 @@
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
+use std::ops::Range;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use failure::{err_msg, Error};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect as SdlRect;
 use sdl2::render::{Canvas, RenderTarget};
 
-use geom::Rect;
+use camera::{Camera, CameraZone};
+use entity::{CrusherDef, Entity, SignDef, TurretDef};
+use geom::{Circle, Direction, Rect, TileCoord, Vec2};
+use light::Darkness;
+use graphics::RenderContext;
+use theme::Theme;
+use tileset::Tileset;
+
+/// Seconds blink tiles stay solid, and then intangible
+pub const BLINK_TIME: f32 = 2.0;
 
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Room {
@@ -15,6 +26,45 @@ pub struct Room {
     height: u32,
     tiles: Vec<TileKind>,
     tile_size: u32,
+    /// Whether the player may jump a second time in mid-air
+    #[serde(default)]
+    double_jump: bool,
+    /// Whether the player may flip gravity with a key, besides touching gravity flip tiles
+    #[serde(default)]
+    gravity_flip_key: bool,
+    /// Whether the player may shoot projectiles
+    #[serde(default)]
+    shooting: bool,
+    /// Seconds the blink tiles' cycle is ahead of the time spent in the room
+    #[serde(default)]
+    blink_offset: f32,
+    /// Whether crumble tiles come back a while after collapsing, rather than only
+    /// when restarting the room
+    #[serde(default)]
+    crumble_regenerates: bool,
+    /// How dark the room is, for caves only lit around the player, if at all
+    #[serde(default)]
+    darkness: Option<Darkness>,
+    /// Index of the tile the player spawns on top of
+    #[serde(default)]
+    spawn: Option<(u32, u32)>,
+    #[serde(default)]
+    exits: Vec<Exit>,
+    #[serde(default)]
+    entities: Vec<Entity>,
+    #[serde(default)]
+    camera_zones: Vec<CameraZone>,
+    /// Number identifying the current tiles, see `Room::generation`
+    #[serde(skip, default = "next_generation")]
+    generation: usize,
+}
+
+/// Source of room generations, so that no two rooms ever have the same tiles
+/// under the same generation
+static NEXT_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+fn next_generation() -> usize {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
 }
 
 impl Room {
@@ -32,9 +82,26 @@ impl Room {
             height,
             tiles,
             tile_size,
+            double_jump: false,
+            gravity_flip_key: false,
+            shooting: false,
+            blink_offset: 0.0,
+            crumble_regenerates: false,
+            darkness: None,
+            spawn: None,
+            exits: Vec::new(),
+            entities: Vec::new(),
+            camera_zones: Vec::new(),
+            generation: next_generation(),
         }
     }
 
+    /// Returns a number that changes whenever the tiles change. A copy of the room
+    /// has the same generation until either of them changes.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
     pub fn width(&self) -> u32 {
         self.width
     }
@@ -47,26 +114,419 @@ impl Room {
         self.tile_size
     }
 
-    pub fn tile_at_index(&self, x: u32, y: u32) -> Tile {
-        let kind = if x < self.width && y < self.height {
+    /// Returns the width of the room in pixels.
+    pub fn pixel_width(&self) -> f32 {
+        (self.width * self.tile_size) as f32
+    }
+
+    /// Returns the height of the room in pixels.
+    pub fn pixel_height(&self) -> f32 {
+        (self.height * self.tile_size) as f32
+    }
+
+    pub fn allows_double_jump(&self) -> bool {
+        self.double_jump
+    }
+
+    pub fn allows_gravity_flip_key(&self) -> bool {
+        self.gravity_flip_key
+    }
+
+    pub fn allows_shooting(&self) -> bool {
+        self.shooting
+    }
+
+    pub fn crumble_regenerates(&self) -> bool {
+        self.crumble_regenerates
+    }
+
+    pub fn darkness(&self) -> Option<&Darkness> {
+        self.darkness.as_ref()
+    }
+
+    /// Returns whether blink tiles are solid `time` seconds after entering the room,
+    /// and how many seconds are left until that changes.
+    ///
+    /// They're solid for `BLINK_TIME` seconds, then intangible for as long, and so on.
+    pub fn blink_state(&self, time: f32) -> (bool, f32) {
+        let mut cycle_time = (time + self.blink_offset) % (2.0 * BLINK_TIME);
+        if cycle_time < 0.0 {
+            cycle_time += 2.0 * BLINK_TIME;
+        }
+        if cycle_time < BLINK_TIME {
+            (true, BLINK_TIME - cycle_time)
+        } else {
+            (false, 2.0 * BLINK_TIME - cycle_time)
+        }
+    }
+
+    /// Returns the tile the player spawns on top of.
+    ///
+    /// Defaults to the second tile of the bottom row.
+    pub fn spawn_tile(&self) -> Tile {
+        let (x, y) = self.spawn.unwrap_or((1, self.height - 1));
+        self.tile_at_index(x as i32, y as i32)
+    }
+
+    pub fn set_spawn(&mut self, x: u32, y: u32) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Spawn index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        self.spawn = Some((x, y));
+        Ok(())
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn camera_zones(&self) -> &[CameraZone] {
+        &self.camera_zones
+    }
+
+    /// Returns the index of the first camera zone containing the point (`x`, `y`), if any.
+    pub fn camera_zone_at(&self, x: f32, y: f32) -> Option<usize> {
+        self.camera_zones.iter().position(|zone| zone.contains(x, y))
+    }
+
+    /// Adds a coin centered on the tile at the given index, or removes it if
+    /// there already is one.
+    pub fn toggle_coin_at_index(&mut self, x: u32, y: u32) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let center = TileCoord::new(x as i32, y as i32).center(self.tile_size);
+        let existing = self.entities.iter().position(|entity| match *entity {
+            Entity::Coin { x, y } => x == center.x && y == center.y,
+            _ => false,
+        });
+        match existing {
+            Some(index) => {
+                self.entities.remove(index);
+            }
+            None => self.entities.push(Entity::Coin {
+                x: center.x,
+                y: center.y,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Adds a key centered on the tile at the given index, or removes it if
+    /// there already is one.
+    pub fn toggle_key_at_index(&mut self, x: u32, y: u32) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let center = TileCoord::new(x as i32, y as i32).center(self.tile_size);
+        let existing = self.entities.iter().position(|entity| match *entity {
+            Entity::Key { x, y } => x == center.x && y == center.y,
+            _ => false,
+        });
+        match existing {
+            Some(index) => {
+                self.entities.remove(index);
+            }
+            None => self.entities.push(Entity::Key {
+                x: center.x,
+                y: center.y,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Adds a tile sized crate at the given index, or removes it if there
+    /// already is one.
+    pub fn toggle_crate_at_index(&mut self, x: u32, y: u32) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let tile_size = self.tile_size as f32;
+        let (left, top) = (x as f32 * tile_size, y as f32 * tile_size);
+        let existing = self.entities.iter().position(|entity| match *entity {
+            Entity::Crate { x, y, .. } => x == left && y == top,
+            _ => false,
+        });
+        match existing {
+            Some(index) => {
+                self.entities.remove(index);
+            }
+            None => self.entities.push(Entity::Crate {
+                x: left,
+                y: top,
+                size: tile_size,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Adds a one tile crusher at the given index that strikes down to the floor
+    /// below it, or removes it if there already is one.
+    pub fn toggle_crusher_at_index(&mut self, x: u32, y: u32) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let tile_size = self.tile_size as f32;
+        let start = (x as f32 * tile_size, y as f32 * tile_size);
+        let existing = self.entities.iter().position(|entity| match *entity {
+            Entity::Crusher(ref def) => def.start == start,
+            _ => false,
+        });
+        if let Some(index) = existing {
+            self.entities.remove(index);
+            return Ok(());
+        }
+        let floor_y = (y + 1..self.height)
+            .find(|&below| self.tile_at_index(x as i32, below as i32).kind.is_solid())
+            .unwrap_or(self.height);
+        self.entities.push(Entity::Crusher(CrusherDef {
+            width: tile_size,
+            height: tile_size,
+            start,
+            end: (start.0, (floor_y - 1) as f32 * tile_size),
+            wait_time: 1.0,
+            strike_time: 0.1,
+            hold_time: 0.5,
+            retract_time: 1.0,
+        }));
+        Ok(())
+    }
+
+    /// Adds a one tile turret at the given index, or removes it if there already is
+    /// one. It fires away from a solid tile to its right, and to the right otherwise.
+    pub fn toggle_turret_at_index(&mut self, x: u32, y: u32) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let tile_size = self.tile_size as f32;
+        let (left, top) = (x as f32 * tile_size, y as f32 * tile_size);
+        let existing = self.entities.iter().position(|entity| match *entity {
+            Entity::Turret(ref def) => def.x == left && def.y == top,
+            _ => false,
+        });
+        if let Some(index) = existing {
+            self.entities.remove(index);
+            return Ok(());
+        }
+        let speed = 8.0 * tile_size;
+        let blocked_right = self.tile_at_index(x as i32 + 1, y as i32).kind.is_solid();
+        self.entities.push(Entity::Turret(TurretDef {
+            x: left,
+            y: top,
+            size: tile_size,
+            projectile_velocity: (if blocked_right { -speed } else { speed }, 0.0),
+            interval: 2.0,
+        }));
+        Ok(())
+    }
+
+    /// Returns the text of the sign at the given index, if there is one.
+    pub fn sign_text_at_index(&self, x: u32, y: u32) -> Option<&str> {
+        let tile_size = self.tile_size as f32;
+        let (left, top) = (x as f32 * tile_size, y as f32 * tile_size);
+        self.entities
+            .iter()
+            .filter_map(|entity| match *entity {
+                Entity::Sign(ref def) if def.x == left && def.y == top => Some(def.text.as_str()),
+                _ => None,
+            })
+            .next()
+    }
+
+    /// Places a tile sized sign with the given text at the given index, replacing
+    /// any sign already there, or removes the sign if `text` is `None`.
+    pub fn set_sign_at_index(&mut self, x: u32, y: u32, text: Option<String>) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let tile_size = self.tile_size as f32;
+        let (left, top) = (x as f32 * tile_size, y as f32 * tile_size);
+        self.entities.retain(|entity| match *entity {
+            Entity::Sign(ref def) => def.x != left || def.y != top,
+            _ => true,
+        });
+        if let Some(text) = text {
+            self.entities.push(Entity::Sign(SignDef {
+                x: left,
+                y: top,
+                size: tile_size,
+                text,
+                automatic: false,
+            }));
+        }
+        Ok(())
+    }
+
+    pub fn exits(&self) -> &[Exit] {
+        &self.exits
+    }
+
+    /// Returns the exit through the given edge of the room, if any.
+    pub fn exit(&self, edge: Edge) -> Option<&Exit> {
+        self.exits.iter().find(|exit| exit.edge == edge)
+    }
+
+    pub fn remove_exit(&mut self, edge: Edge) {
+        self.exits.retain(|exit| exit.edge != edge);
+    }
+
+    /// Returns the tile at the given tile index.
+    ///
+    /// Indices may lie outside the room, including negative ones; such tiles
+    /// are `Empty` but still have the index and rect they would have if the
+    /// room extended that far.
+    pub fn tile_at_index(&self, x: i32, y: i32) -> Tile {
+        let in_bounds = x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height;
+        let kind = if in_bounds {
             self.tiles[self.width as usize * y as usize + x as usize]
         } else {
             TileKind::Empty
         };
-        let rect = Rect::new(
-            (x * self.tile_size) as f32,
-            (y * self.tile_size) as f32,
-            self.tile_size as f32,
-            self.tile_size as f32,
-        );
+        let rect = TileCoord::new(x, y).rect(self.tile_size);
         Tile { x, y, kind, rect }
     }
 
+    /// Returns which sides of the tile at (`x`, `y`) border an empty tile, with
+    /// tiles outside the room counting as empty like in `tile_at_index`.
+    pub fn exposed_edges(&self, x: i32, y: i32) -> ExposedEdges {
+        let is_empty = |dx, dy| self.tile_at_index(x + dx, y + dy).kind == TileKind::Empty;
+        ExposedEdges {
+            top: is_empty(0, -1),
+            bottom: is_empty(0, 1),
+            left: is_empty(-1, 0),
+            right: is_empty(1, 0),
+        }
+    }
+
+    /// Returns the indices of all tiles in the room whose kind `matches`, row by row.
+    pub fn tile_indices<F: Fn(TileKind) -> bool>(&self, matches: F) -> Vec<(u32, u32)> {
+        let mut indices = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if matches(self.tiles[(self.width * y + x) as usize]) {
+                    indices.push((x, y));
+                }
+            }
+        }
+        indices
+    }
+
+    /// Returns the indices of the tiles of the same kind as the one at (`x`, `y`) that
+    /// are connected to it through their sides, including that tile itself.
+    pub fn connected_tiles(&self, x: u32, y: u32) -> Vec<(u32, u32)> {
+        let kind = self.tile_at_index(x as i32, y as i32).kind;
+        let mut connected = vec![(x, y)];
+        let mut next = 0;
+        while next < connected.len() {
+            let (x, y) = connected[next];
+            next += 1;
+            let neighbours = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+            for &(dx, dy) in &neighbours {
+                let (neighbour_x, neighbour_y) = (x as i32 + dx, y as i32 + dy);
+                let in_bounds = neighbour_x >= 0 && neighbour_y >= 0
+                    && (neighbour_x as u32) < self.width
+                    && (neighbour_y as u32) < self.height;
+                let index = (neighbour_x as u32, neighbour_y as u32);
+                if in_bounds && self.tile_at_index(neighbour_x, neighbour_y).kind == kind
+                    && !connected.contains(&index)
+                {
+                    connected.push(index);
+                }
+            }
+        }
+        connected
+    }
+
+    /// Returns the tile containing the given world coordinates.
+    ///
+    /// Coordinates are floored to tile indices, so e.g. `-0.5` lies in the
+    /// tile with index -1 rather than in tile 0.
     pub fn tile_at_coord(&self, x: f32, y: f32) -> Tile {
-        self.tile_at_index(x as u32 / self.tile_size, y as u32 / self.tile_size)
+        let coord = TileCoord::containing(Vec2::new(x, y), self.tile_size);
+        self.tile_at_index(coord.x, coord.y)
     }
 
-    pub fn toggle_tile_at_index(&mut self, x: u32, y: u32) -> Result<(), Error> {
+    /// Returns all tiles inside the room that overlap the given rect.
+    ///
+    /// Tiles that merely touch the rect's edges are not included.
+    pub fn tiles_in_rect(&self, rect: &Rect) -> Vec<Tile> {
+        let tile_size = self.tile_size as f32;
+        let first = TileCoord::containing(Vec2::new(rect.left(), rect.top()), self.tile_size);
+        let (first_x, first_y) = (first.x.max(0) as u32, first.y.max(0) as u32);
+        let end_x = ((rect.right() / tile_size).ceil().max(0.0) as u32).min(self.width);
+        let end_y = ((rect.bottom() / tile_size).ceil().max(0.0) as u32).min(self.height);
+        let mut tiles = Vec::new();
+        for y in first_y..end_y {
+            for x in first_x..end_x {
+                tiles.push(self.tile_at_index(x as i32, y as i32));
+            }
+        }
+        tiles
+    }
+
+    /// Returns all tiles inside the room that overlap the given circle.
+    ///
+    /// Like in `tiles_in_rect`, tiles that merely touch it are not included.
+    pub fn tiles_in_circle(&self, circle: &Circle) -> Vec<Tile> {
+        let mut tiles = self.tiles_in_rect(&circle.bounding_rect());
+        tiles.retain(|tile| circle.intersects_rect(&tile.rect));
+        tiles
+    }
+
+    /// Returns the tiles a ray from (`x`, `y`) in the direction (`dx`, `dy`) passes
+    /// through until it's gone `max_distance` pixels, in order, including tiles
+    /// outside the room. A ray without a direction passes through no tiles.
+    pub fn ray_tiles(&self, x: f32, y: f32, dx: f32, dy: f32, max_distance: f32) -> RayTiles {
+        RayTiles::new(self.tile_size, (x, y), (dx, dy), max_distance)
+    }
+
+    /// Casts a ray from (`x`, `y`) in the direction (`dx`, `dy`), returning where it
+    /// first enters a solid tile, if that's within `max_distance` pixels. A ray
+    /// starting inside a solid tile hits it straight away, without a side.
+    ///
+    /// The ray is followed tile by tile, so thin gaps and corners aren't skipped.
+    pub fn raycast(&self, x: f32, y: f32, dx: f32, dy: f32, max_distance: f32) -> Option<RayTile> {
+        self.ray_tiles(x, y, dx, dy, max_distance)
+            .find(|tile| self.tile_at_index(tile.x, tile.y).kind.is_solid())
+    }
+
+    /// Changes the tile at the given index to `kind`.
+    pub fn set_tile_at_index(&mut self, x: u32, y: u32, kind: TileKind) -> Result<(), Error> {
         ensure!(
             x < self.width && y < self.height,
             "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
@@ -75,29 +535,294 @@ impl Room {
             self.width,
             self.height
         );
-        let kind = &mut self.tiles[(self.width * y) as usize + x as usize];
-        *kind = match *kind {
-            TileKind::Empty => TileKind::Filled,
-            TileKind::Filled => TileKind::Empty,
-        };
+        let tile = &mut self.tiles[(self.width * y) as usize + x as usize];
+        if *tile != kind {
+            *tile = kind;
+            self.generation = next_generation();
+        }
+        Ok(())
+    }
+
+    /// Changes the tile at the given index to `kind`, or back to `Empty` if it
+    /// already is of that kind.
+    pub fn toggle_tile_at_index(&mut self, x: u32, y: u32, kind: TileKind) -> Result<(), Error> {
+        ensure!(
+            x < self.width && y < self.height,
+            "Tile index ({}, {}) out of bounds for room dimensions {}×{}",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let tile = &mut self.tiles[(self.width * y) as usize + x as usize];
+        *tile = if *tile == kind { TileKind::Empty } else { kind };
+        self.generation = next_generation();
+        Ok(())
+    }
+
+    /// Draws the tiles the camera sees, using the art from the context's tileset for
+    /// the kinds of tiles it maps, and plain colors otherwise.
+    ///
+    /// The tiles are copied from the context's room cache, which is only redrawn
+    /// when they change, unless the canvas can't draw into textures. Animated tiles
+    /// are drawn on top as they look `clock` seconds into their animations.
+    pub fn render<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        context: &mut RenderContext<T::Context>,
+        clock: f32,
+    ) -> Result<(), Error> {
+        let (tileset, theme) = (context.tileset.as_ref(), &context.theme);
+        // Redrawing the cache resets the scale and viewport if the canvas is drawing
+        // into a texture itself, so it has to come before setting them up
+        let cached = context.room_cache.update(canvas, self, tileset, theme);
+        camera.letterbox(canvas, theme.letterbox())?;
+        // Clearing would paint over the letterbox bars, so fill only the view
+        let (width, height) = camera.logical_size();
+        canvas.set_draw_color(theme.empty());
+        canvas
+            .fill_rect(SdlRect::new(0, 0, width, height))
+            .map_err(err_msg)?;
+        match cached {
+            Some(texture) => {
+                // Offset by the rounded camera position, like `Camera::sdl_rect` does
+                let (left, top) = (camera.left().round() as i32, camera.top().round() as i32);
+                let view = SdlRect::new(left, top, width, height);
+                // Copy only the part inside the room, since a source rect sticking out
+                // of the texture would be cut down and stretched to fill the view
+                let (room_width, room_height) =
+                    (self.width * self.tile_size, self.height * self.tile_size);
+                let room = SdlRect::new(0, 0, room_width, room_height);
+                if let Some(source) = view.intersection(room) {
+                    let target = SdlRect::new(
+                        source.x() - left,
+                        source.y() - top,
+                        source.width(),
+                        source.height(),
+                    );
+                    canvas.copy(texture, source, target).map_err(err_msg)?;
+                }
+            }
+            None => self.render_tiles(canvas, camera, tileset, theme)?,
+        }
+        self.render_animated_tiles(canvas, camera, tileset, theme, clock)
+    }
+
+    /// Draws the animated tiles the camera sees as they look at `clock` seconds,
+    /// over how `render_tiles` drew them. Tiles drawn with tile art don't animate.
+    ///
+    /// The clock is passed in rather than read here, so that tiles only animate
+    /// while the game does.
+    fn render_animated_tiles<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        tileset: Option<&Tileset>,
+        theme: &Theme,
+        clock: f32,
+    ) -> Result<(), Error> {
+        let (columns, rows) = self.visible_tiles(camera);
+        for row in rows {
+            for column in columns.clone() {
+                let tile = self.tile_at_index(column as i32, row as i32);
+                let animation = match tile_animation(tile.kind) {
+                    Some(animation) => animation,
+                    None => continue,
+                };
+                if let Some(tileset) = tileset {
+                    if tileset.source_rect(tile.kind).is_some() {
+                        continue;
+                    }
+                }
+                let amount = animation.shades[animation.frame(clock)];
+                if amount != 0.0 {
+                    canvas.set_draw_color(shade(tile_color(tile.kind, theme), amount));
+                    canvas.fill_rect(camera.sdl_rect(&tile.rect)).map_err(err_msg)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the columns and rows of the tiles the camera sees.
+    pub fn visible_tiles(&self, camera: &Camera) -> (Range<u32>, Range<u32>) {
+        let size = self.tile_size as f32;
+        let (view_width, view_height) = camera.logical_size();
+        let first_column = (camera.left() / size).floor().max(0.0) as u32;
+        let first_row = (camera.top() / size).floor().max(0.0) as u32;
+        let end_column = ((camera.left() + view_width as f32) / size).ceil().max(0.0) as u32;
+        let end_row = ((camera.top() + view_height as f32) / size).ceil().max(0.0) as u32;
+        (
+            first_column..end_column.min(self.width),
+            first_row..end_row.min(self.height),
+        )
+    }
+
+    /// Draws a light strip along the top and left sides of the filled tiles the
+    /// camera sees where they border empty tiles, and a dark one along the bottom
+    /// and right sides, so that surfaces stand out. Tiles drawn with tile art are
+    /// left alone.
+    fn render_edges<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        tileset: Option<&Tileset>,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        if tileset.and_then(|tileset| tileset.source_rect(TileKind::Filled)).is_some() {
+            return Ok(());
+        }
+        let (columns, rows) = self.visible_tiles(camera);
+        let width = (self.tile_size / 16).max(1) as f32;
+        let size = self.tile_size as f32;
+        let mut light = Vec::new();
+        let mut dark = Vec::new();
+        for row in rows {
+            for column in columns.clone() {
+                if self.tiles[(self.width * row + column) as usize] != TileKind::Filled {
+                    continue;
+                }
+                let (left, top) = (column as f32 * size, row as f32 * size);
+                let edges = self.exposed_edges(column as i32, row as i32);
+                if edges.top {
+                    light.push(Rect::new(left, top, size, width));
+                }
+                if edges.left {
+                    light.push(Rect::new(left, top, width, size));
+                }
+                if edges.bottom {
+                    dark.push(Rect::new(left, top + size - width, size, width));
+                }
+                if edges.right {
+                    dark.push(Rect::new(left + size - width, top, width, size));
+                }
+            }
+        }
+        for &(ref rects, amount) in &[(light, 0.3), (dark, -0.3)] {
+            let rects: Vec<SdlRect> = rects.iter().map(|rect| camera.sdl_rect(rect)).collect();
+            canvas.set_draw_color(shade(theme.filled(), amount));
+            canvas.fill_rects(&rects).map_err(err_msg)?;
+        }
         Ok(())
     }
 
-    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<(), Error> {
-        canvas.set_logical_size(self.width * self.tile_size, self.height * self.tile_size)?;
-        canvas.set_draw_color(Color::RGB(0x20, 0x20, 0x20));
-        canvas.clear();
-        for (i, tile) in self.tiles.iter().enumerate() {
-            let x = i as i32 % self.width as i32 * self.tile_size as i32;
-            let y = i as i32 / self.width as i32 * self.tile_size as i32;
-            let tile_color = match *tile {
-                TileKind::Empty => Color::RGB(0x00, 0x00, 0x00),
-                TileKind::Filled => Color::RGB(0x80, 0x80, 0x80),
-            };
-            canvas.set_draw_color(tile_color);
-            canvas
-                .fill_rect(SdlRect::new(x, y, self.tile_size, self.tile_size))
-                .map_err(err_msg)?;
+    /// Returns the rects covering the tiles in `columns` and `rows`, grouped by the
+    /// color `color` gives their kind, with colors in the order they first appear.
+    /// Tiles without a color are skipped, and runs of tiles in the same color along
+    /// a row are covered by one rect.
+    fn tile_runs<F>(
+        &self,
+        columns: Range<u32>,
+        rows: Range<u32>,
+        color: F,
+    ) -> Vec<(Color, Vec<Rect>)>
+    where
+        F: Fn(TileKind) -> Option<Color>,
+    {
+        let size = self.tile_size as f32;
+        let end_column = columns.end;
+        let mut runs: Vec<(Color, Vec<Rect>)> = Vec::new();
+        for row in rows {
+            let mut run: Option<(Color, u32)> = None;
+            // One column past the end to finish the last run
+            for column in columns.start..end_column + 1 {
+                let color = if column < end_column {
+                    color(self.tiles[(self.width * row + column) as usize])
+                } else {
+                    None
+                };
+                if run.map(|(run_color, _)| run_color) == color {
+                    continue;
+                }
+                if let Some((run_color, start)) = run {
+                    let (x, y) = (start as f32 * size, row as f32 * size);
+                    let rect = Rect::new(x, y, (column - start) as f32 * size, size);
+                    match runs.iter().position(|&(other, _)| other == run_color) {
+                        Some(index) => runs[index].1.push(rect),
+                        None => runs.push((run_color, vec![rect])),
+                    }
+                }
+                run = color.map(|color| (color, column));
+            }
+        }
+        runs
+    }
+
+    /// Draws the tiles the camera sees onto a view already filled with the theme's
+    /// empty color.
+    ///
+    /// Tiles in that color are skipped, and runs of tiles in the same color along a
+    /// row are drawn as one rect, with all rects of a color drawn in one call.
+    pub fn render_tiles<T: RenderTarget>(
+        &self,
+        canvas: &mut Canvas<T>,
+        camera: &Camera,
+        tileset: Option<&Tileset>,
+        theme: &Theme,
+    ) -> Result<(), Error> {
+        let size = self.tile_size as f32;
+        let (columns, rows) = self.visible_tiles(camera);
+        let (first_column, end_column) = (columns.start, columns.end);
+        let (first_row, end_row) = (rows.start, rows.end);
+        let runs = self.tile_runs(columns, rows, |kind| {
+            let textured = tileset
+                .and_then(|tileset| tileset.source_rect(kind))
+                .is_some();
+            let color = tile_color(kind, theme);
+            Some(color).filter(|&color| !textured && color != theme.empty())
+        });
+        for (color, rects) in runs {
+            let rects: Vec<SdlRect> = rects.iter().map(|rect| camera.sdl_rect(rect)).collect();
+            canvas.set_draw_color(color);
+            canvas.fill_rects(&rects).map_err(err_msg)?;
+        }
+        self.render_edges(canvas, camera, tileset, theme)?;
+
+        for row in first_row..end_row {
+            for column in first_column..end_column {
+                let tile = self.tiles[(self.width * row + column) as usize];
+                let rect = TileCoord::new(column as i32, row as i32).rect(self.tile_size);
+                let source = tileset.and_then(|tileset| tileset.source_rect(tile));
+                if let (Some(tileset), Some(source)) = (tileset, source) {
+                    // The background shows through the transparent parts of the tile
+                    canvas
+                        .copy(tileset.texture(), source, camera.sdl_rect(&rect))
+                        .map_err(err_msg)?;
+                }
+                match tile {
+                    TileKind::ToggleBlockOff(channel) => {
+                        canvas.set_draw_color(theme.id_color(channel));
+                        canvas.draw_rect(camera.sdl_rect(&rect)).map_err(err_msg)?;
+                    }
+                    TileKind::Switch { channel, .. } => {
+                        let quarter = size / 4.0;
+                        let (left, top) = (rect.left() + quarter, rect.top() + quarter);
+                        let button = Rect::new(left, top, 2.0 * quarter, 2.0 * quarter);
+                        canvas.set_draw_color(theme.id_color(channel));
+                        canvas.fill_rect(camera.sdl_rect(&button)).map_err(err_msg)?;
+                    }
+                    _ => (),
+                }
+                if source.is_none() && (tile == TileKind::SlopeUp || tile == TileKind::SlopeDown) {
+                    // Fill the part below the surface one pixel column at a time
+                    let slope = Tile {
+                        x: column as i32,
+                        y: row as i32,
+                        kind: tile,
+                        rect,
+                    };
+                    canvas.set_draw_color(theme.filled());
+                    for pixel in 0..self.tile_size {
+                        let column_x = rect.left() + pixel as f32;
+                        let top = slope.surface_at(column_x + 0.5).unwrap_or(rect.top());
+                        let column_rect = Rect::new(column_x, top, 1.0, rect.bottom() - top);
+                        canvas
+                            .fill_rect(camera.sdl_rect(&column_rect))
+                            .map_err(err_msg)?;
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -122,8 +847,75 @@ impl Room {
             room.width,
             room.height
         );
+        for (id, count) in room.teleporter_counts() {
+            if count != 2 {
+                warn!("Teleporter pair {} has {} tiles instead of 2; it won't work", id, count);
+            }
+        }
         Ok(room)
     }
+
+    /// Returns each teleporter pair id in use with how many tiles have it.
+    fn teleporter_counts(&self) -> Vec<(u32, u32)> {
+        let mut counts: Vec<(u32, u32)> = Vec::new();
+        for tile in &self.tiles {
+            if let TileKind::Teleporter(id) = *tile {
+                match counts.iter().position(|&(counted, _)| counted == id) {
+                    Some(index) => counts[index].1 += 1,
+                    None => counts.push((id, 1)),
+                }
+            }
+        }
+        counts
+    }
+
+    /// Returns the teleporter tile linked to `tile`, if it's a teleporter with
+    /// exactly one partner.
+    pub fn teleporter_destination(&self, tile: &Tile) -> Option<Tile> {
+        let id = match tile.kind {
+            TileKind::Teleporter(id) => id,
+            _ => return None,
+        };
+        let mut partners = self.tiles.iter().enumerate().filter(|&(_, &kind)| {
+            kind == TileKind::Teleporter(id)
+        });
+        let (first, second) = (partners.next(), partners.next());
+        if partners.next().is_some() {
+            return None;
+        }
+        let index = match (first, second) {
+            (Some((first, _)), Some((second, _))) => {
+                let own_index = self.width as usize * tile.y as usize + tile.x as usize;
+                if first == own_index {
+                    second
+                } else {
+                    first
+                }
+            }
+            _ => return None,
+        };
+        let width = self.width as usize;
+        Some(self.tile_at_index((index % width) as i32, (index / width) as i32))
+    }
+
+    /// Adds a teleporter at the given index, or removes it if there already is one.
+    ///
+    /// The new teleporter is paired with a teleporter that has no partner yet if
+    /// there is one, and otherwise starts a new pair.
+    pub fn toggle_teleporter_at_index(&mut self, x: u32, y: u32) -> Result<(), Error> {
+        let kind = self.tile_at_index(x as i32, y as i32).kind;
+        let id = match kind {
+            TileKind::Teleporter(id) => id,
+            _ => {
+                let counts = self.teleporter_counts();
+                match counts.iter().find(|&&(_, count)| count == 1) {
+                    Some(&(id, _)) => id,
+                    None => counts.iter().map(|&(id, _)| id + 1).max().unwrap_or(0),
+                }
+            }
+        };
+        self.toggle_tile_at_index(x, y, TileKind::Teleporter(id))
+    }
 }
 
 impl Default for Room {
@@ -132,15 +924,651 @@ impl Default for Room {
     }
 }
 
+/// Sides of a tile that border an empty tile, which are shaded to show where
+/// surfaces are.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ExposedEdges {
+    pub top: bool,
+    pub bottom: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
 pub struct Tile {
-    pub x: u32,
-    pub y: u32,
+    pub x: i32,
+    pub y: i32,
     pub kind: TileKind,
     pub rect: Rect,
 }
 
-#[derive(Clone, Copy, Deserialize, Eq, PartialEq, Serialize)]
+impl Tile {
+    /// Returns the height of a slope tile's surface at horizontal position `x`,
+    /// or `None` if the tile isn't a slope.
+    ///
+    /// Positions outside the tile are treated as lying on its nearest edge.
+    pub fn surface_at(&self, x: f32) -> Option<f32> {
+        let size = self.rect.right() - self.rect.left();
+        let offset = (x - self.rect.left()).min(size).max(0.0);
+        match self.kind {
+            TileKind::SlopeUp => Some(self.rect.bottom() - offset),
+            TileKind::SlopeDown => Some(self.rect.top() + offset),
+            TileKind::Empty
+            | TileKind::Filled
+            | TileKind::Goal
+            | TileKind::GravityFlip
+            | TileKind::Bounce
+            | TileKind::Door
+            | TileKind::Blink
+            | TileKind::BlinkOff
+            | TileKind::Teleporter(_)
+            | TileKind::Crumble
+            | TileKind::Switch { .. }
+            | TileKind::ToggleBlock(_)
+            | TileKind::ToggleBlockOff(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum TileKind {
     Empty,
     Filled,
+    /// Reaching this tile completes the room
+    Goal,
+    /// 45 degree slope rising from the bottom left to the top right corner
+    SlopeUp,
+    /// 45 degree slope falling from the top left to the bottom right corner
+    SlopeDown,
+    /// Touching this tile turns gravity upside down, or back again
+    GravityFlip,
+    /// Solid tile that launches the player back up when landed on
+    Bounce,
+    /// Solid tile that opens when touched while holding a key, using up the key
+    Door,
+    /// Tile that is solid and intangible by turns, see `Room::blink_state`
+    Blink,
+    /// Blink tile while it's intangible
+    BlinkOff,
+    /// Entering this tile moves the player to the other teleporter with the same pair id
+    Teleporter(u32),
+    /// Solid tile that collapses after being stood on for a while
+    Crumble,
+    /// Touching this tile flips the toggle blocks on its channel between solid and
+    /// intangible. A one-shot switch only works once per run through the room.
+    Switch { channel: u32, one_shot: bool },
+    /// Solid tile that the switches on its channel make intangible, or solid again
+    ToggleBlock(u32),
+    /// Toggle block while it's intangible
+    ToggleBlockOff(u32),
+}
+
+impl TileKind {
+    /// Returns the name of the kind of tile without its data, e.g. `"Switch"` for
+    /// every switch regardless of its channel.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TileKind::Empty => "Empty",
+            TileKind::Filled => "Filled",
+            TileKind::Goal => "Goal",
+            TileKind::SlopeUp => "SlopeUp",
+            TileKind::SlopeDown => "SlopeDown",
+            TileKind::GravityFlip => "GravityFlip",
+            TileKind::Bounce => "Bounce",
+            TileKind::Door => "Door",
+            TileKind::Blink => "Blink",
+            TileKind::BlinkOff => "BlinkOff",
+            TileKind::Teleporter(_) => "Teleporter",
+            TileKind::Crumble => "Crumble",
+            TileKind::Switch { .. } => "Switch",
+            TileKind::ToggleBlock(_) => "ToggleBlock",
+            TileKind::ToggleBlockOff(_) => "ToggleBlockOff",
+        }
+    }
+
+    /// Returns whether the tile blocks movement from all sides.
+    pub fn is_solid(&self) -> bool {
+        match *self {
+            TileKind::Filled
+            | TileKind::Bounce
+            | TileKind::Door
+            | TileKind::Blink
+            | TileKind::Crumble
+            | TileKind::ToggleBlock(_) => true,
+            TileKind::Empty
+            | TileKind::Goal
+            | TileKind::SlopeUp
+            | TileKind::SlopeDown
+            | TileKind::GravityFlip
+            | TileKind::BlinkOff
+            | TileKind::Teleporter(_)
+            | TileKind::Switch { .. }
+            | TileKind::ToggleBlockOff(_) => false,
+        }
+    }
+
+    /// Returns the channel of a toggle block, whether it's solid or not.
+    pub fn toggle_channel(&self) -> Option<u32> {
+        match *self {
+            TileKind::ToggleBlock(channel) | TileKind::ToggleBlockOff(channel) => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+/// How a kind of tile animates, by cycling through shades of its color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileAnimation {
+    /// Seconds each frame is shown
+    pub frame_time: f32,
+    /// How much each frame lightens the tile's color, or darkens it if negative,
+    /// as `shade` takes it
+    pub shades: &'static [f32],
+}
+
+impl TileAnimation {
+    /// Returns the index of the frame shown `clock` seconds into the animation,
+    /// which loops forever.
+    pub fn frame(&self, clock: f32) -> usize {
+        if self.shades.is_empty() || self.frame_time <= 0.0 {
+            return 0;
+        }
+        let frame = (clock.max(0.0) / self.frame_time).floor() as usize;
+        frame % self.shades.len()
+    }
+}
+
+/// Returns how tiles of `kind` animate, if they do. This is the one place listing
+/// the animated kinds of tiles.
+pub fn tile_animation(kind: TileKind) -> Option<TileAnimation> {
+    match kind {
+        // Pulse brighter and back, to draw the eye
+        TileKind::Goal => Some(TileAnimation {
+            frame_time: 0.12,
+            shades: &[0.0, 0.1, 0.2, 0.3, 0.2, 0.1],
+        }),
+        // Shimmer between lighter and darker
+        TileKind::Teleporter(_) => Some(TileAnimation {
+            frame_time: 0.2,
+            shades: &[0.0, 0.25, 0.0, -0.25],
+        }),
+        _ => None,
+    }
+}
+
+/// Returns `color` moved `amount` of the way towards white if it's positive, or
+/// towards black if it's negative.
+fn shade(color: Color, amount: f32) -> Color {
+    let target = if amount > 0.0 { 255.0 } else { 0.0 };
+    let amount = amount.abs().min(1.0);
+    let channel = |value: u8| {
+        let value = f32::from(value);
+        (value + (target - value) * amount).round() as u8
+    };
+    Color::RGB(channel(color.r), channel(color.g), channel(color.b))
+}
+
+/// Returns the color `kind` of tile is drawn in without a tileset.
+pub fn tile_color(kind: TileKind, theme: &Theme) -> Color {
+    match kind {
+        TileKind::Empty | TileKind::SlopeUp | TileKind::SlopeDown => theme.empty(),
+        TileKind::Blink => theme.blink(),
+        TileKind::BlinkOff => theme.blink_off(),
+        TileKind::Teleporter(id) | TileKind::ToggleBlock(id) => theme.id_color(id),
+        TileKind::Switch { .. } => theme.switch(),
+        TileKind::ToggleBlockOff(_) => theme.empty(),
+        TileKind::Crumble => theme.crumble(),
+        TileKind::Filled => theme.filled(),
+        TileKind::Goal => theme.goal(),
+        TileKind::GravityFlip => theme.gravity_flip(),
+        TileKind::Bounce => theme.bounce(),
+        TileKind::Door => theme.door(),
+    }
+}
+
+/// A link from an edge of a room to another room.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Exit {
+    pub edge: Edge,
+    /// File path of the room this exit leads to
+    pub room: String,
+}
+
+/// A tile a ray passes through, see `Room::ray_tiles`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayTile {
+    /// Index of the tile
+    pub x: i32,
+    pub y: i32,
+    /// Point at which the ray enters the tile, which is where it starts for the
+    /// first tile
+    pub point: (f32, f32),
+    /// Distance along the ray to `point`
+    pub distance: f32,
+    /// Side of the tile through which the ray enters it, or `None` for the tile the
+    /// ray starts in
+    pub side: Option<Edge>,
+}
+
+/// Iterator over the tiles along a ray, stepping from each tile to whichever
+/// neighbour the ray crosses into first (a digital differential analyzer).
+///
+/// When the ray passes exactly through a corner, it steps vertically first.
+#[derive(Clone, Debug)]
+pub struct RayTiles {
+    tile_size: f32,
+    origin: (f32, f32),
+    /// Direction of the ray with a length of 1
+    direction: (f32, f32),
+    max_distance: f32,
+    /// Tile to be returned next, if the ray isn't done yet
+    next: Option<RayTile>,
+    /// Distances along the ray to the next tile boundary on each axis
+    next_boundary: (f32, f32),
+}
+
+impl RayTiles {
+    fn new(
+        tile_size: u32,
+        origin: (f32, f32),
+        direction: (f32, f32),
+        max_distance: f32,
+    ) -> RayTiles {
+        let (x, y) = origin;
+        let length = (direction.0 * direction.0 + direction.1 * direction.1).sqrt();
+        let (dx, dy) = if length > 0.0 {
+            (direction.0 / length, direction.1 / length)
+        } else {
+            (0.0, 0.0)
+        };
+        let start = TileCoord::containing(Vec2::new(x, y), tile_size);
+        let (tile_x, tile_y) = (start.x, start.y);
+        let tile_size = tile_size as f32;
+        // Distance along the ray to the first tile boundary crossed on an axis
+        let first_boundary = |pos: f32, tile: i32, dir: f32| {
+            if dir > 0.0 {
+                ((tile + 1) as f32 * tile_size - pos) / dir
+            } else if dir < 0.0 {
+                (tile as f32 * tile_size - pos) / dir
+            } else {
+                f32::INFINITY
+            }
+        };
+        let next = if length > 0.0 && max_distance >= 0.0 {
+            Some(RayTile {
+                x: tile_x,
+                y: tile_y,
+                point: origin,
+                distance: 0.0,
+                side: None,
+            })
+        } else {
+            None
+        };
+        RayTiles {
+            tile_size,
+            origin,
+            direction: (dx, dy),
+            max_distance,
+            next,
+            next_boundary: (first_boundary(x, tile_x, dx), first_boundary(y, tile_y, dy)),
+        }
+    }
+}
+
+impl Iterator for RayTiles {
+    type Item = RayTile;
+
+    fn next(&mut self) -> Option<RayTile> {
+        let current = self.next?;
+        let (dx, dy) = self.direction;
+        let (next_x, next_y) = self.next_boundary;
+        let (x, y, distance, side) = if next_x < next_y {
+            self.next_boundary.0 += self.tile_size / dx.abs();
+            if dx > 0.0 {
+                (current.x + 1, current.y, next_x, Edge::Left)
+            } else {
+                (current.x - 1, current.y, next_x, Edge::Right)
+            }
+        } else {
+            self.next_boundary.1 += self.tile_size / dy.abs();
+            if dy > 0.0 {
+                (current.x, current.y + 1, next_y, Edge::Top)
+            } else {
+                (current.x, current.y - 1, next_y, Edge::Bottom)
+            }
+        };
+        self.next = if distance <= self.max_distance {
+            let (origin_x, origin_y) = self.origin;
+            Some(RayTile {
+                x,
+                y,
+                point: (origin_x + dx * distance, origin_y + dy * distance),
+                distance,
+                side: Some(side),
+            })
+        } else {
+            None
+        };
+        Some(current)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    /// Returns the direction in which a room is left through this edge.
+    pub fn direction(self) -> Direction {
+        match self {
+            Edge::Left => Direction::Left,
+            Edge::Right => Direction::Right,
+            Edge::Top => Direction::Up,
+            Edge::Bottom => Direction::Down,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the index and entry side of the first `count` tiles along a ray.
+    fn ray_path(
+        room: &Room,
+        origin: (f32, f32),
+        direction: (f32, f32),
+        count: usize,
+    ) -> Vec<(i32, i32, Option<Edge>)> {
+        room.ray_tiles(origin.0, origin.1, direction.0, direction.1, 1000.0)
+            .take(count)
+            .map(|tile| (tile.x, tile.y, tile.side))
+            .collect()
+    }
+
+    #[test]
+    fn ray_steps_through_each_octant() {
+        let room = Room::new(4, 4, 16);
+        for &(sx, sy) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let side_x = if sx > 0 { Edge::Left } else { Edge::Right };
+            let side_y = if sy > 0 { Edge::Top } else { Edge::Bottom };
+            // Mostly horizontal
+            let direction = (2.0 * sx as f32, sy as f32);
+            assert_eq!(
+                ray_path(&room, (8.0, 8.0), direction, 4),
+                vec![
+                    (0, 0, None),
+                    (sx, 0, Some(side_x)),
+                    (sx, sy, Some(side_y)),
+                    (2 * sx, sy, Some(side_x)),
+                ],
+                "direction {:?}",
+                direction
+            );
+            // Mostly vertical
+            let direction = (sx as f32, 2.0 * sy as f32);
+            assert_eq!(
+                ray_path(&room, (8.0, 8.0), direction, 4),
+                vec![
+                    (0, 0, None),
+                    (0, sy, Some(side_y)),
+                    (sx, sy, Some(side_x)),
+                    (sx, 2 * sy, Some(side_y)),
+                ],
+                "direction {:?}",
+                direction
+            );
+        }
+    }
+
+    #[test]
+    fn axis_aligned_ray_stays_in_its_row() {
+        let room = Room::new(4, 4, 16);
+        let tiles: Vec<_> = room.ray_tiles(8.0, 8.0, 3.0, 0.0, 40.0).collect();
+        let indices: Vec<_> = tiles.iter().map(|tile| (tile.x, tile.y)).collect();
+        assert_eq!(indices, vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        let distances: Vec<_> = tiles.iter().map(|tile| tile.distance).collect();
+        assert_eq!(distances, vec![0.0, 8.0, 24.0, 40.0]);
+        assert_eq!(tiles[1].point, (16.0, 8.0));
+
+        assert_eq!(
+            ray_path(&room, (8.0, 8.0), (0.0, -1.0), 3),
+            vec![(0, 0, None), (0, -1, Some(Edge::Bottom)), (0, -2, Some(Edge::Bottom))]
+        );
+    }
+
+    #[test]
+    fn ray_from_tile_boundary_starts_in_the_tile_after_it() {
+        let room = Room::new(4, 4, 16);
+        let tiles: Vec<_> = room.ray_tiles(16.0, 8.0, 1.0, 0.0, 20.0).collect();
+        assert_eq!((tiles[0].x, tiles[0].y), (1, 0));
+        assert_eq!((tiles[1].x, tiles[1].y, tiles[1].distance), (2, 0, 16.0));
+        assert_eq!(tiles.len(), 2);
+
+        // Going left, the ray leaves the tile it starts in straight away
+        let tiles: Vec<_> = room.ray_tiles(16.0, 8.0, -1.0, 0.0, 20.0).collect();
+        assert_eq!((tiles[0].x, tiles[0].y), (1, 0));
+        assert_eq!((tiles[1].x, tiles[1].y, tiles[1].side), (0, 0, Some(Edge::Right)));
+        assert_eq!(tiles[1].distance, 0.0);
+    }
+
+    #[test]
+    fn ray_through_corner_steps_vertically_first() {
+        let room = Room::new(4, 4, 16);
+        assert_eq!(
+            ray_path(&room, (0.0, 0.0), (1.0, 1.0), 3),
+            vec![(0, 0, None), (0, 1, Some(Edge::Top)), (1, 1, Some(Edge::Left))]
+        );
+    }
+
+    #[test]
+    fn ray_without_direction_passes_through_nothing() {
+        let room = Room::new(4, 4, 16);
+        assert_eq!(room.ray_tiles(8.0, 8.0, 0.0, 0.0, 100.0).count(), 0);
+    }
+
+    #[test]
+    fn raycast_reports_side_hit() {
+        let mut room = Room::new(4, 4, 16);
+        room.set_tile_at_index(3, 1, TileKind::Filled).unwrap();
+        room.set_tile_at_index(0, 1, TileKind::Filled).unwrap();
+
+        let hit = room.raycast(24.0, 8.0, 0.0, 1.0, 100.0).unwrap();
+        assert_eq!((hit.x, hit.y, hit.side), (1, 3, Some(Edge::Top)));
+        assert_eq!((hit.point, hit.distance), ((24.0, 48.0), 40.0));
+
+        let hit = room.raycast(24.0, 24.0, 1.0, 0.0, 100.0).unwrap();
+        assert_eq!((hit.x, hit.y, hit.side), (3, 1, Some(Edge::Left)));
+        assert_eq!(hit.point, (48.0, 24.0));
+
+        let hit = room.raycast(40.0, 24.0, -1.0, 0.0, 100.0).unwrap();
+        assert_eq!((hit.x, hit.y, hit.side), (0, 1, Some(Edge::Right)));
+        assert_eq!(hit.point, (16.0, 24.0));
+
+        let hit = room.raycast(24.0, 8.0, 0.0, -1.0, 100.0);
+        assert!(hit.is_none(), "nothing solid above the room: {:?}", hit);
+    }
+
+    #[test]
+    fn raycast_stops_at_max_distance() {
+        let room = Room::new(4, 4, 16);
+        assert!(room.raycast(8.0, 8.0, 0.0, 1.0, 39.0).is_none());
+        assert!(room.raycast(8.0, 8.0, 0.0, 1.0, 40.0).is_some());
+    }
+
+    #[test]
+    fn exposed_edges_border_empty_tiles_and_the_room_edges() {
+        let mut room = Room::new(4, 4, 16);
+        for &(x, y) in &[(1, 1), (2, 1), (1, 2)] {
+            room.set_tile_at_index(x, y, TileKind::Filled).unwrap();
+        }
+        let edges = |x, y| {
+            let edges = room.exposed_edges(x, y);
+            (edges.top, edges.bottom, edges.left, edges.right)
+        };
+        assert_eq!(edges(1, 1), (true, false, true, false));
+        assert_eq!(edges(2, 1), (true, true, false, true));
+        assert_eq!(edges(1, 2), (false, false, true, true));
+        // The floor's outer sides border the outside of the room
+        assert_eq!(edges(0, 3), (true, true, true, false));
+        assert_eq!(edges(3, 3), (true, true, false, true));
+    }
+
+    #[test]
+    fn tile_animation_frame_loops_with_the_clock() {
+        let animation = TileAnimation {
+            frame_time: 0.5,
+            shades: &[0.0, 0.1, 0.2],
+        };
+        let frames: Vec<usize> = [0.0, 0.49, 0.5, 1.0, 1.49, 1.5, 2.0, 3.25]
+            .iter()
+            .map(|&clock| animation.frame(clock))
+            .collect();
+        assert_eq!(frames, [0, 0, 1, 2, 2, 0, 1, 0]);
+        assert_eq!(animation.frame(-1.0), 0);
+    }
+
+    #[test]
+    fn tile_animation_without_frames_stays_on_the_first() {
+        let still = TileAnimation {
+            frame_time: 0.0,
+            shades: &[0.0, 0.1],
+        };
+        assert_eq!(still.frame(5.0), 0);
+        let empty = TileAnimation {
+            frame_time: 0.5,
+            shades: &[],
+        };
+        assert_eq!(empty.frame(5.0), 0);
+    }
+
+    #[test]
+    fn only_goals_and_teleporters_animate() {
+        assert!(tile_animation(TileKind::Goal).is_some());
+        assert!(tile_animation(TileKind::Teleporter(3)).is_some());
+        assert!(tile_animation(TileKind::Filled).is_none());
+        assert!(tile_animation(TileKind::Empty).is_none());
+        // The goal's pulse starts over once every frame has been shown
+        let goal = tile_animation(TileKind::Goal).unwrap();
+        let period = goal.frame_time * goal.shades.len() as f32;
+        assert_eq!(goal.frame(period + goal.frame_time / 2.0), 0);
+    }
+
+    #[test]
+    fn raycast_from_inside_solid_hits_without_side() {
+        let room = Room::new(4, 4, 16);
+        let hit = room.raycast(8.0, 56.0, 1.0, 0.0, 100.0).unwrap();
+        assert_eq!((hit.x, hit.y, hit.side, hit.distance), (0, 3, None, 0.0));
+    }
+
+    #[test]
+    fn tiles_in_circle_skip_corners_outside_it() {
+        let room = Room::new(4, 4, 16);
+        let indices = |circle| {
+            room.tiles_in_circle(&circle)
+                .iter()
+                .map(|tile| (tile.x, tile.y))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(indices(Circle::new(Vec2::new(8.0, 8.0), 8.0)), vec![(0, 0)]);
+        assert_eq!(
+            indices(Circle::new(Vec2::new(16.0, 16.0), 3.0)),
+            vec![(0, 0), (1, 0), (0, 1), (1, 1)]
+        );
+        assert_eq!(
+            indices(Circle::new(Vec2::new(20.0, 20.0), 5.0)),
+            vec![(1, 0), (0, 1), (1, 1)]
+        );
+    }
+
+    #[test]
+    fn tile_at_coord_floors_negative_coordinates() {
+        let mut room = Room::new(4, 4, 16);
+        room.set_tile_at_index(0, 0, TileKind::Filled).unwrap();
+
+        let tile = room.tile_at_coord(-0.5, 8.0);
+        assert_eq!((tile.x, tile.y, tile.kind), (-1, 0, TileKind::Empty));
+        assert_eq!((tile.rect.left(), tile.rect.right()), (-16.0, 0.0));
+        let tile = room.tile_at_coord(8.0, -0.5);
+        assert_eq!((tile.x, tile.y, tile.kind), (0, -1, TileKind::Empty));
+        let tile = room.tile_at_coord(-16.0, -16.0);
+        assert_eq!((tile.x, tile.y, tile.kind), (-1, -1, TileKind::Empty));
+        let tile = room.tile_at_coord(0.0, 0.0);
+        assert_eq!((tile.x, tile.y, tile.kind), (0, 0, TileKind::Filled));
+    }
+
+    #[test]
+    fn tile_at_coord_past_right_and_bottom_edges_is_empty() {
+        let room = Room::new(4, 4, 16);
+        let tile = room.tile_at_coord(63.9, 56.0);
+        assert_eq!((tile.x, tile.y, tile.kind), (3, 3, TileKind::Filled));
+        let tile = room.tile_at_coord(64.0, 56.0);
+        assert_eq!((tile.x, tile.y, tile.kind), (4, 3, TileKind::Empty));
+        let tile = room.tile_at_coord(8.0, 64.0);
+        assert_eq!((tile.x, tile.y, tile.kind), (0, 4, TileKind::Empty));
+        let tile = room.tile_at_coord(100.0, 100.0);
+        assert_eq!((tile.x, tile.y, tile.kind), (6, 6, TileKind::Empty));
+        assert_eq!((tile.rect.left(), tile.rect.top()), (96.0, 96.0));
+    }
+
+    /// Returns the runs of a room's tiles with filled tiles in red, bounce tiles in
+    /// blue and the rest skipped, as the number of tiles each rect covers.
+    fn run_lengths(room: &Room, columns: Range<u32>, rows: Range<u32>) -> Vec<(Color, Vec<f32>)> {
+        let runs = room.tile_runs(columns, rows, |kind| match kind {
+            TileKind::Filled => Some(Color::RGB(0xff, 0x00, 0x00)),
+            TileKind::Bounce => Some(Color::RGB(0x00, 0x00, 0xff)),
+            _ => None,
+        });
+        runs.into_iter()
+            .map(|(color, rects)| {
+                let tiles = rects.iter().map(|rect| (rect.right() - rect.left()) / 16.0);
+                (color, tiles.collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mostly_empty_room_is_drawn_with_few_rects() {
+        let room = Room::new(40, 30, 16);
+        let theme = Theme::default();
+        let runs = room.tile_runs(0..40, 0..30, |kind| {
+            Some(tile_color(kind, &theme)).filter(|&color| color != theme.empty())
+        });
+        assert_eq!(runs.len(), 1);
+        let (color, ref rects) = runs[0];
+        assert_eq!(color, theme.filled());
+        assert_eq!(rects.len(), 1);
+        assert_eq!(
+            (rects[0].left(), rects[0].top(), rects[0].right(), rects[0].bottom()),
+            (0.0, 29.0 * 16.0, 40.0 * 16.0, 30.0 * 16.0)
+        );
+    }
+
+    #[test]
+    fn tile_runs_split_at_other_kinds_and_gaps() {
+        let mut room = Room::new(8, 2, 16);
+        for &(x, kind) in &[(2, TileKind::Empty), (4, TileKind::Bounce), (5, TileKind::Bounce)] {
+            room.set_tile_at_index(x, 1, kind).unwrap();
+        }
+        room.set_tile_at_index(7, 0, TileKind::Filled).unwrap();
+        let red = Color::RGB(0xff, 0x00, 0x00);
+        let blue = Color::RGB(0x00, 0x00, 0xff);
+        assert_eq!(
+            run_lengths(&room, 0..8, 0..2),
+            vec![(red, vec![1.0, 2.0, 1.0, 2.0]), (blue, vec![2.0])]
+        );
+    }
+
+    #[test]
+    fn tile_runs_end_at_the_given_columns() {
+        let room = Room::new(8, 2, 16);
+        let red = Color::RGB(0xff, 0x00, 0x00);
+        assert_eq!(run_lengths(&room, 2..5, 0..2), vec![(red, vec![3.0])]);
+        let runs = room.tile_runs(2..5, 1..2, |_| Some(red));
+        assert_eq!(runs[0].1[0].left(), 32.0);
+    }
 }
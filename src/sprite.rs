@@ -0,0 +1,118 @@
+//! Animated sprites loaded from an image
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use failure::{err_msg, Error};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::surface::Surface;
+
+/// What the player is doing, which decides the frames they're drawn with.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Animation {
+    Idle,
+    Walk,
+    Jump,
+    Fall,
+}
+
+/// The frames of a sprite sheet each animation consists of, as stored in a JSON
+/// file, e.g. `{"image": "player.bmp", "frame_width": 8, "frame_height": 20,
+/// "frame_time": 0.1, "animations": {"Idle": [0], "Walk": [1, 2, 3, 2]}}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpriteMapping {
+    /// Path of the BMP image, relative to the mapping file
+    pub image: String,
+    /// Size of a frame in pixels; the frames are laid out in a single row
+    pub frame_width: u32,
+    pub frame_height: u32,
+    /// Seconds each frame of an animation is shown
+    pub frame_time: f32,
+    /// Indices of the frames of each animation, in order
+    pub animations: HashMap<Animation, Vec<u32>>,
+}
+
+/// A sprite sheet loaded into a texture, together with its animations.
+pub struct SpriteSheet<'r> {
+    texture: Texture<'r>,
+    mapping: SpriteMapping,
+}
+
+impl<'r> SpriteSheet<'r> {
+    /// Loads the sprite mapping at `path` and the image it refers to.
+    ///
+    /// Magenta pixels in the image are transparent.
+    pub fn load<T, P: AsRef<Path>>(
+        texture_creator: &'r TextureCreator<T>,
+        path: P,
+    ) -> Result<SpriteSheet<'r>, Error> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let mapping: SpriteMapping = ::serde_json::from_reader(reader)?;
+        ensure!(
+            mapping.frame_width > 0 && mapping.frame_height > 0,
+            "Sprite frame size must not be zero"
+        );
+        let image_path = path.parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&mapping.image);
+        let mut surface = Surface::load_bmp(&image_path).map_err(err_msg)?;
+        surface
+            .set_color_key(true, Color::RGB(0xff, 0x00, 0xff))
+            .map_err(err_msg)?;
+        let texture = texture_creator.create_texture_from_surface(&surface)?;
+        Ok(SpriteSheet { texture, mapping })
+    }
+
+    pub fn texture(&self) -> &Texture<'r> {
+        &self.texture
+    }
+
+    /// Returns the part of the image to draw `time` seconds into `animation`, or
+    /// `None` if neither it nor the idle animation has any frames.
+    pub fn source_rect(&self, animation: Animation, time: f32) -> Option<SdlRect> {
+        let frames = match self.mapping.animations.get(&animation) {
+            Some(frames) if !frames.is_empty() => frames,
+            _ => self.mapping.animations.get(&Animation::Idle)?,
+        };
+        let index = frame_index(frames.len(), self.mapping.frame_time, time);
+        let frame = *frames.get(index)?;
+        let (width, height) = (self.mapping.frame_width, self.mapping.frame_height);
+        Some(SdlRect::new((frame * width) as i32, 0, width, height))
+    }
+}
+
+/// Returns which of an animation's `frame_count` frames to show `time` seconds
+/// into it, showing each for `frame_time` seconds and starting over after the last.
+pub fn frame_index(frame_count: usize, frame_time: f32, time: f32) -> usize {
+    if frame_count == 0 || frame_time <= 0.0 || time <= 0.0 {
+        return 0;
+    }
+    (time / frame_time).floor() as usize % frame_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_index_cycles_through_frames() {
+        let frames: Vec<usize> = [0.05, 0.15, 0.25, 0.35, 0.45, 1.05]
+            .iter()
+            .map(|&time| frame_index(4, 0.1, time))
+            .collect();
+        assert_eq!(frames, vec![0, 1, 2, 3, 0, 2]);
+    }
+
+    #[test]
+    fn frame_index_without_frames_or_time_is_first_frame() {
+        assert_eq!(frame_index(0, 0.1, 0.25), 0);
+        assert_eq!(frame_index(4, 0.0, 0.25), 0);
+        assert_eq!(frame_index(4, 0.1, -0.25), 0);
+    }
+}
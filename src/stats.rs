@@ -0,0 +1,300 @@
+//! Measuring how often frames are drawn and ticks simulated, and how long frames take
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use failure::{err_msg, Error};
+use floating_duration::TimeAsFloat;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Canvas, RenderTarget};
+
+use font;
+use theme::Theme;
+
+/// Counts events over the last second, e.g. frames drawn, as a rolling average.
+#[derive(Clone, Debug, Default)]
+pub struct RateCounter {
+    /// When events happened and how many, oldest first
+    events: VecDeque<(Instant, u64)>,
+}
+
+impl RateCounter {
+    /// Records that `count` events happened at `time`, which must not be earlier
+    /// than previously recorded times.
+    pub fn record(&mut self, time: Instant, count: u64) {
+        self.events.push_back((time, count));
+        let window = Duration::from_secs(1);
+        while let Some(&(oldest, _)) = self.events.front() {
+            if time - oldest < window {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+
+    /// Returns the number of events recorded in the second before the last recorded time.
+    pub fn per_second(&self) -> u64 {
+        self.events.iter().map(|&(_, count)| count).sum()
+    }
+}
+
+/// Draws the frame and tick rates in the top left corner of the view.
+pub fn render_rates<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    frames_per_second: u64,
+    ticks_per_second: u64,
+    theme: &Theme,
+) -> Result<(), Error> {
+    const SCALE: u32 = 1;
+    const PADDING: u32 = 2;
+    let lines = [
+        format!("FPS {}", frames_per_second),
+        format!("TPS {}", ticks_per_second),
+    ];
+    let width = lines
+        .iter()
+        .map(|line| font::text_width(line, SCALE))
+        .max()
+        .unwrap_or(0) + 2 * PADDING;
+    let height = lines.len() as u32 * font::LINE_HEIGHT * SCALE + 2 * PADDING - 2 * SCALE;
+    canvas.set_draw_color(Color::RGBA(0x00, 0x00, 0x00, 0xa0));
+    canvas
+        .fill_rect(SdlRect::new(0, 0, width, height))
+        .map_err(err_msg)?;
+    canvas.set_draw_color(theme.rates_text());
+    for (index, line) in lines.iter().enumerate() {
+        let top = PADDING + index as u32 * font::LINE_HEIGHT * SCALE;
+        font::render_text(canvas, line, PADDING as i32, top as i32, SCALE)?;
+    }
+    Ok(())
+}
+
+/// How long a frame took, and how much of that was spent updating the model and
+/// rendering. The rest is mostly waiting for the next frame.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FrameTime {
+    pub update: Duration,
+    pub render: Duration,
+    pub total: Duration,
+}
+
+/// Keeps the times of the last few frames, to find hitches an average would hide.
+#[derive(Clone, Debug, Default)]
+pub struct FrameTimes {
+    /// Oldest first
+    frames: VecDeque<FrameTime>,
+}
+
+impl FrameTimes {
+    /// Number of frames kept
+    pub const CAPACITY: usize = 200;
+
+    /// Records `frame`, forgetting the oldest frame if there are too many.
+    pub fn record(&mut self, frame: FrameTime) {
+        if self.frames.len() == FrameTimes::CAPACITY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    pub fn frames(&self) -> &VecDeque<FrameTime> {
+        &self.frames
+    }
+
+    /// Returns the total time that at least `percentile` percent of the recorded
+    /// frames took at most, or `None` if there are none.
+    pub fn percentile(&self, percentile: f32) -> Option<Duration> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        let mut totals: Vec<Duration> = self.frames.iter().map(|frame| frame.total).collect();
+        totals.sort();
+        let rank = (percentile / 100.0 * totals.len() as f32).ceil() as usize;
+        Some(totals[rank.max(1).min(totals.len()) - 1])
+    }
+}
+
+/// Draws a bar for each of the recorded frame times in the bottom left corner of
+/// the view, newest on the right, split into updating, rendering and the rest.
+/// Lines mark the times of frames at 60 and 30 frames per second.
+pub fn render_frame_times<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    frame_times: &FrameTimes,
+    theme: &Theme,
+) -> Result<(), Error> {
+    const PADDING: u32 = 2;
+    /// Height of the bars in logical pixels per millisecond
+    const PIXELS_PER_MS: f32 = 2.0;
+    /// Longest time the bars show, so that a long hitch doesn't cover the view
+    const MAX_MS: f32 = 50.0;
+    let graph_height = (MAX_MS * PIXELS_PER_MS) as u32;
+    let text_height = font::LINE_HEIGHT;
+    let width = FrameTimes::CAPACITY as u32 + 2 * PADDING;
+    let height = graph_height + text_height + 2 * PADDING;
+    let view_height = canvas.viewport().height();
+    let top = view_height.saturating_sub(height) as i32;
+    let bottom = top + (PADDING + graph_height) as i32;
+    canvas.set_draw_color(Color::RGBA(0x00, 0x00, 0x00, 0xa0));
+    canvas
+        .fill_rect(SdlRect::new(0, top, width, height))
+        .map_err(err_msg)?;
+
+    let pixels = |time: Duration| {
+        let ms = (time.as_fractional_secs() * 1000.0) as f32;
+        (ms.min(MAX_MS) * PIXELS_PER_MS).round() as u32
+    };
+    // Collect the bars per series, so each series is drawn in one go
+    let (mut updates, mut renders, mut rests) = (Vec::new(), Vec::new(), Vec::new());
+    for (index, frame) in frame_times.frames().iter().enumerate() {
+        let x = (PADDING + index as u32) as i32;
+        let update = pixels(frame.update);
+        let render = pixels(frame.update + frame.render).saturating_sub(update);
+        let rest = pixels(frame.total).saturating_sub(update + render);
+        // Stack the update time at the bottom, then the render time, then the rest
+        let render_bottom = bottom - update as i32;
+        let rest_bottom = render_bottom - render as i32;
+        if update > 0 {
+            updates.push(SdlRect::new(x, render_bottom, 1, update));
+        }
+        if render > 0 {
+            renders.push(SdlRect::new(x, rest_bottom, 1, render));
+        }
+        if rest > 0 {
+            rests.push(SdlRect::new(x, rest_bottom - rest as i32, 1, rest));
+        }
+    }
+    let series = [
+        (rests, Color::RGB(0x60, 0x60, 0x60)),
+        (updates, Color::RGB(0x40, 0x80, 0xff)),
+        (renders, Color::RGB(0x40, 0xff, 0x80)),
+    ];
+    for &(ref rects, color) in &series {
+        if !rects.is_empty() {
+            canvas.set_draw_color(color);
+            canvas.fill_rects(rects).map_err(err_msg)?;
+        }
+    }
+
+    let lines = [
+        (16.6, Color::RGB(0xff, 0xff, 0x00)),
+        (33.3, Color::RGB(0xff, 0x40, 0x40)),
+    ];
+    for &(ms, color) in &lines {
+        let y = bottom - (ms * PIXELS_PER_MS).round() as i32;
+        canvas.set_draw_color(color);
+        canvas
+            .fill_rect(SdlRect::new(PADDING as i32, y, FrameTimes::CAPACITY as u32, 1))
+            .map_err(err_msg)?;
+    }
+
+    let format_ms = |time: Option<Duration>| match time {
+        Some(time) => format!("{:.1}MS", time.as_fractional_secs() * 1000.0),
+        None => "-".to_string(),
+    };
+    let text = format!(
+        "P95 {} P99 {}",
+        format_ms(frame_times.percentile(95.0)),
+        format_ms(frame_times.percentile(99.0))
+    );
+    canvas.set_draw_color(theme.rates_text());
+    font::render_text(canvas, &text, PADDING as i32, bottom + PADDING as i32, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn rate_counter_without_events_is_zero() {
+        assert_eq!(RateCounter::default().per_second(), 0);
+    }
+
+    #[test]
+    fn rate_counter_averages_over_the_last_second() {
+        let start = Instant::now();
+        let mut frames = RateCounter::default();
+        for frame in 0..200 {
+            frames.record(start + ms(10 * frame), 1);
+        }
+        assert_eq!(frames.per_second(), 100);
+    }
+
+    #[test]
+    fn rate_counter_adds_up_counts() {
+        let start = Instant::now();
+        let mut ticks = RateCounter::default();
+        for frame in 0..100 {
+            ticks.record(start + ms(20 * frame), 3);
+        }
+        assert_eq!(ticks.per_second(), 150);
+    }
+
+    #[test]
+    fn rate_counter_forgets_events_a_second_old() {
+        let start = Instant::now();
+        let mut frames = RateCounter::default();
+        frames.record(start, 5);
+        frames.record(start + ms(999), 1);
+        assert_eq!(frames.per_second(), 6);
+        frames.record(start + ms(1000), 1);
+        assert_eq!(frames.per_second(), 2);
+        // After a hitch only the frame after it counts
+        frames.record(start + ms(3000), 1);
+        assert_eq!(frames.per_second(), 1);
+    }
+
+    fn frame_taking(total: Duration) -> FrameTime {
+        FrameTime {
+            total,
+            ..FrameTime::default()
+        }
+    }
+
+    #[test]
+    fn frame_times_keep_only_the_latest_frames() {
+        let mut frame_times = FrameTimes::default();
+        for frame in 0..FrameTimes::CAPACITY as u64 + 10 {
+            frame_times.record(frame_taking(ms(frame)));
+        }
+        let frames = frame_times.frames();
+        assert_eq!(frames.len(), FrameTimes::CAPACITY);
+        assert_eq!(frames.front().unwrap().total, ms(10));
+        assert_eq!(frames.back().unwrap().total, ms(FrameTimes::CAPACITY as u64 + 9));
+    }
+
+    #[test]
+    fn percentile_without_frames_is_none() {
+        assert_eq!(FrameTimes::default().percentile(95.0), None);
+    }
+
+    #[test]
+    fn percentile_is_time_most_frames_took_at_most() {
+        let mut frame_times = FrameTimes::default();
+        // Out of order, so that the percentile can't depend on the order
+        for frame in (1..=100).rev() {
+            frame_times.record(frame_taking(ms(frame)));
+        }
+        assert_eq!(frame_times.percentile(50.0), Some(ms(50)));
+        assert_eq!(frame_times.percentile(95.0), Some(ms(95)));
+        assert_eq!(frame_times.percentile(99.0), Some(ms(99)));
+        assert_eq!(frame_times.percentile(100.0), Some(ms(100)));
+        assert_eq!(frame_times.percentile(0.0), Some(ms(1)));
+    }
+
+    #[test]
+    fn single_hitch_shows_in_high_percentile_only() {
+        let mut frame_times = FrameTimes::default();
+        for _ in 0..99 {
+            frame_times.record(frame_taking(ms(16)));
+        }
+        frame_times.record(frame_taking(ms(250)));
+        assert_eq!(frame_times.percentile(95.0), Some(ms(16)));
+        assert_eq!(frame_times.percentile(99.0), Some(ms(16)));
+        assert_eq!(frame_times.percentile(99.5), Some(ms(250)));
+    }
+}
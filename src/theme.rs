@@ -0,0 +1,480 @@
+//! Colors the game is drawn in
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use failure::Error;
+use sdl2::pixels::Color;
+
+/// Colors of the things drawn without a tileset or sprite sheet, as red, green
+/// and blue components, and alpha for those drawn over other things, so they can
+/// be changed without recompiling.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Bars filling the space around the view when the window doesn't fit it
+    pub letterbox: (u8, u8, u8),
+    /// Empty tiles, which is also what shows through transparent tile art
+    pub empty: (u8, u8, u8),
+    /// Filled and slope tiles
+    pub filled: (u8, u8, u8),
+    /// Blink tiles while solid, and while intangible
+    pub blink: (u8, u8, u8),
+    pub blink_off: (u8, u8, u8),
+    pub switch: (u8, u8, u8),
+    pub crumble: (u8, u8, u8),
+    pub goal: (u8, u8, u8),
+    pub gravity_flip: (u8, u8, u8),
+    pub bounce: (u8, u8, u8),
+    pub door: (u8, u8, u8),
+    /// Colors telling teleporter pairs and switch channels apart, taken in turn by id
+    pub id_colors: Vec<(u8, u8, u8)>,
+    /// Dot on the switches whose channel is flipped
+    pub switch_flipped: (u8, u8, u8),
+    /// Cross over the one-shot switches that were used
+    pub switch_used: (u8, u8, u8),
+    /// Tint of crumble tiles, deepening the longer they're stood on
+    pub crumbling: (u8, u8, u8),
+    /// Tint of the blink tiles in their last moments of being solid, and of being intangible
+    pub blink_warning_solid: (u8, u8, u8, u8),
+    pub blink_warning_off: (u8, u8, u8, u8),
+    pub player: (u8, u8, u8),
+    pub second_player: (u8, u8, u8),
+    /// Notch on the side the player faces
+    pub player_notch: (u8, u8, u8),
+    /// Copies of the player trailing behind them while they sprint
+    pub afterimage: (u8, u8, u8),
+    pub grapple_rope: (u8, u8, u8),
+    /// Flash a dying player shrinks into
+    pub dying: (u8, u8, u8),
+    /// Particles kicked up by jumping and landing hard
+    pub dust: (u8, u8, u8),
+    pub death_particles: (u8, u8, u8),
+    pub platform: (u8, u8, u8),
+    pub crusher: (u8, u8, u8),
+    pub crusher_outline: (u8, u8, u8),
+    pub turret: (u8, u8, u8),
+    pub turret_barrel: (u8, u8, u8),
+    pub projectile: (u8, u8, u8),
+    pub sign_post: (u8, u8, u8),
+    pub sign_board: (u8, u8, u8),
+    pub coin: (u8, u8, u8),
+    pub key: (u8, u8, u8),
+    pub crate_fill: (u8, u8, u8),
+    pub crate_outline: (u8, u8, u8),
+    pub enemy: (u8, u8, u8),
+    /// What the screen fades to around respawning
+    pub fade: (u8, u8, u8),
+    /// Dimming behind the pause symbol, the results and the physics overlay
+    pub dim: (u8, u8, u8, u8),
+    /// Pause symbol and game over cross
+    pub overlay_symbol: (u8, u8, u8),
+    pub game_over_tint: (u8, u8, u8, u8),
+    /// Box behind sign messages, and their text
+    pub message_box: (u8, u8, u8, u8),
+    pub message_text: (u8, u8, u8),
+    /// Outlines of the coins not collected in the results
+    pub results_missed_coin: (u8, u8, u8),
+    /// Squares counting the deaths in the results
+    pub results_death: (u8, u8, u8),
+    /// Outline of what the camera sees on the minimap
+    pub minimap_view: (u8, u8, u8),
+    /// Outline of the tile being edited
+    pub editor_cursor: (u8, u8, u8),
+    /// Outline of the tile the player spawns at in the editor
+    pub editor_spawn: (u8, u8, u8),
+    /// Outline of the player's collision rect in the physics overlay
+    pub debug_outline: (u8, u8, u8),
+    /// Text of the physics overlay
+    pub debug_text: (u8, u8, u8),
+    /// Tint of the solid and the free tiles the collision checks looked at in the collision overlay
+    pub collision_solid: (u8, u8, u8, u8),
+    pub collision_free: (u8, u8, u8, u8),
+    /// Outline of the player's collision rect in the collision overlay
+    pub collision_outline: (u8, u8, u8),
+    /// Collision checks that hit something, and those that didn't by kind
+    pub probe_hit: (u8, u8, u8),
+    pub probe_horizontal: (u8, u8, u8),
+    pub probe_vertical: (u8, u8, u8),
+    pub probe_floor: (u8, u8, u8),
+    /// Text of the frame and tick rate overlay
+    pub rates_text: (u8, u8, u8),
+    /// Text of the status bar
+    pub hud_text: (u8, u8, u8),
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            letterbox: (0x00, 0x00, 0x00),
+            empty: (0x00, 0x00, 0x00),
+            filled: (0x80, 0x80, 0x80),
+            blink: (0x30, 0x90, 0xc0),
+            blink_off: (0x10, 0x28, 0x38),
+            switch: (0x30, 0x30, 0x30),
+            crumble: (0xa0, 0x88, 0x60),
+            goal: (0x20, 0xa0, 0x40),
+            gravity_flip: (0x80, 0x30, 0xa0),
+            bounce: (0xe0, 0x80, 0x20),
+            door: (0x90, 0x60, 0x30),
+            id_colors: vec![
+                (0xff, 0x40, 0xc0),
+                (0x40, 0xff, 0xc0),
+                (0xc0, 0xc0, 0x40),
+                (0x40, 0x80, 0xff),
+                (0xff, 0x80, 0x80),
+                (0xc0, 0x80, 0xff),
+            ],
+            switch_flipped: (0xff, 0xff, 0xff),
+            switch_used: (0x00, 0x00, 0x00),
+            crumbling: (0x40, 0x10, 0x00),
+            blink_warning_solid: (0x00, 0x00, 0x00, 0x80),
+            blink_warning_off: (0x30, 0x90, 0xc0, 0x60),
+            player: (0xff, 0xff, 0xff),
+            second_player: (0x80, 0xc0, 0xff),
+            player_notch: (0x40, 0x40, 0x40),
+            afterimage: (0xa0, 0xa0, 0xff),
+            grapple_rope: (0xc0, 0xa0, 0x60),
+            dying: (0xff, 0x40, 0x40),
+            dust: (0xc0, 0xb0, 0x90),
+            death_particles: (0xff, 0x60, 0x60),
+            platform: (0x80, 0x50, 0x20),
+            crusher: (0x70, 0x70, 0x80),
+            crusher_outline: (0x30, 0x30, 0x38),
+            turret: (0x50, 0x50, 0x50),
+            turret_barrel: (0xd0, 0x20, 0x20),
+            projectile: (0xff, 0x80, 0x40),
+            sign_post: (0x80, 0x50, 0x20),
+            sign_board: (0xc0, 0x90, 0x50),
+            coin: (0xff, 0xd0, 0x00),
+            key: (0x40, 0xe0, 0xe0),
+            crate_fill: (0xb0, 0x70, 0x30),
+            crate_outline: (0x60, 0x38, 0x10),
+            enemy: (0xd0, 0x20, 0x20),
+            fade: (0x00, 0x00, 0x00),
+            dim: (0x00, 0x00, 0x00, 0xa0),
+            overlay_symbol: (0xff, 0xff, 0xff),
+            game_over_tint: (0x60, 0x00, 0x00, 0xc0),
+            message_box: (0x00, 0x00, 0x00, 0xc0),
+            message_text: (0xff, 0xff, 0xff),
+            results_missed_coin: (0x80, 0x68, 0x00),
+            results_death: (0xd0, 0x20, 0x20),
+            minimap_view: (0xff, 0xff, 0xff),
+            editor_cursor: (0xff, 0x00, 0x00),
+            editor_spawn: (0x00, 0xff, 0x00),
+            debug_outline: (0xff, 0x00, 0xff),
+            debug_text: (0x80, 0xff, 0x80),
+            collision_solid: (0xff, 0x40, 0x40, 0x60),
+            collision_free: (0x40, 0xff, 0x40, 0x40),
+            collision_outline: (0xff, 0xff, 0xff),
+            probe_hit: (0xff, 0x40, 0x40),
+            probe_horizontal: (0x40, 0xc0, 0xff),
+            probe_vertical: (0xff, 0xc0, 0x40),
+            probe_floor: (0x40, 0xff, 0x40),
+            rates_text: (0xff, 0xff, 0x00),
+            hud_text: (0xff, 0xff, 0xff),
+        }
+    }
+}
+
+impl Theme {
+    /// Loads a theme from a JSON file. Colors missing from the file keep their defaults.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Theme, Error> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let theme = ::serde_json::from_reader(reader)?;
+        Ok(theme)
+    }
+
+    pub fn letterbox(&self) -> Color {
+        rgb(self.letterbox)
+    }
+
+    pub fn empty(&self) -> Color {
+        rgb(self.empty)
+    }
+
+    pub fn filled(&self) -> Color {
+        rgb(self.filled)
+    }
+
+    pub fn blink(&self) -> Color {
+        rgb(self.blink)
+    }
+
+    pub fn blink_off(&self) -> Color {
+        rgb(self.blink_off)
+    }
+
+    pub fn switch(&self) -> Color {
+        rgb(self.switch)
+    }
+
+    pub fn crumble(&self) -> Color {
+        rgb(self.crumble)
+    }
+
+    pub fn goal(&self) -> Color {
+        rgb(self.goal)
+    }
+
+    pub fn gravity_flip(&self) -> Color {
+        rgb(self.gravity_flip)
+    }
+
+    pub fn bounce(&self) -> Color {
+        rgb(self.bounce)
+    }
+
+    pub fn door(&self) -> Color {
+        rgb(self.door)
+    }
+
+    /// Returns the color of the teleporters or switch channel with the given id.
+    pub fn id_color(&self, id: u32) -> Color {
+        match self.id_colors.len() {
+            0 => self.filled(),
+            count => rgb(self.id_colors[id as usize % count]),
+        }
+    }
+
+    pub fn switch_flipped(&self) -> Color {
+        rgb(self.switch_flipped)
+    }
+
+    pub fn switch_used(&self) -> Color {
+        rgb(self.switch_used)
+    }
+
+    pub fn crumbling(&self) -> Color {
+        rgb(self.crumbling)
+    }
+
+    pub fn blink_warning_solid(&self) -> Color {
+        rgba(self.blink_warning_solid)
+    }
+
+    pub fn blink_warning_off(&self) -> Color {
+        rgba(self.blink_warning_off)
+    }
+
+    pub fn player(&self) -> Color {
+        rgb(self.player)
+    }
+
+    pub fn second_player(&self) -> Color {
+        rgb(self.second_player)
+    }
+
+    pub fn player_notch(&self) -> Color {
+        rgb(self.player_notch)
+    }
+
+    pub fn afterimage(&self) -> Color {
+        rgb(self.afterimage)
+    }
+
+    pub fn grapple_rope(&self) -> Color {
+        rgb(self.grapple_rope)
+    }
+
+    pub fn dying(&self) -> Color {
+        rgb(self.dying)
+    }
+
+    pub fn dust(&self) -> Color {
+        rgb(self.dust)
+    }
+
+    pub fn death_particles(&self) -> Color {
+        rgb(self.death_particles)
+    }
+
+    pub fn platform(&self) -> Color {
+        rgb(self.platform)
+    }
+
+    pub fn crusher(&self) -> Color {
+        rgb(self.crusher)
+    }
+
+    pub fn crusher_outline(&self) -> Color {
+        rgb(self.crusher_outline)
+    }
+
+    pub fn turret(&self) -> Color {
+        rgb(self.turret)
+    }
+
+    pub fn turret_barrel(&self) -> Color {
+        rgb(self.turret_barrel)
+    }
+
+    pub fn projectile(&self) -> Color {
+        rgb(self.projectile)
+    }
+
+    pub fn sign_post(&self) -> Color {
+        rgb(self.sign_post)
+    }
+
+    pub fn sign_board(&self) -> Color {
+        rgb(self.sign_board)
+    }
+
+    pub fn coin(&self) -> Color {
+        rgb(self.coin)
+    }
+
+    pub fn key(&self) -> Color {
+        rgb(self.key)
+    }
+
+    pub fn crate_fill(&self) -> Color {
+        rgb(self.crate_fill)
+    }
+
+    pub fn crate_outline(&self) -> Color {
+        rgb(self.crate_outline)
+    }
+
+    pub fn enemy(&self) -> Color {
+        rgb(self.enemy)
+    }
+
+    pub fn fade(&self) -> Color {
+        rgb(self.fade)
+    }
+
+    pub fn dim(&self) -> Color {
+        rgba(self.dim)
+    }
+
+    pub fn overlay_symbol(&self) -> Color {
+        rgb(self.overlay_symbol)
+    }
+
+    pub fn game_over_tint(&self) -> Color {
+        rgba(self.game_over_tint)
+    }
+
+    pub fn message_box(&self) -> Color {
+        rgba(self.message_box)
+    }
+
+    pub fn message_text(&self) -> Color {
+        rgb(self.message_text)
+    }
+
+    pub fn results_missed_coin(&self) -> Color {
+        rgb(self.results_missed_coin)
+    }
+
+    pub fn results_death(&self) -> Color {
+        rgb(self.results_death)
+    }
+
+    pub fn minimap_view(&self) -> Color {
+        rgb(self.minimap_view)
+    }
+
+    pub fn editor_cursor(&self) -> Color {
+        rgb(self.editor_cursor)
+    }
+
+    pub fn editor_spawn(&self) -> Color {
+        rgb(self.editor_spawn)
+    }
+
+    pub fn debug_outline(&self) -> Color {
+        rgb(self.debug_outline)
+    }
+
+    pub fn debug_text(&self) -> Color {
+        rgb(self.debug_text)
+    }
+
+    pub fn collision_solid(&self) -> Color {
+        rgba(self.collision_solid)
+    }
+
+    pub fn collision_free(&self) -> Color {
+        rgba(self.collision_free)
+    }
+
+    pub fn collision_outline(&self) -> Color {
+        rgb(self.collision_outline)
+    }
+
+    pub fn probe_hit(&self) -> Color {
+        rgb(self.probe_hit)
+    }
+
+    pub fn probe_horizontal(&self) -> Color {
+        rgb(self.probe_horizontal)
+    }
+
+    pub fn probe_vertical(&self) -> Color {
+        rgb(self.probe_vertical)
+    }
+
+    pub fn probe_floor(&self) -> Color {
+        rgb(self.probe_floor)
+    }
+
+    pub fn rates_text(&self) -> Color {
+        rgb(self.rates_text)
+    }
+
+    pub fn hud_text(&self) -> Color {
+        rgb(self.hud_text)
+    }
+}
+
+fn rgb((r, g, b): (u8, u8, u8)) -> Color {
+    Color::RGB(r, g, b)
+}
+
+fn rgba((r, g, b, a): (u8, u8, u8, u8)) -> Color {
+    Color::RGBA(r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn custom_theme_overrides_only_the_colors_it_has() {
+        let path = env::temp_dir().join("grot-test-theme.json");
+        let json = r#"{
+            "filled": [16, 32, 48],
+            "dim": [255, 255, 255, 64],
+            "id_colors": [[1, 2, 3], [4, 5, 6]]
+        }"#;
+        fs::write(&path, json).unwrap();
+        let theme = Theme::load(&path);
+        fs::remove_file(&path).unwrap();
+        let theme = theme.unwrap();
+        assert_eq!(theme.filled(), Color::RGB(16, 32, 48));
+        assert_eq!(theme.dim(), Color::RGBA(255, 255, 255, 64));
+        assert_eq!(theme.id_color(0), Color::RGB(1, 2, 3));
+        assert_eq!(theme.id_color(3), Color::RGB(4, 5, 6));
+        // Everything else keeps its default
+        let default = Theme::default();
+        assert_eq!(theme.player(), default.player());
+        assert_eq!(theme.crate_fill(), Color::RGB(0xb0, 0x70, 0x30));
+    }
+
+    #[test]
+    fn theme_without_id_colors_falls_back_to_filled() {
+        let theme = Theme {
+            id_colors: Vec::new(),
+            ..Theme::default()
+        };
+        assert_eq!(theme.id_color(5), theme.filled());
+    }
+}
@@ -0,0 +1,77 @@
+//! Tile art loaded from an image, replacing the flat colors tiles are drawn with
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use failure::{err_msg, Error};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect as SdlRect;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::surface::Surface;
+
+use room::TileKind;
+
+/// Which part of a tileset image each kind of tile is drawn with, as stored in a
+/// JSON file, e.g. `{"image": "tiles.bmp", "tile_size": 16, "tiles": {"Filled": [0, 0]}}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TileMapping {
+    /// Path of the BMP image, relative to the mapping file
+    pub image: String,
+    /// Size of a tile in the image in pixels
+    pub tile_size: u32,
+    /// Column and row in the image for each tile kind name, see `TileKind::name`
+    pub tiles: HashMap<String, (u32, u32)>,
+}
+
+/// A tileset image loaded into a texture, together with the part of it each kind
+/// of tile is drawn with.
+pub struct Tileset<'r> {
+    texture: Texture<'r>,
+    sources: HashMap<String, SdlRect>,
+}
+
+impl<'r> Tileset<'r> {
+    /// Loads the tile mapping at `path` and the image it refers to.
+    ///
+    /// Magenta pixels in the image are transparent.
+    pub fn load<T, P: AsRef<Path>>(
+        texture_creator: &'r TextureCreator<T>,
+        path: P,
+    ) -> Result<Tileset<'r>, Error> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let mapping: TileMapping = ::serde_json::from_reader(reader)?;
+        ensure!(mapping.tile_size > 0, "Tileset tile size must not be zero");
+        let image_path = path.parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&mapping.image);
+        let mut surface = Surface::load_bmp(&image_path).map_err(err_msg)?;
+        surface
+            .set_color_key(true, Color::RGB(0xff, 0x00, 0xff))
+            .map_err(err_msg)?;
+        let texture = texture_creator.create_texture_from_surface(&surface)?;
+        let size = mapping.tile_size;
+        let sources = mapping
+            .tiles
+            .iter()
+            .map(|(name, &(column, row))| {
+                let source = SdlRect::new((column * size) as i32, (row * size) as i32, size, size);
+                (name.clone(), source)
+            })
+            .collect();
+        Ok(Tileset { texture, sources })
+    }
+
+    pub fn texture(&self) -> &Texture<'r> {
+        &self.texture
+    }
+
+    /// Returns the part of the image to draw `kind` with, or `None` if it isn't
+    /// mapped and should be drawn in its plain color.
+    pub fn source_rect(&self, kind: TileKind) -> Option<SdlRect> {
+        self.sources.get(kind.name()).cloned()
+    }
+}
@@ -0,0 +1,103 @@
+//! Sliding from one room to the next when the player leaves a room
+
+use std::time::{Duration, Instant};
+
+use floating_duration::TimeAsFloat;
+
+use room::Edge;
+
+/// A room change being shown by sliding the last frame of the old room out of view
+/// while the new room slides in, in the direction the player went.
+///
+/// The transition follows wall time rather than frames, so it takes as long at
+/// any frame rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoomTransition {
+    /// Edge of the old room through which it was left
+    edge: Edge,
+    started: Instant,
+}
+
+impl RoomTransition {
+    /// How long a transition takes
+    pub const DURATION: Duration = Duration::from_millis(300);
+
+    pub fn new(edge: Edge, started: Instant) -> RoomTransition {
+        RoomTransition { edge, started }
+    }
+
+    /// Returns how far along the transition is at `now`, from 0 when it starts to 1
+    /// when it's finished.
+    pub fn progress(&self, now: Instant) -> f32 {
+        if now <= self.started {
+            return 0.0;
+        }
+        let elapsed = (now - self.started).as_fractional_secs();
+        (elapsed / RoomTransition::DURATION.as_fractional_secs()).min(1.0) as f32
+    }
+
+    pub fn is_finished(&self, now: Instant) -> bool {
+        self.progress(now) >= 1.0
+    }
+
+    /// Returns where the old and the new frame go at `now`, as offsets in pixels
+    /// from where they'd normally be drawn on an output of `size`.
+    pub fn offsets(&self, now: Instant, size: (u32, u32)) -> ((i32, i32), (i32, i32)) {
+        let progress = self.progress(now);
+        // Direction in which the frames move, opposite to where the player went
+        let (dx, dy) = self.edge.direction().opposite().delta();
+        let (width, height) = (size.0 as f32, size.1 as f32);
+        let old_x = (dx as f32 * width * progress).round() as i32;
+        let old_y = (dy as f32 * height * progress).round() as i32;
+        let (new_x, new_y) = (old_x - dx * size.0 as i32, old_y - dy * size.1 as i32);
+        ((old_x, old_y), (new_x, new_y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn progress_follows_wall_time() {
+        let start = Instant::now() + ms(1000);
+        let transition = RoomTransition::new(Edge::Right, start);
+        assert_eq!(transition.progress(start - ms(100)), 0.0);
+        assert_eq!(transition.progress(start), 0.0);
+        assert_eq!(transition.progress(start + ms(150)), 0.5);
+        assert_eq!(transition.progress(start + ms(300)), 1.0);
+        assert_eq!(transition.progress(start + ms(5000)), 1.0);
+    }
+
+    #[test]
+    fn transition_finishes_after_its_duration() {
+        let start = Instant::now();
+        let transition = RoomTransition::new(Edge::Left, start);
+        assert!(!transition.is_finished(start));
+        assert!(!transition.is_finished(start + ms(299)));
+        assert!(transition.is_finished(start + RoomTransition::DURATION));
+        // Even if no frame was drawn in between
+        assert!(transition.is_finished(start + ms(2000)));
+    }
+
+    #[test]
+    fn frames_slide_against_the_way_the_player_went() {
+        let start = Instant::now();
+        let size = (640, 480);
+        let right = RoomTransition::new(Edge::Right, start);
+        assert_eq!(right.offsets(start, size), ((0, 0), (640, 0)));
+        assert_eq!(right.offsets(start + ms(150), size), ((-320, 0), (320, 0)));
+        assert_eq!(right.offsets(start + ms(300), size), ((-640, 0), (0, 0)));
+
+        let top = RoomTransition::new(Edge::Top, start);
+        assert_eq!(top.offsets(start + ms(150), size), ((0, 240), (0, -240)));
+        let left = RoomTransition::new(Edge::Left, start);
+        assert_eq!(left.offsets(start + ms(300), size), ((640, 0), (0, 0)));
+        let bottom = RoomTransition::new(Edge::Bottom, start);
+        assert_eq!(bottom.offsets(start, size), ((0, 0), (0, 480)));
+    }
+}